@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Returns the URL of the JSON API describing the show at `show_url`, along with the name
+/// of its cache file.
+pub fn show_json_url(show_url: &str) -> Result<(String, String)> {
+    let mut parsed =
+        Url::parse(show_url).with_context(|| format!("Invalid show URL: {}", show_url))?;
+    // The JSON is the same whatever page of the listing the URL points to.
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+    let path = format!("{}.json", parsed.path().trim_end_matches('/'));
+    parsed.set_path(&path);
+    Ok((parsed.to_string(), crate::blocks::cache_name(&path)))
+}
+
+/// An episode listed in the JSON API of a show.
+#[derive(Debug)]
+pub struct Card {
+    /// Path of the episode metadata.
+    pub path: String,
+    /// The card itself, which usually carries the same fields as the episode metadata.
+    pub value: Value,
+    /// Season of the block the card is listed in, when its title names one.
+    pub season: Option<usize>,
+}
+
+/// Collects the episode cards found anywhere in the JSON value, in order.
+///
+/// A card is an object whose `path_id` points to the metadata of an audio item. Blocks
+/// titled after a season, like "Stagione 2", pass their season to the cards below.
+fn collect_cards(value: &Value, season: Option<usize>, cards: &mut Vec<Card>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(path) = map.get("path_id").and_then(Value::as_str) {
+                if path.starts_with("/audio/") && path.ends_with(".json") {
+                    cards.push(Card {
+                        path: path.to_string(),
+                        value: value.clone(),
+                        season,
+                    });
+                    return;
+                }
+            }
+            let season = ["title", "name", "label"]
+                .iter()
+                .filter_map(|key| map.get(*key).and_then(Value::as_str))
+                .find_map(crate::season::season_number)
+                .or(season);
+            for child in map.values() {
+                collect_cards(child, season, cards);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_cards(item, season, cards);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses the JSON API of a show into its episode cards.
+pub fn episode_cards(json: &str) -> Result<Vec<Card>> {
+    let value: Value = serde_json::from_str(json).context("Failed to parse show JSON")?;
+    let mut cards = Vec::new();
+    collect_cards(&value, None, &mut cards);
+    let mut seen = HashSet::new();
+    cards.retain(|card| seen.insert(card.path.clone()));
+    Ok(cards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_show_json_url() {
+        let (url, cache_name) =
+            show_json_url("https://www.raiplaysound.it/audiolibri/itremoschettieri/").unwrap();
+        assert_eq!(
+            url,
+            "https://www.raiplaysound.it/audiolibri/itremoschettieri.json"
+        );
+        assert_eq!(cache_name, "audiolibri_itremoschettieri.json");
+
+        let (url, cache_name) =
+            show_json_url("https://WWW.raiplaysound.it/programmi/ilruggitodelconiglio?page=2#top")
+                .unwrap();
+        assert_eq!(
+            url,
+            "https://www.raiplaysound.it/programmi/ilruggitodelconiglio.json"
+        );
+        assert_eq!(cache_name, "programmi_ilruggitodelconiglio.json");
+    }
+
+    #[test]
+    fn test_episode_cards() {
+        let json = include_str!("../tests/fixtures/itremoschettieri.json");
+        let cards = episode_cards(json).unwrap();
+        let paths: Vec<&str> = cards.iter().map(|card| card.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/audio/2015/06/lettura-1.json",
+                "/audio/2015/06/lettura-2.json"
+            ]
+        );
+        assert_eq!(cards[0].value["audio"]["title"], "Lettura I");
+        assert_eq!(cards[0].season, None);
+        assert!(episode_cards("<html>").is_err());
+    }
+
+    #[test]
+    fn test_episode_cards_seasons() {
+        let json = r#"{
+            "title": "Il conte",
+            "blocks": [
+                {"title": "Stagione 1", "cards": [{"path_id": "/audio/a.json"}]},
+                {"title": "Stagione 2", "cards": [{"path_id": "/audio/b.json"}]},
+                {"title": "Extra", "cards": [{"path_id": "/audio/c.json"}]}
+            ]
+        }"#;
+        let seasons: Vec<Option<usize>> = episode_cards(json)
+            .unwrap()
+            .iter()
+            .map(|card| card.season)
+            .collect();
+        assert_eq!(seasons, vec![Some(1), Some(2), None]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::relinker::EXTENSIONS;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// How an episode found in another folder was put in the output folder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placed {
+    Linked,
+    /// Copied, because the folders are on different devices or links are not supported.
+    Copied,
+}
+
+/// Returns the audio file of the folder whose `.info.json` sidecar describes the
+/// episode, by id or, for episodes without one, by audio URL.
+pub fn find_by_info(folder: &Path, id: Option<&str>, audio_url: &str) -> Result<Option<PathBuf>> {
+    if !folder.is_dir() {
+        return Ok(None);
+    }
+    for entry in std::fs::read_dir(folder)
+        .with_context(|| format!("Failed to read folder: {}", folder.display()))?
+    {
+        let path = entry?.path();
+        let Some(stem) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".info.json"))
+        else {
+            continue;
+        };
+        let Some(info) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+        else {
+            log::debug!("Ignoring unreadable sidecar {}", path.display());
+            continue;
+        };
+        let same = match id {
+            Some(id) => info["id"].as_str() == Some(id),
+            None => info["audio_url"].as_str() == Some(audio_url),
+        };
+        if !same {
+            continue;
+        }
+        let audio = EXTENSIONS
+            .iter()
+            .map(|extension| folder.join(format!("{}.{}", stem, extension)))
+            .find(|audio| audio.is_file());
+        if audio.is_some() {
+            return Ok(audio);
+        }
+    }
+    Ok(None)
+}
+
+/// Hard-links `from` to `to`, or copies it when it cannot be linked.
+pub fn link_or_copy(from: &Path, to: &Path) -> Result<Placed> {
+    if std::fs::hard_link(from, to).is_ok() {
+        return Ok(Placed::Linked);
+    }
+    std::fs::copy(from, to)
+        .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+    Ok(Placed::Copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_by_info() -> Result<()> {
+        let dir = TempDir::new()?;
+        let audio = dir.path().join("Lettura I (2015).mp3");
+        std::fs::write(&audio, b"ID3")?;
+        std::fs::write(
+            dir.path().join("Lettura I (2015).info.json"),
+            r#"{"id": "lettura-1", "audio_url": "https://example.com/1.mp3"}"#,
+        )?;
+        std::fs::write(
+            dir.path().join("Lettura II.info.json"),
+            r#"{"id": "lettura-2", "audio_url": "https://example.com/2.mp3"}"#,
+        )?;
+        std::fs::write(dir.path().join("broken.info.json"), "{")?;
+
+        let url = "https://example.com/1.mp3";
+        assert_eq!(
+            find_by_info(dir.path(), Some("lettura-1"), url)?,
+            Some(audio.clone())
+        );
+        assert_eq!(find_by_info(dir.path(), None, url)?, Some(audio));
+        // The sidecar of an episode whose audio is gone does not count.
+        assert_eq!(find_by_info(dir.path(), Some("lettura-2"), url)?, None);
+        assert_eq!(find_by_info(&dir.path().join("missing"), None, url)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_or_copy() -> Result<()> {
+        let dir = TempDir::new()?;
+        let from = dir.path().join("a.mp3");
+        std::fs::write(&from, b"ID3")?;
+        let to = dir.path().join("b.mp3");
+        assert_eq!(link_or_copy(&from, &to)?, Placed::Linked);
+        assert_eq!(std::fs::read(&to)?, b"ID3");
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// A file written under a temporary name of its folder, which takes its final name once
+/// complete, so that it is never seen half written. Each writer gets a name of its own,
+/// and the temporary file is removed when dropped before [`Partial::persist`].
+#[derive(Debug)]
+pub struct Partial {
+    temp: NamedTempFile,
+    path: PathBuf,
+}
+
+impl Partial {
+    /// Creates the temporary file of `path`, like `.01 - lettura i.mp3.x3Fq9z.part`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let folder = match path.parent() {
+            Some(folder) if !folder.as_os_str().is_empty() => folder,
+            _ => Path::new("."),
+        };
+        let prefix = format!(
+            ".{}.",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".part");
+        // The file keeps the permissions of a file created by `File::create`.
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+        Ok(Partial {
+            temp: builder.tempfile_in(folder)?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        self.temp.as_file_mut()
+    }
+
+    /// Renames the file to its final name, replacing the file there if any.
+    pub fn persist(self) -> io::Result<()> {
+        self.temp.persist(&self.path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+/// Writes `body` to `path` through a [`Partial`] file.
+pub fn write(path: &Path, body: &[u8]) -> io::Result<()> {
+    let mut partial = Partial::create(path)?;
+    partial.file().write_all(body)?;
+    partial.persist()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() -> io::Result<()> {
+        let folder = tempfile::TempDir::new()?;
+        let path = folder.path().join("show.json");
+        write(&path, b"{}")?;
+        write(&path, b"{\"url\": 1}")?;
+        assert_eq!(std::fs::read(&path)?, b"{\"url\": 1}");
+
+        // A file given up on leaves nothing behind.
+        let mut partial = Partial::create(&path)?;
+        partial.file().write_all(b"{")?;
+        drop(partial);
+        let names: Vec<_> = std::fs::read_dir(folder.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<_>>()?;
+        assert_eq!(names, ["show.json"]);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let folder = tempfile::TempDir::new()?;
+        let path = folder.path().join("01 - lettura i.mp3");
+        write(&path, b"ID3")?;
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o044, 0o044, "{:o}", mode);
+        Ok(())
+    }
+}
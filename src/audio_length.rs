@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Relative difference between the measured and advertised durations that is tolerated.
+pub const TOLERANCE: f64 = 0.05;
+
+/// Bitrates in kbps by bitrate index, for MPEG-1 layers I, II and III.
+const MPEG1_BITRATES: [[u32; 14]; 3] = [
+    [
+        32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+
+/// Bitrates in kbps by bitrate index, for MPEG-2 and 2.5 layer I, then layers II and III.
+const MPEG2_BITRATES: [[u32; 14]; 2] = [
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// A decoded MPEG audio frame header.
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    /// Frame length in bytes, header included.
+    length: usize,
+    samples: u32,
+    sample_rate: u32,
+}
+
+fn parse_frame(header: &[u8]) -> Option<Frame> {
+    if header.len() < 4 || header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 0x03; // 3: MPEG-1, 2: MPEG-2, 0: MPEG-2.5
+    let layer = (header[1] >> 1) & 0x03; // 3: layer I, 2: layer II, 1: layer III
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 {
+        return None;
+    }
+    if sample_rate_index == 3 {
+        return None;
+    }
+
+    let mpeg1 = version == 3;
+    let bitrate = 1000
+        * if mpeg1 {
+            MPEG1_BITRATES[(3 - layer) as usize][bitrate_index - 1]
+        } else {
+            MPEG2_BITRATES[usize::from(layer != 3)][bitrate_index - 1]
+        };
+    let sample_rate = [44100, 48000, 32000][sample_rate_index]
+        / match version {
+            3 => 1,
+            2 => 2,
+            _ => 4,
+        };
+
+    let (samples, length) = match layer {
+        3 => (384, (12 * bitrate / sample_rate + padding as u32) * 4),
+        2 => (1152, 144 * bitrate / sample_rate + padding as u32),
+        _ if mpeg1 => (1152, 144 * bitrate / sample_rate + padding as u32),
+        _ => (576, 72 * bitrate / sample_rate + padding as u32),
+    };
+    Some(Frame {
+        length: length as usize,
+        samples,
+        sample_rate,
+    })
+}
+
+/// Measures the duration of MPEG audio by walking its frame headers.
+fn mp3_duration(data: &[u8]) -> Option<f64> {
+    let mut position = 0;
+    if data.len() >= 10 && data.starts_with(b"ID3") {
+        let size = data[6..10]
+            .iter()
+            .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7F) as usize);
+        let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+        position = 10 + size + footer;
+    }
+
+    let mut seconds = 0.0;
+    let mut frames = 0;
+    while position + 4 <= data.len() {
+        match parse_frame(&data[position..]) {
+            Some(frame) if frame.length > 0 => {
+                seconds += f64::from(frame.samples) / f64::from(frame.sample_rate);
+                frames += 1;
+                position += frame.length;
+            }
+            // Skip garbage between frames, such as trailing tags.
+            _ => position += 1,
+        }
+    }
+    (frames > 0).then_some(seconds)
+}
+
+/// Iterates over the boxes of an MP4 container, yielding their type and content.
+fn mp4_boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        if position + 8 > data.len() {
+            return None;
+        }
+        let size = u32::from_be_bytes(data[position..position + 4].try_into().ok()?) as usize;
+        let kind = &data[position + 4..position + 8];
+        let (header, size) = match size {
+            0 => (8, data.len() - position),
+            1 => {
+                let large = data.get(position + 8..position + 16)?;
+                (16, u64::from_be_bytes(large.try_into().ok()?) as usize)
+            }
+            size => (8, size),
+        };
+        if size < header || position + size > data.len() {
+            return None;
+        }
+        let content = &data[position + header..position + size];
+        position += size;
+        Some((kind, content))
+    })
+}
+
+/// Reads the duration of an MP4 (m4a) file from its movie header.
+fn mp4_duration(data: &[u8]) -> Option<f64> {
+    let (_, moov) = mp4_boxes(data).find(|(kind, _)| *kind == b"moov")?;
+    let (_, mvhd) = mp4_boxes(moov).find(|(kind, _)| *kind == b"mvhd")?;
+    let read_u32 = |at: usize| Some(u32::from_be_bytes(mvhd.get(at..at + 4)?.try_into().ok()?));
+    let (timescale, duration) = if mvhd.first()? == &1 {
+        let duration = u64::from_be_bytes(mvhd.get(24..32)?.try_into().ok()?);
+        (read_u32(20)?, duration)
+    } else {
+        (read_u32(12)?, u64::from(read_u32(16)?))
+    };
+    (timescale > 0).then(|| duration as f64 / f64::from(timescale))
+}
+
+/// Measures the duration of the audio file in seconds, when its format is supported.
+pub fn measure(path: &Path) -> Result<Option<f64>> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    let measure = match extension {
+        "mp3" => mp3_duration,
+        "m4a" => mp4_duration,
+        _ => return Ok(None),
+    };
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok(measure(&data))
+}
+
+/// Returns whether the measured duration is within [`TOLERANCE`] of the expected one.
+pub fn matches(measured: f64, expected: u64) -> bool {
+    let expected = expected as f64;
+    (measured - expected).abs() <= expected * TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MPEG-1 layer III, 128 kbps, 44.1 kHz, no padding: 417 bytes and 1152 samples.
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+
+    fn mp3(frames: usize) -> Vec<u8> {
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x05hello".to_vec();
+        for _ in 0..frames {
+            let start = data.len();
+            data.extend_from_slice(&HEADER);
+            data.resize(start + 417, 0);
+        }
+        data.extend_from_slice(b"TAG trailing");
+        data
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        assert_eq!(
+            parse_frame(&HEADER),
+            Some(Frame {
+                length: 417,
+                samples: 1152,
+                sample_rate: 44100
+            })
+        );
+        // MPEG-2 layer III, 64 kbps, 22.05 kHz, padded.
+        assert_eq!(
+            parse_frame(&[0xFF, 0xF3, 0x82, 0x00]),
+            Some(Frame {
+                length: 209,
+                samples: 576,
+                sample_rate: 22050
+            })
+        );
+        assert_eq!(parse_frame(&[0xFF, 0xFB, 0xF0, 0x00]), None);
+        assert_eq!(parse_frame(b"<htm"), None);
+    }
+
+    #[test]
+    fn test_mp3_duration() {
+        let seconds = mp3_duration(&mp3(1000)).unwrap();
+        assert!((seconds - 1000.0 * 1152.0 / 44100.0).abs() < 1e-9);
+        assert_eq!(mp3_duration(b"<html></html>"), None);
+    }
+
+    #[test]
+    fn test_mp4_duration() {
+        let mut mvhd = vec![0u8; 4 + 4 + 4];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&1_155_000u32.to_be_bytes());
+        let mut moov = ((mvhd.len() + 8) as u32).to_be_bytes().to_vec();
+        moov.extend_from_slice(b"mvhd");
+        moov.extend_from_slice(&mvhd);
+
+        let mut data = 16u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftypM4A \0\0\0\0");
+        data.extend_from_slice(&((moov.len() + 8) as u32).to_be_bytes());
+        data.extend_from_slice(b"moov");
+        data.extend_from_slice(&moov);
+        assert_eq!(mp4_duration(&data), Some(1155.0));
+        assert_eq!(mp4_duration(b"not an mp4"), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(1155.0, 1155));
+        assert!(matches(1100.0, 1155));
+        assert!(!matches(600.0, 1155));
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A show listed in a batch file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BatchEntry {
+    /// 1-based line number in the batch file.
+    pub line: usize,
+    pub url: String,
+    /// Output folder overriding `--folder` for this show, relative to it unless absolute.
+    pub folder: Option<PathBuf>,
+}
+
+impl BatchEntry {
+    /// Returns the folder the show is saved in, given the folder of `--folder`.
+    pub fn output_folder(&self, base: &Path) -> PathBuf {
+        match &self.folder {
+            Some(folder) => base.join(folder),
+            None => base.to_path_buf(),
+        }
+    }
+}
+
+/// Checks that the URL is an absolute http(s) URL with a host.
+pub fn validate_url(url: &str) -> Result<()> {
+    let parsed = Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(anyhow!("Invalid URL: {}: expected an http(s) address", url));
+    }
+    Ok(())
+}
+
+/// Parses the contents of a batch file: one show URL per line, optionally followed by a
+/// tab and an output folder. Blank lines and `#` comments are ignored.
+///
+/// Invalid and duplicate lines are reported and skipped.
+pub fn parse_batch(contents: &str) -> Vec<BatchEntry> {
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    for (idx, raw_line) in contents.lines().enumerate() {
+        let line = idx + 1;
+        let text = raw_line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let (url, folder) = match raw_line.split_once('\t') {
+            Some((url, folder)) if !folder.trim().is_empty() => {
+                (url.trim(), Some(PathBuf::from(folder.trim())))
+            }
+            Some((url, _)) => (url.trim(), None),
+            None => (text, None),
+        };
+        if let Err(err) = validate_url(url) {
+            eprintln!("Warning: skipping batch line {}: {:#}", line, err);
+            continue;
+        }
+        if !seen.insert(url.to_string()) {
+            eprintln!(
+                "Warning: skipping batch line {}: duplicate URL {}",
+                line, url
+            );
+            continue;
+        }
+        entries.push(BatchEntry {
+            line,
+            url: url.to_string(),
+            folder,
+        });
+    }
+    entries
+}
+
+/// Checks the output folders of the entries before any show is downloaded, so that a
+/// mistyped folder fails the run rather than a show halfway through it.
+///
+/// Folders relative to `base` are created as needed. An absolute folder that does not
+/// exist must at least have its parent, which catches typos in mount points.
+pub fn check_folders(entries: &[BatchEntry], base: &Path) -> Result<()> {
+    let mut problems = Vec::new();
+    for entry in entries {
+        let Some(folder) = &entry.folder else {
+            continue;
+        };
+        let path = entry.output_folder(base);
+        let problem = if path.exists() {
+            (!path.is_dir()).then(|| format!("{} is not a folder", path.display()))
+        } else if folder.is_absolute() {
+            match path.parent() {
+                Some(parent) if parent.is_dir() => None,
+                Some(parent) => Some(format!(
+                    "neither {} nor its parent {} exist",
+                    path.display(),
+                    parent.display()
+                )),
+                None => Some(format!("{} does not exist", path.display())),
+            }
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            problems.push(format!("batch line {}: {}", entry.line, problem));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid output folders:\n  {}",
+            problems.join("\n  ")
+        ))
+    }
+}
+
+/// Reads and parses a batch file.
+pub fn read_batch_file(path: &Path) -> Result<Vec<BatchEntry>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read batch file: {}", path.display()))?;
+    Ok(parse_batch(&contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch() {
+        let contents = "\
+# Audiobooks
+https://www.raiplaysound.it/audiolibri/itremoschettieri\tlibri/moschettieri
+
+  https://www.raiplaysound.it/programmi/ilruggitodelconiglio  
+   # indented comment
+not a url
+ftp://www.raiplaysound.it/programmi/show
+https://www.raiplaysound.it/audiolibri/itremoschettieri\tsomewhere/else
+https://www.raiplaysound.it/programmi/radio3scienza\t
+";
+        let entries = parse_batch(contents);
+        assert_eq!(
+            entries,
+            vec![
+                BatchEntry {
+                    line: 2,
+                    url: "https://www.raiplaysound.it/audiolibri/itremoschettieri".to_string(),
+                    folder: Some(PathBuf::from("libri/moschettieri")),
+                },
+                BatchEntry {
+                    line: 4,
+                    url: "https://www.raiplaysound.it/programmi/ilruggitodelconiglio".to_string(),
+                    folder: None,
+                },
+                BatchEntry {
+                    line: 9,
+                    url: "https://www.raiplaysound.it/programmi/radio3scienza".to_string(),
+                    folder: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_folders() -> Result<()> {
+        let base = tempfile::TempDir::new()?;
+        std::fs::create_dir(base.path().join("books"))?;
+        std::fs::write(base.path().join("notes.txt"), "")?;
+        let entry = |line, folder: Option<PathBuf>| BatchEntry {
+            line,
+            url: format!("https://www.raiplaysound.it/programmi/show{}", line),
+            folder,
+        };
+
+        let good = vec![
+            entry(1, None),
+            entry(2, Some(PathBuf::from("libri/moschettieri"))),
+            entry(3, Some(base.path().join("books"))),
+            entry(4, Some(base.path().join("books/new"))),
+        ];
+        check_folders(&good, base.path())?;
+        assert_eq!(
+            good[1].output_folder(base.path()),
+            base.path().join("libri/moschettieri")
+        );
+        assert_eq!(
+            good[2].output_folder(base.path()),
+            base.path().join("books")
+        );
+        assert_eq!(good[0].output_folder(base.path()), base.path());
+
+        let bad = vec![
+            entry(5, Some(PathBuf::from("notes.txt"))),
+            entry(6, Some(base.path().join("bookz/new"))),
+        ];
+        let message = format!("{:#}", check_folders(&bad, base.path()).unwrap_err());
+        assert!(message.contains("batch line 5: "));
+        assert!(message.contains("notes.txt is not a folder"));
+        assert!(message.contains("batch line 6: neither "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("https://www.raiplaysound.it/programmi/show").is_ok());
+        assert!(validate_url("http://localhost:8080/show").is_ok());
+        assert!(validate_url("www.raiplaysound.it/programmi/show").is_err());
+        assert!(validate_url("file:///tmp/show.html").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use scraper::{Html, Selector};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Returns the paths of the further episode sets referenced by a programme page.
+///
+/// Long programmes only embed their first block of episodes in the page; every other
+/// block is referenced by an element with a `data-set-path` attribute pointing to a
+/// JSON endpoint listing its episodes.
+pub fn set_paths(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("[data-set-path]").expect("Invalid selector");
+    let mut seen = HashSet::new();
+    document
+        .select(&selector)
+        .filter_map(|element| element.value().attr("data-set-path"))
+        .filter(|path| path.starts_with('/') && path.ends_with(".json"))
+        .map(str::to_string)
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+/// Returns the cache file name of a set.
+///
+/// Sets of different programmes share their last path segment (e.g. `set-2.json`), so
+/// the whole path is used.
+pub fn cache_name(path: &str) -> String {
+    path.trim_start_matches('/').replace('/', "_")
+}
+
+/// Extracts the metadata paths of the episodes listed in a set.
+pub fn extract_set_items(json: &str) -> Result<Vec<String>> {
+    let value: Value = serde_json::from_str(json).context("Failed to parse episode set")?;
+    let mut paths = Vec::new();
+    crate::playlist::collect_paths(&value, &mut paths);
+    Ok(paths)
+}
+
+/// Returns the season of a set titled after one, like "Stagione 2".
+pub fn set_season(json: &str) -> Option<usize> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    crate::season::season_number(value["title"].as_str()?)
+}
+
+/// Appends the paths that are not listed yet, keeping their order.
+///
+/// Returns the number of paths added.
+pub fn merge_items(items: &mut Vec<String>, more: Vec<String>) -> usize {
+    let mut seen: HashSet<String> = items.iter().cloned().collect();
+    let before = items.len();
+    items.extend(more.into_iter().filter(|path| seen.insert(path.clone())));
+    items.len() - before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_paths() {
+        let html = include_str!("../tests/fixtures/multiblock.html");
+        assert_eq!(
+            set_paths(html),
+            vec![
+                "/programmi/ilconte/puntate/set-2.json",
+                "/programmi/ilconte/puntate/set-3.json",
+            ]
+        );
+        assert!(set_paths("<rps-play-with-labels></rps-play-with-labels>").is_empty());
+    }
+
+    #[test]
+    fn test_cache_name() {
+        assert_eq!(
+            cache_name("/programmi/ilconte/puntate/set-2.json"),
+            "programmi_ilconte_puntate_set-2.json"
+        );
+    }
+
+    #[test]
+    fn test_merge_items() {
+        let mut items = vec!["/audio/a.json".to_string(), "/audio/b.json".to_string()];
+        let more = vec![
+            "/audio/b.json".to_string(),
+            "/audio/c.json".to_string(),
+            "/audio/c.json".to_string(),
+        ];
+        assert_eq!(merge_items(&mut items, more), 1);
+        assert_eq!(
+            items,
+            vec!["/audio/a.json", "/audio/b.json", "/audio/c.json"]
+        );
+    }
+
+    #[test]
+    fn test_set_season() {
+        let json = include_str!("../tests/fixtures/ilconte-set-2.json");
+        assert_eq!(set_season(json), None);
+        assert_eq!(
+            set_season(r#"{"title": "Stagione 3", "cards": []}"#),
+            Some(3)
+        );
+    }
+}
@@ -0,0 +1,585 @@
+#[cfg(feature = "cookies-from-browser")]
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use anyhow::Result;
+#[cfg(feature = "cookies-from-browser")]
+use anyhow::{anyhow, Context};
+use reqwest::cookie::Jar;
+use reqwest::Url;
+#[cfg(feature = "cookies-from-browser")]
+use rusqlite::{Connection, OpenFlags};
+#[cfg(feature = "cookies-from-browser")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "cookies-from-browser")]
+use std::process::Command;
+#[cfg(feature = "cookies-from-browser")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Domains whose cookies are read from the browser.
+#[cfg(feature = "cookies-from-browser")]
+const DOMAINS: [&str; 2] = ["raiplaysound.it", "rai.it"];
+
+/// Password Chromium encrypts `v10` cookies with on Linux when no keyring is used.
+#[cfg(feature = "cookies-from-browser")]
+const CHROMIUM_PASSWORD: &str = "peanuts";
+
+/// Seconds between 1601-01-01, where Chromium times start, and the Unix epoch.
+#[cfg(feature = "cookies-from-browser")]
+const CHROMIUM_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// A browser whose cookies `--cookies-from-browser` reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Browser {
+    Firefox,
+    Chromium,
+    Chrome,
+}
+
+#[cfg(feature = "cookies-from-browser")]
+impl Browser {
+    fn name(self) -> &'static str {
+        match self {
+            Browser::Firefox => "Firefox",
+            Browser::Chromium => "Chromium",
+            Browser::Chrome => "Chrome",
+        }
+    }
+}
+
+/// The value of `--cookies-from-browser`: a browser and optionally its profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserSpec {
+    pub browser: Browser,
+    /// Name or path of the profile; the default profile when missing.
+    pub profile: Option<String>,
+}
+
+/// Parses `firefox`, `chromium` or `chrome`, optionally followed by `:profile`.
+pub fn parse_spec(value: &str) -> Result<BrowserSpec, String> {
+    let (name, profile) = match value.split_once(':') {
+        Some((name, profile)) => (name, Some(profile.to_string())),
+        None => (value, None),
+    };
+    let browser = match name.to_lowercase().as_str() {
+        "firefox" => Browser::Firefox,
+        "chromium" => Browser::Chromium,
+        "chrome" => Browser::Chrome,
+        _ => {
+            return Err(format!(
+                "expected firefox, chromium or chrome, optionally followed by :PROFILE, got {:?}",
+                value
+            ))
+        }
+    };
+    Ok(BrowserSpec {
+        browser,
+        profile: profile.filter(|profile| !profile.is_empty()),
+    })
+}
+
+/// A cookie read from a browser profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    /// The host, with a leading dot when the cookie is sent to its subdomains too.
+    pub host: String,
+    pub name: String,
+    pub value: String,
+    pub path: String,
+    pub secure: bool,
+}
+
+/// Reads the cookies of the RAI sites from the cookie store of the browser.
+///
+/// The store is opened read-only and as immutable, so nothing is written to the profile,
+/// not even a lock, and a running browser is not disturbed. Changes the browser has not
+/// yet moved from its write-ahead log into the store are not seen.
+#[cfg(feature = "cookies-from-browser")]
+pub fn load(spec: &BrowserSpec) -> Result<Vec<Cookie>> {
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("Cannot find the home folder to read browser cookies from"))?;
+    match spec.browser {
+        Browser::Firefox => {
+            let store = firefox_store(&home, spec.profile.as_deref())?;
+            read_firefox(&store)
+        }
+        browser => {
+            if !cfg!(target_os = "linux") {
+                return Err(anyhow!(
+                    "Reading {} cookies is only supported on Linux",
+                    browser.name()
+                ));
+            }
+            let store = chromium_store(&home, browser, spec.profile.as_deref())?;
+            read_chromium(&store, &chromium_passwords(browser))
+        }
+    }
+}
+
+#[cfg(not(feature = "cookies-from-browser"))]
+pub fn load(_spec: &BrowserSpec) -> Result<Vec<Cookie>> {
+    Err(anyhow::anyhow!(
+        "this rsnd was built without browser cookies, rebuild it with --features cookies-from-browser"
+    ))
+}
+
+/// Returns a cookie jar holding the cookies, for the HTTP client of the run.
+pub fn jar(cookies: &[Cookie]) -> Jar {
+    let jar = Jar::default();
+    for cookie in cookies {
+        let host = cookie.host.trim_start_matches('.');
+        let url = Url::parse(&format!("https://{}{}", host, cookie.path));
+        // A name can't be escaped: the cookies whose name would break the header are left out.
+        let name_fits = !cookie.name.is_empty() && cookie.name.bytes().all(is_token);
+        let (Ok(url), true) = (url, name_fits) else {
+            log::debug!("Skipping cookie {} of {}", cookie.name, cookie.host);
+            continue;
+        };
+        let mut text = format!(
+            "{}={}; Path={}",
+            cookie.name,
+            escape(&cookie.value),
+            cookie.path.replace(';', "%3B")
+        );
+        if cookie.host.starts_with('.') {
+            text.push_str(&format!("; Domain={}", host));
+        }
+        if cookie.secure {
+            text.push_str("; Secure");
+        }
+        jar.add_cookie_str(&text, &url);
+    }
+    jar
+}
+
+/// Percent-encodes the characters a cookie value can't hold, like `;` and spaces, so
+/// that the value is sent whole.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        // The cookie-octet of RFC 6265, `%` aside so that escapes stay apart.
+        if matches!(byte, 0x21 | 0x23..=0x24 | 0x26..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+        {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// Whether the byte can be part of a cookie name, a token of RFC 2616.
+fn is_token(byte: u8) -> bool {
+    byte.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&byte)
+}
+
+/// Whether the cookie of `host` belongs to one of the RAI sites.
+#[cfg(feature = "cookies-from-browser")]
+fn wanted(host: &str) -> bool {
+    let host = host.trim_start_matches('.').to_lowercase();
+    DOMAINS
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// Opens the SQLite store without writing anything next to it.
+#[cfg(feature = "cookies-from-browser")]
+fn open_read_only(path: &Path) -> Result<Connection> {
+    // Characters that mean something in a URI are escaped from the path.
+    let path = path
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    Connection::open_with_flags(
+        format!("file:{}?immutable=1", path),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("Failed to open the cookie store {}", path))
+}
+
+#[cfg(feature = "cookies-from-browser")]
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// Finds `cookies.sqlite` of the Firefox profile, by default the most recently used one.
+#[cfg(feature = "cookies-from-browser")]
+fn firefox_store(home: &Path, profile: Option<&str>) -> Result<PathBuf> {
+    let roots: Vec<PathBuf> = if cfg!(target_os = "macos") {
+        vec![home.join("Library/Application Support/Firefox/Profiles")]
+    } else if cfg!(windows) {
+        return Err(anyhow!(
+            "Reading Firefox cookies is not supported on Windows"
+        ));
+    } else {
+        vec![
+            home.join(".mozilla/firefox"),
+            home.join("snap/firefox/common/.mozilla/firefox"),
+            home.join(".var/app/org.mozilla.firefox/.mozilla/firefox"),
+        ]
+    };
+    if let Some(store) = profile
+        .map(|profile| Path::new(profile).join("cookies.sqlite"))
+        .filter(|store| store.is_file())
+    {
+        return Ok(store);
+    }
+    let mut stores: Vec<(SystemTime, PathBuf)> = roots
+        .iter()
+        .filter_map(|root| std::fs::read_dir(root).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            profile
+                .is_none_or(|profile| name == profile || name.ends_with(&format!(".{}", profile)))
+        })
+        .map(|entry| entry.path().join("cookies.sqlite"))
+        .filter_map(|store| {
+            let modified = store.metadata().and_then(|meta| meta.modified()).ok()?;
+            Some((modified, store))
+        })
+        .collect();
+    stores.sort();
+    stores
+        .pop()
+        .map(|(_, store)| store)
+        .ok_or_else(|| match profile {
+            Some(profile) => anyhow!("No Firefox profile {:?} with cookies found", profile),
+            None => anyhow!(
+                "No Firefox profile with cookies found in {}",
+                roots[0].display()
+            ),
+        })
+}
+
+/// Reads the cookies of the RAI sites that have not expired from a Firefox store.
+#[cfg(feature = "cookies-from-browser")]
+fn read_firefox(store: &Path) -> Result<Vec<Cookie>> {
+    let connection = open_read_only(store)?;
+    let mut statement = connection
+        .prepare("SELECT host, name, value, path, expiry, isSecure FROM moz_cookies")
+        .with_context(|| format!("Not a Firefox cookie store: {}", store.display()))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            Cookie {
+                host: row.get(0)?,
+                name: row.get(1)?,
+                value: row.get(2)?,
+                path: row.get(3)?,
+                secure: row.get::<_, i64>(5)? != 0,
+            },
+            row.get::<_, i64>(4)?,
+        ))
+    })?;
+    let now = now();
+    let mut cookies = Vec::new();
+    for row in rows {
+        let (cookie, expiry) = row?;
+        // Recent versions store the expiry in milliseconds.
+        let expiry = if expiry > 100_000_000_000 {
+            expiry / 1000
+        } else {
+            expiry
+        };
+        if wanted(&cookie.host) && expiry > now {
+            cookies.push(cookie);
+        }
+    }
+    Ok(cookies)
+}
+
+/// Finds the `Cookies` store of the Chromium profile, `Default` unless one is given.
+#[cfg(feature = "cookies-from-browser")]
+fn chromium_store(home: &Path, browser: Browser, profile: Option<&str>) -> Result<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|config| config.is_absolute())
+        .unwrap_or_else(|| home.join(".config"));
+    let root = config.join(match browser {
+        Browser::Chrome => "google-chrome",
+        _ => "chromium",
+    });
+    let profile = profile.unwrap_or("Default");
+    let profile = if Path::new(profile).is_dir() {
+        PathBuf::from(profile)
+    } else {
+        root.join(profile)
+    };
+    [profile.join("Network/Cookies"), profile.join("Cookies")]
+        .into_iter()
+        .find(|store| store.is_file())
+        .ok_or_else(|| {
+            anyhow!(
+                "No {} cookie store found in {}",
+                browser.name(),
+                profile.display()
+            )
+        })
+}
+
+/// Passwords the cookies may be encrypted with: the one of the keyring, when
+/// `secret-tool` can read it, then the built-in one.
+#[cfg(feature = "cookies-from-browser")]
+fn chromium_passwords(browser: Browser) -> Vec<String> {
+    let application = match browser {
+        Browser::Chrome => "chrome",
+        _ => "chromium",
+    };
+    let keyring = Command::new("secret-tool")
+        .args(["lookup", "application", application])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|password| !password.is_empty());
+    if keyring.is_none() {
+        log::debug!("No {} password in the keyring", browser.name());
+    }
+    keyring
+        .into_iter()
+        .chain([CHROMIUM_PASSWORD.to_string(), String::new()])
+        .collect()
+}
+
+/// Reads the cookies of the RAI sites that have not expired from a Chromium store,
+/// decrypting their values with the first password that fits.
+#[cfg(feature = "cookies-from-browser")]
+fn read_chromium(store: &Path, passwords: &[String]) -> Result<Vec<Cookie>> {
+    let connection = open_read_only(store)?;
+    // From version 24 the decrypted value starts with a hash of the host.
+    let version: i64 = connection
+        .query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(0);
+    let keys: Vec<[u8; 16]> = passwords
+        .iter()
+        .map(|password| chromium_key(password))
+        .collect();
+    let mut statement = connection
+        .prepare(
+            "SELECT host_key, name, value, encrypted_value, path, expires_utc, is_secure FROM cookies",
+        )
+        .with_context(|| format!("Not a Chromium cookie store: {}", store.display()))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Vec<u8>>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, i64>(5)?,
+            row.get::<_, i64>(6)? != 0,
+        ))
+    })?;
+    let now = now();
+    let (mut cookies, mut undecryptable) = (Vec::new(), 0);
+    for row in rows {
+        let (host, name, value, encrypted, path, expires, secure) = row?;
+        // Session cookies never expire.
+        let expired = expires != 0 && expires / 1_000_000 - CHROMIUM_EPOCH_OFFSET <= now;
+        if !wanted(&host) || expired {
+            continue;
+        }
+        let value = if encrypted.is_empty() {
+            value
+        } else {
+            match decrypt(&encrypted, &keys, version >= 24) {
+                Some(value) => value,
+                None => {
+                    undecryptable += 1;
+                    continue;
+                }
+            }
+        };
+        cookies.push(Cookie {
+            host,
+            name,
+            value,
+            path,
+            secure,
+        });
+    }
+    if undecryptable > 0 {
+        eprintln!(
+            "Warning: {} cookies could not be decrypted; is the keyring unlocked?",
+            undecryptable
+        );
+    }
+    Ok(cookies)
+}
+
+/// Derives the AES key Chromium encrypts cookies with on Linux from its password.
+#[cfg(feature = "cookies-from-browser")]
+fn chromium_key(password: &str) -> [u8; 16] {
+    let mut key = [0; 16];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(password.as_bytes(), b"saltysalt", 1, &mut key);
+    key
+}
+
+/// Decrypts a `v10` or `v11` value, trying each key in turn.
+#[cfg(feature = "cookies-from-browser")]
+fn decrypt(encrypted: &[u8], keys: &[[u8; 16]], hashed_host: bool) -> Option<String> {
+    let data = encrypted
+        .strip_prefix(b"v10")
+        .or_else(|| encrypted.strip_prefix(b"v11"))?;
+    keys.iter().find_map(|key| {
+        let plain = cbc::Decryptor::<aes::Aes128>::new(key.into(), &[b' '; 16].into())
+            .decrypt_padded_vec_mut::<Pkcs7>(data)
+            .ok()?;
+        let plain = if hashed_host {
+            plain.get(32..)?.to_vec()
+        } else {
+            plain
+        };
+        String::from_utf8(plain).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "cookies-from-browser")]
+    use aes::cipher::BlockEncryptMut;
+    #[cfg(feature = "cookies-from-browser")]
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            parse_spec("firefox"),
+            Ok(BrowserSpec {
+                browser: Browser::Firefox,
+                profile: None
+            })
+        );
+        assert_eq!(
+            parse_spec("Chromium:Profile 1"),
+            Ok(BrowserSpec {
+                browser: Browser::Chromium,
+                profile: Some("Profile 1".to_string())
+            })
+        );
+        assert!(parse_spec("safari").is_err());
+    }
+
+    #[cfg(feature = "cookies-from-browser")]
+    #[test]
+    fn test_wanted() {
+        assert!(wanted(".raiplaysound.it"));
+        assert!(wanted("www.rai.it"));
+        assert!(wanted("rai.it"));
+        assert!(!wanted("notrai.it"));
+        assert!(!wanted(".example.com"));
+    }
+
+    #[test]
+    fn test_jar() -> Result<()> {
+        let cookie = |name: &str, value: &str| Cookie {
+            host: ".raiplaysound.it".to_string(),
+            name: name.to_string(),
+            value: value.to_string(),
+            path: "/".to_string(),
+            secure: false,
+        };
+        let jar = jar(&[
+            cookie("consent", "a b;c=\"d\"%"),
+            cookie("bad;name", "x"),
+            cookie("token", "abc"),
+        ]);
+        let url = Url::parse("https://www.raiplaysound.it/audiolibri")?;
+        let header = reqwest::cookie::CookieStore::cookies(&jar, &url).unwrap();
+        let mut pairs: Vec<&str> = header.to_str()?.split("; ").collect();
+        pairs.sort();
+        assert_eq!(pairs, ["consent=a%20b%3Bc=%22d%22%25", "token=abc"]);
+        Ok(())
+    }
+
+    #[cfg(feature = "cookies-from-browser")]
+    #[test]
+    fn test_read_firefox() -> Result<()> {
+        let folder = TempDir::new()?;
+        let profile = folder
+            .path()
+            .join(".mozilla/firefox/abcd1234.default-release");
+        std::fs::create_dir_all(&profile)?;
+        let store = profile.join("cookies.sqlite");
+        let connection = Connection::open(&store)?;
+        connection.execute_batch(
+            "CREATE TABLE moz_cookies (id INTEGER PRIMARY KEY, name TEXT, value TEXT, host TEXT,
+                path TEXT, expiry INTEGER, isSecure INTEGER);
+            INSERT INTO moz_cookies (name, value, host, path, expiry, isSecure) VALUES
+                ('session', 'abc', '.raiplaysound.it', '/', 4102444800, 1),
+                ('old', 'x', '.raiplaysound.it', '/', 1000, 0),
+                ('other', 'y', '.example.com', '/', 4102444800, 0),
+                ('ms', 'z', 'www.rai.it', '/', 4102444800000, 0);",
+        )?;
+        drop(connection);
+
+        assert_eq!(firefox_store(folder.path(), None)?, store);
+        assert_eq!(
+            firefox_store(folder.path(), Some("default-release"))?,
+            store
+        );
+        assert!(firefox_store(folder.path(), Some("work")).is_err());
+
+        let cookies = read_firefox(&store)?;
+        let names: Vec<&str> = cookies.iter().map(|cookie| cookie.name.as_str()).collect();
+        assert_eq!(names, ["session", "ms"]);
+        assert!(cookies[0].secure);
+
+        let jar = jar(&cookies);
+        let url = Url::parse("https://www.raiplaysound.it/audiolibri")?;
+        let header = reqwest::cookie::CookieStore::cookies(&jar, &url).unwrap();
+        assert_eq!(header.to_str()?, "session=abc");
+        Ok(())
+    }
+
+    #[cfg(feature = "cookies-from-browser")]
+    #[test]
+    fn test_read_chromium() -> Result<()> {
+        let folder = TempDir::new()?;
+        let store = folder.path().join("Cookies");
+        let connection = Connection::open(&store)?;
+        connection.execute_batch(
+            "CREATE TABLE meta (key TEXT, value TEXT);
+            INSERT INTO meta VALUES ('version', '24');
+            CREATE TABLE cookies (host_key TEXT, name TEXT, value TEXT, encrypted_value BLOB,
+                path TEXT, expires_utc INTEGER, is_secure INTEGER);",
+        )?;
+        // A hash of the host comes before the value since version 24.
+        let mut plain = vec![0; 32];
+        plain.extend_from_slice(b"secret");
+        let mut encrypted = b"v10".to_vec();
+        encrypted.extend(
+            cbc::Encryptor::<aes::Aes128>::new(
+                &chromium_key(CHROMIUM_PASSWORD).into(),
+                &[b' '; 16].into(),
+            )
+            .encrypt_padded_vec_mut::<Pkcs7>(&plain),
+        );
+        connection.execute(
+            "INSERT INTO cookies VALUES ('.raiplaysound.it', 'token', '', ?1, '/', 0, 1)",
+            [&encrypted],
+        )?;
+        connection.execute(
+            "INSERT INTO cookies VALUES ('.example.com', 'other', 'y', x'', '/', 0, 0)",
+            [],
+        )?;
+        drop(connection);
+
+        let cookies = read_chromium(&store, &[CHROMIUM_PASSWORD.to_string()])?;
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].name, "token");
+        assert_eq!(cookies[0].value, "secret");
+
+        // Without the right password the cookie is left out.
+        assert!(read_chromium(&store, &["wrong".to_string()])?.is_empty());
+        Ok(())
+    }
+}
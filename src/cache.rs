@@ -0,0 +1,491 @@
+use crate::errln;
+use crate::error::RsndError;
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A cache shared by the tasks of a run.
+pub type SharedCache = Arc<dyn Cache>;
+
+/// Opens the line HTML entries start with, giving the length of the page that follows,
+/// so that a page cut short on disk is told from a complete one.
+const LENGTH_MARKER: &str = "<!-- rsnd-cache length=";
+
+/// Closes the length line of HTML entries.
+const LENGTH_MARKER_END: &str = " -->\n";
+
+/// An entry of the cache: the full URL the body was fetched from, which is its key, and
+/// the name of the file the filesystem cache keeps it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub url: String,
+    pub name: String,
+}
+
+impl Entry {
+    pub fn new(url: impl Into<String>, name: impl Into<String>) -> Self {
+        Entry {
+            url: url.into(),
+            name: name.into(),
+        }
+    }
+
+    /// Returns the entry of an HTML page, named after the last segment of its path.
+    ///
+    /// The URL is normalized first, so that `show/`, `show#episodes` and an uppercase host
+    /// share the file of `show`, while `show?page=2` gets its own file named with a hash
+    /// of the query, whose characters cannot all be used in file names.
+    pub fn for_page(url: &str) -> Result<Self> {
+        let mut parsed = Url::parse(url).with_context(|| format!("Invalid page URL: {}", url))?;
+        if !matches!(parsed.scheme(), "http" | "https") || !parsed.has_host() {
+            return Err(anyhow!(
+                "Invalid page URL: {}: expected an http(s) address, or --input-file for a saved page",
+                url
+            ));
+        }
+        parsed.set_fragment(None);
+        let segment = parsed
+            .path_segments()
+            .and_then(|segments| segments.rev().find(|segment| !segment.is_empty()))
+            .map(|segment| file_safe(&percent_decode(segment)))
+            .filter(|segment| !segment.is_empty())
+            .unwrap_or_else(|| "index".to_string());
+        let name = match parsed.query().filter(|query| !query.is_empty()) {
+            Some(query) => format!("{}-{}.html", segment, digest(query)),
+            None => format!("{}.html", segment),
+        };
+        Ok(Entry::new(parsed, name))
+    }
+}
+
+/// Returns the SHA-1 of the key as 40 hex digits, for the names of the files whose key
+/// can't be used as one. Unlike a CRC-32, it tells apart the thousands of entries a
+/// cache holds.
+pub fn digest(key: &str) -> String {
+    use sha1::Digest;
+    sha1::Sha1::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Decodes the `%XX` escapes of a URL segment, leaving malformed ones as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut position = 0;
+    while position < bytes.len() {
+        let escaped = bytes
+            .get(position + 1..position + 3)
+            .filter(|_| bytes[position] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                position += 3;
+            }
+            None => {
+                decoded.push(bytes[position]);
+                position += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Replaces the characters that some filesystems reject in file names.
+fn file_safe(text: &str) -> String {
+    let safe: String = text
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    safe.trim_matches('.').to_string()
+}
+
+/// Returns the body to write for an entry: HTML pages are prefixed with their length.
+fn with_length(entry: &Entry, body: &str) -> String {
+    if entry.name.ends_with(".html") {
+        format!(
+            "{}{}{}{}",
+            LENGTH_MARKER,
+            body.len(),
+            LENGTH_MARKER_END,
+            body
+        )
+    } else {
+        body.to_string()
+    }
+}
+
+/// Returns the body of an entry read from disk, or why it cannot be trusted: an HTML
+/// page shorter than the length written before it, or JSON that does not parse. Pages
+/// without a length, cached by earlier versions, are taken as they are.
+fn check_body(entry: &Entry, body: String) -> Result<String, String> {
+    if entry.name.ends_with(".json") {
+        return match serde_json::from_str::<serde::de::IgnoredAny>(crate::sniff::json_text(&body)) {
+            Ok(_) => Ok(body),
+            Err(err) => Err(format!("invalid JSON: {}", err)),
+        };
+    }
+    let Some(marked) = body
+        .strip_prefix(LENGTH_MARKER)
+        .filter(|_| entry.name.ends_with(".html"))
+    else {
+        return Ok(body);
+    };
+    let (length, page) = marked
+        .split_once(LENGTH_MARKER_END)
+        .and_then(|(length, page)| Some((length.parse::<usize>().ok()?, page)))
+        .ok_or_else(|| "unreadable length".to_string())?;
+    if page.len() != length {
+        return Err(format!("{} of {} bytes", page.len(), length));
+    }
+    Ok(page.to_string())
+}
+
+/// Where fetched pages and JSON are kept between requests.
+pub trait Cache: Send + Sync {
+    /// Returns the cached body of the entry, if any.
+    fn get(&self, entry: &Entry) -> Result<Option<String>, RsndError>;
+
+    /// Stores the body of the entry, replacing any previous one.
+    fn put(&self, entry: &Entry, body: &str) -> Result<(), RsndError>;
+
+    /// Returns the cached bytes of the entry, if any, for bodies that are not text.
+    fn get_bytes(&self, entry: &Entry) -> Result<Option<Vec<u8>>, RsndError>;
+
+    /// Stores the bytes of the entry, replacing any previous body.
+    fn put_bytes(&self, entry: &Entry, body: &[u8]) -> Result<(), RsndError>;
+
+    /// Drops the entry, so that the next fetch goes to the network.
+    fn invalidate(&self, entry: &Entry) -> Result<(), RsndError>;
+
+    /// Returns whether the entry is cached.
+    fn contains(&self, entry: &Entry) -> bool {
+        matches!(self.get(entry), Ok(Some(_)))
+    }
+
+    /// Returns how long ago the entry was stored, when it is cached and the cache knows.
+    fn age(&self, _entry: &Entry) -> Option<Duration> {
+        None
+    }
+}
+
+/// Keeps each entry in a file of the cache folder, where it stays across runs.
+#[derive(Debug)]
+pub struct FileCache {
+    dir: PathBuf,
+}
+
+impl FileCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileCache { dir: dir.into() }
+    }
+
+    fn path(&self, entry: &Entry) -> PathBuf {
+        self.dir.join(&entry.name)
+    }
+}
+
+impl Cache for FileCache {
+    fn get(&self, entry: &Entry) -> Result<Option<String>, RsndError> {
+        let path = self.path(entry);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let body =
+            std::fs::read_to_string(&path).map_err(|source| RsndError::Cache { path, source })?;
+        match check_body(entry, body) {
+            Ok(body) => Ok(Some(body)),
+            Err(reason) => {
+                // Fetched again and replaced, as if it had never been cached.
+                errln!(
+                    "Warning: ignoring damaged cache file {}: {}",
+                    self.path(entry).display(),
+                    reason
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    fn put(&self, entry: &Entry, body: &str) -> Result<(), RsndError> {
+        self.put_bytes(entry, with_length(entry, body).as_bytes())
+    }
+
+    fn get_bytes(&self, entry: &Entry) -> Result<Option<Vec<u8>>, RsndError> {
+        let path = self.path(entry);
+        if !path.exists() {
+            return Ok(None);
+        }
+        std::fs::read(&path)
+            .map(Some)
+            .map_err(|source| RsndError::Cache { path, source })
+    }
+
+    /// Writes the body through a temporary file, so that a run that dies halfway never
+    /// leaves a partial entry behind.
+    fn put_bytes(&self, entry: &Entry, body: &[u8]) -> Result<(), RsndError> {
+        let path = self.path(entry);
+        crate::atomic::write(&path, body).map_err(|source| RsndError::Cache { path, source })
+    }
+
+    fn invalidate(&self, entry: &Entry) -> Result<(), RsndError> {
+        let path = self.path(entry);
+        match std::fs::remove_file(&path) {
+            Err(source) if source.kind() != std::io::ErrorKind::NotFound => {
+                Err(RsndError::Cache { path, source })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn contains(&self, entry: &Entry) -> bool {
+        self.path(entry).exists()
+    }
+
+    fn age(&self, entry: &Entry) -> Option<Duration> {
+        let modified = self.path(entry).metadata().ok()?.modified().ok()?;
+        // A file from the future, as after a clock change, counts as new.
+        Some(modified.elapsed().unwrap_or_default())
+    }
+}
+
+/// Keeps the entries in memory, for tests that should not touch the disk.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MemoryCache {
+    fn entries(&self) -> std::sync::MutexGuard<'_, std::collections::HashMap<String, Vec<u8>>> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+impl Cache for MemoryCache {
+    fn get(&self, entry: &Entry) -> Result<Option<String>, RsndError> {
+        Ok(self
+            .entries()
+            .get(&entry.url)
+            .map(|body| String::from_utf8_lossy(body).into_owned()))
+    }
+
+    fn put(&self, entry: &Entry, body: &str) -> Result<(), RsndError> {
+        self.put_bytes(entry, body.as_bytes())
+    }
+
+    fn get_bytes(&self, entry: &Entry) -> Result<Option<Vec<u8>>, RsndError> {
+        Ok(self.entries().get(&entry.url).cloned())
+    }
+
+    fn put_bytes(&self, entry: &Entry, body: &[u8]) -> Result<(), RsndError> {
+        self.entries().insert(entry.url.clone(), body.to_vec());
+        Ok(())
+    }
+
+    fn invalidate(&self, entry: &Entry) -> Result<(), RsndError> {
+        self.entries().remove(&entry.url);
+        Ok(())
+    }
+}
+
+/// Keeps nothing, for `--no-cache`: every fetch goes to the network.
+#[derive(Debug, Default)]
+pub struct NoCache;
+
+impl Cache for NoCache {
+    fn get(&self, _entry: &Entry) -> Result<Option<String>, RsndError> {
+        Ok(None)
+    }
+
+    fn put(&self, _entry: &Entry, _body: &str) -> Result<(), RsndError> {
+        Ok(())
+    }
+
+    fn get_bytes(&self, _entry: &Entry) -> Result<Option<Vec<u8>>, RsndError> {
+        Ok(None)
+    }
+
+    fn put_bytes(&self, _entry: &Entry, _body: &[u8]) -> Result<(), RsndError> {
+        Ok(())
+    }
+
+    fn invalidate(&self, _entry: &Entry) -> Result<(), RsndError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the behavior every cache that keeps entries shares.
+    fn check_round_trip(cache: &dyn Cache) {
+        let page = Entry::new(
+            "https://www.raiplaysound.it/audiolibri/itremoschettieri",
+            "itremoschettieri.html",
+        );
+        let other = Entry::new("https://www.raiplaysound.it/audio/p1.json", "p1.json");
+        assert_eq!(cache.get(&page).unwrap(), None);
+        assert!(!cache.contains(&page));
+
+        cache.put(&page, "<html>1</html>").unwrap();
+        cache.put(&page, "<html>2</html>").unwrap();
+        assert_eq!(cache.get(&page).unwrap().as_deref(), Some("<html>2</html>"));
+        assert!(cache.contains(&page));
+        assert!(!cache.contains(&other));
+
+        let image = Entry::new("https://img.example.com/cover.webp", "cover.webp");
+        cache.put_bytes(&image, &[0x52, 0x49, 0xff, 0x00]).unwrap();
+        assert_eq!(
+            cache.get_bytes(&image).unwrap().as_deref(),
+            Some(&[0x52, 0x49, 0xff, 0x00][..])
+        );
+
+        cache.invalidate(&page).unwrap();
+        assert_eq!(cache.get(&page).unwrap(), None);
+        // Dropping a missing entry is not an error.
+        cache.invalidate(&other).unwrap();
+    }
+
+    #[test]
+    fn test_file_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FileCache::new(dir.path());
+        check_round_trip(&cache);
+
+        // Entries written by earlier runs are found by their file name.
+        std::fs::write(dir.path().join("p1.json"), "{}").unwrap();
+        let entry = Entry::new("https://www.raiplaysound.it/audio/p1.json", "p1.json");
+        assert_eq!(cache.get(&entry).unwrap().as_deref(), Some("{}"));
+        assert!(cache.age(&entry).unwrap() < Duration::from_secs(60));
+        let hour_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("p1.json"))
+            .and_then(|file| file.set_modified(hour_ago))
+            .unwrap();
+        assert!(cache.age(&entry).unwrap() >= Duration::from_secs(3600));
+        let missing = Entry::new("https://www.raiplaysound.it/audio/p2.json", "p2.json");
+        assert_eq!(cache.age(&missing), None);
+
+        assert!(!dir.path().join("p1.json.part").exists());
+
+        std::fs::create_dir(dir.path().join("dir.html")).unwrap();
+        let unreadable = Entry::new("https://example.com/dir", "dir.html");
+        assert!(matches!(
+            cache.get(&unreadable),
+            Err(RsndError::Cache { .. })
+        ));
+    }
+
+    #[test]
+    fn test_damaged_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FileCache::new(dir.path());
+        let page = Entry::new("https://www.raiplaysound.it/audiolibri/show", "show.html");
+        cache.put(&page, "<html>episodes</html>").unwrap();
+        let written = std::fs::read_to_string(dir.path().join("show.html")).unwrap();
+        assert_eq!(
+            written,
+            "<!-- rsnd-cache length=21 -->\n<html>episodes</html>"
+        );
+
+        // A page cut short is a miss, and so is JSON that does not parse.
+        std::fs::write(dir.path().join("show.html"), &written[..written.len() - 7]).unwrap();
+        assert_eq!(cache.get(&page).unwrap(), None);
+        let json = Entry::new("https://www.raiplaysound.it/audio/p1.json", "p1.json");
+        std::fs::write(dir.path().join("p1.json"), r#"{"audio": {"title": "Lett"#).unwrap();
+        assert_eq!(cache.get(&json).unwrap(), None);
+        cache.put(&json, r#"{"audio": {}}"#).unwrap();
+        assert!(cache.get(&json).unwrap().is_some());
+
+        // Pages cached before lengths were written are taken as they are.
+        std::fs::write(dir.path().join("show.html"), "<html>old</html>").unwrap();
+        assert_eq!(
+            cache.get(&page).unwrap().as_deref(),
+            Some("<html>old</html>")
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        assert_eq!(digest("page=2"), "b941a131dcbf32cc906cd9a100bc5b7eafebf9fb");
+    }
+
+    #[test]
+    fn test_page_entry() {
+        let name = |url: &str| Entry::for_page(url).unwrap().name;
+        let show = "https://www.raiplaysound.it/programmi/ilruggitodelconiglio";
+        assert_eq!(name(show), "ilruggitodelconiglio.html");
+        // Trailing slashes, fragments and the case of the host name the same page.
+        assert_eq!(name(&format!("{}/", show)), "ilruggitodelconiglio.html");
+        assert_eq!(name(&format!("{}//", show)), "ilruggitodelconiglio.html");
+        assert_eq!(
+            name(&format!("{}#episodi", show)),
+            "ilruggitodelconiglio.html"
+        );
+        let upper =
+            Entry::for_page("https://WWW.RaiPlaySound.it/programmi/ilruggitodelconiglio").unwrap();
+        assert_eq!(upper, Entry::for_page(show).unwrap());
+        assert_eq!(
+            Entry::for_page(&format!("{}#episodi", show)).unwrap().url,
+            show
+        );
+
+        // Queries get their own file, without their characters.
+        let page_2 = name(&format!("{}?page=2", show));
+        assert!(page_2.starts_with("ilruggitodelconiglio-"));
+        assert!(page_2.ends_with(".html"));
+        assert!(!page_2.contains(['?', '=']));
+        assert_ne!(page_2, name(&format!("{}?page=3", show)));
+        assert_eq!(page_2, name(&format!("{}/?page=2#top", show)));
+        assert_eq!(name(&format!("{}?", show)), "ilruggitodelconiglio.html");
+
+        assert_eq!(
+            name("https://www.raiplaysound.it/programmi/l%27isola%20del%20tesoro"),
+            "l_isola_del_tesoro.html"
+        );
+        assert_eq!(
+            name("https://www.raiplaysound.it/programmi/citt%C3%A0"),
+            "città.html"
+        );
+        assert_eq!(name("https://www.raiplaysound.it/"), "index.html");
+
+        assert!(Entry::for_page("www.raiplaysound.it/programmi/show").is_err());
+        let err = Entry::for_page("ftp://www.raiplaysound.it/programmi/show").unwrap_err();
+        assert!(err.to_string().contains("expected an http(s) address"));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b%2"), "a b%2");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+    }
+
+    #[test]
+    fn test_memory_cache() {
+        check_round_trip(&MemoryCache::default());
+    }
+
+    #[test]
+    fn test_no_cache() {
+        let entry = Entry::new("https://www.raiplaysound.it/audio/p1.json", "p1.json");
+        NoCache.put(&entry, "{}").unwrap();
+        assert_eq!(NoCache.get(&entry).unwrap(), None);
+        assert!(!NoCache.contains(&entry));
+    }
+}
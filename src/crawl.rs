@@ -0,0 +1,221 @@
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Most catalog pages followed in a crawl, in case the pagination loops.
+pub const MAX_PAGES: usize = 100;
+
+/// A show linked from a catalog page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShowLink {
+    pub url: String,
+    /// Text of the link, usually the show title.
+    pub title: String,
+}
+
+/// A show linked from a landing page, as `--list-shows --json` prints it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FoundShow {
+    #[serde(flatten)]
+    pub link: ShowLink,
+    /// The landing page linking the show.
+    pub found_on: String,
+}
+
+/// Returns whether the path is the page of a show, like `/audiolibri/itremoschettieri`.
+fn is_show_path(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(
+        segments.as_slice(),
+        ["audiolibri" | "programmi" | "podcast", slug] if !slug.is_empty() && !slug.contains('.')
+    )
+}
+
+/// Extracts the links to shows on a catalog or genre page, in order and without
+/// duplicates.
+pub fn show_links(html: &str, page_url: &str) -> Vec<ShowLink> {
+    links_in(&Html::parse_document(html), page_url, "a[href]")
+}
+
+/// Extracts the links to the programmes a landing page presents, like
+/// [`show_links`], leaving out those of the site menus when the page marks its content.
+pub fn landing_links(html: &str, page_url: &str) -> Vec<ShowLink> {
+    let document = Html::parse_document(html);
+    let links = links_in(&document, page_url, "main a[href]");
+    if links.is_empty() {
+        links_in(&document, page_url, "a[href]")
+    } else {
+        links
+    }
+}
+
+fn links_in(document: &Html, page_url: &str, selector: &str) -> Vec<ShowLink> {
+    let Ok(base) = Url::parse(page_url) else {
+        return Vec::new();
+    };
+    let selector = Selector::parse(selector).expect("Invalid selector");
+    let mut seen = HashSet::new();
+    let mut links = Vec::new();
+    for element in document.select(&selector) {
+        let Some(mut url) = element
+            .value()
+            .attr("href")
+            .and_then(|href| base.join(href).ok())
+        else {
+            continue;
+        };
+        if url.host() != base.host() || !is_show_path(url.path()) {
+            continue;
+        }
+        url.set_query(None);
+        url.set_fragment(None);
+        let url = url.as_str().trim_end_matches('/').to_string();
+        if seen.insert(url.clone()) {
+            let title = element.text().collect::<String>().trim().to_string();
+            links.push(ShowLink { url, title });
+        }
+    }
+    links
+}
+
+/// Formats the shows linked from a landing page for `--list-shows`, numbered like search
+/// results.
+pub fn render_links(links: &[ShowLink]) -> String {
+    let mut text = String::new();
+    for (position, link) in links.iter().enumerate() {
+        let title = if link.title.is_empty() {
+            "(untitled)"
+        } else {
+            &link.title
+        };
+        text.push_str(&format!(
+            "{:>2}. {}\n    {}\n",
+            position + 1,
+            title,
+            link.url
+        ));
+    }
+    text
+}
+
+/// Returns the cache file name of a catalog page, which keeps its query so that each
+/// page of a catalog is cached apart.
+pub fn cache_name(page_url: &str) -> String {
+    let name: String = page_url
+        .split_once("://")
+        .map_or(page_url, |(_, rest)| {
+            rest.split_once('/').map_or("", |(_, path)| path)
+        })
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("catalog_{}.html", name)
+}
+
+/// Returns the URL of the next catalog page, from a `rel="next"` link.
+pub fn next_page(html: &str, page_url: &str) -> Option<String> {
+    let base = Url::parse(page_url).ok()?;
+    let document = Html::parse_document(html);
+    let selector =
+        Selector::parse(r#"a[rel~="next"], link[rel~="next"]"#).expect("Invalid selector");
+    document
+        .select(&selector)
+        .find_map(|element| element.value().attr("href"))
+        .and_then(|href| base.join(href).ok())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+        <a href="/audiolibri/itremoschettieri">I tre moschettieri</a>
+        <a href="/audiolibri/itremoschettieri/">I tre moschettieri</a>
+        <a href="https://www.raiplaysound.it/programmi/ilconte?from=genre"> Il conte </a>
+        <a href="/audio/2015/06/lettura-1.html">Lettura I</a>
+        <a href="/generi/audiolibri">Audiolibri</a>
+        <a href="https://example.com/audiolibri/other">Other site</a>
+        <a rel="next" href="?page=2">Next</a>
+    "#;
+
+    #[test]
+    fn test_show_links() {
+        let links = show_links(PAGE, "https://www.raiplaysound.it/generi/audiolibri");
+        assert_eq!(
+            links,
+            vec![
+                ShowLink {
+                    url: "https://www.raiplaysound.it/audiolibri/itremoschettieri".to_string(),
+                    title: "I tre moschettieri".to_string(),
+                },
+                ShowLink {
+                    url: "https://www.raiplaysound.it/programmi/ilconte".to_string(),
+                    title: "Il conte".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_landing_links() {
+        let page = r#"
+            <header><a href="/programmi/radio2social">Radio2 Social</a></header>
+            <main>
+                <rps-card><a href="/audiolibri/itremoschettieri">I tre moschettieri</a></rps-card>
+                <rps-card><a href="/audiolibri/ilconte/">Il conte</a></rps-card>
+            </main>
+        "#;
+        let links = landing_links(page, "https://www.raiplaysound.it/audiolibri");
+        let urls: Vec<&str> = links.iter().map(|link| link.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://www.raiplaysound.it/audiolibri/itremoschettieri",
+                "https://www.raiplaysound.it/audiolibri/ilconte"
+            ]
+        );
+        // Without a main element, every link to a show counts.
+        assert_eq!(
+            landing_links(PAGE, "https://www.raiplaysound.it/generi/audiolibri"),
+            show_links(PAGE, "https://www.raiplaysound.it/generi/audiolibri")
+        );
+    }
+
+    #[test]
+    fn test_render_links() {
+        let links = show_links(PAGE, "https://www.raiplaysound.it/generi/audiolibri");
+        assert_eq!(
+            render_links(&links),
+            " 1. I tre moschettieri\n    https://www.raiplaysound.it/audiolibri/itremoschettieri\n \
+             2. Il conte\n    https://www.raiplaysound.it/programmi/ilconte\n"
+        );
+    }
+
+    #[test]
+    fn test_next_page() {
+        assert_eq!(
+            next_page(PAGE, "https://www.raiplaysound.it/generi/audiolibri").as_deref(),
+            Some("https://www.raiplaysound.it/generi/audiolibri?page=2")
+        );
+        assert_eq!(
+            next_page("<a href='/x'>x</a>", "https://www.raiplaysound.it/"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_name() {
+        assert_eq!(
+            cache_name("https://www.raiplaysound.it/generi/audiolibri?page=2"),
+            "catalog_generi_audiolibri_page_2.html"
+        );
+    }
+}
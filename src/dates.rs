@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Days, Local, Months, NaiveDate, NaiveDateTime, NaiveTime};
+use std::time::SystemTime;
+
+/// Italian month names as used in RAI literal dates, matched on their first three letters.
+const MONTHS: [&str; 12] = [
+    "gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+];
+
+/// Parses a date in one of the formats found in RAI metadata.
+///
+/// Supported forms are `2015-06-12`, `2015-06-12T10:00:00`, `12-06-2015`, `12/06/2015`
+/// and literal dates like `12 Giugno 2015` or `12 giu 2015`.
+pub fn parse_rai_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    for format in ["%Y-%m-%d", "%d-%m-%Y", "%d/%m/%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+            return Some(date);
+        }
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(datetime.date());
+        }
+    }
+    parse_literal_date(text)
+}
+
+/// Parses an Italian literal date like `12 Giugno 2015`.
+fn parse_literal_date(text: &str) -> Option<NaiveDate> {
+    let mut parts = text.split_whitespace();
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?.to_lowercase();
+    let year: i32 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let month = MONTHS
+        .iter()
+        .position(|prefix| month_name.starts_with(prefix))?;
+    NaiveDate::from_ymd_opt(year, month as u32 + 1, day)
+}
+
+/// Parses a date bound given on the command line, relative to `today`.
+///
+/// Accepts ISO dates (`2024-01-01`) and relative forms counting back from today:
+/// `30d` (days), `2w` (weeks), `6m` (months) and `1y` (years).
+pub fn parse_date_bound_at(text: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let text = text.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    let invalid = || {
+        anyhow!(
+            "Invalid date {:?}: expected YYYY-MM-DD or a relative form like 30d, 2w, 6m, 1y",
+            text
+        )
+    };
+    let unit = text.chars().last().ok_or_else(invalid)?;
+    let amount: u32 = text[..text.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    match unit {
+        'd' => today.checked_sub_days(Days::new(amount.into())),
+        'w' => today.checked_sub_days(Days::new(u64::from(amount) * 7)),
+        'm' => today.checked_sub_months(Months::new(amount)),
+        'y' => today.checked_sub_months(Months::new(amount.saturating_mul(12))),
+        _ => return Err(invalid()),
+    }
+    .ok_or_else(invalid)
+}
+
+/// Parses a date bound given on the command line, relative to the current local date.
+pub fn parse_date_bound(text: &str) -> Result<NaiveDate> {
+    parse_date_bound_at(text, Local::now().date_naive())
+}
+
+/// Returns the local midnight that starts the day, to date files with.
+pub fn start_of_day(date: NaiveDate) -> SystemTime {
+    let midnight = date.and_time(NaiveTime::MIN);
+    let start: DateTime<chrono::Utc> = midnight
+        .and_local_timezone(Local)
+        .earliest()
+        .map_or_else(|| midnight.and_utc(), |start| start.to_utc());
+    start.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rai_date() {
+        let cases = [
+            ("2015-06-12", Some(date(2015, 6, 12))),
+            ("2015-06-12T10:30:00", Some(date(2015, 6, 12))),
+            ("2015-06-12 10:30:00", Some(date(2015, 6, 12))),
+            ("12-06-2015", Some(date(2015, 6, 12))),
+            ("12/06/2015", Some(date(2015, 6, 12))),
+            ("12 Giugno 2015", Some(date(2015, 6, 12))),
+            ("1 gen 2024", Some(date(2024, 1, 1))),
+            (" 31 Dicembre 2023 ", Some(date(2023, 12, 31))),
+            ("31 Febbraio 2023", None),
+            ("12 June 2015", None),
+            ("ieri", None),
+            ("", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_rai_date(text), expected, "parsing {:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_date_bound() {
+        let today = date(2024, 3, 31);
+        assert_eq!(
+            parse_date_bound_at("2024-01-01", today).unwrap(),
+            date(2024, 1, 1)
+        );
+        assert_eq!(parse_date_bound_at("30d", today).unwrap(), date(2024, 3, 1));
+        assert_eq!(parse_date_bound_at("2w", today).unwrap(), date(2024, 3, 17));
+        assert_eq!(parse_date_bound_at("1m", today).unwrap(), date(2024, 2, 29));
+        assert_eq!(parse_date_bound_at("1y", today).unwrap(), date(2023, 3, 31));
+        assert!(parse_date_bound_at("30", today).is_err());
+        assert!(parse_date_bound_at("d", today).is_err());
+        assert!(parse_date_bound_at("3x", today).is_err());
+        assert!(parse_date_bound_at("01/02/2024", today).is_err());
+    }
+
+    #[test]
+    fn test_start_of_day() {
+        let start = DateTime::<Local>::from(start_of_day(date(2015, 6, 12)));
+        assert_eq!(start.date_naive(), date(2015, 6, 12));
+        assert_eq!(start.time(), NaiveTime::MIN);
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+/// Name of the folder rsnd keeps its files in, under the platform folders.
+const APP_NAME: &str = "rsnd";
+
+/// Returns the folder the cache defaults to: `$XDG_CACHE_HOME/rsnd` or `~/.cache/rsnd` on
+/// Linux, `~/Library/Caches/rsnd` on macOS and `%LOCALAPPDATA%\rsnd\cache` on Windows.
+///
+/// Falls back to a folder of the temporary directory when the home folder is unknown.
+pub fn cache_dir() -> PathBuf {
+    platform_cache_dir(|name| std::env::var_os(name).map(PathBuf::from))
+        .unwrap_or_else(|| std::env::temp_dir().join(APP_NAME))
+}
+
+/// Returns the platform cache folder of rsnd, reading environment variables with `var`.
+fn platform_cache_dir(var: impl Fn(&str) -> Option<PathBuf>) -> Option<PathBuf> {
+    // Relative paths in these variables are invalid and ignored, as the XDG spec asks.
+    let var = |name: &str| var(name).filter(|path| path.is_absolute());
+    if cfg!(windows) {
+        var("LOCALAPPDATA").map(|dir| dir.join(APP_NAME).join("cache"))
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Caches").join(APP_NAME))
+    } else {
+        var("XDG_CACHE_HOME")
+            .or_else(|| var("HOME").map(|home| home.join(".cache")))
+            .map(|dir| dir.join(APP_NAME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_platform_cache_dir() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| PathBuf::from(value))
+            }
+        };
+        assert_eq!(
+            platform_cache_dir(env(&[("HOME", "/home/ada")])),
+            Some(PathBuf::from("/home/ada/.cache/rsnd"))
+        );
+        assert_eq!(
+            platform_cache_dir(env(&[
+                ("HOME", "/home/ada"),
+                ("XDG_CACHE_HOME", "/var/cache/ada")
+            ])),
+            Some(PathBuf::from("/var/cache/ada/rsnd"))
+        );
+        assert_eq!(
+            platform_cache_dir(env(&[("HOME", "/home/ada"), ("XDG_CACHE_HOME", "cache")])),
+            Some(PathBuf::from("/home/ada/.cache/rsnd"))
+        );
+        assert_eq!(platform_cache_dir(env(&[])), None);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::errln;
+use anyhow::{Context, Result};
+use reqwest::Response;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The dump installed for the run by `--dump-pages`, if any.
+static DUMP: RwLock<Option<Arc<Dump>>> = RwLock::new(None);
+
+tokio::task_local! {
+    /// The folder of the show being processed, under the root of the dump.
+    static FOLDER: String;
+}
+
+/// Name of the file describing the files of a show folder.
+pub const INDEX_FILE: &str = "index.json";
+
+/// Folder of the requests made before any show, such as searches and crawls.
+const RUN_FOLDER: &str = "_run";
+
+/// Headers whose value is replaced before they are saved.
+const REDACTED: [&str; 4] = [
+    "cookie",
+    "set-cookie",
+    "authorization",
+    "proxy-authorization",
+];
+
+/// What a dumped file holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// An HTML page.
+    Page,
+    /// Show or episode JSON.
+    Json,
+    /// Status, final URL and headers of a relinker response.
+    Relinker,
+}
+
+/// A dumped file, as listed in the index of its folder.
+#[derive(Debug, Clone, Serialize)]
+pub struct Item {
+    pub file: String,
+    pub kind: Kind,
+    pub url: String,
+    /// Whether the body came from the cache rather than the network.
+    pub cached: bool,
+}
+
+/// Saves what the run fetches under a folder per show, with an index of the files.
+///
+/// The folder is the one of the show the saving task runs for, so shows run at the same
+/// time with `--show-jobs` each fill their own.
+#[derive(Debug)]
+pub struct Dump {
+    root: PathBuf,
+    /// The files saved so far in each folder, by folder name.
+    items: Mutex<HashMap<String, Vec<Item>>>,
+}
+
+impl Dump {
+    pub fn new(root: &Path) -> Self {
+        Dump {
+            root: root.to_path_buf(),
+            items: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Saves a file in the folder of the current show and rewrites the index of the
+    /// folder.
+    pub fn save(&self, name: &str, kind: Kind, url: &str, cached: bool, body: &[u8]) -> Result<()> {
+        let show = FOLDER
+            .try_with(Clone::clone)
+            .unwrap_or_else(|_| RUN_FOLDER.to_string());
+        let folder = self.root.join(&show);
+        let mut items = self.items.lock().unwrap_or_else(|err| err.into_inner());
+        let items = items.entry(show).or_default();
+        std::fs::create_dir_all(&folder)
+            .with_context(|| format!("Failed to create folder: {}", folder.display()))?;
+        let file = format!("{:03}-{}", items.len() + 1, name);
+        let path = folder.join(&file);
+        std::fs::write(&path, body)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        items.push(Item {
+            file,
+            kind,
+            url: url.to_string(),
+            cached,
+        });
+        let index = folder.join(INDEX_FILE);
+        let json = serde_json::to_string_pretty(&items)?;
+        std::fs::write(&index, json + "\n")
+            .with_context(|| format!("Failed to write {}", index.display()))
+    }
+}
+
+/// Returns the name of the folder the files of the show are saved in: its slug.
+fn folder_name(show_url: &str) -> String {
+    show_url
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .map(|name| crate::naming::sanitize(name, &crate::naming::Naming::default()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "show".to_string())
+}
+
+/// The relinker response as it is dumped.
+#[derive(Debug, Serialize)]
+struct RelinkerResponse {
+    status: u16,
+    final_url: String,
+    headers: Vec<(String, String)>,
+}
+
+fn relinker_response(response: &Response) -> RelinkerResponse {
+    RelinkerResponse {
+        status: response.status().as_u16(),
+        final_url: response.url().to_string(),
+        headers: response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if REDACTED.contains(&name.as_str()) {
+                    "<redacted>".to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect(),
+    }
+}
+
+/// Installs the dump of the run in `root`, if any, in place of the one of an earlier run
+/// of `--watch`, whose files the new one writes over.
+pub fn install(root: Option<&Path>) {
+    *DUMP.write().unwrap_or_else(|err| err.into_inner()) =
+        root.map(|root| Arc::new(Dump::new(root)));
+}
+
+/// Returns the dump of the run, if any.
+fn dump() -> Option<Arc<Dump>> {
+    DUMP.read().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+/// Runs the processing of a show, whose files are saved in a folder of their own.
+pub async fn scope<F: Future>(show_url: &str, future: F) -> F::Output {
+    FOLDER.scope(folder_name(show_url), future).await
+}
+
+/// Wraps a future about to be spawned, so that what it fetches is saved in the folder of
+/// the show spawning it.
+pub fn inherit<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let folder = FOLDER.try_with(Clone::clone).ok();
+    async move {
+        match folder {
+            Some(folder) => FOLDER.scope(folder, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Dumps a fetched page or JSON, named after its cache file. Failures to dump are
+/// warnings, since they do not affect the run.
+pub fn body(name: &str, url: &str, cached: bool, body: &str) {
+    let Some(dump) = dump() else {
+        return;
+    };
+    let kind = if name.ends_with(".json") {
+        Kind::Json
+    } else {
+        Kind::Page
+    };
+    if let Err(err) = dump.save(name, kind, url, cached, body.as_bytes()) {
+        errln!("Warning: failed to dump {}: {:#}", url, err);
+    }
+}
+
+/// Dumps the status, final URL and headers of a relinker response, cookies redacted.
+pub fn relinker(url: &str, response: &Response) {
+    let Some(dump) = dump() else {
+        return;
+    };
+    let result = serde_json::to_string_pretty(&relinker_response(response))
+        .map_err(anyhow::Error::from)
+        .and_then(|json| dump.save("relinker.json", Kind::Relinker, url, false, json.as_bytes()));
+    if let Err(err) = result {
+        errln!("Warning: failed to dump {}: {:#}", url, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{ResponseBuilderExt, Url};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_dump() -> Result<()> {
+        let root = TempDir::new()?;
+        let dump = Dump::new(root.path());
+        dump.save(
+            "search.json",
+            Kind::Json,
+            "https://www.raiplaysound.it/search",
+            false,
+            b"{}",
+        )?;
+        // Two shows run at the same time, each saving in its own folder.
+        let (first, second) = tokio::join!(
+            scope(
+                "https://www.raiplaysound.it/audiolibri/itremoschettieri/",
+                async {
+                    dump.save(
+                        "itremoschettieri.html",
+                        Kind::Page,
+                        "https://www.raiplaysound.it/audiolibri/itremoschettieri",
+                        true,
+                        b"<html>",
+                    )?;
+                    tokio::task::yield_now().await;
+                    dump.save(
+                        "lettura-1.json",
+                        Kind::Json,
+                        "https://www.raiplaysound.it/audio/2015/06/lettura-1.json",
+                        false,
+                        b"{}",
+                    )
+                }
+            ),
+            scope("https://www.raiplaysound.it/audiolibri/ilconte", async {
+                tokio::task::yield_now().await;
+                dump.save(
+                    "ilconte.html",
+                    Kind::Page,
+                    "https://www.raiplaysound.it/audiolibri/ilconte",
+                    false,
+                    b"<html>",
+                )
+            })
+        );
+        first?;
+        second?;
+        assert!(root
+            .path()
+            .join("ilconte")
+            .join("001-ilconte.html")
+            .exists());
+
+        let folder = root.path().join("itremoschettieri");
+        assert_eq!(
+            std::fs::read(folder.join("001-itremoschettieri.html"))?,
+            b"<html>"
+        );
+        let index: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(folder.join(INDEX_FILE))?)?;
+        assert_eq!(index[1]["file"], "002-lettura-1.json");
+        assert_eq!(index[1]["kind"], "json");
+        assert_eq!(index[0]["cached"], true);
+        assert!(root
+            .path()
+            .join(RUN_FOLDER)
+            .join("001-search.json")
+            .exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_relinker_response_redacts_cookies() {
+        let response = http::Response::builder()
+            .status(302)
+            .url(Url::parse("https://cdn.rai.it/lettura-1.mp3").unwrap())
+            .header("content-type", "audio/mpeg")
+            .header("set-cookie", "session=secret")
+            .body(Vec::new())
+            .unwrap();
+        let dumped = relinker_response(&Response::from(response));
+        assert_eq!(dumped.status, 302);
+        assert_eq!(dumped.final_url, "https://cdn.rai.it/lettura-1.mp3");
+        assert!(dumped
+            .headers
+            .contains(&("set-cookie".to_string(), "<redacted>".to_string())));
+        assert!(dumped
+            .headers
+            .contains(&("content-type".to_string(), "audio/mpeg".to_string())));
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+/// Parses a clock duration like `00:19:15` or `19:15` into seconds.
+pub fn parse_clock(text: &str) -> Option<u64> {
+    let parts = text
+        .trim()
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    match parts[..] {
+        [hours, minutes, seconds] if minutes < 60 && seconds < 60 => {
+            Some(hours * 3600 + minutes * 60 + seconds)
+        }
+        [minutes, seconds] if seconds < 60 => Some(minutes * 60 + seconds),
+        _ => None,
+    }
+}
+
+/// Formats seconds as a clock duration like `00:19:15`.
+pub fn format_clock(seconds: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parses a duration given on the command line: plain seconds (`90`) or a combination
+/// of hours, minutes and seconds (`2m`, `1h30m`, `1h30m15s`).
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let invalid = || {
+        anyhow!(
+            "Invalid duration {:?}: expected seconds or a form like 90s, 2m, 1h30m",
+            text
+        )
+    };
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut last_unit = 0;
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let (rank, factor) = match c {
+            'h' => (3, 3600),
+            'm' => (2, 60),
+            's' => (1, 1),
+            _ => return Err(invalid()),
+        };
+        // Units must appear at most once, from the largest to the smallest.
+        if number.is_empty() || (last_unit != 0 && rank >= last_unit) {
+            return Err(invalid());
+        }
+        let amount: u64 = number.parse().map_err(|_| invalid())?;
+        seconds += amount * factor;
+        number.clear();
+        last_unit = rank;
+    }
+    if !number.is_empty() || last_unit == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clock() {
+        assert_eq!(parse_clock("00:19:15"), Some(1155));
+        assert_eq!(parse_clock("1:02:03"), Some(3723));
+        assert_eq!(parse_clock("19:15"), Some(1155));
+        assert_eq!(parse_clock("00:75:00"), None);
+        assert_eq!(parse_clock("19 min"), None);
+        assert_eq!(parse_clock(""), None);
+    }
+
+    #[test]
+    fn test_format_clock() {
+        assert_eq!(format_clock(1155), "00:19:15");
+        assert_eq!(format_clock(36000), "10:00:00");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let secs = |text| parse_duration(text).map(|d| d.as_secs()).ok();
+        assert_eq!(secs("90"), Some(90));
+        assert_eq!(secs("90s"), Some(90));
+        assert_eq!(secs("2m"), Some(120));
+        assert_eq!(secs("1h30m"), Some(5400));
+        assert_eq!(secs("1h30m15s"), Some(5415));
+        assert_eq!(secs("1h"), Some(3600));
+        assert_eq!(secs("30m1h"), None);
+        assert_eq!(secs("1h30"), None);
+        assert_eq!(secs("m"), None);
+        assert_eq!(secs("2 m"), None);
+        assert_eq!(secs(""), None);
+    }
+}
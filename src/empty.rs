@@ -0,0 +1,182 @@
+use crate::fetcher::Fetcher;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use std::fmt;
+
+/// Words of the titles of not-found pages.
+const NOT_FOUND_TITLES: [&str; 4] = ["404", "not found", "non trovata", "non esiste"];
+
+/// What a show page without episodes looks like, to tell a mistyped URL or a redirect to
+/// the home page from a change of the site.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub url: String,
+    /// Where the URL leads after redirects, when it could be probed.
+    pub final_url: Option<String>,
+    pub status: Option<u16>,
+    /// The `<title>` of the page.
+    pub title: Option<String>,
+    /// `rps-play-with-labels` elements on the page, and those with an `options` attribute.
+    pub elements: usize,
+    pub with_options: usize,
+}
+
+impl Diagnosis {
+    /// Inspects the page that was read for the show at `url`.
+    pub fn from_page(url: &str, html: &str) -> Self {
+        let document = Html::parse_document(html);
+        let title = Selector::parse("title").expect("Invalid selector");
+        let elements = Selector::parse("rps-play-with-labels").expect("Invalid selector");
+        Diagnosis {
+            url: url.to_string(),
+            title: document
+                .select(&title)
+                .map(|element| element.text().collect::<String>().trim().to_string())
+                .find(|title| !title.is_empty()),
+            elements: document.select(&elements).count(),
+            with_options: document
+                .select(&elements)
+                .filter(|element| element.value().attr("options").is_some())
+                .count(),
+            ..Diagnosis::default()
+        }
+    }
+
+    /// Requests the page again to learn where it leads and with which status, since the
+    /// cached page keeps neither. Failures leave both unknown.
+    pub async fn probe<F: Fetcher>(&mut self, client: &F) {
+        match client.get_stream(&self.url).await {
+            Ok(response) => {
+                self.final_url = Some(response.url().to_string());
+                self.status = Some(response.status().as_u16());
+            }
+            Err(err) => log::debug!("Cannot probe {}: {}", self.url, err),
+        }
+    }
+
+    /// Guesses what kind of page was served instead of the show.
+    pub fn looks_like(&self) -> Option<&'static str> {
+        let title = self.title.as_deref().unwrap_or("").to_lowercase();
+        if self.status == Some(404) || NOT_FOUND_TITLES.iter().any(|word| title.contains(word)) {
+            return Some("a not-found page");
+        }
+        let final_path = self
+            .final_url
+            .as_deref()
+            .and_then(|url| Url::parse(url).ok())
+            .map(|url| url.path().trim_end_matches('/').to_string())?;
+        if final_path.is_empty() {
+            Some("the home page")
+        } else if Url::parse(&self.url)
+            .is_ok_and(|url| url.path().trim_end_matches('/') != final_path)
+        {
+            Some("another page, the show may have moved")
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "No episodes found on page {}", self.url)?;
+        match (&self.final_url, self.status) {
+            (Some(final_url), Some(status)) => {
+                writeln!(f, "  Final URL:  {} (status {})", final_url, status)?
+            }
+            _ => writeln!(f, "  Final URL:  unknown, the page was not requested again")?,
+        }
+        match &self.title {
+            Some(title) => writeln!(f, "  Title:      {:?}", title)?,
+            None => writeln!(f, "  Title:      none")?,
+        }
+        match (self.elements, self.with_options) {
+            (0, _) => writeln!(f, "  Episodes:   no rps-play-with-labels elements")?,
+            (elements, with_options) => writeln!(
+                f,
+                "  Episodes:   {} rps-play-with-labels elements, {} with options, none readable",
+                elements, with_options
+            )?,
+        }
+        if let Some(kind) = self.looks_like() {
+            writeln!(f, "  Looks like: {}", kind)?;
+        }
+        write!(
+            f,
+            "Check the URL, or pass --allow-empty to accept shows without episodes"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::MockFetcher;
+    use reqwest::StatusCode;
+
+    const URL: &str = "https://www.raiplaysound.it/audiolibri/itremoshettieri";
+
+    #[test]
+    fn test_from_page() {
+        let diagnosis = Diagnosis::from_page(
+            URL,
+            r#"<html><head><title> I tre moschettieri </title></head><body>
+            <rps-play-with-labels options='{"ur": 1}'></rps-play-with-labels>
+            <rps-play-with-labels></rps-play-with-labels></body></html>"#,
+        );
+        assert_eq!(diagnosis.title.as_deref(), Some("I tre moschettieri"));
+        assert_eq!((diagnosis.elements, diagnosis.with_options), (2, 1));
+        assert_eq!(diagnosis.looks_like(), None);
+        let text = diagnosis.to_string();
+        assert!(text.contains("  Episodes:   2 rps-play-with-labels elements, 1 with options"));
+        assert!(text.contains("the page was not requested again"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_home_page() {
+        let client = MockFetcher::default().fixture(
+            URL,
+            StatusCode::OK,
+            "text/html",
+            "<title>RaiPlay Sound</title>",
+            Some("https://www.raiplaysound.it/"),
+        );
+        let mut diagnosis = Diagnosis::from_page(URL, "<title>RaiPlay Sound</title>");
+        diagnosis.probe(&client).await;
+        assert_eq!(
+            diagnosis.final_url.as_deref(),
+            Some("https://www.raiplaysound.it/")
+        );
+        assert_eq!(diagnosis.looks_like(), Some("the home page"));
+        assert!(diagnosis
+            .to_string()
+            .contains("  Looks like: the home page\n"));
+    }
+
+    #[test]
+    fn test_looks_like() {
+        let diagnosis = |title: &str, final_url: &str, status| Diagnosis {
+            url: URL.to_string(),
+            final_url: Some(final_url.to_string()),
+            status: Some(status),
+            title: Some(title.to_string()),
+            ..Diagnosis::default()
+        };
+        assert_eq!(
+            diagnosis("Pagina non trovata", URL, 200).looks_like(),
+            Some("a not-found page")
+        );
+        assert_eq!(
+            diagnosis("RaiPlay Sound", URL, 404).looks_like(),
+            Some("a not-found page")
+        );
+        assert_eq!(
+            diagnosis("Audiolibri", "https://www.raiplaysound.it/audiolibri", 200).looks_like(),
+            Some("another page, the show may have moved")
+        );
+        assert_eq!(
+            diagnosis("I tre moschettieri", &format!("{}/", URL), 200).looks_like(),
+            None
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::empty::Diagnosis;
+use reqwest::StatusCode;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The failures rsnd tells apart, to pick exit codes and decide what to retry.
+///
+/// The core functions return them wrapped in `anyhow::Error`, which adds context for
+/// display; [`RsndError::of`] finds the variant again.
+#[derive(Debug, Error)]
+pub enum RsndError {
+    /// The request could not be sent, e.g. because the host is unreachable.
+    #[error("Failed to fetch URL: {url}")]
+    PageFetch {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The request was answered with an unsuccessful status, and for a 429 or a 503
+    /// the wait the server asked for in `Retry-After`.
+    #[error("Failed to fetch URL: {url}. Status: {status}")]
+    Status {
+        url: String,
+        status: StatusCode,
+        retry_after: Option<Duration>,
+    },
+    /// The show page or JSON lists no episodes, with what the page looked like when it
+    /// was inspected.
+    #[error("{}", .diagnosis.as_ref().map_or_else(|| format!("No episodes listed at {}", .url), ToString::to_string))]
+    NoEpisodes {
+        url: String,
+        diagnosis: Option<Box<Diagnosis>>,
+    },
+    /// The episode JSON is not valid JSON.
+    #[error("Failed to parse JSON: {url}")]
+    MetadataParse {
+        url: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// An HTML page, usually an error or maintenance page, was served in place of the
+    /// episode JSON.
+    #[error("Metadata endpoint returned HTML (status page?): {url}: {snippet}")]
+    MetadataHtml { url: String, snippet: String },
+    /// The episode JSON has no audio URL in any of the fields it is read from.
+    #[error("No audio URL for episode {title:?}")]
+    MissingAudioUrl { title: String },
+    /// The episode JSON links to the web page of the episode but to no audio, so there is
+    /// nothing to download.
+    #[error("no audio, only the web page {url}")]
+    WebPageOnly { url: String },
+    /// The relinker served a placeholder instead of the audio, usually because the
+    /// episode is geo-blocked outside Italy, with the status it was served with.
+    #[error("unavailable (geo-blocked?): {reason}")]
+    GeoBlocked { reason: String, status: StatusCode },
+    /// The audio is outside the sizes `--min-filesize` and `--max-filesize` allow.
+    #[error("skipped for size: {reason}")]
+    SizeLimit { reason: String },
+    /// A request for the audio, or what leads to it, could not be sent.
+    #[error("Failed to fetch audio URL: {url}")]
+    Download {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The download came back empty, or ended before the advertised Content-Length was
+    /// received.
+    #[error("{}", short_read_message(*received, *expected))]
+    ShortRead {
+        received: u64,
+        expected: Option<u64>,
+    },
+    /// The download stayed below `--stall-speed` for `--stall-timeout`.
+    #[error(
+        "Download stalled: {}/s over the last {secs}s, below --stall-speed, after {}",
+        crate::format_size(*speed),
+        crate::format_size(*received)
+    )]
+    Stalled {
+        received: u64,
+        speed: u64,
+        secs: u64,
+    },
+    /// A file name built from the metadata leads out of the folder it is saved in.
+    #[error("Refusing to write {} outside of {}", path.display(), folder.display())]
+    UnsafePath { path: PathBuf, folder: PathBuf },
+    /// A download would replace a file that does not look like one rsnd saved.
+    #[error(
+        "Refusing to overwrite {}, which rsnd did not create (pass --force-unsafe to replace it)",
+        path.display()
+    )]
+    Clobber { path: PathBuf },
+    /// The URL is not cached and `--offline` forbids fetching it.
+    #[error("Not cached, offline: {url}")]
+    Offline { url: String },
+    /// A cache file could not be read or written.
+    #[error("Failed to access cache file: {}", path.display())]
+    Cache {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+fn short_read_message(received: u64, expected: Option<u64>) -> String {
+    match expected {
+        Some(expected) => format!(
+            "Incomplete download: received {} of {} bytes",
+            received, expected
+        ),
+        None => "Empty download: received no data".to_string(),
+    }
+}
+
+impl RsndError {
+    /// The error for the unsuccessful `response` to a request for `url`.
+    pub fn status(url: &str, response: &reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = match status {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(crate::retry::parse_retry_after),
+            _ => None,
+        };
+        RsndError::Status {
+            url: url.to_string(),
+            status,
+            retry_after,
+        }
+    }
+
+    /// Returns the variant wrapped in `err`, under any context added since.
+    pub fn of(err: &anyhow::Error) -> Option<&RsndError> {
+        err.downcast_ref()
+    }
+
+    /// Returns whether the error means the show page could not be had, as opposed to a
+    /// local problem such as an unwritable cache.
+    pub fn is_page_unavailable(&self) -> bool {
+        matches!(
+            self,
+            RsndError::PageFetch { .. }
+                | RsndError::Status { .. }
+                | RsndError::NoEpisodes { .. }
+                | RsndError::Offline { .. }
+        )
+    }
+
+    /// Returns whether the error hints at an expired audio URL, which a fresh copy of
+    /// the metadata may fix.
+    pub fn looks_expired(&self) -> bool {
+        match self {
+            RsndError::GeoBlocked { .. } => true,
+            RsndError::Status { status, .. } => matches!(
+                *status,
+                StatusCode::FORBIDDEN | StatusCode::NOT_FOUND | StatusCode::GONE
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_of_finds_variant_under_context() {
+        let err = Err::<(), _>(RsndError::NoEpisodes {
+            url: "https://www.raiplaysound.it/audiolibri/x.json".to_string(),
+            diagnosis: None,
+        })
+        .context("Failed to list episodes")
+        .unwrap_err();
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::NoEpisodes { .. })
+        ));
+        assert!(RsndError::of(&anyhow::anyhow!("other")).is_none());
+    }
+
+    #[test]
+    fn test_no_episodes_message() {
+        let url = "https://www.raiplaysound.it/audiolibri/x";
+        let err = RsndError::NoEpisodes {
+            url: url.to_string(),
+            diagnosis: None,
+        };
+        assert_eq!(err.to_string(), format!("No episodes listed at {}", url));
+        let err = RsndError::NoEpisodes {
+            url: url.to_string(),
+            diagnosis: Some(Box::new(Diagnosis::from_page(url, "<html></html>"))),
+        };
+        assert!(err
+            .to_string()
+            .starts_with(&format!("No episodes found on page {}\n", url)));
+    }
+
+    #[test]
+    fn test_short_read_message() {
+        let err = RsndError::ShortRead {
+            received: 20,
+            expected: Some(100),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Incomplete download: received 20 of 100 bytes"
+        );
+        let err = RsndError::ShortRead {
+            received: 0,
+            expected: None,
+        };
+        assert_eq!(err.to_string(), "Empty download: received no data");
+    }
+
+    #[test]
+    fn test_stalled_message() {
+        let err = RsndError::Stalled {
+            received: 3 * 1024 * 1024,
+            speed: 2048,
+            secs: 30,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Download stalled: 2.0 KB/s over the last 30s, below --stall-speed, after 3.0 MB"
+        );
+    }
+}
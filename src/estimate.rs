@@ -0,0 +1,124 @@
+use crate::format_size;
+use anyhow::{Context, Result};
+use inquire::Confirm;
+use std::fmt;
+use std::io::IsTerminal;
+
+/// The size of a pending download, as its CDN advertises it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Probe {
+    Known(u64),
+    /// Why the size is not known.
+    Unknown(String),
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::Known(bytes) => write!(f, "{}", format_size(*bytes)),
+            Probe::Unknown(reason) => write!(f, "unknown size: {}", reason),
+        }
+    }
+}
+
+/// An episode whose size could not be probed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownSize {
+    pub index: usize,
+    pub title: String,
+    pub reason: String,
+}
+
+/// The total size of the pending downloads, for `--estimate`.
+#[derive(Debug, Default)]
+pub struct Estimate {
+    pub bytes: u64,
+    /// Episodes of known size.
+    pub known: usize,
+    pub unknown: Vec<UnknownSize>,
+}
+
+impl Estimate {
+    pub fn add(&mut self, index: usize, title: &str, probe: &Probe) {
+        match probe {
+            Probe::Known(bytes) => {
+                self.bytes += bytes;
+                self.known += 1;
+            }
+            Probe::Unknown(reason) => self.unknown.push(UnknownSize {
+                index,
+                title: title.to_string(),
+                reason: reason.clone(),
+            }),
+        }
+    }
+
+    /// Number of pending episodes, of known size or not.
+    pub fn count(&self) -> usize {
+        self.known + self.unknown.len()
+    }
+
+    /// The one-line total, like "About 7.4 GB across 112 new episodes".
+    pub fn headline(&self) -> String {
+        let mut headline = format!(
+            "About {} across {} new episodes",
+            format_size(self.bytes),
+            self.count()
+        );
+        if !self.unknown.is_empty() {
+            headline.push_str(&format!(" ({} of unknown size)", self.unknown.len()));
+        }
+        headline
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.headline())?;
+        for episode in &self.unknown {
+            writeln!(
+                f,
+                "    {:03} - {}: {}",
+                episode.index, episode.title, episode.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks whether to go on with the downloads of the estimate. Without a terminal to ask
+/// in, the run goes on.
+pub fn confirm(estimate: &Estimate) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    let answer = Confirm::new(&format!("{}, continue?", estimate.headline()))
+        .with_default(false)
+        .prompt_skippable()
+        .context("Failed to read the confirmation")?;
+    Ok(answer.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let mut estimate = Estimate::default();
+        estimate.add(1, "Lettura I", &Probe::Known(3 * 1024 * 1024));
+        estimate.add(2, "Lettura II", &Probe::Known(1024 * 1024));
+        assert_eq!(estimate.headline(), "About 4.0 MB across 2 new episodes");
+
+        estimate.add(
+            3,
+            "Lettura III",
+            &Probe::Unknown("not advertised".to_string()),
+        );
+        assert_eq!(estimate.count(), 3);
+        assert_eq!(
+            estimate.to_string(),
+            "About 4.0 MB across 3 new episodes (1 of unknown size)\n    003 - Lettura III: not advertised\n"
+        );
+    }
+}
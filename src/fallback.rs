@@ -0,0 +1,130 @@
+use crate::errln;
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Returns the metadata path of an audio item referenced by `text`, which may be the
+/// address of an episode page or the path of its metadata.
+fn item_path(text: &str) -> Option<String> {
+    let path = match text.find("://") {
+        Some(_) => crate::episode_metadata_path(text)?,
+        None => text.to_string(),
+    };
+    let stem = path.strip_prefix("/audio/")?;
+    let stem = stem
+        .strip_suffix(".html")
+        .or_else(|| stem.strip_suffix(".json"))?;
+    Some(format!("/audio/{}.json", stem))
+}
+
+/// Collects the audio items referenced by any string in the JSON value, in order.
+fn collect_items(value: &Value, items: &mut Vec<String>) {
+    match value {
+        Value::String(text) => items.extend(item_path(text)),
+        Value::Array(values) => values.iter().for_each(|value| collect_items(value, items)),
+        Value::Object(map) => map.values().for_each(|value| collect_items(value, items)),
+        _ => {}
+    }
+}
+
+fn dedup(mut items: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    items.retain(|item| seen.insert(item.clone()));
+    items
+}
+
+/// Extracts the audio items listed in the `application/ld+json` blocks of the page.
+pub fn json_ld_items(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let selector =
+        Selector::parse(r#"script[type="application/ld+json"]"#).expect("Invalid selector");
+    let mut items = Vec::new();
+    for script in document.select(&selector) {
+        let text: String = script.text().collect();
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => collect_items(&value, &mut items),
+            Err(err) => errln!("Warning: skipping unparsable JSON-LD block: {}", err),
+        }
+    }
+    dedup(items)
+}
+
+/// Extracts the audio items from a state object assigned in a page script, such as
+/// `window.__INIT_STATE__ = {...};`.
+pub fn embedded_state_items(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("script:not([src])").expect("Invalid selector");
+    let assignment = Regex::new(r"window\.__\w+__\s*=\s*").expect("Invalid regex");
+    let mut items = Vec::new();
+    for script in document.select(&selector) {
+        let text: String = script.text().collect();
+        for found in assignment.find_iter(&text) {
+            // The object is usually followed by more code, so only read the first value.
+            let mut values =
+                serde_json::Deserializer::from_str(&text[found.end()..]).into_iter::<Value>();
+            match values.next() {
+                Some(Ok(value)) => collect_items(&value, &mut items),
+                Some(Err(err)) => errln!("Warning: skipping unparsable page state: {}", err),
+                None => {}
+            }
+        }
+    }
+    dedup(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_path() {
+        assert_eq!(
+            item_path("https://www.raiplaysound.it/audio/2015/06/lettura-1.html").as_deref(),
+            Some("/audio/2015/06/lettura-1.json")
+        );
+        assert_eq!(
+            item_path("/audio/2015/06/lettura-1.json").as_deref(),
+            Some("/audio/2015/06/lettura-1.json")
+        );
+        assert_eq!(item_path("https://www.raiplaysound.it/audiolibri/x"), None);
+        assert_eq!(item_path("/audio/2015/06/cover.jpg"), None);
+    }
+
+    #[test]
+    fn test_json_ld_items() {
+        let html = r#"<html><head><script type="application/ld+json">
+            {"@type": "PodcastSeries", "name": "I tre moschettieri",
+             "url": "https://www.raiplaysound.it/audiolibri/itremoschettieri",
+             "episode": [
+                {"@type": "PodcastEpisode", "url": "https://www.raiplaysound.it/audio/2015/06/lettura-1.html"},
+                {"@type": "PodcastEpisode", "url": "https://www.raiplaysound.it/audio/2015/06/lettura-2.html"}
+             ]}
+            </script><script type="application/ld+json">{not json</script></head></html>"#;
+        assert_eq!(
+            json_ld_items(html),
+            vec![
+                "/audio/2015/06/lettura-1.json",
+                "/audio/2015/06/lettura-2.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_embedded_state_items() {
+        let html = r#"<html><body><script>
+            window.__INIT_STATE__ = {"items": [
+                {"path_id": "/audio/2015/06/lettura-1.json", "weblink": "/audio/2015/06/lettura-1.html"},
+                {"path_id": "/audio/2015/06/lettura-2.json"}
+            ]}; window.dataLayer = [];
+            </script><script src="/app.js"></script></body></html>"#;
+        assert_eq!(
+            embedded_state_items(html),
+            vec![
+                "/audio/2015/06/lettura-1.json",
+                "/audio/2015/06/lettura-2.json"
+            ]
+        );
+        assert!(embedded_state_items("<script>var x = 1;</script>").is_empty());
+    }
+}
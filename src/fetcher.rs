@@ -0,0 +1,239 @@
+use crate::error::RsndError;
+use crate::ratelimit;
+use reqwest::{Client, Response};
+use std::future::Future;
+
+/// The HTTP requests rsnd makes, so that they can be served from fixtures in tests.
+///
+/// Implementations are cheap to clone, so that concurrent tasks can each own one.
+pub trait Fetcher: Clone + Send + Sync + 'static {
+    /// Fetches the body of the URL, failing on unsuccessful statuses.
+    fn get_text(&self, url: &str) -> impl Future<Output = Result<String, RsndError>> + Send;
+
+    /// Sends a GET request for the audio, or what leads to it, and returns the response,
+    /// whatever its status, to stream the body from.
+    fn get_stream(&self, url: &str) -> impl Future<Output = Result<Response, RsndError>> + Send;
+
+    /// Sends a HEAD request and returns the response, whatever its status.
+    fn head(&self, url: &str) -> impl Future<Output = Result<Response, RsndError>> + Send;
+}
+
+/// Fetches over the network with reqwest, within the `--rps` limit, unless it is
+/// offline.
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    client: Client,
+    offline: bool,
+}
+
+impl HttpFetcher {
+    pub fn new(client: Client) -> Self {
+        HttpFetcher {
+            client,
+            offline: false,
+        }
+    }
+
+    /// Returns a fetcher that refuses every request, for `--offline`.
+    pub fn offline(client: Client) -> Self {
+        HttpFetcher {
+            client,
+            offline: true,
+        }
+    }
+
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        url: &str,
+    ) -> Result<Response, RsndError> {
+        if self.offline {
+            return Err(RsndError::Offline {
+                url: url.to_string(),
+            });
+        }
+        ratelimit::throttle(url).await;
+        request.send().await.map_err(|source| RsndError::Download {
+            url: url.to_string(),
+            source,
+        })
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    async fn get_text(&self, url: &str) -> Result<String, RsndError> {
+        if self.offline {
+            return Err(RsndError::Offline {
+                url: url.to_string(),
+            });
+        }
+        ratelimit::throttle(url).await;
+        let page_error = |source| RsndError::PageFetch {
+            url: url.to_string(),
+            source,
+        };
+        let response = self.client.get(url).send().await.map_err(page_error)?;
+        if !response.status().is_success() {
+            return Err(RsndError::status(url, &response));
+        }
+        response.text().await.map_err(page_error)
+    }
+
+    async fn get_stream(&self, url: &str) -> Result<Response, RsndError> {
+        self.send(self.client.get(url), url).await
+    }
+
+    async fn head(&self, url: &str) -> Result<Response, RsndError> {
+        self.send(self.client.head(url), url).await
+    }
+}
+
+/// Returns the size the response advertises. It is read from the header, since the body
+/// of a HEAD response is empty and reqwest counts that instead.
+pub fn content_length(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+pub use mock::MockFetcher;
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use reqwest::{ResponseBuilderExt, StatusCode, Url};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// A canned response of the [`MockFetcher`].
+    #[derive(Debug, Clone)]
+    struct Fixture {
+        status: StatusCode,
+        headers: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+        /// The URL the response claims to come from, after redirects.
+        final_url: Option<String>,
+    }
+
+    /// Serves canned responses by URL, and 404 for any other URL.
+    #[derive(Debug, Clone, Default)]
+    pub struct MockFetcher {
+        fixtures: Arc<Mutex<HashMap<String, Fixture>>>,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockFetcher {
+        /// Serves `body` with a 200 status and the given Content-Type at `url`.
+        pub fn serve(self, url: &str, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+            self.fixture(url, StatusCode::OK, content_type, body, None)
+        }
+
+        /// Serves a response with any status, optionally redirected to `final_url`.
+        pub fn fixture(
+            self,
+            url: &str,
+            status: StatusCode,
+            content_type: &str,
+            body: impl Into<Vec<u8>>,
+            final_url: Option<&str>,
+        ) -> Self {
+            let body = body.into();
+            let fixture = Fixture {
+                status,
+                headers: vec![
+                    ("content-type", content_type.to_string()),
+                    ("content-length", body.len().to_string()),
+                ],
+                body,
+                final_url: final_url.map(str::to_string),
+            };
+            self.fixtures
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), fixture);
+            self
+        }
+
+        /// The requests received so far, as `METHOD url`.
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn respond(&self, method: &str, url: &str) -> Response {
+            self.requests
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", method, url));
+            let fixture = self.fixtures.lock().unwrap().get(url).cloned();
+            let fixture = fixture.unwrap_or_else(|| Fixture {
+                status: StatusCode::NOT_FOUND,
+                headers: vec![("content-type", "text/html".to_string())],
+                body: b"<html>Not found</html>".to_vec(),
+                final_url: None,
+            });
+            let final_url = fixture.final_url.as_deref().unwrap_or(url);
+            let mut builder = http::Response::builder()
+                .status(fixture.status)
+                .url(Url::parse(final_url).expect("Invalid fixture URL"));
+            for (name, value) in &fixture.headers {
+                builder = builder.header(*name, value);
+            }
+            let body = if method == "HEAD" {
+                Vec::new()
+            } else {
+                fixture.body
+            };
+            Response::from(builder.body(body).expect("Invalid fixture"))
+        }
+    }
+
+    impl Fetcher for MockFetcher {
+        async fn get_text(&self, url: &str) -> Result<String, RsndError> {
+            let response = self.respond("GET", url);
+            if !response.status().is_success() {
+                return Err(RsndError::status(url, &response));
+            }
+            response
+                .text()
+                .await
+                .map_err(|source| RsndError::PageFetch {
+                    url: url.to_string(),
+                    source,
+                })
+        }
+
+        async fn get_stream(&self, url: &str) -> Result<Response, RsndError> {
+            Ok(self.respond("GET", url))
+        }
+
+        async fn head(&self, url: &str) -> Result<Response, RsndError> {
+            Ok(self.respond("HEAD", url))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_offline_fetcher_refuses_requests() {
+        let fetcher = HttpFetcher::offline(Client::new());
+        let url = "https://www.raiplaysound.it/audio/2015/06/lettura-1.json";
+        assert!(matches!(
+            fetcher.get_text(url).await,
+            Err(RsndError::Offline { .. })
+        ));
+        assert!(matches!(
+            fetcher.get_stream(url).await,
+            Err(RsndError::Offline { .. })
+        ));
+        assert!(matches!(
+            fetcher.head(url).await,
+            Err(RsndError::Offline { .. })
+        ));
+    }
+}
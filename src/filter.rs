@@ -0,0 +1,71 @@
+use chrono::NaiveDate;
+use regex::{Regex, RegexBuilder};
+
+/// Parses a case-insensitive title pattern given on the command line.
+pub fn parse_title_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// Returns whether the title matches at least one of `matches` (if any) and none of `rejects`.
+pub fn title_allowed(title: &str, matches: &[Regex], rejects: &[Regex]) -> bool {
+    (matches.is_empty() || matches.iter().any(|re| re.is_match(title)))
+        && !rejects.iter().any(|re| re.is_match(title))
+}
+
+/// Returns whether the date is on or after `after` and strictly before `before`.
+pub fn date_in_range(date: NaiveDate, after: Option<NaiveDate>, before: Option<NaiveDate>) -> bool {
+    after.is_none_or(|after| date >= after) && before.is_none_or(|before| date < before)
+}
+
+/// Returns whether the duration in seconds lies within the inclusive bounds.
+pub fn duration_in_range(seconds: u64, min: Option<u64>, max: Option<u64>) -> bool {
+    min.is_none_or(|min| seconds >= min) && max.is_none_or(|max| seconds <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_allowed() {
+        let matches = vec![parse_title_regex("lettura").unwrap()];
+        let rejects = vec![
+            parse_title_regex("speciale").unwrap(),
+            parse_title_regex(r"\btrailer\b").unwrap(),
+        ];
+
+        assert!(title_allowed(
+            "I tre moschettieri - Lettura I",
+            &matches,
+            &rejects
+        ));
+        assert!(!title_allowed("Lettura speciale", &matches, &rejects));
+        assert!(!title_allowed("Intervista", &matches, &rejects));
+        assert!(!title_allowed("TRAILER", &[], &rejects));
+        assert!(title_allowed("Intervista", &[], &rejects));
+        assert!(title_allowed("Anything", &[], &[]));
+    }
+
+    #[test]
+    fn test_parse_title_regex_invalid() {
+        assert!(parse_title_regex("(unclosed").is_err());
+    }
+
+    #[test]
+    fn test_date_in_range() {
+        let date = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        assert!(date_in_range(date(1), Some(date(1)), Some(date(31))));
+        assert!(!date_in_range(date(31), Some(date(1)), Some(date(31))));
+        assert!(!date_in_range(date(1), Some(date(2)), None));
+        assert!(date_in_range(date(1), None, Some(date(2))));
+        assert!(date_in_range(date(15), None, None));
+    }
+
+    #[test]
+    fn test_duration_in_range() {
+        assert!(duration_in_range(1155, Some(90), Some(3600)));
+        assert!(duration_in_range(90, Some(90), None));
+        assert!(!duration_in_range(30, Some(90), None));
+        assert!(!duration_in_range(21600, None, Some(3600)));
+    }
+}
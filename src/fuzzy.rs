@@ -0,0 +1,263 @@
+use crate::relinker::EXTENSIONS;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Shortest share of common words for two titles to be taken as the same episode.
+const SIMILARITY: f64 = 0.5;
+
+/// Returns the lowercase ASCII words of a title, whatever the case, accents and
+/// separators it was saved with.
+fn words(title: &str) -> Vec<String> {
+    deunicode::deunicode(title)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns the number a file stem starts with and the rest, whatever separates them, as
+/// in `07 - lettura vii`, `007_Lettura_VII` or `7-lettura-vii`.
+fn leading_index(stem: &str) -> (Option<usize>, &str) {
+    let digits = stem.len() - stem.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = stem[digits..].trim_start_matches(|c: char| !c.is_alphanumeric());
+    match stem[..digits].parse() {
+        Ok(number) if rest.len() < stem.len() - digits => (Some(number), rest),
+        _ => (None, stem),
+    }
+}
+
+/// Returns the words of a title that number a part, in digits or roman numerals.
+fn numerals(words: &[String]) -> Vec<&str> {
+    let mut numerals: Vec<&str> = words
+        .iter()
+        .filter(|word| {
+            word.chars().all(|c| c.is_ascii_digit())
+                || word.chars().all(|c| matches!(c, 'i' | 'v' | 'x'))
+        })
+        .map(String::as_str)
+        .collect();
+    numerals.sort_unstable();
+    numerals
+}
+
+/// Returns whether all the numerals of `part` are among those of `whole`.
+fn numerals_within(part: &[&str], whole: &[&str]) -> bool {
+    let mut whole = whole.to_vec();
+    part.iter().all(|numeral| {
+        let found = whole.iter().position(|other| other == numeral);
+        found.map(|position| whole.swap_remove(position)).is_some()
+    })
+}
+
+/// Returns whether a file name and an episode title look like the same episode: one
+/// holds all the words of the other, as when a name was cut short, or they share most
+/// of their words, as when the title was edited since the download. Parts are told
+/// apart: the part numbers of one must all be in the other.
+pub fn similar_titles(name: &str, title: &str) -> bool {
+    let name = words(name);
+    let title = words(title);
+    if name.is_empty() || title.is_empty() {
+        return false;
+    }
+    let (name_numerals, title_numerals) = (numerals(&name), numerals(&title));
+    if !numerals_within(&name_numerals, &title_numerals)
+        && !numerals_within(&title_numerals, &name_numerals)
+    {
+        return false;
+    }
+    let holds =
+        |long: &[String], short: &[String]| long.windows(short.len()).any(|window| window == short);
+    if holds(&name, &title) || holds(&title, &name) {
+        return true;
+    }
+    let name: HashSet<&String> = name.iter().collect();
+    let title: HashSet<&String> = title.iter().collect();
+    let common = name.intersection(&title).count();
+    let all = name.union(&title).count();
+    common as f64 / all as f64 >= SIMILARITY
+}
+
+/// Lists the audio files of the folder, in name order, leaving out partial downloads.
+pub fn audio_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(folder)
+        .with_context(|| format!("Failed to read folder: {}", folder.display()))?
+    {
+        let path = entry?.path();
+        let audio = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+        if audio && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Finds the file of an episode among those not matched yet, for files renamed since
+/// the download or named by older versions that sanitized titles differently: the one
+/// numbered like it with a similar name, or else the only one with a similar name.
+pub fn find_file<'a>(
+    files: &'a [PathBuf],
+    claimed: &HashSet<PathBuf>,
+    number: Option<usize>,
+    title: &str,
+) -> Option<&'a PathBuf> {
+    let free = || files.iter().filter(|file| !claimed.contains(*file));
+    let stem = |file: &Path| {
+        file.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let numbered_like = free().find(|file| {
+        let stem = stem(file);
+        let (found, name) = leading_index(&stem);
+        found.is_some() && found == number && similar_titles(name, title)
+    });
+    if numbered_like.is_some() {
+        return numbered_like;
+    }
+    let mut similar = free().filter(|file| {
+        let stem = stem(file);
+        similar_titles(leading_index(&stem).1, title)
+    });
+    match (similar.next(), similar.next()) {
+        (Some(file), None) => Some(file),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leading_index() {
+        assert_eq!(leading_index("07 - lettura vii"), (Some(7), "lettura vii"));
+        assert_eq!(leading_index("007_Lettura_VII"), (Some(7), "Lettura_VII"));
+        assert_eq!(leading_index("lettura vii"), (None, "lettura vii"));
+        assert_eq!(leading_index("2015"), (None, "2015"));
+    }
+
+    #[test]
+    fn test_similar_titles() {
+        assert!(similar_titles("lettura_i", "Lettura I"));
+        assert!(similar_titles(
+            "la città e le stelle",
+            "La citta e le stelle"
+        ));
+        assert!(similar_titles(
+            "i tre moschettieri",
+            "I tre moschettieri - Lettura I"
+        ));
+        assert!(similar_titles(
+            "lettura i - d'artagnan",
+            "Lettura I: D'Artagnan a Parigi"
+        ));
+        assert!(!similar_titles("lettura i", "Lettura II"));
+        assert!(!similar_titles(
+            "il conte di montecristo",
+            "I tre moschettieri"
+        ));
+        assert!(!similar_titles("", "Lettura I"));
+        assert!(similar_titles("lettura__i", "Lettura I"));
+        assert!(similar_titles("L'isola del tesoro", "L’isola del tesoro"));
+        // A word cut short by an older length limit.
+        assert!(similar_titles(
+            "i tre moschettieri lettura dodicesim",
+            "I tre moschettieri - Lettura dodicesima"
+        ));
+        // Parts are told apart, however long the title.
+        assert!(!similar_titles(
+            "i tre moschettieri lettura 12",
+            "I tre moschettieri - Lettura 13"
+        ));
+        assert!(!similar_titles("lettura", "Letture"));
+        assert!(!similar_titles("2015", "Lettura I"));
+    }
+
+    #[test]
+    fn test_find_file() {
+        let files: Vec<PathBuf> = [
+            "01 - lettura i.mp3",
+            "02 - lettura ii.mp3",
+            "05 - lettura v.mp3",
+            "07 - lettura v.mp3",
+            "notes.mp3",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let mut claimed = HashSet::new();
+        let find = |claimed: &HashSet<PathBuf>, number, title| {
+            find_file(&files, claimed, number, title).map(|file| file.to_str().unwrap())
+        };
+
+        assert_eq!(
+            find(&claimed, Some(2), "Lettura II"),
+            Some("02 - lettura ii.mp3")
+        );
+        // Renumbered since the download, found by its title alone.
+        assert_eq!(
+            find(&claimed, Some(3), "Lettura I"),
+            Some("01 - lettura i.mp3")
+        );
+        // Two files have the title: only the number tells them apart.
+        assert_eq!(
+            find(&claimed, Some(7), "Lettura V"),
+            Some("07 - lettura v.mp3")
+        );
+        assert_eq!(find(&claimed, Some(6), "Lettura V"), None);
+        claimed.insert(PathBuf::from("07 - lettura v.mp3"));
+        assert_eq!(
+            find(&claimed, Some(6), "Lettura V"),
+            Some("05 - lettura v.mp3")
+        );
+        assert_eq!(find(&claimed, Some(4), "Lettura IV"), None);
+    }
+
+    #[test]
+    fn test_find_file_named_otherwise() {
+        let files: Vec<PathBuf> = ["01_Lettura_I.mp3", "Lettura_II.mp3", "notes.mp3"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut claimed = HashSet::new();
+        assert_eq!(
+            find_file(&files, &claimed, Some(2), "Lettura II"),
+            Some(&files[1])
+        );
+        claimed.insert(files[1].clone());
+        assert_eq!(find_file(&files, &claimed, Some(2), "Lettura II"), None);
+        assert_eq!(
+            find_file(&files, &claimed, Some(1), "Lettura I"),
+            Some(&files[0])
+        );
+    }
+
+    #[test]
+    fn test_audio_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "02 - b.mp3",
+            "01 - a.M4A",
+            "01 - a.info.json",
+            "03 - c.mp3.part",
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let files = audio_files(dir.path()).unwrap();
+        assert_eq!(
+            files,
+            vec![dir.path().join("01 - a.M4A"), dir.path().join("02 - b.mp3")]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::error::RsndError;
+use reqwest::{Response, StatusCode};
+
+/// Fragments of the URLs of the placeholder assets served instead of unavailable media.
+const PLACEHOLDER_MARKERS: &[&str] = &["video_no_available", "no_available", "unavailable"];
+
+/// Responses smaller than this are placeholders rather than audio.
+const MIN_MEDIA_BYTES: u64 = 16 * 1024;
+
+/// Phrases of the pages the CDN refuses requests from abroad with, lowercase.
+const GEO_PHRASES: &[&str] = &[
+    "geoblock",
+    "geo-block",
+    "geo block",
+    "not available in your country",
+    "non disponibile nel tuo paese",
+    "only available in italy",
+    "disponibile solo in italia",
+];
+
+/// Explains why the response looks like a placeholder, if it does.
+///
+/// Other unsuccessful statuses, a 403 among them, are not a sign of geo-blocking by
+/// themselves: the caller reports them as they are, unless [`refusal_reason`] finds
+/// one in the body.
+pub fn unavailable_reason(
+    final_url: &str,
+    status: StatusCode,
+    content_type: Option<&str>,
+    content_length: Option<u64>,
+) -> Option<String> {
+    if status == StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS {
+        return Some(format!("status {}", status));
+    }
+    let lower_url = final_url.to_lowercase();
+    if let Some(marker) = PLACEHOLDER_MARKERS
+        .iter()
+        .find(|marker| lower_url.contains(*marker))
+    {
+        return Some(format!(
+            "redirected to the {} placeholder {}",
+            marker, final_url
+        ));
+    }
+    if !status.is_success() {
+        return None;
+    }
+    if let Some(content_type) = content_type {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        if matches!(mime, "text/html" | "text/xml" | "application/xml") {
+            return Some(format!("served {} instead of audio", mime));
+        }
+    }
+    if let Some(length) = content_length.filter(|length| *length < MIN_MEDIA_BYTES) {
+        return Some(format!("suspiciously small response of {} bytes", length));
+    }
+    None
+}
+
+/// Explains why the body of a refused request tells of geo-blocking, if it does.
+pub fn refusal_reason(status: StatusCode, body: &str) -> Option<String> {
+    let lower = body.to_lowercase();
+    let phrase = GEO_PHRASES.iter().find(|phrase| lower.contains(*phrase))?;
+    Some(format!("status {}, the page says {:?}", status, phrase))
+}
+
+/// Checks the response of the relinker and returns a [`RsndError::GeoBlocked`] error for
+/// placeholders.
+pub fn check_response(response: &Response) -> Result<(), RsndError> {
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    match unavailable_reason(
+        response.url().as_str(),
+        response.status(),
+        content_type,
+        response.content_length(),
+    ) {
+        Some(reason) => Err(RsndError::GeoBlocked {
+            reason,
+            status: response.status(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEDIA_URL: &str = "https://creativemedia1-rai-it.akamaized.net/podcastcdn/lettura-1.mp3";
+
+    #[test]
+    fn test_unavailable_reason() {
+        assert_eq!(
+            unavailable_reason(
+                MEDIA_URL,
+                StatusCode::OK,
+                Some("audio/mpeg"),
+                Some(18_000_000)
+            ),
+            None
+        );
+        assert_eq!(
+            unavailable_reason(MEDIA_URL, StatusCode::OK, None, None),
+            None
+        );
+        assert_eq!(
+            unavailable_reason(
+                MEDIA_URL,
+                StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                None,
+                None
+            )
+            .as_deref(),
+            Some("status 451 Unavailable For Legal Reasons")
+        );
+        // A 403 alone is left to the caller, whatever the page served with it.
+        assert_eq!(
+            unavailable_reason(
+                MEDIA_URL,
+                StatusCode::FORBIDDEN,
+                Some("text/html"),
+                Some(300)
+            ),
+            None
+        );
+        assert!(unavailable_reason(
+            "https://download.rai.it/video_no_available.mp4",
+            StatusCode::OK,
+            Some("video/mp4"),
+            Some(300_000)
+        )
+        .unwrap()
+        .contains("video_no_available"));
+        assert_eq!(
+            unavailable_reason(
+                MEDIA_URL,
+                StatusCode::OK,
+                Some("text/xml; charset=utf-8"),
+                None
+            )
+            .as_deref(),
+            Some("served text/xml instead of audio")
+        );
+        assert_eq!(
+            unavailable_reason(MEDIA_URL, StatusCode::OK, Some("audio/mpeg"), Some(512)).as_deref(),
+            Some("suspiciously small response of 512 bytes")
+        );
+    }
+
+    #[test]
+    fn test_refusal_reason() {
+        assert_eq!(
+            refusal_reason(
+                StatusCode::FORBIDDEN,
+                "<h1>Contenuto non disponibile nel tuo paese</h1>"
+            )
+            .as_deref(),
+            Some("status 403 Forbidden, the page says \"non disponibile nel tuo paese\"")
+        );
+        assert_eq!(
+            refusal_reason(StatusCode::FORBIDDEN, "<h1>Access denied</h1>"),
+            None
+        );
+    }
+}
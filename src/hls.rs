@@ -0,0 +1,306 @@
+use crate::error::RsndError;
+use crate::fetcher::Fetcher;
+use crate::relinker::Container;
+use crate::{errln, outln};
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use std::path::{Path, PathBuf};
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// Attempts made for each segment before giving up.
+const SEGMENT_ATTEMPTS: usize = 3;
+
+/// Returns whether the Content-Type is the one of an HLS playlist.
+pub fn is_playlist(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.eq_ignore_ascii_case("application/vnd.apple.mpegurl")
+        || mime.eq_ignore_ascii_case("application/x-mpegurl")
+        || mime.eq_ignore_ascii_case("audio/mpegurl")
+}
+
+/// A parsed HLS playlist, with absolute URLs.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Playlist {
+    /// A master playlist, listing variants with their bandwidth.
+    Master(Vec<(u64, String)>),
+    /// A media playlist, listing its segments in order.
+    Media(Vec<String>),
+}
+
+/// Parses an HLS playlist, resolving its URIs against the playlist URL.
+pub fn parse_playlist(text: &str, base: &Url) -> Result<Playlist> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(anyhow!("Not an HLS playlist: missing #EXTM3U header"));
+    }
+
+    let mut variants = Vec::new();
+    let mut segments = Vec::new();
+    let mut bandwidth = None;
+    for line in lines {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            bandwidth = Some(
+                attributes
+                    .split(',')
+                    .find_map(|attribute| attribute.strip_prefix("BANDWIDTH="))
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0),
+            );
+        } else if !line.starts_with('#') {
+            let uri = base
+                .join(line)
+                .with_context(|| format!("Invalid playlist URI: {}", line))?
+                .to_string();
+            match bandwidth.take() {
+                Some(bandwidth) => variants.push((bandwidth, uri)),
+                None => segments.push(uri),
+            }
+        }
+    }
+
+    if !variants.is_empty() {
+        Ok(Playlist::Master(variants))
+    } else if !segments.is_empty() {
+        Ok(Playlist::Media(segments))
+    } else {
+        Err(anyhow!("Empty HLS playlist"))
+    }
+}
+
+async fn fetch_text<F: Fetcher>(client: &F, url: &str) -> Result<String> {
+    let response = client
+        .get_stream(url)
+        .await
+        .with_context(|| format!("Failed to fetch playlist: {}", url))?;
+    if !response.status().is_success() {
+        return Err(RsndError::status(url, &response))
+            .with_context(|| format!("Failed to fetch playlist: {}", url));
+    }
+    response
+        .text()
+        .await
+        .with_context(|| format!("Failed to read playlist: {}", url))
+}
+
+/// Returns the segments of the playlist at `url`, picking the variant with the highest
+/// bandwidth from a master playlist, and the container they form once concatenated.
+pub async fn media_segments<F: Fetcher>(client: &F, url: &str) -> Result<(Vec<String>, Container)> {
+    let (_, segments, container) = media_playlist(client, url).await?;
+    Ok((segments, container))
+}
+
+/// Like `media_segments`, also returning the URL of the media playlist, which live
+/// streams keep adding segments to.
+pub async fn media_playlist<F: Fetcher>(
+    client: &F,
+    url: &str,
+) -> Result<(String, Vec<String>, Container)> {
+    let mut url = url.to_string();
+    // A master playlist points to media playlists, which cannot nest further.
+    for _ in 0..2 {
+        let base = Url::parse(&url).with_context(|| format!("Invalid playlist URL: {}", url))?;
+        match parse_playlist(&fetch_text(client, &url).await?, &base)? {
+            Playlist::Master(variants) => {
+                let (_, best) = variants
+                    .into_iter()
+                    .max_by_key(|(bandwidth, _)| *bandwidth)
+                    .expect("Master playlists have variants");
+                url = best;
+            }
+            Playlist::Media(segments) => {
+                let container = match Container::from_url(&segments[0]) {
+                    Some(Container::Aac) => Container::Aac,
+                    _ => Container::Ts,
+                };
+                return Ok((url, segments, container));
+            }
+        }
+    }
+    Err(anyhow!("Nested master playlists: {}", url))
+}
+
+/// Path of the partial output, which is renamed to `output_path` once complete.
+fn partial_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// Path of the file recording how many segments the partial output holds.
+fn state_path(output_path: &Path) -> PathBuf {
+    let mut name = partial_path(output_path).into_os_string();
+    name.push(".segments");
+    PathBuf::from(name)
+}
+
+/// Reads the number of complete segments and their total size from the state file.
+fn read_state(output_path: &Path) -> Option<(usize, u64)> {
+    let state = std::fs::read_to_string(state_path(output_path)).ok()?;
+    let (segments, bytes) = state.trim().split_once(' ')?;
+    Some((segments.parse().ok()?, bytes.parse().ok()?))
+}
+
+/// Fetches the segments a media playlist lists now.
+pub async fn refresh_segments<F: Fetcher>(client: &F, url: &str) -> Result<Vec<String>> {
+    let base = Url::parse(url).with_context(|| format!("Invalid playlist URL: {}", url))?;
+    match parse_playlist(&fetch_text(client, url).await?, &base)? {
+        Playlist::Media(segments) => Ok(segments),
+        Playlist::Master(_) => Err(anyhow!("Expected a media playlist: {}", url)),
+    }
+}
+
+pub async fn fetch_segment<F: Fetcher>(
+    client: &F,
+    url: &str,
+    accept_any_content: bool,
+) -> Result<Vec<u8>> {
+    let response = client
+        .get_stream(url)
+        .await
+        .with_context(|| format!("Failed to fetch segment: {}", url))?;
+    if !response.status().is_success() {
+        return Err(RsndError::status(url, &response))
+            .with_context(|| format!("Failed to fetch segment: {}", url));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let data = response.bytes().await?.to_vec();
+    if !accept_any_content {
+        crate::sniff::check_audio(content_type.as_deref(), &data[..data.len().min(512)])
+            .with_context(|| format!("Refusing to save segment {}", url))?;
+    }
+    Ok(data)
+}
+
+/// Downloads the segments in order and concatenates them into `output_path`.
+///
+/// Segments are appended to a `.part` file; an interrupted download resumes from the
+/// first segment that was not completely written. Returns the size of the output.
+pub async fn download_segments<F: Fetcher>(
+    client: &F,
+    segments: &[String],
+    output_path: &Path,
+    accept_any_content: bool,
+) -> Result<u64> {
+    let partial = partial_path(output_path);
+    let (mut done, mut bytes) = match read_state(output_path) {
+        Some((done, bytes)) if partial.exists() && done <= segments.len() => (done, bytes),
+        _ => (0, 0),
+    };
+    if done > 0 {
+        outln!("Resuming from segment {}/{}", done + 1, segments.len());
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&partial)
+        .await
+        .with_context(|| format!("Failed to open file: {}", partial.display()))?;
+    // Drop whatever was written of a segment that did not complete.
+    file.set_len(bytes).await?;
+    file.seek(std::io::SeekFrom::End(0)).await?;
+
+    // The size of the whole stream is only known at the end.
+    let mut progress = crate::observer::Progress::new(output_path, None);
+    for (position, url) in segments.iter().enumerate().skip(done) {
+        let mut attempt = 1;
+        let data = loop {
+            match fetch_segment(client, url, accept_any_content).await {
+                Ok(data) => break data,
+                Err(err) if attempt < SEGMENT_ATTEMPTS => {
+                    errln!("Warning: retrying segment {}: {:#}", position + 1, err);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        file.write_all(&data)
+            .await
+            .with_context(|| format!("Failed to write to file: {}", partial.display()))?;
+        file.flush().await?;
+        done += 1;
+        bytes += data.len() as u64;
+        progress.update(bytes);
+        std::fs::write(state_path(output_path), format!("{} {}", done, bytes))
+            .context("Failed to record the download progress")?;
+        crate::output::print_progress(&format!("Segment {}/{}", done, segments.len()));
+    }
+    outln!();
+
+    std::fs::rename(&partial, output_path)
+        .with_context(|| format!("Failed to rename {}", partial.display()))?;
+    let _ = std::fs::remove_file(state_path(output_path));
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_master_playlist() {
+        let base = Url::parse("https://cdn.rai.it/hls/lettura-1/master.m3u8").unwrap();
+        let text = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"\n\
+            low/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=128000,CODECS=\"mp4a.40.2\"\n\
+            https://cdn2.rai.it/hls/lettura-1/high/index.m3u8\n";
+        assert_eq!(
+            parse_playlist(text, &base).unwrap(),
+            Playlist::Master(vec![
+                (
+                    64000,
+                    "https://cdn.rai.it/hls/lettura-1/low/index.m3u8".to_string()
+                ),
+                (
+                    128000,
+                    "https://cdn2.rai.it/hls/lettura-1/high/index.m3u8".to_string()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_media_playlist() {
+        let base = Url::parse("https://cdn.rai.it/hls/lettura-1/high/index.m3u8").unwrap();
+        let text = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n\
+            #EXTINF:10.0,\nsegment0.ts\n\
+            #EXTINF:10.0,\n/hls/lettura-1/high/segment1.ts\n\
+            #EXT-X-ENDLIST\n";
+        assert_eq!(
+            parse_playlist(text, &base).unwrap(),
+            Playlist::Media(vec![
+                "https://cdn.rai.it/hls/lettura-1/high/segment0.ts".to_string(),
+                "https://cdn.rai.it/hls/lettura-1/high/segment1.ts".to_string(),
+            ])
+        );
+        assert!(parse_playlist("<html>", &base).is_err());
+        assert!(parse_playlist("#EXTM3U\n#EXT-X-ENDLIST\n", &base).is_err());
+    }
+
+    #[test]
+    fn test_is_playlist() {
+        assert!(is_playlist("application/vnd.apple.mpegurl"));
+        assert!(is_playlist("application/x-mpegURL; charset=utf-8"));
+        assert!(!is_playlist("audio/mpeg"));
+    }
+
+    #[test]
+    fn test_resume_state() {
+        let folder = tempfile::tempdir().unwrap();
+        let output_path = folder.path().join("001 - lettura i.ts");
+        assert_eq!(read_state(&output_path), None);
+        std::fs::write(state_path(&output_path), "3 1024").unwrap();
+        assert_eq!(read_state(&output_path), Some((3, 1024)));
+        assert_eq!(
+            state_path(&output_path),
+            folder.path().join("001 - lettura i.ts.part.segments")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Builds an ID3v2.3 tag holding the given text frames, like `("TIT2", "Title")`.
+///
+/// Text is encoded as UTF-16 with a byte order mark, which every player reads and which
+/// keeps accented titles intact. Empty values are left out.
+pub fn tag(frames: &[(&str, &str)]) -> Vec<u8> {
+    build(3, &text_frames(frames))
+}
+
+/// Builds the tag of [`tag`] on top of the `old` one: the frames given replace those of
+/// the same id, and the others, like the artwork or comments, are kept. The tag keeps the
+/// version of the old one, 2.3 or 2.4.
+///
+/// Fails on tags this can't take apart without losing data: ID3v2.2, and tags with
+/// unsynchronisation.
+pub fn merge(old: &[u8], frames: &[(&str, &str)]) -> Result<Vec<u8>> {
+    if old.is_empty() {
+        return Ok(tag(frames));
+    }
+    let (version, old) = parse(old)?;
+    let mut new = text_frames(frames);
+    if version == 4 {
+        new = date_frame(new);
+    }
+    let mut merged: Vec<_> = old
+        .into_iter()
+        .filter(|frame| {
+            // The year and day of 2.3 stand for the date of 2.4.
+            let replaced = |id: &[u8; 4]| {
+                frame.id == *id
+                    || (id == b"TYER" || id == b"TDAT") && frame.id == *b"TDRC"
+                    || id == b"TDRC" && (frame.id == *b"TYER" || frame.id == *b"TDAT")
+            };
+            !new.iter().any(|new| replaced(&new.id))
+        })
+        .collect();
+    merged.append(&mut new);
+    Ok(build(version, &merged))
+}
+
+/// A frame as stored in the tag: the body is kept as is, whatever its content.
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    id: [u8; 4],
+    flags: [u8; 2],
+    body: Vec<u8>,
+}
+
+/// Encodes the text frames as UTF-16 with a byte order mark, leaving out empty values.
+fn text_frames(frames: &[(&str, &str)]) -> Vec<Frame> {
+    frames
+        .iter()
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(id, text)| {
+            let id = id
+                .as_bytes()
+                .try_into()
+                .expect("ID3 frame ids have four characters");
+            let mut body = vec![1, 0xFF, 0xFE];
+            for unit in text.encode_utf16() {
+                body.extend_from_slice(&unit.to_le_bytes());
+            }
+            Frame {
+                id,
+                flags: [0, 0],
+                body,
+            }
+        })
+        .collect()
+}
+
+/// Turns the year and day frames of 2.3 into the date frame of 2.4, `2015-06-03`.
+fn date_frame(frames: Vec<Frame>) -> Vec<Frame> {
+    let text = |id: &[u8; 4]| {
+        let frame = frames.iter().find(|frame| frame.id == *id)?;
+        let units: Vec<u16> = frame.body[3..]
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16(&units).ok()
+    };
+    let date = match (text(b"TYER"), text(b"TDAT")) {
+        (Some(year), Some(day)) if day.len() == 4 && day.is_ascii() => {
+            format!("{}-{}-{}", year, &day[2..], &day[..2])
+        }
+        (Some(year), _) => year,
+        (None, _) => return frames,
+    };
+    let mut frames: Vec<_> = frames
+        .into_iter()
+        .filter(|frame| frame.id != *b"TYER" && frame.id != *b"TDAT")
+        .collect();
+    frames.extend(text_frames(&[("TDRC", &date)]));
+    frames
+}
+
+/// Splits an ID3v2.3 or 2.4 tag into its version and frames, padding left out.
+fn parse(tag: &[u8]) -> Result<(u8, Vec<Frame>)> {
+    let len = tag_len(tag);
+    if len == 0 || len > tag.len() {
+        bail!("Not an ID3v2 tag");
+    }
+    let version = tag[3];
+    if version != 3 && version != 4 {
+        bail!("ID3v2.{} tags are not supported", version);
+    }
+    // Bit 7 of the flags announces unsynchronisation, bit 6 an extended header.
+    if tag[5] & 0x80 != 0 {
+        bail!("ID3 tags with unsynchronisation are not supported");
+    }
+    let end = len - if tag[5] & 0x10 != 0 { 10 } else { 0 };
+    let mut at = 10;
+    if tag[5] & 0x40 != 0 {
+        let size = tag.get(10..14).context("Truncated ID3 extended header")?;
+        // The size of 2.3 leaves itself out, the one of 2.4 doesn't.
+        at += match version {
+            3 => 4 + u32::from_be_bytes(size.try_into()?) as usize,
+            _ => unsyncsafe(size),
+        };
+    }
+
+    let mut frames = Vec::new();
+    while at + 10 <= end && tag[at] != 0 {
+        let header = &tag[at..at + 10];
+        let size = match version {
+            3 => u32::from_be_bytes(header[4..8].try_into()?) as usize,
+            _ => unsyncsafe(&header[4..8]),
+        };
+        let body = tag
+            .get(at + 10..at + 10 + size)
+            .filter(|_| at + 10 + size <= end)
+            .context("Truncated ID3 frame")?;
+        frames.push(Frame {
+            id: header[..4].try_into()?,
+            flags: header[8..10].try_into()?,
+            body: body.to_vec(),
+        });
+        at += 10 + size;
+    }
+    Ok((version, frames))
+}
+
+/// Builds a tag of the given minor version out of its frames.
+fn build(version: u8, frames: &[Frame]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for frame in frames {
+        let size = frame.body.len() as u32;
+        body.extend_from_slice(&frame.id);
+        match version {
+            3 => body.extend_from_slice(&size.to_be_bytes()),
+            _ => body.extend_from_slice(&syncsafe(size)),
+        }
+        body.extend_from_slice(&frame.flags);
+        body.extend_from_slice(&frame.body);
+    }
+    let mut tag = vec![b'I', b'D', b'3', version, 0, 0];
+    tag.extend_from_slice(&syncsafe(body.len() as u32));
+    tag.extend_from_slice(&body);
+    tag
+}
+
+/// Returns the length of the ID3v2 tag the data starts with, header and footer
+/// included, or 0 when it has none.
+pub fn tag_len(head: &[u8]) -> usize {
+    if head.len() < 10 || &head[..3] != b"ID3" {
+        return 0;
+    }
+    let size = unsyncsafe(&head[6..10]);
+    // Bit 4 of the flags announces a footer, a copy of the header.
+    let footer = if head[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+/// Returns the ID3v2 tag the file starts with, empty when it has none.
+pub fn read_tag(path: &Path) -> Result<Vec<u8>> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut head = [0; 10];
+    let len = match file.read_exact(&mut head) {
+        Ok(()) => tag_len(&head),
+        Err(_) => 0,
+    };
+    let mut tag = vec![0; len];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut tag)
+        .with_context(|| format!("Truncated ID3 tag in {}", path.display()))?;
+    Ok(tag)
+}
+
+/// Puts the tag at the start of the file, in place of the one it has, keeping its
+/// modification time. Returns whether the file changed.
+pub fn write_tag(path: &Path, tag: &[u8]) -> Result<bool> {
+    let old = read_tag(path)?;
+    if old == tag {
+        return Ok(false);
+    }
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let modified = file.metadata()?.modified()?;
+    file.seek(SeekFrom::Start(old.len() as u64))?;
+
+    let permissions = file.metadata()?.permissions();
+    (|| -> std::io::Result<()> {
+        let mut partial = crate::atomic::Partial::create(path)?;
+        let out = partial.file();
+        out.write_all(tag)?;
+        std::io::copy(&mut file, out)?;
+        out.set_modified(modified)?;
+        out.set_permissions(permissions)?;
+        partial.persist()
+    })()
+    .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+/// Encodes the size with 7 bits per byte, as the ID3 header wants it.
+fn syncsafe(size: u32) -> [u8; 4] {
+    [
+        (size >> 21 & 0x7F) as u8,
+        (size >> 14 & 0x7F) as u8,
+        (size >> 7 & 0x7F) as u8,
+        (size & 0x7F) as u8,
+    ]
+}
+
+/// Decodes a size of 7 bits per byte.
+fn unsyncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |size, byte| size << 7 | (*byte & 0x7F) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag() {
+        let tag = tag(&[("TPE1", "Radio3"), ("TALB", "")]);
+        assert_eq!(&tag[..6], b"ID3\x03\x00\x00");
+        // One frame: 10 bytes of header, encoding, BOM and 6 UTF-16 units.
+        assert_eq!(&tag[6..10], &[0, 0, 0, 25]);
+        assert_eq!(&tag[10..14], b"TPE1");
+        assert_eq!(&tag[14..18], &[0, 0, 0, 15]);
+        assert_eq!(&tag[20..25], &[1, 0xFF, 0xFE, b'R', 0]);
+        assert_eq!(tag.len(), 35);
+    }
+
+    #[test]
+    fn test_tag_len() {
+        let tag = tag(&[("TIT2", "Lettura I")]);
+        assert_eq!(tag_len(&tag), tag.len());
+        assert_eq!(tag_len(b"ID3\x04\x00\x10\x00\x00\x01\x00"), 148);
+        assert_eq!(tag_len(b"\xFF\xFB\x90\x00"), 0);
+    }
+
+    #[test]
+    fn test_merge() {
+        let artwork = Frame {
+            id: *b"APIC",
+            flags: [0, 0],
+            body: b"\0image/jpeg\0\x03\0\xFF\xD8\xFF".to_vec(),
+        };
+        let mut old = text_frames(&[("TIT2", "lettura 1"), ("TRCK", "1")]);
+        old.push(artwork.clone());
+        let old = build(3, &old);
+        let frames = [("TIT2", "Lettura I"), ("TALB", "")];
+        let merged = merge(&old, &frames).unwrap();
+        let (version, parsed) = parse(&merged).unwrap();
+        assert_eq!(version, 3);
+        let mut expected = text_frames(&[("TRCK", "1")]);
+        expected.push(artwork.clone());
+        expected.extend(text_frames(&[("TIT2", "Lettura I")]));
+        assert_eq!(parsed, expected);
+        assert_eq!(merge(&merged, &frames).unwrap(), merged);
+        assert_eq!(merge(&[], &frames).unwrap(), tag(&frames));
+
+        // Tags of 2.4 stay so, with the date in its frame of 2.4.
+        let old = build(4, std::slice::from_ref(&artwork));
+        let merged = merge(&old, &[("TYER", "2015"), ("TDAT", "0306")]).unwrap();
+        let (version, parsed) = parse(&merged).unwrap();
+        assert_eq!(version, 4);
+        let mut expected = vec![artwork];
+        expected.extend(text_frames(&[("TDRC", "2015-06-03")]));
+        assert_eq!(parsed, expected);
+
+        assert!(merge(b"ID3\x02\x00\x00\x00\x00\x00", &frames).is_err());
+        assert!(merge(b"ID3\x03\x00\x80\x00\x00\x00", &frames).is_err());
+        assert!(merge(b"ID3\x03\x00\x00\x00\x00\x20TIT2\x00\x00\x01\x00", &frames).is_err());
+    }
+
+    #[test]
+    fn test_write_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("01 - lettura i.mp3");
+        std::fs::write(&path, b"\xFF\xFBaudio").unwrap();
+        let first = tag(&[("TIT2", "Lettura I")]);
+        assert!(write_tag(&path, &first).unwrap());
+        assert!(!write_tag(&path, &first).unwrap());
+
+        // A new tag replaces the old one instead of piling up.
+        let second = tag(&[("TIT2", "Lettura I"), ("TALB", "I tre moschettieri")]);
+        assert!(write_tag(&path, &second).unwrap());
+        let mut expected = second.clone();
+        expected.extend_from_slice(b"\xFF\xFBaudio");
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        assert!(!dir.path().join("01 - lettura i.mp3.part").exists());
+    }
+
+    #[test]
+    fn test_syncsafe() {
+        assert_eq!(syncsafe(127), [0, 0, 0, 127]);
+        assert_eq!(syncsafe(128), [0, 0, 1, 0]);
+        assert_eq!(syncsafe(0x0FFF_FFFF), [0x7F, 0x7F, 0x7F, 0x7F]);
+    }
+}
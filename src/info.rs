@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Version of the `.info.json` schema, raised when a field changes meaning or goes away.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Everything known about a downloaded episode, saved next to it by `--write-info-json`.
+///
+/// Fields that are unknown are written as `null` rather than left out, so that every
+/// sidecar has the same keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Info {
+    pub schema: u32,
+    /// The episode `uniquename`, or its `id` when missing.
+    pub id: Option<String>,
+    pub title: String,
+    /// Part number read from the title, as in "Lettura XIV".
+    pub part: Option<usize>,
+    pub show: Option<String>,
+    pub date: Option<NaiveDate>,
+    /// Duration in seconds, as the metadata gives it.
+    pub duration: Option<u64>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    /// URL of the episode JSON the rest was read from.
+    pub metadata_url: String,
+    /// The audio URL of the metadata, before the relinker.
+    pub audio_url: String,
+    /// The CDN URL the file was downloaded from.
+    pub media_url: Option<String>,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// CRC-32 of the file, as 8 hex digits.
+    pub crc32: String,
+    /// When the download finished, unknown for files tagged by `retag` without a sidecar.
+    pub downloaded_at: Option<DateTime<Utc>>,
+    /// Seconds from the request to the last byte of the download, unknown for files
+    /// tagged by `retag`.
+    pub elapsed_secs: Option<f64>,
+    /// Average and peak (over a second) download speed, in bytes per second.
+    pub throughput: Option<f64>,
+    pub peak_throughput: Option<f64>,
+}
+
+/// Returns the path of the sidecar of the audio file, `<basename>.info.json`.
+pub fn sidecar_path(audio: &Path) -> PathBuf {
+    let mut name = audio.file_stem().unwrap_or_default().to_owned();
+    name.push(".info.json");
+    audio.with_file_name(name)
+}
+
+/// Returns the CRC-32 of the file, as 8 hex digits.
+pub fn checksum(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:08x}", hasher.finalize()))
+}
+
+/// Reads the sidecar of the audio file, if it has a readable one.
+pub fn read(audio: &Path) -> Option<Info> {
+    let json = std::fs::read_to_string(sidecar_path(audio)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Writes the sidecar of the audio file, replacing the one of an earlier download.
+pub fn write(audio: &Path, info: &Info) -> Result<PathBuf> {
+    let path = sidecar_path(audio);
+    let json = serde_json::to_string_pretty(info)?;
+    std::fs::write(&path, json + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("/music/01 - lettura i.mp3")),
+            PathBuf::from("/music/01 - lettura i.info.json")
+        );
+    }
+
+    #[test]
+    fn test_write() -> Result<()> {
+        let dir = TempDir::new()?;
+        let audio = dir.path().join("01 - lettura i.mp3");
+        std::fs::write(&audio, b"123456789")?;
+        let info = Info {
+            schema: SCHEMA_VERSION,
+            id: Some("lettura-1".to_string()),
+            title: "Lettura I".to_string(),
+            part: Some(1),
+            show: None,
+            date: NaiveDate::from_ymd_opt(2015, 6, 1),
+            duration: Some(1800),
+            description: None,
+            image: None,
+            metadata_url: "https://www.raiplaysound.it/audio/lettura-1.json".to_string(),
+            audio_url: "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=1".to_string(),
+            media_url: None,
+            size: 9,
+            crc32: checksum(&audio)?,
+            downloaded_at: DateTime::from_timestamp(1_700_000_000, 0),
+            elapsed_secs: Some(0.5),
+            throughput: Some(18.0),
+            peak_throughput: Some(18.0),
+        };
+        // The check value of CRC-32.
+        assert_eq!(info.crc32, "cbf43926");
+
+        let path = write(&audio, &info)?;
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        assert_eq!(written["schema"], 1);
+        assert_eq!(written["part"], 1);
+        assert_eq!(written["date"], "2015-06-01");
+        assert_eq!(written["show"], serde_json::Value::Null);
+        assert_eq!(written["downloaded_at"], "2023-11-14T22:13:20Z");
+        assert_eq!(read(&audio), Some(info));
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::format_size;
+use crate::variant::Variant;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// Width used when the terminal size cannot be detected.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Minimum width of the title column, even on very narrow terminals.
+const MIN_TITLE_WIDTH: usize = 10;
+
+/// A single row of the episode table.
+#[derive(Debug, Serialize)]
+pub struct EpisodeRow {
+    pub index: usize,
+    pub date: Option<NaiveDate>,
+    pub duration: Option<String>,
+    pub title: String,
+    /// Size in bytes of the file, or of the audio the CDN advertises, when known.
+    pub size: Option<u64>,
+    /// Part number read from the title, which only the JSON output shows.
+    pub part: Option<usize>,
+    pub present: bool,
+    /// Renditions the metadata lists, which only the JSON output shows.
+    pub variants: Vec<Variant>,
+}
+
+/// Returns the width of the terminal, falling back to `$COLUMNS` and then to 80.
+pub fn terminal_width() -> usize {
+    if let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size() {
+        return width as usize;
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Shortens the text to at most `width` characters, marking the cut with an ellipsis.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Renders the rows as an aligned table that fits in the given width.
+pub fn render_table(rows: &[EpisodeRow], width: usize) -> String {
+    let index_width = rows
+        .iter()
+        .map(|row| row.index.to_string().len())
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    // Index, date, duration, size and status columns plus the separating spaces.
+    let fixed_width = index_width + 10 + 8 + 8 + 7 + 10;
+    let title_width = width.saturating_sub(fixed_width).max(MIN_TITLE_WIDTH);
+
+    let mut table = format!(
+        "{:>index_width$}  {:<10}  {:<8}  {:>8}  {:<7}  {}\n",
+        "#", "DATE", "DURATION", "SIZE", "STATUS", "TITLE"
+    );
+    for row in rows {
+        let date = row
+            .date
+            .map_or_else(|| "-".to_string(), |date| date.to_string());
+        // Sizes left unprobed are unknown rather than missing.
+        let size = row.size.map_or_else(|| "?".to_string(), format_size);
+        table.push_str(&format!(
+            "{:>index_width$}  {:<10}  {:<8}  {:>8}  {:<7}  {}\n",
+            row.index,
+            date,
+            row.duration.as_deref().unwrap_or("-"),
+            size,
+            if row.present { "present" } else { "missing" },
+            truncate(&row.title, title_width)
+        ));
+    }
+    table
+}
+
+/// An episode row together with the show it was listed from.
+#[derive(Debug, Serialize)]
+pub struct ListedRow<'a> {
+    pub show: &'a str,
+    #[serde(flatten)]
+    pub row: &'a EpisodeRow,
+}
+
+/// Renders the rows of every show as one JSON array, for `--list --json`.
+pub fn render_json(listings: &[(String, Vec<EpisodeRow>)]) -> serde_json::Result<String> {
+    let rows: Vec<ListedRow> = listings
+        .iter()
+        .flat_map(|(show, rows)| rows.iter().map(move |row| ListedRow { show, row }))
+        .collect();
+    serde_json::to_string_pretty(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(index: usize, date: Option<&str>, title: &str) -> EpisodeRow {
+        EpisodeRow {
+            index,
+            date: date.map(|date| date.parse().unwrap()),
+            duration: Some("00:19:15".to_string()),
+            title: title.to_string(),
+            size: (index >= 10).then_some(18_400_000),
+            part: crate::part::part_number(title),
+            present: index >= 10,
+            variants: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_render_table_truncates_titles() {
+        let rows = vec![
+            row(9, Some("2015-06-12"), "I tre moschettieri - Lettura I"),
+            row(10, None, "Short"),
+        ];
+        let table = render_table(&rows, 70);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            " #  DATE        DURATION      SIZE  STATUS   TITLE"
+        );
+        assert_eq!(
+            lines[1],
+            " 9  2015-06-12  00:19:15         ?  missing  I tre moschettieri - Let…"
+        );
+        assert_eq!(
+            lines[2],
+            "10  -           00:19:15   17.5 MB  present  Short"
+        );
+        assert!(lines.iter().all(|line| line.chars().count() <= 70));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let mut first = row(1, Some("2015-06-12"), "Lettura I");
+        first.variants = vec![Variant::from_url("https://example.com/a.mp3")];
+        let listings = vec![
+            ("https://example.com/a".to_string(), vec![first]),
+            ("https://example.com/b".to_string(), vec![]),
+            (
+                "https://example.com/c".to_string(),
+                vec![row(2, None, "Lettura II")],
+            ),
+        ];
+        let json: serde_json::Value =
+            serde_json::from_str(&render_json(&listings).unwrap()).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[1]["show"], "https://example.com/c");
+        assert_eq!(json[1]["index"], 2);
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "show": "https://example.com/a",
+                "index": 1,
+                "date": "2015-06-12",
+                "duration": "00:19:15",
+                "title": "Lettura I",
+                "size": null,
+                "part": 1,
+                "present": false,
+                "variants": [{"url": "https://example.com/a.mp3", "format": "mp3", "bitrate": null}],
+            })
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use crate::dump;
+use crate::fetcher::Fetcher;
+use crate::relinker::Resolved;
+use crate::resolutions;
+use anyhow::Result;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+/// Episodes resolved ahead of the one being downloaded.
+pub const DEPTH: usize = 2;
+
+/// Resolves the audio of the next episodes through the relinker in the background, so
+/// that their downloads start as soon as the current one ends. Downloads themselves
+/// still run one at a time, in order.
+#[derive(Debug, Default)]
+pub struct Lookahead {
+    tasks: HashMap<usize, JoinHandle<Result<Resolved>>>,
+}
+
+impl Lookahead {
+    /// Starts resolving `url` for the episode numbered `index`, whose metadata is at
+    /// `metadata_url`, unless already started.
+    pub fn prefetch<F: Fetcher>(
+        &mut self,
+        client: &F,
+        index: usize,
+        metadata_url: &str,
+        url: &str,
+    ) {
+        self.tasks.entry(index).or_insert_with(|| {
+            let (client, metadata_url, url) =
+                (client.clone(), metadata_url.to_string(), url.to_string());
+            tokio::spawn(dump::inherit(async move {
+                resolutions::resolve(&client, &metadata_url, &url).await
+            }))
+        });
+    }
+
+    /// Waits for the resolution of the episode numbered `index`, if it was prefetched.
+    ///
+    /// A failed resolution is dropped: the download resolves again, with retries.
+    pub async fn take(&mut self, index: usize) -> Option<Resolved> {
+        let task = self.tasks.remove(&index)?;
+        match task.await {
+            Ok(Ok(resolved)) => Some(resolved),
+            Ok(Err(err)) => {
+                log::debug!("Prefetching episode {} failed: {:#}", index, err);
+                None
+            }
+            Err(err) => {
+                log::debug!("Prefetching episode {} failed: {}", index, err);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Lookahead {
+    /// Stops the resolutions nobody will wait for, as after `--fail-fast` or `--limit`.
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::MockFetcher;
+    use reqwest::StatusCode;
+
+    const METADATA: &str = "https://www.raiplaysound.it/audio/2015/06/lettura-1.json";
+    const RELINKER: &str = "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=1";
+
+    #[tokio::test]
+    async fn test_lookahead() {
+        let client = MockFetcher::default().fixture(
+            RELINKER,
+            StatusCode::OK,
+            "audio/mpeg",
+            "",
+            Some("https://cdn.rai.it/lettura-1.mp3"),
+        );
+        let mut lookahead = Lookahead::default();
+        // Prefetching twice sends one request.
+        lookahead.prefetch(&client, 1, METADATA, RELINKER);
+        lookahead.prefetch(&client, 1, METADATA, RELINKER);
+
+        let resolved = lookahead.take(1).await.unwrap();
+        assert_eq!(resolved.url, "https://cdn.rai.it/lettura-1.mp3");
+        assert!(lookahead.take(1).await.is_none());
+        assert!(lookahead.take(2).await.is_none());
+        assert_eq!(
+            client
+                .requests()
+                .iter()
+                .filter(|request| request.contains("cont=1"))
+                .count(),
+            1
+        );
+    }
+}
@@ -1,308 +1,5075 @@
+mod api;
+mod archive;
+mod atomic;
+mod audio_length;
+mod batch;
+mod blocks;
+mod browser;
+mod cache;
+mod crawl;
+mod dates;
+mod dirs;
+mod dump;
+mod duration;
+mod empty;
+mod error;
+mod estimate;
+mod fallback;
+mod fetcher;
+mod filter;
+mod fuzzy;
+mod geo;
+mod hls;
+mod id3;
+mod info;
+mod list;
+mod lookahead;
+mod mirror;
+mod naming;
+mod notify;
+mod numbering;
+mod observer;
+mod order;
+mod original;
+mod output;
+mod pace;
+mod part;
+mod pick;
+mod plan;
+mod playlist;
+mod ratelimit;
+mod record;
+mod relinker;
+mod resolutions;
+mod retry;
+mod schedule;
+mod search;
+mod season;
+mod select;
+mod show;
+mod show_file;
+mod site;
+mod size;
+mod sniff;
+mod stall;
+mod state;
+mod summary;
+mod thumbnail;
+mod title;
+mod transfer;
+mod variant;
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use cache::{Cache, SharedCache};
+use chrono::NaiveDate;
+use clap::{ArgGroup, Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use error::RsndError;
+use fetcher::{Fetcher, HttpFetcher};
+use futures::StreamExt;
+use list::EpisodeRow;
+use lookahead::Lookahead;
+use naming::Naming;
+use pick::PickItem;
+use playlist::PageKind;
 use regex::Regex;
+use relinker::Container;
 use reqwest::header::HeaderMap;
-use reqwest::Client;
+use reqwest::{Client, Response};
 use scraper::{Html, Selector};
+use select::Selection;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::fs::{create_dir_all, File};
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
+use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use summary::{FailedEpisode, NewEpisodes, ShowSummary, Summary};
 use tokio::fs::File as TokioFile;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Semaphore};
+use transfer::Transfer;
+
+/// Exit status for usage and argument errors, and for unexpected failures.
+const EXIT_USAGE: i32 = 1;
+
+/// How a run ended, mapped to the process exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Every episode was processed.
+    Success,
+    /// The run stopped because of an unexpected error.
+    Error,
+    /// The show page could not be fetched.
+    PageUnavailable,
+    /// One or more episodes failed.
+    EpisodesFailed,
+    /// A dry run found nothing to download.
+    NothingToDo,
+    /// `--check` found episodes missing from the folder.
+    Incomplete,
+    /// The run was interrupted with Ctrl+C.
+    Interrupted,
+}
+
+impl Outcome {
+    /// Severity used to combine the outcomes of several shows; the most severe wins.
+    fn severity(self) -> u8 {
+        match self {
+            Outcome::NothingToDo => 0,
+            Outcome::Success => 1,
+            Outcome::Incomplete => 2,
+            Outcome::EpisodesFailed => 3,
+            Outcome::PageUnavailable => 4,
+            Outcome::Error => 5,
+            Outcome::Interrupted => 6,
+        }
+    }
+
+    /// Combines the outcomes of two shows into the outcome of the run.
+    fn combine(self, other: Outcome) -> Outcome {
+        if other.severity() > self.severity() {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Error => EXIT_USAGE,
+            Outcome::PageUnavailable => 2,
+            Outcome::EpisodesFailed => 3,
+            Outcome::NothingToDo => 4,
+            Outcome::Incomplete => 5,
+            Outcome::Interrupted => 130,
+        }
+    }
+}
+
+/// Which episodes count towards `--limit`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum LimitCount {
+    /// Every processed episode, including skipped ones
+    All,
+    /// Only episodes that are actually downloaded
+    #[default]
+    Downloads,
+}
+
+impl LimitCount {
+    /// Returns whether a processed episode counts towards `--limit`.
+    fn counts(self, downloaded: bool) -> bool {
+        downloaded || self == LimitCount::All
+    }
+}
+
+/// What to do when a download's duration differs from its metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum DurationCheck {
+    /// Print a warning
+    Warn,
+    /// Count the episode as failed
+    Strict,
+}
+
+/// What the modification time of downloaded files is set to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum MtimeMode {
+    /// The publication date of the episode, when known
+    #[default]
+    Source,
+    /// The time of the download
+    Now,
+}
+
+/// How the files of the folder are matched to the episodes before downloading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum MatchExisting {
+    /// Files named as this version names them, in any container and case
+    #[default]
+    Exact,
+    /// Also files whose normalized name is within a few edits of the title, as saved
+    /// by older versions
+    Fuzzy,
+}
+
+/// How numbered file names are prefixed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum NameScheme {
+    /// Episode number, e.g. "07 - title"
+    #[default]
+    Index,
+    /// Season and episode within the season, e.g. "S02E05 - title"
+    Sxxeyy,
+}
+
+/// Order in which episodes are numbered and processed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    /// Page order
+    #[default]
+    Index,
+    /// Publication date, oldest first
+    Date,
+    /// Title, alphabetically
+    Title,
+    /// Part number read from the title, as in "Lettura XIV"
+    Part,
+}
+
+/// Commands besides downloading the given shows.
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Search RaiPlaySound for shows and print their URLs
+    Search(SearchArgs),
+    /// Record a live channel into a single file of the output folder
+    Record(RecordArgs),
+    /// Tag the episodes already in the output folder and write their sidecars, without
+    /// downloading any audio
+    Retag(RetagArgs),
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct SearchArgs {
+    /// Words to search for
+    query: String,
 
-static URL_BASE: &str = "https://www.raiplaysound.it";
+    /// Download the Nth result with the other options given
+    #[arg(long, value_name = "N")]
+    download: Option<usize>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct RecordArgs {
+    /// Channel to record, as in the URL of its live page (e.g. radio3)
+    #[arg(long)]
+    channel: String,
+
+    /// How long to record (e.g. 90m, 1h30m)
+    #[arg(long, value_parser = duration::parse_duration)]
+    duration: Duration,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct RetagArgs {
+    /// Rename the files saved under an older naming scheme to their current name
+    #[arg(long)]
+    rename_to_current_scheme: bool,
+}
 
 /// Simple command line tool
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
+#[command(group(ArgGroup::new("input").required(true).multiple(true).args(["url", "urls", "input_file", "batch_file", "crawl", "resume_plan", "list_retry_classes"])))]
 struct Args {
-    /// URL of the HTML page
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// URL of the HTML page (repeatable)
     #[arg(short, long)]
-    url: String,
+    url: Vec<String>,
+
+    /// More show URLs, processed after those given with --url
+    #[arg(value_name = "URL")]
+    urls: Vec<String>,
+
+    /// Saved HTML page of a show to read instead of fetching one (repeatable)
+    #[arg(long, value_name = "PATH")]
+    input_file: Vec<PathBuf>,
+
+    /// File with one show URL per line, optionally followed by a tab and an output folder
+    #[arg(long, value_name = "PATH")]
+    batch_file: Option<PathBuf>,
+
+    /// Catalog or genre page whose shows are all downloaded, each in its own subfolder
+    #[arg(long, value_name = "URL")]
+    crawl: Option<String>,
+
+    /// Print the shows linked from landing pages, like a genre or /audiolibri, instead of downloading
+    #[arg(long, conflicts_with = "follow_shows")]
+    list_shows: bool,
+
+    /// Download the shows linked from landing pages, like a genre or /audiolibri, one level deep
+    #[arg(long)]
+    follow_shows: bool,
+
+    /// Download at most N of the crawled shows
+    #[arg(long, value_name = "N", requires = "crawl")]
+    crawl_limit: Option<usize>,
+
+    /// Only download the crawled shows whose title matches this case-insensitive regex (repeatable)
+    #[arg(long, value_name = "REGEX", value_parser = filter::parse_title_regex, requires = "crawl")]
+    crawl_match: Vec<Regex>,
+
+    /// Index prefix for the file name when the URL points to a single episode
+    #[arg(long, value_name = "N")]
+    index: Option<usize>,
+
+    /// Scrape the show's HTML page instead of reading its JSON API
+    #[arg(long)]
+    scrape_html: bool,
 
     /// Path to the local folder
     #[arg(short, long, default_value = ".")]
     folder: PathBuf,
 
-    /// Path to the cache folder
-    #[arg(short, long, default_value_t = std::env::temp_dir().to_str().unwrap().to_string())]
-    cache: String,
+    /// Path to the cache folder [default: the user cache folder, e.g. ~/.cache/rsnd]
+    #[arg(short, long)]
+    cache: Option<PathBuf>,
+
+    /// Fetch every page and metadata file again instead of reading or writing the cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Work from the cache alone, without network access (--list, --dry-run and --check only)
+    #[arg(long, conflicts_with_all = ["no_cache", "sizes", "estimate"])]
+    offline: bool,
+
+    /// Name files after the episode date and title instead of their position on the page
+    #[arg(long)]
+    stable_names: bool,
+
+    /// Number given to the first episode, e.g. to continue a previous volume
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    start_index: usize,
+
+    /// Number new episodes after the highest number in the folder instead of by their position on the page
+    #[arg(long, conflicts_with_all = ["start_index", "stable_names", "no_index", "name_by_date", "original_names"])]
+    continue_numbering: bool,
+
+    /// Leave out the episode number from the file names
+    #[arg(long, conflicts_with_all = ["start_index", "pad_width"])]
+    no_index: bool,
+
+    /// Zero-pad episode numbers to this width [default: 3 from 100 episodes, 2 otherwise]
+    #[arg(long, value_name = "N")]
+    pad_width: Option<usize>,
+
+    /// Keep the casing of the titles in the file names instead of lowercasing them
+    #[arg(long)]
+    keep_case: bool,
+
+    /// Transliterate file names to ASCII (e.g. "è" becomes "e"); tags keep the full title
+    #[arg(long)]
+    ascii_names: bool,
+
+    /// Character that replaces punctuation and symbols in file names (e.g. "_")
+    #[arg(long, value_name = "CHAR", default_value = " ", value_parser = naming::parse_replacement)]
+    replacement_char: char,
+
+    /// Longest file name in bytes; longer titles are cut and end with a short hash
+    #[arg(long, value_name = "N", default_value_t = naming::DEFAULT_MAX_BYTES)]
+    max_filename_bytes: usize,
+
+    /// Download video items too, as MP4, instead of skipping them
+    #[arg(long)]
+    include_video: bool,
+
+    /// Remove matches of this case-insensitive regex from the episode titles (repeatable)
+    #[arg(long, value_name = "REGEX", value_parser = filter::parse_title_regex)]
+    title_trim: Vec<Regex>,
+
+    /// Split the episode titles at this delimiter and keep only the part given by --title-part
+    #[arg(long, value_name = "DELIMITER")]
+    title_split: Option<String>,
+
+    /// Part of the split title to keep: first, last or a position from 1
+    #[arg(long, value_name = "PART", default_value = "last", value_parser = title::parse_title_part, requires = "title_split")]
+    title_part: title::TitlePart,
+
+    /// Rendition to download when the metadata lists several: best, worst or a bitrate in kbit/s
+    #[arg(long, value_name = "QUALITY", value_parser = variant::parse_quality)]
+    quality: Option<variant::Quality>,
+
+    /// Download the renditions in this format when the metadata lists several
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    prefer_format: Option<variant::Format>,
+
+    /// Save every file with this extension instead of the one matching its content
+    #[arg(long, value_name = "EXT", value_parser = clap::builder::PossibleValuesParser::new(relinker::EXTENSIONS))]
+    extension: Option<String>,
+
+    /// Keep the file names the CDN serves, recording their episodes in names.json
+    #[arg(long, conflicts_with_all = ["stable_names", "extension"])]
+    original_names: bool,
+
+    /// Prefix file names with the publication date, numbering episodes of the same day
+    #[arg(long, conflicts_with_all = ["stable_names", "original_names"])]
+    name_by_date: bool,
+
+    /// Prefix of the numbered file names
+    #[arg(long, value_enum, default_value_t = NameScheme::Index, conflicts_with_all = ["stable_names", "name_by_date"])]
+    name_scheme: NameScheme,
+
+    /// Save each show in a subfolder of --folder named after its title
+    #[arg(long)]
+    show_subdirs: bool,
+
+    /// Save the episodes of each season in their own folder, e.g. "Stagione 02"
+    #[arg(long)]
+    season_dirs: bool,
+
+    /// Keep numbering the files across the whole show with --season-dirs
+    #[arg(long, requires = "season_dirs")]
+    global_index: bool,
+
+    /// Only process the episodes listed before the newest one of the last incremental run
+    #[arg(long, conflicts_with_all = ["reverse", "sort"])]
+    incremental: bool,
+
+    /// Report the episodes missing from the folder without downloading any audio
+    #[arg(long, conflicts_with_all = ["dry_run", "list", "interactive"])]
+    check: bool,
+
+    /// Print the summary, or the search results, as JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Write what the run goes through to stderr as lines of JSON, for front ends
+    #[arg(long)]
+    progress_json: bool,
+
+    /// Print the planned output files without downloading any audio
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Write the planned downloads of the run to this file once the episodes are picked,
+    /// to carry on with --resume-plan if the run is interrupted
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["list", "check"])]
+    save_plan: Option<PathBuf>,
+
+    /// Download the episodes of a plan saved with --save-plan that are not in their folder
+    /// yet, under their planned names, without fetching the shows again
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["save_plan", "url", "urls", "input_file", "batch_file", "crawl", "list_shows", "follow_shows", "list", "check", "dry_run", "interactive"])]
+    resume_plan: Option<PathBuf>,
+
+    /// Warn when resuming a plan older than this, whose audio URLs may have expired
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration, default_value = "6h")]
+    plan_max_age: Duration,
+
+    /// Probe the size of each pending episode with a HEAD request (dry run only)
+    #[arg(long, requires = "dry_run")]
+    sizes: bool,
+
+    /// Probe the size of every pending episode and ask before downloading them (implied by --dry-run --sizes)
+    #[arg(long)]
+    estimate: bool,
+
+    /// Download without asking for confirmation after --estimate
+    #[arg(long, short = 'y')]
+    yes: bool,
+
+    /// Print a table of the episodes and exit
+    #[arg(long, conflicts_with = "dry_run")]
+    list: bool,
+
+    /// Probe the size of the first N episodes missing from the folder for --list
+    #[arg(long, value_name = "N", requires = "list")]
+    probe_sizes: Option<usize>,
+
+    /// Order used to number the episodes
+    #[arg(long, value_enum, default_value_t = SortKey::Index)]
+    sort: SortKey,
+
+    /// Put the episodes in this order by publication date before numbering them, whatever the page order
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = order::PageOrder::Page, conflicts_with_all = ["reverse", "sort"])]
+    order: order::PageOrder,
+
+    /// Episodes to process, as 1-based indices and ranges (e.g. "1,3,12-15,40-")
+    #[arg(short, long)]
+    select: Option<Selection>,
+
+    /// Only process episodes whose title matches this case-insensitive regex (repeatable)
+    #[arg(long = "match", value_name = "REGEX", value_parser = filter::parse_title_regex)]
+    match_patterns: Vec<Regex>,
+
+    /// Skip episodes whose title matches this case-insensitive regex (repeatable)
+    #[arg(long = "reject", value_name = "REGEX", value_parser = filter::parse_title_regex)]
+    reject_patterns: Vec<Regex>,
+
+    /// Only process episodes published on or after this date (YYYY-MM-DD, or relative like 30d)
+    #[arg(long, value_name = "DATE", value_parser = dates::parse_date_bound)]
+    after: Option<NaiveDate>,
+
+    /// Only process episodes published before this date (YYYY-MM-DD, or relative like 30d)
+    #[arg(long, value_name = "DATE", value_parser = dates::parse_date_bound)]
+    before: Option<NaiveDate>,
+
+    /// Exclude episodes without a publication date when filtering by date
+    #[arg(long)]
+    strict_dates: bool,
+
+    /// Skip episodes shorter than this (e.g. 90, 2m, 1h30m)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    min_duration: Option<Duration>,
+
+    /// Skip episodes longer than this (e.g. 90, 2m, 1h30m)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Exclude episodes with an unknown duration when filtering by duration
+    #[arg(long)]
+    strict_duration: bool,
+
+    /// Ignore the first N episodes in processing order
+    #[arg(long, default_value_t = 0)]
+    skip: usize,
+
+    /// Process the episodes in reverse page order, numbering the last one as 1
+    #[arg(short, long)]
+    reverse: bool,
+
+    /// Choose the episodes to download from an interactive list
+    #[arg(short, long, conflicts_with = "list")]
+    interactive: bool,
+
+    /// Stop after this many episodes have been processed
+    #[arg(short, long, conflicts_with = "list")]
+    limit: Option<usize>,
+
+    /// Which episodes count towards --limit
+    #[arg(long, value_enum, default_value_t = LimitCount::Downloads, requires = "limit")]
+    limit_count: LimitCount,
+
+    /// Save the response even when it does not look like audio
+    #[arg(long)]
+    accept_any_content: bool,
+
+    /// Replace a file rsnd did not create when an episode is saved under its name
+    #[arg(long)]
+    force_unsafe: bool,
+
+    /// Flag downloads smaller than this as suspicious (e.g. 300K, 0 to disable)
+    #[arg(long, value_name = "SIZE", default_value = "256K", value_parser = size::parse_size)]
+    min_size: u64,
+
+    /// Skip episodes larger than this before downloading them (e.g. 500M)
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size)]
+    max_filesize: Option<u64>,
+
+    /// Skip episodes smaller than this before downloading them (e.g. 1M)
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size)]
+    min_filesize: Option<u64>,
+
+    /// Also skip episodes whose size the server does not advertise, with --max-filesize or --min-filesize
+    #[arg(long)]
+    strict_size: bool,
+
+    /// Give up on a download, and retry it, when it stays slower than this per second for
+    /// --stall-timeout (e.g. 10K)
+    #[arg(long, value_name = "SIZE", default_value = "10K", value_parser = size::parse_size)]
+    stall_speed: u64,
+
+    /// How long a download may stay slower than --stall-speed before it is given up (0 to never)
+    #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = duration::parse_duration)]
+    stall_timeout: Duration,
+
+    /// Download from host TO when host FROM cannot be reached or refuses the file
+    /// (repeatable, tried in order before the built-in mediapolis mirrors)
+    #[arg(long, value_name = "FROM=TO", value_parser = mirror::parse_substitution)]
+    cdn_fallback: Vec<mirror::Substitution>,
+
+    /// Retries after a failure of one of the --retry-on classes
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: usize,
+
+    /// Comma-separated classes of failures to retry (see --list-retry-classes)
+    #[arg(long, value_enum, value_name = "CLASSES", value_delimiter = ',', default_values_t = retry::RetryClass::DEFAULT)]
+    retry_on: Vec<retry::RetryClass>,
+
+    /// List the classes of failures --retry-on accepts and exit
+    #[arg(long, exclusive = true)]
+    list_retry_classes: bool,
+
+    /// Compare the length of each download with its metadata duration (mp3 and m4a only)
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "warn")]
+    check_duration: Option<DurationCheck>,
+
+    /// Also look for episodes in this folder, e.g. an archive, and skip those found there (repeatable)
+    #[arg(long, value_name = "DIR")]
+    also_check_dir: Vec<PathBuf>,
+
+    /// Hard-link (or copy) episodes found with --also-check-dir into the output folder
+    #[arg(long, requires = "also_check_dir")]
+    link_existing: bool,
+
+    /// How files already in the folder are recognized as episodes
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = MatchExisting::Exact)]
+    match_existing: MatchExisting,
+
+    /// Date downloaded files with the publication date of the episode, or leave the download time
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = MtimeMode::Source)]
+    mtime: MtimeMode,
+
+    /// Save everything known about each download in a `<name>.info.json` file next to it
+    #[arg(long)]
+    write_info_json: bool,
+
+    /// Save the image of each episode that has its own as `<name>.jpg` next to the audio
+    #[arg(long)]
+    write_thumbnails: bool,
+
+    /// Count episodes the relinker serves a placeholder for as failures instead of skipping them
+    #[arg(long)]
+    strict_geo: bool,
+
+    /// Run again and again, waiting --interval between runs, until interrupted
+    #[arg(long, conflicts_with_all = ["list", "check", "dry_run", "interactive", "resume_plan"])]
+    watch: bool,
+
+    /// Time between --watch runs (e.g. 30m), or "auto" to poll shortly after the next
+    /// episode the schedule of the shows announces, hourly for shows without one
+    #[arg(long, value_name = "DURATION|auto", value_parser = schedule::parse_interval, default_value = "1h", requires = "watch")]
+    interval: schedule::Interval,
+
+    /// Accept show pages without episodes instead of failing, e.g. for scripted crawls
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Move the files of episodes the show no longer lists to a removed/ subfolder, with
+    /// their sidecars, instead of only reporting them
+    #[arg(long)]
+    prune_removed: bool,
+
+    /// Abort at the first episode that fails instead of continuing with the others
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Episode metadata fetched at the same time, before any download starts
+    #[arg(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    metadata_jobs: u16,
+
+    /// Shows processed at the same time, their lines prefixed with the show name
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    show_jobs: u16,
+
+    /// Send at most this many HTTP requests per second to each host (e.g. 2 or 0.5)
+    #[arg(long, value_name = "N", value_parser = ratelimit::parse_rps)]
+    rps: Option<f64>,
+
+    /// Send the cookies of the RAI sites from a browser profile, read without changing it
+    /// (firefox, chromium or chrome, optionally followed by :PROFILE; needs the
+    /// cookies-from-browser feature)
+    #[arg(long, value_name = "BROWSER[:PROFILE]", value_parser = browser::parse_spec)]
+    cookies_from_browser: Option<browser::BrowserSpec>,
+
+    /// Pause between consecutive audio downloads (e.g. 30, 2m); episodes already present
+    /// do not wait
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    sleep: Option<Duration>,
+
+    /// Add up to this much to each --sleep pause, at random
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration, requires = "sleep")]
+    sleep_jitter: Option<Duration>,
+
+    /// Site that metadata paths and relative links resolve against, e.g. a local mirror
+    #[arg(long, value_name = "URL", value_parser = site::parse_base_url, default_value = site::DEFAULT_BASE_URL)]
+    base_url: String,
+
+    /// Save the pages, episode JSON and relinker headers fetched in this run to DIR, for bug reports
+    #[arg(long, value_name = "DIR")]
+    dump_pages: Option<PathBuf>,
+
+    /// Speak HTTP/1.1 only, for proxies that mishandle HTTP/2
+    #[arg(long, conflicts_with = "http2_prior_knowledge")]
+    http1_only: bool,
+
+    /// Speak HTTP/2 right away, without negotiating it first
+    #[arg(long)]
+    http2_prior_knowledge: bool,
+
+    /// Close connections left idle for this long (e.g. 90, 2m)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration, default_value = "90")]
+    pool_idle_timeout: Duration,
+
+    /// Idle connections kept open per host, to reuse for the next requests
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    pool_max_idle: usize,
+
+    /// Interval of TCP keepalive probes on open connections, 0 to send none
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration, default_value = "60")]
+    tcp_keepalive: Duration,
+
+    /// Write the end-of-run summary as JSON to this file
+    #[arg(long, value_name = "PATH")]
+    summary_json: Option<PathBuf>,
+
+    /// Send a desktop notification when the run finishes (needs the notify-desktop feature)
+    #[arg(long)]
+    notify_desktop: bool,
+}
+
+/// The field of the episode JSON the audio URL was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AudioSource {
+    /// `audio.url`
+    AudioUrl,
+    /// `downloadable_url`
+    DownloadableUrl,
+    /// `audio.downloadUrl`
+    DownloadUrl,
+    /// `downloadable_audio.url`
+    DownloadableAudio,
+    /// An alternative rendition, picked with `--quality` or `--prefer-format`
+    Rendition,
+    /// `video.url`, of a video item
+    VideoUrl,
+}
+
+impl AudioSource {
+    /// Name of the JSON field.
+    fn field(self) -> &'static str {
+        match self {
+            AudioSource::AudioUrl => "audio.url",
+            AudioSource::DownloadableUrl => "downloadable_url",
+            AudioSource::DownloadUrl => "audio.downloadUrl",
+            AudioSource::DownloadableAudio => "downloadable_audio.url",
+            AudioSource::Rendition => "renditions",
+            AudioSource::VideoUrl => "video.url",
+        }
+    }
+}
+
+impl Args {
+    fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            http1_only: self.http1_only,
+            http2_prior_knowledge: self.http2_prior_knowledge,
+            pool_idle_timeout: self.pool_idle_timeout,
+            pool_max_idle: self.pool_max_idle,
+            tcp_keepalive: Some(self.tcp_keepalive).filter(|interval| !interval.is_zero()),
+        }
+    }
+
+    fn retry_policy(&self) -> retry::Policy {
+        retry::Policy::new(self.retries, &self.retry_on)
+    }
+
+    fn download_options(&self, naming: Naming) -> DownloadOptions {
+        DownloadOptions {
+            accept_any_content: self.accept_any_content,
+            force_unsafe: self.force_unsafe,
+            retry: self.retry_policy(),
+            naming,
+            variant: self.variant_choice(),
+            size_bounds: size::Bounds {
+                min: self.min_filesize,
+                max: self.max_filesize,
+                strict: self.strict_size,
+            },
+            stall: stall::Limit {
+                speed: self.stall_speed,
+                timeout: self.stall_timeout,
+            },
+            mirrors: mirror::substitutions(&self.cdn_fallback),
+        }
+    }
+
+    /// Applies the options that rewrite the metadata of every episode: the rendition
+    /// choice and the title trimming.
+    fn prepare_metadata(&self, metadata: &mut AudioMetadata) {
+        metadata.select_variant(&self.variant_choice());
+        let trim = title::TitleTrim {
+            patterns: self.title_trim.clone(),
+            split: self.title_split.clone(),
+            part: self.title_part,
+        };
+        metadata.title = trim.apply(&metadata.title);
+    }
+
+    /// The filters episodes are picked with, as they were given on the command line.
+    fn filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        if self.skip > 0 {
+            filters.push(format!("--skip {}", self.skip));
+        }
+        for pattern in &self.match_patterns {
+            filters.push(format!("--match {}", pattern));
+        }
+        for pattern in &self.reject_patterns {
+            filters.push(format!("--reject {}", pattern));
+        }
+        if let Some(after) = self.after {
+            filters.push(format!("--after {}", after));
+        }
+        if let Some(before) = self.before {
+            filters.push(format!("--before {}", before));
+        }
+        if self.strict_dates {
+            filters.push("--strict-dates".to_string());
+        }
+        if let Some(min) = self.min_duration {
+            filters.push(format!("--min-duration {}s", min.as_secs()));
+        }
+        if let Some(max) = self.max_duration {
+            filters.push(format!("--max-duration {}s", max.as_secs()));
+        }
+        if self.strict_duration {
+            filters.push("--strict-duration".to_string());
+        }
+        filters
+    }
+
+    fn variant_choice(&self) -> variant::Choice {
+        variant::Choice {
+            quality: self.quality,
+            format: self.prefer_format,
+        }
+    }
+
+    /// Returns whether the file names depend on the other episodes of the show, as season
+    /// numbers, ranks within a day and names shared without an index do. The whole
+    /// listing is then planned before `--skip` and `--select`, so that an episode gets the
+    /// same name whatever is selected.
+    fn names_need_listing(&self) -> bool {
+        self.season_dirs
+            || self.name_scheme == NameScheme::Sxxeyy
+            || self.name_by_date
+            || self.no_index
+            || self.stable_names
+    }
+
+    /// Naming options for a show with `count` episodes.
+    fn naming(&self, count: usize) -> Naming {
+        Naming {
+            stable: self.stable_names,
+            no_index: self.no_index,
+            start_index: self.start_index,
+            pad_width: self
+                .pad_width
+                .unwrap_or_else(|| naming::auto_pad_width(count, self.start_index)),
+            keep_case: self.keep_case,
+            replacement: self.replacement_char,
+            max_bytes: self.max_filename_bytes,
+            extension: relinker::EXTENSIONS
+                .iter()
+                .copied()
+                .find(|extension| self.extension.as_deref() == Some(*extension)),
+            original: self.original_names,
+            by_date: self.name_by_date,
+            sxxeyy: self.name_scheme == NameScheme::Sxxeyy,
+            ascii: self.ascii_names,
+        }
+    }
+}
+
+/// How the HTTP client connects. Pages and episode JSON are many small requests, which
+/// reuse the connections kept open in the pool rather than opening one each.
+#[derive(Debug, Clone, Copy)]
+struct ClientOptions {
+    http1_only: bool,
+    http2_prior_knowledge: bool,
+    pool_idle_timeout: Duration,
+    /// Idle connections kept per host.
+    pool_max_idle: usize,
+    tcp_keepalive: Option<Duration>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            http1_only: false,
+            http2_prior_knowledge: false,
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle: 16,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Options shared by all the downloads of a run.
+#[derive(Debug, Clone, Default)]
+struct DownloadOptions {
+    /// Save responses that do not look like audio.
+    accept_any_content: bool,
+    /// Replace files that do not look like ones rsnd saved.
+    force_unsafe: bool,
+    /// When to try a download again.
+    retry: retry::Policy,
+    naming: Naming,
+    /// Which rendition to download when the metadata lists several.
+    variant: variant::Choice,
+    /// Sizes outside these bounds are skipped.
+    size_bounds: size::Bounds,
+    /// Downloads slower than this are given up and retried.
+    stall: stall::Limit,
+    /// Hosts to download from when the CDN host fails.
+    mirrors: Vec<mirror::Substitution>,
 }
 
 #[derive(Debug)]
 struct AudioMetadata {
     url: String,
     title: String,
+    date: Option<NaiveDate>,
+    /// Duration in seconds.
+    duration: Option<u64>,
+    description: Option<String>,
+    /// Absolute URL of the episode image.
+    image: Option<String>,
+    /// Absolute URL of the show image, which episodes without their own repeat.
+    show_image: Option<String>,
+    /// The episode `uniquename`, or its `id` when missing.
+    id: Option<String>,
+    /// Name of the show the episode belongs to.
+    show: Option<String>,
+    source: AudioSource,
+    /// Renditions listed in the metadata, including `url`; empty when it lists none.
+    variants: Vec<variant::Variant>,
+    /// The item is a video, which its JSON describes under `video` instead of `audio`.
+    video: bool,
+    /// The CDN URL `url` redirects to, resolved right before downloading.
+    media_url: Option<String>,
+    /// The name the CDN serves the file under, known once `media_url` is resolved.
+    original_name: Option<String>,
+    /// Size the CDN advertises for `media_url`, known once it is resolved.
+    media_size: Option<u64>,
+    container: Container,
+    /// Path of the episode JSON, relative to the site.
+    metadata_path: String,
+    /// The cache entry the metadata was read from, when it was not freshly fetched.
+    cache_entry: Option<cache::Entry>,
+    /// Position among the episodes of the show published on the same day, when there
+    /// are several.
+    day_rank: Option<usize>,
+    /// Season of the episode, read from its metadata and then from the show listing.
+    season: Option<usize>,
+    /// Number of the episode within its season.
+    episode: Option<usize>,
+    /// Another episode of the show would get the same file name.
+    duplicate: bool,
+    /// The path of the audio file in a resumed plan, which the name is not rebuilt from
+    /// the metadata for.
+    planned_path: Option<PathBuf>,
 }
 
-/// Fetches the HTML content from the URL or reads it from the cache if available.
-async fn fetch_or_read_page(client: &Client, url: &str, cache_dir: &Path) -> Result<String> {
-    let (_, rawfilename) = url
-        .rsplit_once('/')
-        .with_context(|| format!("Failed to extract page name from: {}", url))?;
-    let filename = format!("{}.html", rawfilename);
-    let filepath = cache_dir.join(filename);
-
-    if filepath.exists() {
-        let mut file = File::open(&filepath)
-            .with_context(|| format!("Failed to open file: {}", filepath.display()))?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .with_context(|| format!("Failed to read file: {}", filepath.display()))?;
-        Ok(contents)
-    } else {
-        let response = client
-            .get(url)
-            .send()
-            .await
-            .with_context(|| format!("Failed to fetch URL: {}", url))?;
+impl AudioMetadata {
+    /// Takes the audio URL and its renditions from freshly fetched metadata, keeping
+    /// everything the file name is built from as it was planned.
+    fn refresh_audio(&mut self, fresh: AudioMetadata) {
+        self.url = fresh.url;
+        self.source = fresh.source;
+        self.variants = fresh.variants;
+        self.container = fresh.container;
+        self.media_url = None;
+        self.original_name = None;
+        self.media_size = None;
+        self.cache_entry = fresh.cache_entry;
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to fetch URL: {}. Status: {}",
-                url,
-                response.status()
-            ));
+    /// Returns the absolute URL of the episode JSON, which identifies the episode.
+    fn metadata_url(&self) -> String {
+        site::absolute_url(&self.metadata_path)
+    }
+
+    /// Takes the CDN URL, container, name and size the relinker resolved `url` to.
+    fn set_resolved(&mut self, resolved: relinker::Resolved) {
+        log::debug!("Resolved {} to {}", self.url, resolved.url);
+        self.container = if self.video {
+            resolved.container.for_video()
+        } else {
+            resolved.container
+        };
+        self.media_url = Some(resolved.url);
+        self.original_name = resolved.filename;
+        self.media_size = resolved.size;
+    }
+
+    /// Switches `url` to the rendition picked by `choice`, if any.
+    fn select_variant(&mut self, choice: &variant::Choice) {
+        let Some(picked) = choice.pick(&self.variants) else {
+            return;
+        };
+        if picked.url != self.url {
+            self.url = picked.url.clone();
+            self.source = AudioSource::Rendition;
+            self.container = Container::from_url(&self.url)
+                .or(picked.format.map(|format| match format {
+                    variant::Format::Mp3 => Container::Mp3,
+                    variant::Format::M4a => Container::M4a,
+                }))
+                .unwrap_or_default();
         }
+    }
 
-        let rsp_txt = response
-            .text()
-            .await
-            .with_context(|| format!("Failed to get text from URL: {}", url))?;
-        let mut file = TokioFile::create(&filepath)
-            .await
-            .with_context(|| format!("Failed to create file: {}", filepath.display()))?;
-        file.write_all(rsp_txt.as_bytes())
-            .await
-            .with_context(|| format!("Failed to write to file: {}", filepath.display()))?;
-        Ok(rsp_txt)
+    /// The image of the episode, unless it only repeats the image of the show.
+    fn own_image(&self) -> Option<&str> {
+        thumbnail::own_image(self.image.as_deref(), self.show_image.as_deref())
+    }
+
+    /// Part number read from the title, as in "Lettura XIV".
+    fn part(&self) -> Option<usize> {
+        part::part_number(&self.title)
+    }
+
+    /// What the file name of the episode is built from.
+    fn name_parts(&self, idx: Option<usize>) -> naming::NameParts<'_> {
+        naming::NameParts {
+            title: &self.title,
+            date: self.date,
+            id: self.id.as_deref(),
+            idx,
+            day_rank: self.day_rank,
+            season: self.season.zip(self.episode),
+            key: Some(episode_key(self)),
+            duplicate: self.duplicate,
+        }
     }
 }
 
-/// Extracts audio options from the HTML content.
-fn extract_options(html: &str) -> Vec<String> {
-    let document = Html::parse_document(html);
-    let selector = Selector::parse("rps-play-with-labels").expect("Invalid selector");
+/// An episode with its final 1-based index.
+#[derive(Debug)]
+struct Episode {
+    index: usize,
+    metadata: AudioMetadata,
+}
 
-    document
-        .select(&selector)
-        .filter_map(|element| {
-            if let Some(options) = element.value().attr("options") {
-                let options_map: HashMap<String, Value> = serde_json::from_str(options)
-                    .expect("Unable to parse JSON in options attribute");
-                if let Some(url) = options_map.get("url") {
-                    return Some(url.as_str().unwrap().to_string());
-                }
-            }
-            None
+/// Returns the cached body of the entry, or fetches and caches it.
+async fn fetch_cached<F: Fetcher>(
+    client: &F,
+    cache: &dyn Cache,
+    entry: &cache::Entry,
+) -> Result<String> {
+    if let Some(body) = cache.get(entry)? {
+        dump::body(&entry.name, &entry.url, true, &body);
+        return Ok(body);
+    }
+    let body = retry::policy()
+        .run(&entry.url, || async {
+            Ok(client.get_text(&entry.url).await?)
         })
-        .collect()
+        .await?;
+    dump::body(&entry.name, &entry.url, false, &body);
+    cache.put(entry, &body)?;
+    Ok(body)
 }
 
-/// Fetches audio metadata from the given URL or reads it from the cache if available.
-async fn fetch_audio_metadata(
-    client: &Client,
+/// Fetches the HTML content from the URL or reads it from the cache if available.
+async fn fetch_or_read_page<F: Fetcher>(
+    client: &F,
     url: &str,
-    cache_dir: &Path,
-) -> Result<AudioMetadata> {
-    let full_url = format!("{}{}", URL_BASE, url);
-    let (_, filename) = full_url
-        .rsplit_once('/')
-        .with_context(|| format!("Failed to extract file name from: {}", full_url))?;
-    let filepath = cache_dir.join(filename);
-
-    let json_content = if filepath.exists() {
-        let mut file = File::open(&filepath)
-            .with_context(|| format!("Failed to open file: {}", filepath.display()))?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .with_context(|| format!("Failed to read file: {}", filepath.display()))?;
-        contents
-    } else {
-        let response = client
-            .get(&full_url)
-            .send()
-            .await
-            .with_context(|| format!("Failed to fetch URL: {}", full_url))?;
+    cache: &dyn Cache,
+) -> Result<String> {
+    if let Some(path) = local_page(url) {
+        return std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read page: {}", path.display()));
+    }
+    fetch_cached(client, cache, &cache::Entry::for_page(url)?).await
+}
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to fetch URL: {}. Status: {}",
-                full_url,
-                response.status()
-            ));
-        }
+/// Returns the path of a `file://` URL, a page saved on disk.
+fn local_page(url: &str) -> Option<PathBuf> {
+    reqwest::Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "file")?
+        .to_file_path()
+        .ok()
+}
 
-        let rsp_txt = response
-            .text()
-            .await
-            .with_context(|| format!("Failed to get text from URL: {}", full_url))?;
-        let mut file = TokioFile::create(&filepath)
-            .await
-            .with_context(|| format!("Failed to create file: {}", filepath.display()))?;
-        file.write_all(rsp_txt.as_bytes())
-            .await
-            .with_context(|| format!("Failed to write to file: {}", filepath.display()))?;
-        rsp_txt
-    };
+/// Returns the `file://` URL of a saved page given with `--input-file`.
+fn input_file_url(path: &Path) -> Result<String> {
+    let path = std::path::absolute(path)
+        .with_context(|| format!("Failed to resolve path: {}", path.display()))?;
+    if !path.is_file() {
+        return Err(anyhow::anyhow!("No such file: {}", path.display()));
+    }
+    reqwest::Url::from_file_path(&path)
+        .map(String::from)
+        .map_err(|_| anyhow::anyhow!("Invalid path: {}", path.display()))
+}
 
-    let json_value: Value = serde_json::from_str(&json_content)
-        .with_context(|| format!("Failed to parse JSON: {}", full_url))?;
-    let audio_url = json_value["audio"]["url"]
-        .as_str()
-        .context("Missing field `url`")?
-        .to_string();
-    let audio_title = json_value["audio"]["title"]
-        .as_str()
-        .context("Missing field `title`")?
-        .to_string();
+/// Drops the cached listing of the show, its JSON API and page, so that the next fetch
+/// sees the episodes published since.
+fn forget_listing(show_url: &str, cache: &dyn Cache) -> Result<()> {
+    let (json_url, json_name) = api::show_json_url(show_url)?;
+    cache.invalidate(&cache::Entry::new(json_url, json_name))?;
+    cache.invalidate(&cache::Entry::for_page(show_url)?)?;
+    Ok(())
+}
 
-    Ok(AudioMetadata {
-        url: audio_url,
-        title: audio_title,
-    })
+/// Number of characters of a malformed options attribute quoted in warnings.
+const SNIPPET_LEN: usize = 80;
+
+/// Decodes the HTML entities left in an attribute value that was escaped twice.
+///
+/// The HTML parser already decoded the attribute once, so this is only for values that
+/// are not valid JSON as they are: see [`parse_options`].
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#34;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
 }
 
-/// Downloads audio from the given metadata and saves it to the specified folder.
-async fn download_audio(
-    client: &Client,
+/// Parses an options attribute as it is, and only when that fails as one that was
+/// escaped twice, so that entities spelled out in valid JSON are kept.
+fn parse_options(options: &str) -> serde_json::Result<Value> {
+    serde_json::from_str(options).or_else(|err| {
+        let decoded = decode_entities(options);
+        if decoded == options {
+            return Err(err);
+        }
+        serde_json::from_str(&decoded)
+    })
+}
+
+/// Returns the URLs of a parsed options attribute: its own `url`, or the `url` of the
+/// objects nested one level deeper, alone or in an array of items.
+fn option_urls(value: &Value) -> Vec<String> {
+    let url_of = |value: &Value| value.get("url").and_then(Value::as_str).map(str::to_string);
+    if let Some(url) = url_of(value) {
+        return vec![url];
+    }
+    let children: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(map) => map.values().collect(),
+        _ => Vec::new(),
+    };
+    children
+        .into_iter()
+        .flat_map(|child| match child {
+            Value::Array(items) => items.iter().filter_map(url_of).collect(),
+            _ => url_of(child).into_iter().collect::<Vec<_>>(),
+        })
+        .collect()
+}
+
+/// Extracts audio options from the HTML content.
+///
+/// Pages may repeat an episode (e.g. in a hero element and in the list); only the first
+/// occurrence of each option URL is kept. Malformed options are skipped with a warning,
+/// and an error is returned only when none of them can be read.
+fn extract_options(html: &str) -> Result<Vec<String>> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("rps-play-with-labels").expect("Invalid selector");
+    let mut seen = HashSet::new();
+    let mut urls = Vec::new();
+    let mut elements = 0;
+    let mut invalid = 0;
+
+    for element in document.select(&selector) {
+        let Some(options) = element.value().attr("options") else {
+            continue;
+        };
+        elements += 1;
+        let snippet: String = options.chars().take(SNIPPET_LEN).collect();
+        let found = match parse_options(options) {
+            Ok(value) => option_urls(&value),
+            Err(err) => {
+                errln!("Warning: skipping malformed options {:?}: {}", snippet, err);
+                invalid += 1;
+                continue;
+            }
+        };
+        if found.is_empty() {
+            errln!("Warning: skipping options without a url {:?}", snippet);
+            invalid += 1;
+            continue;
+        }
+        for url in found {
+            if seen.insert(url.clone()) {
+                urls.push(url);
+            } else {
+                outln!("Dropped duplicate episode {}", url);
+            }
+        }
+    }
+
+    if elements > 0 && invalid == elements {
+        return Err(anyhow::anyhow!(
+            "None of the {} options attributes on the page could be read",
+            elements
+        ));
+    }
+    Ok(urls)
+}
+
+/// Extracts the episodes of the page, trying the extractor for its kind of page first and
+/// then the ones reading the JSON-LD data and the embedded page state.
+fn extract_episodes(html: &str, kind: PageKind) -> Vec<String> {
+    type Extractor = fn(&str) -> Result<Vec<String>>;
+    let primary: (&str, Extractor) = match kind {
+        PageKind::Programme | PageKind::Landing => {
+            ("rps-play-with-labels elements", extract_options)
+        }
+        PageKind::Playlist => ("playlist data", |html| {
+            Ok(playlist::extract_playlist_items(html))
+        }),
+    };
+    let strategies: [(&str, Extractor); 3] = [
+        primary,
+        ("JSON-LD data", |html| Ok(fallback::json_ld_items(html))),
+        ("embedded page state", |html| {
+            Ok(fallback::embedded_state_items(html))
+        }),
+    ];
+    for (position, (name, extract)) in strategies.into_iter().enumerate() {
+        let items = extract(html).unwrap_or_else(|err| {
+            errln!("Warning: {:#}", err);
+            Vec::new()
+        });
+        if !items.is_empty() {
+            if position > 0 {
+                outln!("Found {} episodes in the {}", items.len(), name);
+            }
+            return items;
+        }
+    }
+    Vec::new()
+}
+
+/// Returns the path of the metadata JSON when the URL points to a single episode page.
+///
+/// Episode pages live under `/audio/` and their metadata sits next to them, with a `.json`
+/// extension instead of `.html`.
+fn episode_metadata_path(url: &str) -> Option<String> {
+    let path = reqwest::Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() != "file")?
+        .path()
+        .to_string();
+    let stem = path.strip_prefix("/audio/")?.strip_suffix(".html")?;
+    Some(format!("/audio/{}.json", stem))
+}
+
+/// The episodes of a show, as listed by its JSON API or page.
+struct Listing {
+    /// Metadata paths of the episodes, in page order.
+    audio_urls: Vec<String>,
+    /// Metadata already known, by metadata path.
+    prefetched: HashMap<String, AudioMetadata>,
+    /// Seasons of the blocks listing the episodes, by metadata path.
+    seasons: HashMap<String, usize>,
+    /// Title of the show.
+    title: Option<String>,
+    /// Absolute URL of the show image.
+    image: Option<String>,
+    description: Option<String>,
+    /// When the show publishes, as its JSON tells.
+    schedule: Option<String>,
+}
+
+/// Fetches the JSON API of the show and returns the metadata paths of its episodes, with
+/// the metadata of those whose card already carries it.
+async fn fetch_show_json<F: Fetcher>(
+    client: &F,
+    show_url: &str,
+    cache: &dyn Cache,
+) -> Result<Listing> {
+    let (json_url, cache_name) = api::show_json_url(show_url)?;
+    let entry = cache::Entry::new(&json_url, cache_name);
+    let cached = cache.contains(&entry);
+    let json = fetch_cached(client, cache, &entry).await?;
+    let cards = api::episode_cards(&json).with_context(|| format!("Invalid JSON: {}", json_url))?;
+    if cards.is_empty() {
+        return Err(RsndError::NoEpisodes {
+            url: json_url,
+            diagnosis: None,
+        }
+        .into());
+    }
+
+    let mut audio_urls = Vec::with_capacity(cards.len());
+    let mut prefetched = HashMap::new();
+    let mut seasons = HashMap::new();
+    for card in cards {
+        if let Ok(mut metadata) = parse_audio_metadata(&card.value) {
+            metadata.metadata_path = card.path.clone();
+            metadata.cache_entry = cached.then(|| entry.clone());
+            prefetched.insert(card.path.clone(), metadata);
+        }
+        if let Some(season) = card.season {
+            seasons.insert(card.path.clone(), season);
+        }
+        audio_urls.push(card.path);
+    }
+    Ok(Listing {
+        audio_urls,
+        prefetched,
+        seasons,
+        title: show::json_title(&json),
+        image: show::json_image(&json).map(|image| site::absolute_url(&image)),
+        description: show::json_description(&json),
+        schedule: show::json_schedule(&json),
+    })
+}
+
+/// Fetches the further episode sets referenced by a programme page and appends their
+/// episodes to `audio_urls`.
+///
+/// Returns the seasons of the episodes listed in sets titled after a season.
+async fn fetch_remaining_sets<F: Fetcher>(
+    client: &F,
+    page_html: &str,
+    cache: &dyn Cache,
+    audio_urls: &mut Vec<String>,
+) -> Result<HashMap<String, usize>> {
+    let mut seasons = HashMap::new();
+    let set_paths = blocks::set_paths(page_html);
+    if set_paths.is_empty() {
+        return Ok(seasons);
+    }
+
+    let mut added = 0;
+    for set_path in &set_paths {
+        let set_url = site::absolute_url(set_path);
+        let entry = cache::Entry::new(&set_url, blocks::cache_name(set_path));
+        let json = fetch_cached(client, cache, &entry).await?;
+        let items = blocks::extract_set_items(&json)
+            .with_context(|| format!("Failed to read episode set: {}", set_url))?;
+        if let Some(season) = blocks::set_season(&json) {
+            seasons.extend(items.iter().map(|item| (item.clone(), season)));
+        }
+        added += blocks::merge_items(audio_urls, items);
+    }
+    outln!(
+        "Fetched {} more episodes from {} episode sets",
+        added,
+        set_paths.len()
+    );
+    Ok(seasons)
+}
+
+/// Assigns 1-based indices to the items and keeps the selected ones.
+///
+/// Indices follow the processing order, so with `reverse` the last item is number 1.
+/// Both `skip` and `select` refer to these indices.
+fn number_episodes<T>(
+    items: Vec<T>,
+    reverse: bool,
+    skip: usize,
+    select: Option<&Selection>,
+) -> Vec<(usize, T)> {
+    let mut ordered = items;
+    if reverse {
+        ordered.reverse();
+    }
+
+    ordered
+        .into_iter()
+        .enumerate()
+        .map(|(idx, audio_url)| (idx + 1, audio_url))
+        .filter(|(idx, _)| *idx > skip)
+        .filter(|(idx, _)| select.is_none_or(|s| s.contains(*idx)))
+        .collect()
+}
+
+/// Sorts the metadata by the given key, keeping the current order for ties.
+fn sort_metadata(metadata: &mut [AudioMetadata], key: SortKey) {
+    match key {
+        SortKey::Index => {}
+        // Episodes without a date go last.
+        SortKey::Date => metadata.sort_by_key(|m| (m.date.is_none(), m.date)),
+        SortKey::Title => metadata.sort_by_key(|m| m.title.to_lowercase()),
+        // Episodes without a part go last, in page order.
+        SortKey::Part => metadata.sort_by_cached_key(|m| {
+            let part = m.part();
+            (part.is_none(), part)
+        }),
+    }
+}
+
+/// Drops items whose audio URL was already seen, keeping the first occurrence, and adds
+/// the metadata paths of those dropped to `dropped`.
+///
+/// Different metadata paths can point to the same media, which would otherwise be
+/// downloaded twice under two indices.
+fn dedup_by_audio_url<T>(
+    items: Vec<T>,
+    metadata: impl Fn(&T) -> &AudioMetadata,
+    dropped: &mut HashSet<String>,
+) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|item| {
+            let metadata = metadata(item);
+            let first = seen.insert(metadata.url.clone());
+            if !first {
+                outln!(
+                    "Dropped duplicate episode {}: same audio as an earlier episode",
+                    metadata.title
+                );
+                dropped.insert(metadata.metadata_path.clone());
+            }
+            first
+        })
+        .collect()
+}
+
+/// Fetches the metadata of the selected episodes and assigns their final indices.
+///
+/// Sorting by anything but page order, putting the page in an `--order`, or naming
+/// files after the rest of the listing needs the metadata of every episode, so in that
+/// case all of them are returned, and `--skip` and `--select` are left to
+/// [`select_episodes`]. Episodes
+/// whose metadata cannot be fetched are recorded as failed, unless `--fail-fast` is set.
+/// The metadata paths of the episodes left out on purpose, video items, duplicates and
+/// those only on the web page, are added to `set_aside`.
+async fn plan_episodes<F: Fetcher>(
+    client: &F,
+    audio_urls: &[String],
+    prefetched: &mut HashMap<String, AudioMetadata>,
+    cache: &SharedCache,
+    args: &Args,
+    summary: &ShowSummary,
+    set_aside: &mut HashSet<String>,
+) -> Result<Vec<Episode>> {
+    let urls: Vec<&String> = audio_urls.iter().collect();
+    let jobs = usize::from(args.metadata_jobs);
+
+    if args.sort == SortKey::Index
+        && args.order == order::PageOrder::Page
+        && !args.names_need_listing()
+    {
+        let numbered = number_episodes(urls, args.reverse, args.skip, args.select.as_ref());
+        let resolved =
+            resolve_metadata(client, summary.url(), numbered, prefetched, cache, jobs).await;
+        let mut episodes = Vec::new();
+        for (index, audio_url, result) in resolved {
+            match result {
+                Ok(metadata) if !keep_media(index, &metadata, args, summary) => {
+                    set_aside.insert(metadata.metadata_path);
+                }
+                Ok(mut metadata) => {
+                    args.prepare_metadata(&mut metadata);
+                    episodes.push(Episode { index, metadata })
+                }
+                Err(err) if args.check => summary.add_unknown(index, audio_url, &reason_of(&err)),
+                Err(err) if web_page_only(&err) => {
+                    summary.add_unavailable(index, audio_url, &reason_of(&err));
+                    set_aside.insert(audio_url.clone());
+                }
+                Err(err) if args.fail_fast => return Err(err),
+                Err(err) => summary.add_failed(FailedEpisode::new(index, audio_url, err)),
+            }
+        }
+        let dates: Vec<Option<NaiveDate>> = episodes
+            .iter()
+            .map(|episode| episode.metadata.date)
+            .collect();
+        if order::detect(&dates) == order::Detected::NewestFirst {
+            errln!(
+                "Warning: the episodes are numbered newest first; use --order oldest-first to number the oldest as 1"
+            );
+        }
+        // A selection leaves holes of its own.
+        if args.skip == 0 && args.select.is_none() {
+            warn_missing_parts(episodes.iter().map(|episode| &episode.metadata));
+        }
+        return Ok(dedup_by_audio_url(
+            episodes,
+            |episode| &episode.metadata,
+            set_aside,
+        ));
+    }
+
+    let mut all = Vec::with_capacity(urls.len());
+    let numbered = number_episodes(urls, args.reverse, 0, None);
+    for (position, audio_url, result) in
+        resolve_metadata(client, summary.url(), numbered, prefetched, cache, jobs).await
+    {
+        match result {
+            Ok(metadata) if !keep_media(position, &metadata, args, summary) => {
+                set_aside.insert(metadata.metadata_path);
+            }
+            Ok(mut metadata) => {
+                args.prepare_metadata(&mut metadata);
+                all.push(metadata)
+            }
+            Err(err) if args.check => summary.add_unknown(position, audio_url, &reason_of(&err)),
+            Err(err) if web_page_only(&err) => {
+                summary.add_unavailable(position, audio_url, &reason_of(&err));
+                set_aside.insert(audio_url.clone());
+            }
+            Err(err) if args.fail_fast => return Err(err),
+            // The final index is not known yet, report the position on the page.
+            Err(err) => summary.add_failed(FailedEpisode::new(position, audio_url, err)),
+        }
+    }
+    let mut all = dedup_by_audio_url(all, |metadata| metadata, set_aside);
+    warn_missing_parts(all.iter());
+    sort_metadata(&mut all, args.sort);
+    if args.order != order::PageOrder::Page {
+        let dates: Vec<Option<NaiveDate>> = all.iter().map(|metadata| metadata.date).collect();
+        match order::detect(&dates) {
+            order::Detected::Unknown => {
+                outln!("Cannot tell the order of the episodes by date, keeping the page order")
+            }
+            detected if order::needs_reverse(detected, args.order) => all.reverse(),
+            _ => {}
+        }
+    }
+    Ok(number_episodes(all, false, 0, None)
+        .into_iter()
+        .map(|(index, metadata)| Episode { index, metadata })
+        .collect())
+}
+
+/// Keeps the episodes `--skip` and `--select` pick, once the names that depend on the
+/// whole listing are known.
+fn select_episodes(episodes: &mut Vec<Episode>, args: &Args) {
+    episodes.retain(|episode| {
+        episode.index > args.skip
+            && args
+                .select
+                .as_ref()
+                .is_none_or(|selection| selection.contains(episode.index))
+    });
+}
+
+/// Warns when the titles number the episodes as parts and some parts are not on the
+/// page, as when "Lettura XIV" was taken down.
+fn warn_missing_parts<'a>(metadata: impl Iterator<Item = &'a AudioMetadata>) {
+    let parts: Vec<Option<usize>> = metadata.map(AudioMetadata::part).collect();
+    let missing = part::missing_parts(&parts);
+    if !missing.is_empty() {
+        let (noun, verb) = if missing.len() == 1 {
+            ("part", "is")
+        } else {
+            ("parts", "are")
+        };
+        errln!(
+            "Warning: {} {} {} missing from the page",
+            noun,
+            part::format_parts(&missing),
+            verb
+        );
+    }
+}
+
+/// Counts video items and returns whether the episode is kept, which video items only
+/// are with `--include-video`.
+fn keep_media(index: usize, metadata: &AudioMetadata, args: &Args, summary: &ShowSummary) -> bool {
+    if !metadata.video {
+        return true;
+    }
+    summary.add_video();
+    if !args.include_video {
+        outln!(
+            "Skipping video item {:03} - {} (use --include-video to download it)",
+            index,
+            metadata.title
+        );
+    }
+    args.include_video
+}
+
+/// Numbers the episodes within their seasons, taking the season from the show listing
+/// when the metadata has none. Returns whether the show is split into seasons.
+fn number_seasons(episodes: &mut [Episode], seasons: &HashMap<String, usize>) -> bool {
+    for episode in episodes.iter_mut() {
+        let metadata = &mut episode.metadata;
+        if metadata.season.is_none() {
+            metadata.season = seasons.get(&metadata.metadata_path).copied();
+        }
+    }
+    let has_seasons = episodes
+        .iter()
+        .any(|episode| episode.metadata.season.is_some());
+    let known: Vec<(Option<usize>, Option<usize>)> = episodes
+        .iter()
+        .map(|episode| (episode.metadata.season, episode.metadata.episode))
+        .collect();
+    for (episode, (season, number)) in episodes.iter_mut().zip(season::number_episodes(&known)) {
+        episode.metadata.season = Some(season);
+        episode.metadata.episode = Some(number);
+    }
+    has_seasons
+}
+
+/// Where the episodes of a show are saved.
+#[derive(Debug, Clone, Copy, Default)]
+struct Layout {
+    /// Save each season in its own folder.
+    season_dirs: bool,
+    /// Keep numbering the files across seasons even when they have their own folders.
+    global_index: bool,
+}
+
+impl Layout {
+    /// Returns the folder the episode is saved in, which is its season folder with
+    /// `--season-dirs`.
+    fn folder(&self, folder: &Path, metadata: &AudioMetadata) -> PathBuf {
+        match metadata.season {
+            Some(season) if self.season_dirs => folder.join(season::dir_name(season)),
+            _ => folder.to_path_buf(),
+        }
+    }
+
+    /// Returns the number the file of the episode is prefixed with: within season
+    /// folders, numbering restarts with each season unless `--global-index` is given.
+    fn file_index(&self, episode: &Episode) -> Option<usize> {
+        match episode.metadata.episode {
+            Some(number) if self.season_dirs && !self.global_index => Some(number),
+            _ => Some(episode.index),
+        }
+    }
+}
+
+/// Numbers the episodes after the files of the folder for `--continue-numbering`:
+/// episodes numbered in an earlier run, or found in the folder by title, keep their
+/// number, and the others get the numbers after the highest, in page order.
+///
+/// Numbers used twice and gaps are reported, not fixed. Returns the naming options for
+/// the new numbers, padded like the files of the folder unless `--pad-width` is given,
+/// and the numbers to save as the episodes are downloaded.
+fn continue_numbering(
+    episodes: &mut [Episode],
+    folder: &Path,
+    naming: Naming,
+    count: usize,
+    args: &Args,
+) -> Result<(Naming, numbering::NumberMap)> {
+    let files = numbering::scan(folder)?;
+    for problem in numbering::problems(&files) {
+        errln!("Warning: {}", problem);
+    }
+    let mut numbers = numbering::NumberMap::load(folder)?;
+    let mut titles = Vec::with_capacity(episodes.len());
+    for episode in episodes.iter() {
+        let stem = naming::file_stem(&episode.metadata.name_parts(None), &naming)?;
+        titles.push((episode_key(&episode.metadata).to_string(), stem));
+    }
+    for (episode, number) in episodes.iter_mut().zip(numbers.assign(&files, &titles)) {
+        episode.index = number;
+    }
+    for number in numbers.shared_numbers() {
+        errln!(
+            "Warning: number {:03} is given to several episodes in {}",
+            number,
+            numbering::NUMBERS_FILE
+        );
+    }
+    episodes.sort_by_key(|episode| episode.index);
+    let highest = episodes
+        .iter()
+        .map(|episode| episode.index)
+        .chain(files.iter().map(|file| file.number))
+        .fold(count, usize::max);
+    let mut naming = args.naming(highest);
+    if args.pad_width.is_none() {
+        let width = files.iter().map(|file| file.width).max().unwrap_or(0);
+        naming.pad_width = naming.pad_width.max(width);
+    }
+    Ok((naming, numbers))
+}
+
+/// Flags the episodes whose file name another episode of the show would get too, which
+/// happens with `--no-index` or `--stable-names`, so that their names end with a hash
+/// of their id. Every episode sharing a name gets the hash, whatever their order.
+fn mark_duplicates(
+    episodes: &mut [Episode],
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+) -> Result<()> {
+    let mut names: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (position, episode) in episodes.iter().enumerate() {
+        let parts = episode.metadata.name_parts(layout.file_index(episode));
+        let stem = naming::file_stem(&parts, naming)?.to_lowercase();
+        let path = layout.folder(folder, &episode.metadata).join(stem);
+        names.entry(path).or_default().push(position);
+    }
+    for positions in names.into_values().filter(|positions| positions.len() > 1) {
+        for position in positions {
+            let metadata = &mut episodes[position].metadata;
+            metadata.duplicate = true;
+            errln!(
+                "Warning: {:03} - {} has the same file name as another episode, adding ~{}",
+                episodes[position].index,
+                metadata.title,
+                naming::short_hash(episode_key(metadata))
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Numbers the episodes published on the same day for `--name-by-date`, warning about
+/// those without a date, which keep their index.
+fn rank_by_day(episodes: &mut [Episode]) {
+    let dates: Vec<(usize, Option<NaiveDate>)> = episodes
+        .iter()
+        .map(|episode| (episode.index, episode.metadata.date))
+        .collect();
+    let ranks = naming::day_ranks(&dates);
+    for episode in episodes {
+        if episode.metadata.date.is_none() {
+            errln!(
+                "Warning: {:03} - {} has no date, naming it by its index",
+                episode.index,
+                episode.metadata.title
+            );
+        }
+        episode.metadata.day_rank = ranks.get(&episode.index).copied();
+    }
+}
+
+/// Drops the episodes excluded by the title, duration and date filters, logging each one.
+fn filter_episodes(episodes: &mut Vec<Episode>, args: &Args, summary: &ShowSummary) {
+    let before = episodes.len();
+    episodes.retain(|episode| {
+        let allowed = filter::title_allowed(
+            &episode.metadata.title,
+            &args.match_patterns,
+            &args.reject_patterns,
+        );
+        if !allowed {
+            outln!(
+                "Filtered {:03} - {} (title)",
+                episode.index,
+                episode.metadata.title
+            );
+        }
+        allowed
+    });
+    if args.min_duration.is_some() || args.max_duration.is_some() {
+        let min = args.min_duration.map(|d| d.as_secs());
+        let max = args.max_duration.map(|d| d.as_secs());
+        episodes.retain(|episode| match episode.metadata.duration {
+            Some(seconds) => {
+                let allowed = filter::duration_in_range(seconds, min, max);
+                if !allowed {
+                    outln!(
+                        "Filtered {:03} - {} (duration {})",
+                        episode.index,
+                        episode.metadata.title,
+                        duration::format_clock(seconds)
+                    );
+                }
+                allowed
+            }
+            None if args.strict_duration => {
+                outln!(
+                    "Filtered {:03} - {} (unknown duration)",
+                    episode.index,
+                    episode.metadata.title
+                );
+                false
+            }
+            None => true,
+        });
+    }
+    if args.after.is_some() || args.before.is_some() {
+        episodes.retain(|episode| match episode.metadata.date {
+            Some(date) => {
+                let allowed = filter::date_in_range(date, args.after, args.before);
+                if !allowed {
+                    outln!(
+                        "Filtered {:03} - {} (published {})",
+                        episode.index,
+                        episode.metadata.title,
+                        date
+                    );
+                }
+                allowed
+            }
+            None if args.strict_dates => {
+                outln!(
+                    "Filtered {:03} - {} (no publication date)",
+                    episode.index,
+                    episode.metadata.title
+                );
+                false
+            }
+            None => {
+                errln!(
+                    "Warning: {:03} - {} has no publication date, including it",
+                    episode.index,
+                    episode.metadata.title
+                );
+                true
+            }
+        });
+    }
+    summary.add_filtered(before - episodes.len());
+}
+
+/// Returns the metadata of the numbered episodes in their order, each taken from the
+/// show JSON when it was listed there and fetched from its own metadata file otherwise.
+///
+/// Up to `jobs` metadata files are fetched at the same time, so that all of them are
+/// known before the first download starts.
+async fn resolve_metadata<'a, F: Fetcher>(
+    client: &F,
+    show_url: &str,
+    episodes: Vec<(usize, &'a String)>,
+    prefetched: &mut HashMap<String, AudioMetadata>,
+    cache: &SharedCache,
+    jobs: usize,
+) -> Vec<(usize, &'a String, Result<AudioMetadata>)> {
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    for (slot, (_, url)) in episodes.iter().enumerate() {
+        if let Some(metadata) = prefetched.remove(*url) {
+            let _ = sender.send((slot, Ok(metadata)));
+            continue;
+        }
+        let (client, url, cache) = (client.clone(), url.to_string(), Arc::clone(cache));
+        let (sender, semaphore) = (sender.clone(), Arc::clone(&semaphore));
+        tokio::spawn(dump::inherit(async move {
+            let Ok(_permit) = semaphore.acquire_owned().await else {
+                return;
+            };
+            let _ = sender.send((slot, fetch_audio_metadata(&client, &url, &*cache).await));
+        }));
+    }
+    drop(sender);
+
+    let mut results: Vec<Option<Result<AudioMetadata>>> = episodes.iter().map(|_| None).collect();
+    while let Some((slot, result)) = receiver.recv().await {
+        if let Ok(metadata) = &result {
+            observer::emit(|| observer::Event::MetadataFetched {
+                show_url: show_url.to_string(),
+                index: episodes[slot].0,
+                title: metadata.title.clone(),
+                date: metadata.date,
+                duration: metadata.duration,
+            });
+        }
+        results[slot] = Some(result);
+    }
+    episodes
+        .into_iter()
+        .zip(results)
+        .map(|((index, url), result)| {
+            let result = result
+                .unwrap_or_else(|| Err(anyhow::anyhow!("Metadata fetch was cancelled: {}", url)));
+            (index, url, result)
+        })
+        .collect()
+}
+
+/// Fetches audio metadata from the given URL or reads it from the cache if available.
+async fn fetch_audio_metadata<F: Fetcher>(
+    client: &F,
+    url: &str,
+    cache: &dyn Cache,
+) -> Result<AudioMetadata> {
+    let full_url = site::absolute_url(url);
+    let (_, filename) = full_url
+        .rsplit_once('/')
+        .with_context(|| format!("Failed to extract file name from: {}", full_url))?;
+    let entry = cache::Entry::new(&full_url, filename);
+    let cached = cache.contains(&entry);
+
+    let json_content = fetch_cached(client, cache, &entry).await?;
+    let parsed = parse_metadata_body(&full_url, &json_content);
+    if parsed.is_err() {
+        // A bad body would otherwise be read again by every later run.
+        cache.invalidate(&entry)?;
+    }
+    let mut metadata = parse_audio_metadata(&parsed?)?;
+    log::debug!(
+        "Episode {:?} of show {:?}: image {:?}, description {:?}",
+        metadata.id,
+        metadata.show,
+        metadata.image,
+        metadata.description
+    );
+    metadata.metadata_path = url.to_string();
+    metadata.cache_entry = cached.then_some(entry);
+    Ok(metadata)
+}
+
+/// Parses the body of an episode JSON, which some endpoints prefix with a byte order
+/// mark or an anti-XSSI guard, or replace with an HTML page when they fail.
+fn parse_metadata_body(url: &str, body: &str) -> Result<Value, RsndError> {
+    if let Some(snippet) = sniff::html_snippet(body) {
+        return Err(RsndError::MetadataHtml {
+            url: url.to_string(),
+            snippet,
+        });
+    }
+    serde_json::from_str(sniff::json_text(body)).map_err(|source| RsndError::MetadataParse {
+        url: url.to_string(),
+        source,
+    })
+}
+
+/// Reads the audio metadata from an episode JSON, or from a card of the show JSON.
+fn parse_audio_metadata(json_value: &Value) -> Result<AudioMetadata> {
+    let video = !json_value["audio"].is_object() && json_value["video"].is_object();
+    let media = &json_value[if video { "video" } else { "audio" }];
+    let audio_title = media["title"]
+        .as_str()
+        .or_else(|| json_value["title"].as_str().filter(|_| video))
+        .context("Missing field `title`")?
+        .to_string();
+    let (audio_url, source) =
+        audio_url(json_value).ok_or_else(|| missing_audio(json_value, &audio_title))?;
+    let audio_date = [
+        &json_value["track_info"]["date"],
+        &json_value["create_date"],
+        &json_value["literal_publication_date"],
+    ]
+    .into_iter()
+    .filter_map(Value::as_str)
+    .find_map(dates::parse_rai_date);
+    let audio_duration = media["duration"]
+        .as_str()
+        .and_then(duration::parse_clock)
+        .or_else(|| media["duration_in_seconds"].as_u64());
+    let description = first_str(&[
+        &json_value["description"],
+        &json_value["audio"]["description"],
+    ]);
+    let image = first_str(&[
+        &json_value["image"],
+        &json_value["images"]["landscape"],
+        &json_value["images"]["square"],
+        &json_value["audio"]["image"],
+    ])
+    .map(|image| site::absolute_url(&image));
+    let show_image = first_str(&[
+        &json_value["podcast_info"]["image"],
+        &json_value["program"]["image"],
+        &json_value["program_info"]["image"],
+    ])
+    .map(|image| site::absolute_url(&image));
+    let id = first_str(&[&json_value["uniquename"], &json_value["id"]])
+        .or_else(|| json_value["id"].as_u64().map(|id| id.to_string()));
+    let show = first_str(&[
+        &json_value["podcast_info"]["title"],
+        &json_value["program"]["name"],
+        &json_value["program_info"]["name"],
+    ]);
+
+    let (season, episode) = season::from_metadata(json_value);
+    let mut container = Container::from_url(&audio_url).unwrap_or_default();
+    if video {
+        container = container.for_video();
+    }
+    let mut variants = variant::parse_variants(json_value);
+    if !variants.is_empty() && !variants.iter().any(|variant| variant.url == audio_url) {
+        variants.insert(0, variant::Variant::from_url(&audio_url));
+    }
+
+    Ok(AudioMetadata {
+        url: audio_url,
+        title: audio_title,
+        date: audio_date,
+        duration: audio_duration,
+        description,
+        image,
+        show_image,
+        id,
+        show,
+        source,
+        variants,
+        video,
+        media_url: None,
+        original_name: None,
+        media_size: None,
+        container,
+        metadata_path: String::new(),
+        cache_entry: None,
+        day_rank: None,
+        season,
+        episode,
+        duplicate: false,
+        planned_path: None,
+    })
+}
+
+/// Returns the first of the JSON values that is a non-empty string.
+fn first_str(values: &[&Value]) -> Option<String> {
+    values
+        .iter()
+        .filter_map(|value| value.as_str())
+        .map(str::trim)
+        .find(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the audio URL of the episode, trying the known fields in order of preference.
+fn audio_url(json_value: &Value) -> Option<(String, AudioSource)> {
+    let fields = [
+        (&json_value["audio"]["url"], AudioSource::AudioUrl),
+        (
+            &json_value["downloadable_url"],
+            AudioSource::DownloadableUrl,
+        ),
+        (
+            &json_value["audio"]["downloadUrl"],
+            AudioSource::DownloadUrl,
+        ),
+        (
+            &json_value["downloadable_audio"]["url"],
+            AudioSource::DownloadableAudio,
+        ),
+        (&json_value["video"]["url"], AudioSource::VideoUrl),
+    ];
+    fields
+        .into_iter()
+        .find_map(|(field, source)| Some((field.as_str().filter(|url| !url.is_empty())?, source)))
+        .map(|(url, source)| (url.to_string(), source))
+}
+
+/// Returns the error of an episode without an audio URL: its `weblink` is the web page
+/// of the episode, which is not audio, so the episode is skipped as unavailable.
+fn missing_audio(json_value: &Value, title: &str) -> RsndError {
+    match json_value["weblink"].as_str().filter(|url| !url.is_empty()) {
+        Some(weblink) => RsndError::WebPageOnly {
+            url: site::absolute_url(weblink),
+        },
+        None => RsndError::MissingAudioUrl {
+            title: title.to_string(),
+        },
+    }
+}
+
+/// Builds the output path of the audio file for the given metadata.
+///
+/// Without an index, or with stable names, the file name does not depend on the page
+/// position. The extension follows the container unless `--extension` forces one. An
+/// episode of a resumed plan keeps the path it was planned under.
+fn output_path(
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+) -> Result<PathBuf> {
+    if let Some(path) = &metadata.planned_path {
+        return Ok(path.clone());
+    }
+    let stem = naming::file_stem(&metadata.name_parts(idx), naming)?;
+    let extension = naming
+        .extension
+        .unwrap_or_else(|| metadata.container.extension());
+    let path = naming::long_path(folder.join(format!("{}.{}", stem, extension)));
+    naming::confine(folder, &path)?;
+    Ok(path)
+}
+
+/// Returns the path of the audio file when it was already downloaded, in any container,
+/// with any pad width and in any case.
+fn existing_output(
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+) -> Result<Option<PathBuf>> {
+    if naming.original {
+        let names = original::NameMap::load(folder)?;
+        if let Some(name) = names.find(episode_key(metadata)) {
+            let path = folder.join(name);
+            if path.exists() {
+                return Ok(Some(path));
+            }
+        }
+    }
+    let parts = metadata.name_parts(idx);
+    let (folder, stems) = match &metadata.planned_path {
+        Some(path) => (
+            path.parent().unwrap_or(folder),
+            path.file_stem()
+                .map(|stem| vec![stem.to_string_lossy().into_owned()])
+                .unwrap_or_default(),
+        ),
+        None => {
+            let mut stems = vec![naming::file_stem(&parts, naming)?];
+            stems.extend(naming::alternatives(&parts, naming)?);
+            (folder, stems)
+        }
+    };
+    let names: Vec<String> = stems
+        .iter()
+        .flat_map(|stem| {
+            relinker::EXTENSIONS
+                .iter()
+                .map(move |extension| format!("{}.{}", stem, extension))
+        })
+        .collect();
+    naming::find_any_case(folder, &names)
+}
+
+/// Identifies the episode in `names.json` and in the names of episodes with the same
+/// title.
+fn episode_key(metadata: &AudioMetadata) -> &str {
+    metadata.id.as_deref().unwrap_or(&metadata.url)
+}
+
+/// Returns the path of the episode under the name the CDN serves it, prefixed with its
+/// index, when `--original-names` is given and the name is known.
+///
+/// The name is recorded in `names.json`, made unique if another episode has it already.
+fn original_path(
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+) -> Result<Option<PathBuf>> {
+    let Some(name) = metadata
+        .original_name
+        .as_deref()
+        .filter(|_| naming.original)
+    else {
+        return Ok(None);
+    };
+    let name = match naming::index_prefix(idx, naming) {
+        Some(prefix) => format!("{}{}", prefix, name),
+        None => name.to_string(),
+    };
+    let mut names = original::NameMap::load(folder)?;
+    let name = names.assign(
+        &name,
+        original::NameEntry {
+            key: episode_key(metadata).to_string(),
+            title: metadata.title.clone(),
+        },
+    );
+    names.save()?;
+    let path = naming::long_path(folder.join(name));
+    naming::confine(folder, &path)?;
+    Ok(Some(path))
+}
+
+/// Fails with [`RsndError::Clobber`] when a file is in the way that does not look like
+/// one rsnd saved: named like its files, described by an `.info.json` or listed in
+/// `names.json`. `--force-unsafe` replaces it anyway.
+fn check_overwrite(path: &Path, force_unsafe: bool) -> Result<()> {
+    if force_unsafe || !path.exists() {
+        return Ok(());
+    }
+    let name = |name: Option<&std::ffi::OsStr>| {
+        name.and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let listed = match path.parent() {
+        Some(folder) => original::NameMap::load(folder)?.lists(&name(path.file_name())),
+        None => false,
+    };
+    if listed
+        || naming::scheme_prefixed(&name(path.file_stem()))
+        || info::sidecar_path(path).exists()
+    {
+        return Ok(());
+    }
+    Err(RsndError::Clobber {
+        path: path.to_path_buf(),
+    }
+    .into())
+}
+
+/// Warns when the existing file of an episode is numbered with another pad width than
+/// the one it would be saved with now.
+fn warn_other_width(existing: &Path, output_path: &Path) {
+    let stem = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+    };
+    if stem(existing) != stem(output_path) {
+        errln!(
+            "Warning: {} is numbered with another pad width than {}",
+            existing.display(),
+            output_path.display()
+        );
+    }
+}
+
+/// Formats a byte count as a human readable size.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Requests only the headers of the given URL and returns the advertised size, if any.
+async fn fetch_content_length<F: Fetcher>(client: &F, url: &str) -> Result<Option<u64>> {
+    let response = client
+        .head(url)
+        .await
+        .with_context(|| format!("Failed to fetch headers of URL: {}", url))?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Failed to fetch headers of URL: {}. Status: {}",
+            url,
+            response.status()
+        ));
+    }
+
+    Ok(fetcher::content_length(&response))
+}
+
+/// Fails with [`RsndError::SizeLimit`] when the advertised size of the audio is outside
+/// the bounds. The size comes from the relinker resolution, or else a HEAD request; HLS
+/// playlists and servers that do not answer HEAD requests count as not advertising one.
+async fn check_size<F: Fetcher>(
+    client: &F,
+    metadata: &AudioMetadata,
+    media_url: &str,
+    bounds: &size::Bounds,
+) -> Result<()> {
+    if !bounds.is_set() {
+        return Ok(());
+    }
+    let size = if metadata.media_size.is_some() || metadata.container == Container::Hls {
+        metadata.media_size
+    } else {
+        let size = fetch_content_length(client, media_url)
+            .await
+            .unwrap_or_else(|err| {
+                log::debug!("Cannot probe the size of {}: {:#}", media_url, err);
+                None
+            });
+        if let Some(size) = size {
+            resolutions::set_size(&metadata.metadata_url(), size);
+        }
+        size
+    };
+    match bounds.check(size) {
+        Some(reason) => Err(RsndError::SizeLimit { reason }.into()),
+        None => Ok(()),
+    }
+}
+
+/// Prints what `download_audio` would do for the given metadata without writing anything,
+/// with the probed size of the audio, if any.
+///
+/// Returns `true` when the episode would actually be downloaded.
+fn plan_audio(
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    size: Option<&estimate::Probe>,
+) -> Result<bool> {
+    let output_path = output_path(metadata, folder, idx, naming)?;
+    if let Some(existing) = existing_output(metadata, folder, idx, naming)? {
+        warn_other_width(&existing, &output_path);
+        outln!("{} (exists, would skip)", existing.display());
+        return Ok(false);
+    }
+
+    match size {
+        Some(size) => outln!("{} (would download, {})", output_path.display(), size),
+        None => outln!("{} (would download)", output_path.display()),
+    }
+    Ok(true)
+}
+
+/// Resolves the audio of each episode through the relinker, up to `jobs` at a time, and
+/// returns the sizes the CDN advertises. The resolution is kept for the download.
+async fn probe_sizes<F: Fetcher>(
+    client: &F,
+    pending: &mut [&mut AudioMetadata],
+    jobs: usize,
+) -> Vec<estimate::Probe> {
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = Vec::with_capacity(pending.len());
+    for metadata in pending.iter() {
+        let (client, url) = (client.clone(), metadata.url.clone());
+        let (metadata_url, semaphore) = (metadata.metadata_url(), Arc::clone(&semaphore));
+        tasks.push(tokio::spawn(dump::inherit(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            resolutions::resolve(&client, &metadata_url, &url).await
+        })));
+    }
+    let mut probes = Vec::with_capacity(tasks.len());
+    for (metadata, task) in pending.iter_mut().zip(tasks) {
+        let probe = match task.await {
+            Ok(Ok(resolved)) => {
+                metadata.set_resolved(resolved);
+                match metadata.media_size {
+                    Some(bytes) => estimate::Probe::Known(bytes),
+                    None => estimate::Probe::Unknown("size not advertised".to_string()),
+                }
+            }
+            Ok(Err(err)) => estimate::Probe::Unknown(format!("{:#}", err)),
+            Err(err) => estimate::Probe::Unknown(err.to_string()),
+        };
+        probes.push(probe);
+    }
+    probes
+}
+
+/// Starts resolving the audio of the upcoming episodes that are not in their folder yet,
+/// while the current one downloads.
+fn prefetch_audio<F: Fetcher>(
+    client: &F,
+    lookahead: &mut Lookahead,
+    upcoming: &[Episode],
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+) -> Result<()> {
+    for episode in upcoming {
+        if episode.metadata.media_url.is_some() {
+            continue;
+        }
+        let folder = layout.folder(folder, &episode.metadata);
+        let idx = layout.file_index(episode);
+        if existing_output(&episode.metadata, &folder, idx, naming)?.is_none() {
+            lookahead.prefetch(
+                client,
+                episode.index,
+                &episode.metadata.metadata_url(),
+                &episode.metadata.url,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Probes the size of the episodes that would be downloaded, those not in their folder
+/// yet and within `--limit`, and returns their total with the size of each by index.
+async fn estimate_episodes<F: Fetcher>(
+    client: &F,
+    episodes: &mut [Episode],
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+    args: &Args,
+) -> Result<(estimate::Estimate, HashMap<usize, estimate::Probe>)> {
+    let mut pending = Vec::new();
+    for (position, episode) in episodes.iter_mut().enumerate() {
+        let limit = match args.limit_count {
+            LimitCount::All => position,
+            LimitCount::Downloads => pending.len(),
+        };
+        if args.limit.is_some_and(|max| limit >= max) {
+            break;
+        }
+        let folder = layout.folder(folder, &episode.metadata);
+        let idx = layout.file_index(episode);
+        if existing_output(&episode.metadata, &folder, idx, naming)?.is_none() {
+            pending.push((episode.index, &mut episode.metadata));
+        }
+    }
+    let (indices, mut metadata): (Vec<usize>, Vec<&mut AudioMetadata>) =
+        pending.into_iter().unzip();
+    let probes = probe_sizes(client, &mut metadata, usize::from(args.metadata_jobs)).await;
+
+    let mut estimate = estimate::Estimate::default();
+    for ((index, metadata), probe) in indices.iter().zip(&metadata).zip(&probes) {
+        estimate.add(*index, &metadata.title, probe);
+    }
+    Ok((estimate, indices.into_iter().zip(probes).collect()))
+}
+
+/// Prints the estimate before downloading and asks whether to go on, unless `--yes` is
+/// given. Returns whether to download.
+fn confirm_estimate(estimate: &estimate::Estimate, args: &Args) -> Result<bool> {
+    outln!("{}", estimate.to_string().trim_end_matches('\n'));
+    if args.yes || estimate.count() == 0 {
+        return Ok(true);
+    }
+    let confirmed = estimate::confirm(estimate)?;
+    if !confirmed {
+        outln!("Nothing downloaded");
+    }
+    Ok(confirmed)
+}
+
+/// Returns the size of a file on disk, if it can be read.
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|file| file.len())
+}
+
+/// Returns the cache entry holding the size the CDN advertised for the audio of an
+/// episode, so that a later `--list` shows it without probing again.
+fn size_entry(metadata: &AudioMetadata) -> cache::Entry {
+    let key = format!("{} {}", metadata.metadata_url(), metadata.url);
+    cache::Entry::new(
+        format!("{}#size", metadata.url),
+        format!("size-{}.txt", cache::digest(&key)),
+    )
+}
+
+/// Returns whether each episode is in its folder and its size when known, for `--list`:
+/// the size of the file for those present, else the one probed by an earlier list, else
+/// the one the CDN advertises for the first `--probe-sizes` episodes left.
+async fn list_sizes<F: Fetcher>(
+    client: &F,
+    episodes: &mut [Episode],
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+    cache: &dyn Cache,
+    args: &Args,
+) -> Result<Vec<(bool, Option<u64>)>> {
+    let mut sizes = Vec::with_capacity(episodes.len());
+    let mut pending = Vec::new();
+    for (position, episode) in episodes.iter_mut().enumerate() {
+        let folder = layout.folder(folder, &episode.metadata);
+        let idx = layout.file_index(episode);
+        if let Some(existing) = existing_output(&episode.metadata, &folder, idx, naming)? {
+            sizes.push((true, file_size(&existing)));
+            continue;
+        }
+        let memoized = cache
+            .get(&size_entry(&episode.metadata))?
+            .and_then(|body| body.trim().parse().ok());
+        if memoized.is_none() && args.probe_sizes.is_some_and(|max| pending.len() < max) {
+            pending.push((position, &mut episode.metadata));
+        }
+        sizes.push((false, memoized));
+    }
+    let (positions, mut metadata): (Vec<usize>, Vec<&mut AudioMetadata>) =
+        pending.into_iter().unzip();
+    let probes = probe_sizes(client, &mut metadata, usize::from(args.metadata_jobs)).await;
+    for ((position, metadata), probe) in positions.into_iter().zip(&metadata).zip(probes) {
+        match probe {
+            estimate::Probe::Known(bytes) => {
+                cache.put(&size_entry(metadata), &bytes.to_string())?;
+                sizes[position].1 = Some(bytes);
+            }
+            estimate::Probe::Unknown(reason) => {
+                log::debug!("No size for {}: {}", metadata.title, reason);
+            }
+        }
+    }
+    Ok(sizes)
+}
+
+/// Prints the episode table for `--list`; with `--json`, keeps the rows for the JSON
+/// printed once every show is listed.
+fn print_rows(rows: Vec<EpisodeRow>, json: bool, summary: &ShowSummary) {
+    if json {
+        summary.add_listed(rows);
+    } else {
+        let table = list::render_table(&rows, list::terminal_width());
+        outln!("{}", table.trim_end_matches('\n'));
+    }
+}
+
+/// Returns whether the download error hints at an expired audio URL.
+fn looks_expired(err: &anyhow::Error) -> bool {
+    RsndError::of(err).is_some_and(RsndError::looks_expired)
+}
+
+/// Downloads the episode like `download_audio`, refreshing its metadata once when the
+/// audio URL read from the cache turns out to have expired.
+async fn download_fresh_audio<F: Fetcher>(
+    client: &F,
+    metadata: &mut AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    cache: &dyn Cache,
+    options: &DownloadOptions,
+) -> Result<Option<Transfer>> {
+    let err = match download_audio(client, metadata, folder, idx, options).await {
+        Err(err) if looks_expired(&err) => err,
+        result => return result,
+    };
+    let Some(entry) = metadata.cache_entry.take() else {
+        return Err(err);
+    };
+
+    outln!(
+        "Refreshing stale cache entry {} for {} ({:#})",
+        entry.url,
+        metadata.title,
+        err
+    );
+    cache.invalidate(&entry)?;
+    let fresh = fetch_audio_metadata(client, &metadata.metadata_path, cache)
+        .await
+        .context("Failed to refresh the episode metadata")?;
+    metadata.refresh_audio(fresh);
+    metadata.select_variant(&options.variant);
+    download_audio(client, metadata, folder, idx, options).await
+}
+
+/// Downloads audio from the given metadata and saves it to the specified folder.
+///
+/// Returns how the download went, or `None` when the file already exists and the
+/// download was skipped.
+async fn download_audio<F: Fetcher>(
+    client: &F,
+    metadata: &mut AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    options: &DownloadOptions,
+) -> Result<Option<Transfer>> {
+    if let Some(existing) = existing_output(metadata, folder, idx, &options.naming)? {
+        warn_other_width(
+            &existing,
+            &output_path(metadata, folder, idx, &options.naming)?,
+        );
+        outln!(
+            "File {} already exists. Skipping download.",
+            existing.display()
+        );
+        return Ok(None);
+    }
+
+    if metadata.source != AudioSource::AudioUrl {
+        outln!(
+            "Using `{}` as the audio URL of {}",
+            metadata.source.field(),
+            metadata.title
+        );
+    }
+
+    if metadata.media_url.is_none() {
+        let resolved =
+            resolutions::resolve(client, &metadata.metadata_url(), &metadata.url).await?;
+        metadata.set_resolved(resolved);
+    }
+    let media_url = metadata
+        .media_url
+        .clone()
+        .unwrap_or_else(|| metadata.url.clone());
+    check_size(client, metadata, &media_url, &options.size_bounds).await?;
+
+    if metadata.container == Container::Hls {
+        let (segments, container) = hls::media_segments(client, &media_url).await?;
+        metadata.container = container;
+        let output_path = output_path(metadata, folder, idx, &options.naming)?;
+        check_overwrite(&output_path, options.force_unsafe)?;
+        outln!(
+            "Downloading {} segments of {}",
+            segments.len(),
+            metadata.title
+        );
+        let mut meter = transfer::Meter::start();
+        let bytes =
+            hls::download_segments(client, &segments, &output_path, options.accept_any_content)
+                .await?;
+        meter.add(bytes);
+        let transfer = meter.finish();
+        outln!(
+            "Downloaded {} to {} ({})",
+            metadata.title,
+            output_path.display(),
+            transfer
+        );
+        return Ok(Some(transfer));
+    }
+
+    let output_path = match original_path(metadata, folder, idx, &options.naming)? {
+        Some(path) => path,
+        None => output_path(metadata, folder, idx, &options.naming)?,
+    };
+    let video = metadata.video;
+    let metadata_url = metadata.metadata_url();
+    let wasted = AtomicU64::new(0);
+    let attempt = options
+        .retry
+        .run(&metadata.title, || async {
+            let result = stream_audio(client, &media_url, &output_path, video, options).await;
+            if let Err(err) = &result {
+                // The signature of the CDN URL expired: the next run of the episode
+                // resolves it again.
+                if let Some(RsndError::Status {
+                    status: reqwest::StatusCode::FORBIDDEN,
+                    ..
+                }) = RsndError::of(err)
+                {
+                    resolutions::forget(&metadata_url);
+                }
+                if let Some(
+                    RsndError::ShortRead { received, .. } | RsndError::Stalled { received, .. },
+                ) = RsndError::of(err)
+                {
+                    wasted.fetch_add(*received, Ordering::Relaxed);
+                }
+            }
+            result
+        })
+        .await;
+    let (mut transfer, output_path) = match attempt {
+        Err(err) if mirror::should_fall_back(&err) => {
+            let mirrors = mirror::mirrors(&media_url, &options.mirrors);
+            stream_mirrors(client, &mirrors, &output_path, video, options, err).await?
+        }
+        attempt => attempt?,
+    };
+    transfer.wasted = wasted.into_inner();
+    outln!(
+        "Downloaded {} to {} ({})",
+        metadata.title,
+        output_path.display(),
+        transfer
+    );
+    Ok(Some(transfer))
+}
+
+/// Streams the audio from each mirror in turn, after the CDN host failed with `err`,
+/// and returns the transfer of the first one that serves it, or else `err`.
+async fn stream_mirrors<F: Fetcher>(
+    client: &F,
+    mirrors: &[String],
+    output_path: &Path,
+    video: bool,
+    options: &DownloadOptions,
+    err: anyhow::Error,
+) -> Result<(Transfer, PathBuf)> {
+    for url in mirrors {
+        let host = mirror::host(url);
+        outln!("Trying mirror {} ({:#})", host, err);
+        match stream_audio(client, url, output_path, video, options).await {
+            Ok((mut transfer, output_path)) => {
+                outln!("Mirror {} served {}", host, output_path.display());
+                transfer.mirror = Some(host);
+                return Ok((transfer, output_path));
+            }
+            Err(mirror_err) => log::debug!("Mirror {} failed: {:#}", host, mirror_err),
+        }
+    }
+    Err(err)
+}
+
+/// Number of leading bytes inspected to tell audio from error pages.
+const SNIFF_LEN: usize = 512;
+
+/// Streams the audio at `media_url` into `output_path` and returns how the transfer
+/// went and the final path, whose extension follows the container the body turns out
+/// to be in.
+///
+/// The body is written to a `.part` file that is renamed once the byte count matches
+/// the advertised Content-Length, so an interrupted download never looks complete.
+/// MP4 bodies of video items are saved as `.mp4` rather than `.m4a`.
+async fn stream_audio<F: Fetcher>(
+    client: &F,
+    media_url: &str,
+    output_path: &Path,
+    video: bool,
+    options: &DownloadOptions,
+) -> Result<(Transfer, PathBuf)> {
+    let mut meter = transfer::Meter::start();
+    let mut response = client.get_stream(media_url).await?;
+
+    geo::check_response(&response)?;
+    let status = response.status();
+    if !status.is_success() {
+        let refused = RsndError::status(media_url, &response);
+        if status == reqwest::StatusCode::FORBIDDEN {
+            let body = response.text().await.unwrap_or_default();
+            if let Some(reason) = geo::refusal_reason(status, &body) {
+                return Err(RsndError::GeoBlocked { reason, status }.into());
+            }
+        }
+        return Err(refused.into());
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let expected = response.content_length();
+
+    let mut watch = stall::Watch::start(options.stall);
+    let mut head = Vec::new();
+    while head.len() < SNIFF_LEN {
+        match next_chunk(&mut response, &mut watch).await?? {
+            Some(chunk) => head.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    if !options.accept_any_content {
+        sniff::check_audio(content_type.as_deref(), &head[..head.len().min(SNIFF_LEN)])
+            .with_context(|| format!("Refusing to save {}", media_url))?;
+    }
+    // The body knows its container better than the relinker did.
+    let detected = Container::from_magic(&head)
+        .or_else(|| {
+            content_type
+                .as_deref()
+                .and_then(Container::from_content_type)
+        })
+        .filter(|container| *container != Container::Hls)
+        .map(|container| {
+            if video {
+                container.for_video()
+            } else {
+                container
+            }
+        });
+    let output_path = match detected {
+        Some(container) if options.naming.extension.is_none() && !options.naming.original => {
+            output_path.with_extension(container.extension())
+        }
+        _ => output_path.to_path_buf(),
+    };
+    check_overwrite(&output_path, options.force_unsafe)?;
+
+    let mut partial = atomic::Partial::create(&output_path)
+        .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
+    let mut file = TokioFile::from_std(partial.file().try_clone()?);
+
+    let mut received = 0;
+    let mut progress = observer::Progress::new(&output_path, expected);
+    let mut data = head;
+    loop {
+        file.write_all(&data)
+            .await
+            .with_context(|| format!("Failed to write to file: {}", output_path.display()))?;
+        received += data.len() as u64;
+        meter.add(data.len() as u64);
+        progress.update(received);
+        data = match next_chunk(&mut response, &mut watch).await {
+            Ok(Ok(Some(chunk))) => chunk,
+            Ok(Ok(None)) => break,
+            // A dropped connection shows up as a short read below.
+            Ok(Err(err)) => {
+                log::debug!("Download of {} interrupted: {}", media_url, err);
+                break;
+            }
+            Err(stalled) => return Err(stalled.into()),
+        };
+    }
+    file.flush().await?;
+    drop(file);
+
+    if received == 0 || expected.is_some_and(|expected| expected != received) {
+        let expected = expected.filter(|expected| *expected != 0);
+        return Err(RsndError::ShortRead { received, expected }.into());
+    }
+    partial
+        .persist()
+        .with_context(|| format!("Failed to write {}", output_path.display()))?;
+    Ok((meter.finish(), output_path))
+}
+
+/// Waits for the next chunk of the body, failing with [`RsndError::Stalled`] when the
+/// download has been too slow for `--stall-timeout`.
+async fn next_chunk(
+    response: &mut Response,
+    watch: &mut stall::Watch,
+) -> Result<reqwest::Result<Option<Vec<u8>>>, RsndError> {
+    let chunk = match watch.timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, response.chunk())
+            .await
+            .map_err(|_| watch.silent())?,
+        None => response.chunk().await,
+    };
+    if let Ok(Some(chunk)) = &chunk {
+        watch.add(chunk.len() as u64)?;
+    }
+    Ok(chunk.map(|chunk| chunk.map(|chunk| chunk.to_vec())))
+}
+
+/// Builds the HTTP client, keeping the cookies of the run in `jar` when given.
+fn get_client(options: &ClientOptions, jar: Option<Arc<reqwest::cookie::Jar>>) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8".parse().unwrap());
+    headers.insert("accept-language", "en-US,en;q=0.7".parse().unwrap());
+    headers.insert("priority", "u=0, i".parse().unwrap());
+    headers.insert(
+        "sec-ch-ua",
+        "\"Not/A)Brand\";v=\"8\", \"Chromium\";v=\"126\", \"Brave\";v=\"126\""
+            .parse()
+            .unwrap(),
+    );
+    headers.insert("sec-ch-ua-mobile", "?0".parse().unwrap());
+    headers.insert("sec-ch-ua-platform", "\"Linux\"".parse().unwrap());
+    headers.insert("sec-fetch-dest", "document".parse().unwrap());
+    headers.insert("sec-fetch-mode", "navigate".parse().unwrap());
+    headers.insert("sec-fetch-site", "none".parse().unwrap());
+    headers.insert("sec-fetch-user", "?1".parse().unwrap());
+    headers.insert("sec-gpc", "1".parse().unwrap());
+    headers.insert("upgrade-insecure-requests", "1".parse().unwrap());
+    headers.insert("user-agent", "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36".parse().unwrap());
+
+    let mut builder = Client::builder()
+        .default_headers(headers.clone())
+        .redirect(reqwest::redirect::Policy::limited(5))
+        .pool_idle_timeout(options.pool_idle_timeout)
+        .pool_max_idle_per_host(options.pool_max_idle)
+        .tcp_keepalive(options.tcp_keepalive);
+    builder = match jar {
+        Some(jar) => builder.cookie_provider(jar),
+        None => builder.cookie_store(true),
+    };
+    if options.http1_only {
+        builder = builder.http1_only();
+    }
+    if options.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    let client = builder.build().context("Failed to build HTTP client")?;
+    Ok(client)
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(err) => {
+            let _ = err.print();
+            // Help and version requests are not errors.
+            std::process::exit(if err.use_stderr() { EXIT_USAGE } else { 0 });
+        }
+    };
+    if args.list_retry_classes {
+        retry::print_classes();
+        return;
+    }
+
+    // A search without --download prints its results and nothing else.
+    let searching =
+        matches!(&args.command, Some(Command::Search(search)) if search.download.is_none());
+    let recording = matches!(&args.command, Some(Command::Record(_)));
+    let retagging = matches!(&args.command, Some(Command::Retag(_)));
+    let listing = args.list || args.list_shows;
+    let print_summary = !listing && !searching && !recording && !retagging;
+    // --list --json and --list-shows --json print the episodes or shows as JSON instead
+    // of the summary.
+    let print_json = args.json && !searching && !listing;
+    let summary_json = args.summary_json.clone();
+    let notify_desktop = args.notify_desktop;
+    let watch = args.watch.then_some(args.interval);
+
+    loop {
+        let outcome = run_once(
+            args.clone(),
+            recording,
+            print_summary,
+            print_json,
+            summary_json.as_deref(),
+            notify_desktop,
+            watch,
+        )
+        .await;
+        if watch.is_none() || outcome == Outcome::Interrupted {
+            std::process::exit(outcome.exit_code());
+        }
+    }
+}
+
+/// Runs once and reports the run; with `--watch`, waits until the next run is due.
+async fn run_once(
+    args: Args,
+    recording: bool,
+    print_summary: bool,
+    print_json: bool,
+    summary_json: Option<&Path>,
+    notify_desktop: bool,
+    watch: Option<schedule::Interval>,
+) -> Outcome {
+    let summary = Arc::new(Summary::new());
+    let print_listed = args.list && args.json;
+    let failed = |err: anyhow::Error| {
+        eprintln!("Error: {:?}", err);
+        Outcome::Error
+    };
+    // A recording stops on Ctrl+C by itself, keeping what it captured.
+    let outcome = if recording {
+        run(args, summary.clone()).await.unwrap_or_else(failed)
+    } else {
+        tokio::select! {
+            result = run(args, summary.clone()) => result.unwrap_or_else(failed),
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("Interrupted");
+                Outcome::Interrupted
+            }
+        }
+    };
+
+    let mut report = summary.report(outcome == Outcome::Interrupted);
+    let mut next_poll = None;
+    if let (Some(interval), false) = (watch, report.interrupted) {
+        let schedules: Vec<Option<&str>> = report
+            .shows
+            .iter()
+            .map(|show| show.schedule.as_deref())
+            .collect();
+        let (next, reason) =
+            schedule::next_poll(&schedules, interval, chrono::Local::now().naive_local());
+        let next = next
+            .and_local_timezone(chrono::Local)
+            .earliest()
+            .unwrap_or_else(|| chrono::Local::now() + schedule::POLL_DELAY);
+        report.next_poll = Some(next);
+        next_poll = Some((next, reason));
+    }
+    observer::emit(|| observer::Event::RunFinished {
+        found: report.total.found,
+        downloaded: report.total.downloaded,
+        downloaded_bytes: report.total.downloaded_bytes,
+        skipped: report.total.skipped,
+        failed: report.total.failed.len(),
+        elapsed_secs: report.elapsed_secs,
+        interrupted: report.interrupted,
+    });
+    if print_listed {
+        match list::render_json(&summary.take_listed()) {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("Error: {:?}", err),
+        }
+    }
+    if print_json {
+        match report.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("Error: {:?}", err),
+        }
+    } else if print_summary {
+        print!("{}", report);
+    }
+    if let Some(path) = summary_json {
+        if let Err(err) = report.write_json(path) {
+            eprintln!("Error: {:?}", err);
+        }
+    }
+    if notify_desktop {
+        let failed = outcome.severity() >= Outcome::EpisodesFailed.severity();
+        notify::send(&notify::Notification::new(&report, failed));
+    }
+
+    if let Some((next, reason)) = next_poll {
+        outln!(
+            "Next poll at {} ({})",
+            next.format("%Y-%m-%d %H:%M"),
+            reason
+        );
+        let wait = (next - chrono::Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("Interrupted");
+                return Outcome::Interrupted;
+            }
+        }
+    }
+    outcome
+}
+
+/// Records a live channel into the output folder.
+async fn run_record<F: Fetcher>(
+    client: &F,
+    record_args: &RecordArgs,
+    args: &Args,
+) -> Result<Outcome> {
+    let channel = record::fetch_channel(client, &record_args.channel).await?;
+    outln!(
+        "Recording {} for {}",
+        channel.name,
+        duration::format_clock(record_args.duration.as_secs())
+    );
+    let recording = record::record(
+        client,
+        &channel,
+        &args.folder,
+        record_args.duration,
+        args.accept_any_content,
+    )
+    .await?;
+    match recording.stop {
+        record::Stop::Duration => {}
+        record::Stop::Interrupted => errln!("Interrupted"),
+        record::Stop::Ended => errln!("Warning: the stream of {} ended early", channel.name),
+    }
+    outln!(
+        "Recorded {} to {}",
+        recording.transfer,
+        recording.path.display()
+    );
+    if recording.stop == record::Stop::Interrupted {
+        Ok(Outcome::Interrupted)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
+
+/// Returns whether the arguments ask for audio to be downloaded, as opposed to only
+/// listing, planning, checking or searching.
+fn downloads_audio(args: &Args) -> bool {
+    let searching =
+        matches!(&args.command, Some(Command::Search(search)) if search.download.is_none());
+    let retagging = matches!(&args.command, Some(Command::Retag(_)));
+    !(args.list || args.dry_run || args.check || searching || retagging)
+}
+
+/// Runs the whole download pipeline for every show in the arguments.
+///
+/// Shows are processed one after the other, sharing the client, the cache and the
+/// summary; a failing show does not stop the others unless `--fail-fast` is set.
+async fn run(mut args: Args, summary: Arc<Summary>) -> Result<Outcome> {
+    if args.interactive {
+        pick::ensure_terminal()?;
+    }
+    if args.offline && downloads_audio(&args) {
+        return Err(anyhow::anyhow!(
+            "Cannot download audio with --offline; add --list, --dry-run or --check to work \
+             from the cache, or drop --offline"
+        ));
+    }
+
+    create_dir_all(&args.folder).with_context(|| {
+        format!(
+            "Failed to create folder directory: {}. Error: {:?}",
+            &args.folder.display(),
+            std::io::Error::last_os_error()
+        )
+    })?;
+
+    let cache: SharedCache = if args.no_cache {
+        Arc::new(cache::NoCache)
+    } else {
+        let cache_dir = args.cache.clone().unwrap_or_else(dirs::cache_dir);
+        if args.cache.is_none() && !cache_dir.exists() {
+            errln!(
+                "Caching pages in {}; those cached by earlier versions in {} are no longer read",
+                cache_dir.display(),
+                std::env::temp_dir().display()
+            );
+        }
+        create_dir_all(&cache_dir).with_context(|| {
+            format!(
+                "Failed to create cache directory: {}. Error: {:?}",
+                cache_dir.display(),
+                std::io::Error::last_os_error()
+            )
+        })?;
+        Arc::new(cache::FileCache::new(cache_dir))
+    };
+
+    let jar = match &args.cookies_from_browser {
+        Some(spec) => {
+            let cookies = browser::load(spec).context("Failed to read browser cookies")?;
+            errln!("Read {} cookies from the browser", cookies.len());
+            Some(Arc::new(browser::jar(&cookies)))
+        }
+        None => None,
+    };
+    let client = get_client(&args.client_options(), jar).with_context(|| {
+        format!(
+            "Failed to create the reqwest client. Error: {:?}",
+            std::io::Error::last_os_error()
+        )
+    })?;
+    let client = if args.offline {
+        HttpFetcher::offline(client)
+    } else {
+        HttpFetcher::new(client)
+    };
+    // Each run of --watch installs its own, replacing those of the run before.
+    ratelimit::install(args.rps);
+    observer::install(
+        args.progress_json
+            .then(|| Box::new(observer::JsonLines) as Box<dyn observer::Observer>),
+    );
+    output::install_json(args.json);
+    pace::install(args.sleep, args.sleep_jitter.unwrap_or_default());
+    site::install(args.base_url.clone());
+    retry::install(args.retry_policy());
+    resolutions::install();
+    plan::install_saving(args.save_plan.as_deref());
+    plan::install_resumed(None);
+    dump::install(args.dump_pages.as_deref());
+
+    if let Some(Command::Record(record_args)) = &args.command {
+        return run_record(&client, record_args, &args).await;
+    }
+
+    if let Some(Command::Search(search)) = &args.command {
+        let results = run_search(&client, &search.query, &*cache, args.json, args.offline).await?;
+        let Some(n) = search.download else {
+            return Ok(Outcome::Success);
+        };
+        let result = n
+            .checked_sub(1)
+            .and_then(|position| results.get(position))
+            .with_context(|| format!("No search result number {}", n))?;
+        outln!("Downloading {}", result.url);
+        args.url.push(result.url.clone());
+    }
+
+    if let Some(catalog) = &args.crawl {
+        let links = crawl_catalog(&client, catalog, &*cache).await?;
+        let found = links.len();
+        let urls: Vec<String> = links
+            .into_iter()
+            .filter(|link| filter::title_allowed(&link.title, &args.crawl_match, &[]))
+            .take(args.crawl_limit.unwrap_or(usize::MAX))
+            .map(|link| link.url)
+            .collect();
+        outln!(
+            "Crawling {} of the {} shows in {}",
+            urls.len(),
+            found,
+            catalog
+        );
+        args.url.extend(urls);
+        args.show_subdirs = true;
+    }
+
+    let mut shows: Vec<(String, PathBuf)> = args
+        .url
+        .iter()
+        .chain(&args.urls)
+        .map(|url| (url.clone(), args.folder.clone()))
+        .collect();
+    for path in &args.input_file {
+        shows.push((input_file_url(path)?, args.folder.clone()));
+    }
+    if let Some(batch_file) = &args.batch_file {
+        let entries = batch::read_batch_file(batch_file)?;
+        batch::check_folders(&entries, &args.folder)?;
+        for entry in entries {
+            let folder = entry.output_folder(&args.folder);
+            shows.push((entry.url, folder));
+        }
+    }
+    if let Some(path) = &args.resume_plan {
+        let resumed = plan::read(path)?;
+        let age = resumed.age();
+        if age > args.plan_max_age {
+            errln!(
+                "Warning: {} was planned {} ago, the audio URLs it holds may have expired",
+                path.display(),
+                duration::format_clock(age.as_secs())
+            );
+        }
+        shows = resumed
+            .shows
+            .iter()
+            .map(|show| (show.url.clone(), show.folder.clone()))
+            .collect();
+        plan::install_resumed(Some(resumed));
+    }
+    if matches!(&args.command, Some(Command::Retag(_))) && shows.is_empty() {
+        return Err(anyhow::anyhow!(
+            "retag needs the show the folder was downloaded from: give it with --url"
+        ));
+    }
+    // With several destinations, the summary tells which show went where.
+    let destinations = shows
+        .iter()
+        .any(|(_, folder)| !same_folder(folder, &args.folder));
+
+    // A mistyped URL fails the run before the first show is downloaded.
+    for (show_url, _) in &shows {
+        if local_page(show_url).is_none() {
+            batch::validate_url(show_url)?;
+        }
+    }
+
+    let mut found_on = HashMap::new();
+    if args.list_shows || args.follow_shows {
+        (shows, found_on) = follow_landing_pages(&client, shows, &*cache, &args).await?;
+        if args.list_shows {
+            return Ok(Outcome::Success);
+        }
+    }
+
+    // With --show-jobs, shows run at the same time and their lines are told apart by a
+    // prefix instead of a header. A failing show does not stop the others, unless
+    // --fail-fast is given: the shows still running are then dropped.
+    let several = shows.len() > 1;
+    let concurrent = args.show_jobs > 1 && several;
+    if concurrent {
+        check_show_jobs(&args)?;
+    }
+    let (client, cache, args, found_on) = (&client, &cache, &args, &found_on);
+    let mut results = futures::stream::iter(shows.iter().enumerate())
+        .map(|(position, (show_url, folder))| {
+            let show = summary.start_show(show_url);
+            if destinations {
+                show.set_folder(folder);
+            }
+            if let Some(landing_url) = found_on.get(show_url) {
+                show.set_found_on(landing_url);
+            }
+            async move {
+                let running = dump::scope(
+                    show_url,
+                    run_show(client, show_url, folder, cache, args, &show),
+                );
+                if concurrent {
+                    let prefix = output::show_prefix(show_url);
+                    output::scope(prefix, running).await
+                } else {
+                    if several {
+                        if position > 0 {
+                            outln!();
+                        }
+                        outln!("==> {}", show_url);
+                    }
+                    running.await
+                }
+            }
+        })
+        .buffer_unordered(usize::from(args.show_jobs));
+
+    let mut outcome = Outcome::NothingToDo;
+    while let Some(result) = results.next().await {
+        let show_outcome = match result {
+            Ok(show_outcome) => show_outcome,
+            // A page without episodes leaves nothing to do rather than failing the run.
+            Err(err) if matches!(RsndError::of(&err), Some(RsndError::NoEpisodes { .. })) => {
+                errln!("Error: {:?}", err);
+                Outcome::NothingToDo
+            }
+            Err(err) if args.fail_fast => return Err(err),
+            Err(err) => {
+                errln!("Error: {:?}", err);
+                Outcome::Error
+            }
+        };
+        if args.fail_fast && show_outcome != Outcome::Success {
+            return Ok(show_outcome);
+        }
+        outcome = outcome.combine(show_outcome);
+    }
+
+    Ok(outcome)
+}
+
+/// Returns whether two paths name the same folder, one of them possibly relative.
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (std::path::absolute(a), std::path::absolute(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Refuses the options that need the shows to run one after the other, when
+/// `--show-jobs` runs several at once.
+fn check_show_jobs(args: &Args) -> Result<()> {
+    let option = if args.interactive {
+        "--interactive"
+    } else if args.estimate && !args.yes {
+        "--estimate without --yes"
+    } else {
+        return Ok(());
+    };
+    Err(anyhow::anyhow!(
+        "Cannot run several shows at once with {}; drop it or --show-jobs",
+        option
+    ))
+}
+
+/// Runs the download pipeline for a single show.
+async fn run_show<F: Fetcher>(
+    client: &F,
+    show_url: &str,
+    folder: &Path,
+    cache: &SharedCache,
+    args: &Args,
+    summary: &ShowSummary,
+) -> Result<Outcome> {
+    create_dir_all(folder)
+        .with_context(|| format!("Failed to create folder directory: {}", folder.display()))?;
+
+    if let Some(planned) = plan::resumed(show_url) {
+        return resume_show(client, &planned, cache.as_ref(), args, summary).await;
+    }
+
+    if let Some(metadata_path) = episode_metadata_path(show_url) {
+        return run_episode(
+            client,
+            &metadata_path,
+            folder,
+            cache.as_ref(),
+            args,
+            summary,
+        )
+        .await;
+    }
+
+    let kind = playlist::page_kind(show_url);
+    if kind == PageKind::Landing {
+        let page_html = fetch_or_read_page(client, show_url, cache.as_ref()).await?;
+        return Err(anyhow::anyhow!(
+            "{} is a landing page linking {} shows, not a show: list them with --list-shows \
+             or download them with --follow-shows",
+            show_url,
+            crawl::landing_links(&page_html, show_url).len()
+        ));
+    }
+
+    // A saved page is read as it is; offline, the cached listing is the newest there is.
+    let local = local_page(show_url).is_some();
+    if args.incremental && !args.offline && !local {
+        forget_listing(show_url, cache.as_ref())?;
+    }
+    let listing = if args.scrape_html || kind == PageKind::Playlist || local {
+        None
+    } else {
+        match fetch_show_json(client, show_url, cache.as_ref()).await {
+            Ok(listing) => Some(listing),
+            Err(err) if matches!(RsndError::of(&err), Some(RsndError::NoEpisodes { .. })) => {
+                outln!("The show JSON lists no episodes, scraping the HTML page");
+                None
+            }
+            Err(err) => {
+                outln!("Show JSON unavailable ({:#}), scraping the HTML page", err);
+                None
+            }
+        }
+    };
+
+    let Listing {
+        mut audio_urls,
+        mut prefetched,
+        seasons,
+        title,
+        image,
+        description,
+        schedule,
+    } = match listing {
+        Some(listing) => listing,
+        None => {
+            let page_html = match fetch_or_read_page(client, show_url, cache.as_ref()).await {
+                Ok(page_html) => page_html,
+                Err(err) if RsndError::of(&err).is_some_and(|err| !err.is_page_unavailable()) => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    errln!("Error: {:?}", err);
+                    return Ok(Outcome::PageUnavailable);
+                }
+            };
+            let mut audio_urls = extract_episodes(&page_html, kind);
+            let seasons = if kind == PageKind::Programme {
+                fetch_remaining_sets(client, &page_html, cache.as_ref(), &mut audio_urls).await?
+            } else {
+                HashMap::new()
+            };
+            if audio_urls.is_empty() {
+                if args.allow_empty {
+                    outln!("No episodes found on page {}", show_url);
+                    return Ok(Outcome::Success);
+                }
+                let mut diagnosis = empty::Diagnosis::from_page(show_url, &page_html);
+                if !local {
+                    diagnosis.probe(client).await;
+                }
+                return Err(RsndError::NoEpisodes {
+                    url: show_url.to_string(),
+                    diagnosis: Some(Box::new(diagnosis)),
+                }
+                .into());
+            }
+            Listing {
+                audio_urls,
+                prefetched: HashMap::new(),
+                seasons,
+                title: show::page_title(&page_html),
+                image: show::page_image(&page_html).map(|image| site::absolute_url(&image)),
+                description: show::page_description(&page_html),
+                schedule: None,
+            }
+        }
+    };
+    summary.add_found(audio_urls.len());
+    if audio_urls.is_empty() {
+        return Err(RsndError::NoEpisodes {
+            url: show_url.to_string(),
+            diagnosis: None,
+        }
+        .into());
+    }
+    if let Some(schedule) = &schedule {
+        summary.set_schedule(schedule);
+    }
+    observer::emit(|| observer::Event::ShowResolved {
+        show_url: show_url.to_string(),
+        title: title.clone(),
+        episodes: audio_urls.len(),
+    });
+    for (position, audio_url) in audio_urls.iter().enumerate() {
+        observer::emit(|| observer::Event::EpisodeDiscovered {
+            show_url: show_url.to_string(),
+            position: position + 1,
+            metadata_url: site::absolute_url(audio_url),
+        });
+    }
+    let naming = args.naming(audio_urls.len());
+    let show_folder;
+    let folder = if show_subdir(args, summary) {
+        let name = title.clone().or_else(|| show::url_slug(show_url));
+        show_folder = subfolder(folder, name, &naming, args.dry_run)?;
+        summary.set_folder(&show_folder);
+        &show_folder
+    } else {
+        folder
+    };
+
+    // The whole listing, which --incremental cuts short, is recorded in show.json.
+    let listed_urls = audio_urls.clone();
+    let mut state = None;
+    if args.incremental {
+        let file = state::StateFile::load(folder)?;
+        if let Some(known) = file.get(show_url) {
+            match state::new_count(&audio_urls, known) {
+                Some(count) => {
+                    audio_urls.truncate(count + state::OVERLAP);
+                    summary.set_new_episodes(NewEpisodes {
+                        count,
+                        since: known.date,
+                    });
+                }
+                None => outln!("The newest episode of the last run is no longer listed"),
+            }
+        }
+        state = Some(file);
+    }
+
+    if let Some(selection) = &args.select {
+        for range in selection.out_of_bounds(audio_urls.len()) {
+            errln!(
+                "Warning: selection {} is out of bounds, the page has {} episodes",
+                range,
+                audio_urls.len()
+            );
+        }
+    }
+    // Episodes downloaded, found on disk or left out on purpose, for the state of
+    // --incremental.
+    let mut handled = HashSet::new();
+    let mut episodes = plan_episodes(
+        client,
+        &audio_urls,
+        &mut prefetched,
+        cache,
+        args,
+        summary,
+        &mut handled,
+    )
+    .await?;
+    for episode in &mut episodes {
+        if episode.metadata.show_image.is_none() {
+            episode.metadata.show_image.clone_from(&image);
+        }
+    }
+    if args.name_by_date {
+        rank_by_day(&mut episodes);
+    }
+    let layout = Layout {
+        season_dirs: number_seasons(&mut episodes, &seasons) && args.season_dirs,
+        global_index: args.global_index,
+    };
+    if !naming.original {
+        mark_duplicates(&mut episodes, folder, layout, &naming)?;
+    }
+    select_episodes(&mut episodes, args);
+    let selected: Vec<String> = episodes
+        .iter()
+        .map(|episode| episode.metadata.metadata_path.clone())
+        .collect();
+    filter_episodes(&mut episodes, args, summary);
+    let kept: HashSet<&str> = episodes
+        .iter()
+        .map(|episode| episode.metadata.metadata_path.as_str())
+        .collect();
+    handled.extend(
+        selected
+            .into_iter()
+            .filter(|metadata_path| !kept.contains(metadata_path.as_str())),
+    );
+    let (naming, mut numbers) = if args.continue_numbering {
+        let (naming, numbers) =
+            continue_numbering(&mut episodes, folder, naming, audio_urls.len(), args)?;
+        (naming, Some(numbers))
+    } else {
+        (naming, None)
+    };
+
+    if args.interactive {
+        let mut items = Vec::with_capacity(episodes.len());
+        for episode in &episodes {
+            let folder = layout.folder(folder, &episode.metadata);
+            let idx = layout.file_index(episode);
+            let present = existing_output(&episode.metadata, &folder, idx, &naming)?.is_some();
+            items.push(PickItem {
+                index: episode.index,
+                title: episode.metadata.title.clone(),
+                duration: episode.metadata.duration.map(duration::format_clock),
+                present,
+            });
+        }
+        let chosen = pick::pick_episodes(items)?;
+        episodes.retain(|episode| chosen.contains(&episode.index));
+    }
+
+    if let Some(Command::Retag(retag)) = &args.command {
+        return retag_show(
+            client,
+            &episodes,
+            folder,
+            layout,
+            &naming,
+            cache.as_ref(),
+            retag,
+            args,
+        )
+        .await;
+    }
+
+    if args.list {
+        let sizes = list_sizes(
+            client,
+            &mut episodes,
+            folder,
+            layout,
+            &naming,
+            cache.as_ref(),
+            args,
+        )
+        .await?;
+        let mut rows = Vec::with_capacity(episodes.len());
+        for (episode, (present, size)) in episodes.into_iter().zip(sizes) {
+            rows.push(EpisodeRow {
+                index: episode.index,
+                date: episode.metadata.date,
+                duration: episode.metadata.duration.map(duration::format_clock),
+                size,
+                part: episode.metadata.part(),
+                title: episode.metadata.title,
+                present,
+                variants: episode.metadata.variants,
+            });
+        }
+        print_rows(rows, args.json, summary);
+        return Ok(Outcome::Success);
+    }
+
+    if args.check {
+        return check_show(
+            &episodes,
+            audio_urls.len(),
+            folder,
+            layout,
+            &naming,
+            args.write_thumbnails,
+            summary,
+        );
+    }
+
+    if args.save_plan.is_some() {
+        plan::record(show_plan(
+            show_url,
+            title.clone(),
+            &episodes,
+            folder,
+            layout,
+            &naming,
+            args,
+        )?)?;
+    }
+
+    let mut estimate = None;
+    let mut sizes = HashMap::new();
+    if args.estimate || (args.dry_run && args.sizes) {
+        let (total, probes) =
+            estimate_episodes(client, &mut episodes, folder, layout, &naming, args).await?;
+        if !args.dry_run && !confirm_estimate(&total, args)? {
+            return Ok(Outcome::NothingToDo);
+        }
+        (estimate, sizes) = (Some(total), probes);
+    }
+
+    let mut pending = 0;
+    let mut processed = 0;
+    let total = episodes.len();
+    let mut lookahead = Lookahead::default();
+    // Files named like an episode are never taken for another by a fuzzy match.
+    let mut claimed = HashSet::new();
+    let mut fuzzy_files = HashMap::new();
+    if args.match_existing == MatchExisting::Fuzzy {
+        for episode in &episodes {
+            let folder = layout.folder(folder, &episode.metadata);
+            let idx = layout.file_index(episode);
+            if let Some(path) = existing_output(&episode.metadata, &folder, idx, &naming)? {
+                claimed.insert(path);
+            }
+        }
+    }
+    for position in 0..total {
+        if let Some(limit) = args.limit.filter(|limit| processed >= *limit) {
+            summary.set_limit_reached(limit, total - position);
+            break;
+        }
+
+        if !args.dry_run {
+            // Episodes past --limit are not resolved for nothing.
+            let ahead = args.limit.map_or(lookahead::DEPTH, |limit| {
+                lookahead::DEPTH.min(limit - processed - 1)
+            });
+            let upcoming = &episodes[position + 1..(position + 1 + ahead).min(total)];
+            prefetch_audio(client, &mut lookahead, upcoming, folder, layout, &naming)?;
+        }
+        let episode = &mut episodes[position];
+        let folder = &layout.folder(folder, &episode.metadata);
+        let idx = layout.file_index(episode);
+        if layout.season_dirs && !args.dry_run {
+            create_dir_all(folder).with_context(|| {
+                format!("Failed to create season directory: {}", folder.display())
+            })?;
+        }
+        check_drift(episode, folder, idx, &naming, summary)?;
+        if find_elsewhere(
+            &episode.metadata,
+            episode.index,
+            folder,
+            idx,
+            &naming,
+            args,
+            summary,
+        )? {
+            handled.insert(episode.metadata.metadata_path.clone());
+            if args.limit_count.counts(false) {
+                processed += 1;
+            }
+            continue;
+        }
+        if args.match_existing == MatchExisting::Fuzzy
+            && find_fuzzy(
+                &episode.metadata,
+                episode.index,
+                folder,
+                idx,
+                &naming,
+                &mut fuzzy_files,
+                &mut claimed,
+            )?
+        {
+            summary.add_skipped();
+            handled.insert(episode.metadata.metadata_path.clone());
+            if args.limit_count.counts(false) {
+                processed += 1;
+            }
+            continue;
+        }
+        let outcome = if args.dry_run {
+            let planned = plan_audio(
+                &episode.metadata,
+                folder,
+                idx,
+                &naming,
+                sizes.get(&episode.index),
+            );
+            match planned {
+                Ok(true) => EpisodeOutcome::Downloaded,
+                Ok(false) => EpisodeOutcome::Present,
+                Err(err) => {
+                    record_failure(summary, episode.index, &episode.metadata.title, err, args)?
+                }
+            }
+        } else {
+            if let Some(resolved) = lookahead.take(episode.index).await {
+                episode.metadata.set_resolved(resolved);
+            }
+            download_episode(
+                client,
+                &mut episode.metadata,
+                episode.index,
+                folder,
+                idx,
+                naming,
+                cache.as_ref(),
+                args,
+                summary,
+            )
+            .await?
+        };
+        match outcome {
+            EpisodeOutcome::Failed => continue,
+            // Retrying will not get these, so they do not hold back the state.
+            EpisodeOutcome::SetAside => {
+                handled.insert(episode.metadata.metadata_path.clone());
+                continue;
+            }
+            EpisodeOutcome::Downloaded | EpisodeOutcome::Present => {}
+        }
+        let downloaded = outcome == EpisodeOutcome::Downloaded;
+        if !downloaded {
+            summary.add_skipped();
+        }
+        if downloaded {
+            pending += 1;
+        }
+        if let (Some(numbers), true) = (&mut numbers, downloads_audio(args)) {
+            numbers.commit(episode_key(&episode.metadata))?;
+        }
+        handled.insert(episode.metadata.metadata_path.clone());
+        if args.limit_count.counts(downloaded) {
+            processed += 1;
+        }
+    }
+
+    if args.dry_run {
+        outln!(
+            "{} of {} episodes would be downloaded",
+            pending,
+            episodes.len()
+        );
+        if let Some(estimate) = estimate {
+            outln!("{}", estimate.to_string().trim_end_matches('\n'));
+        }
+    }
+
+    if summary.has_failures() {
+        return Ok(Outcome::EpisodesFailed);
+    }
+
+    if let (Some(state), false) = (state, args.dry_run) {
+        let known = state.get(show_url);
+        if let Some(newest) = state::newest_handled(&audio_urls, known, &handled) {
+            let date = episodes
+                .iter()
+                .find(|episode| episode.metadata.metadata_path == newest)
+                .and_then(|episode| episode.metadata.date)
+                .or_else(|| known.and_then(|known| known.date));
+            state::update(folder, show_url, state::ShowState { newest, date })?;
+        }
+    }
+
+    if args.dry_run && pending == 0 {
+        return Ok(Outcome::NothingToDo);
+    }
+
+    if !args.dry_run {
+        let previous = show_file::read(folder);
+        let listed = listed_episodes(
+            &listed_urls,
+            &episodes,
+            previous.as_ref(),
+            folder,
+            layout,
+            &naming,
+        )?;
+        // A saved page may be older than the show, so it tells nothing of removals.
+        let removed = match previous {
+            Some(previous) if local => previous.removed,
+            previous => track_removed(
+                previous.as_ref(),
+                &listed,
+                folder,
+                args.prune_removed,
+                summary,
+            )?,
+        };
+        let show = show_file::ShowFile {
+            schema: show_file::SCHEMA_VERSION,
+            title,
+            slug: show::url_slug(show_url),
+            url: show_url.to_string(),
+            description,
+            image,
+            episodes: listed_urls.len(),
+            rsnd_version: env!("CARGO_PKG_VERSION").to_string(),
+            synced_at: chrono::Utc::now(),
+            listed,
+            removed,
+        };
+        let path = show_file::write(folder, &show)?;
+        log::debug!("Wrote {}", path.display());
+    }
+
+    Ok(Outcome::Success)
+}
+
+/// Lists the episodes of the show for `show.json`, with the files of those planned in
+/// this run and, for the others, what the last run recorded of them.
+fn listed_episodes(
+    listed_urls: &[String],
+    episodes: &[Episode],
+    previous: Option<&show_file::ShowFile>,
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+) -> Result<Vec<show_file::ListedEpisode>> {
+    let planned: HashMap<&str, &Episode> = episodes
+        .iter()
+        .map(|episode| (episode.metadata.metadata_path.as_str(), episode))
+        .collect();
+    let known: HashMap<&str, &show_file::ListedEpisode> = previous
+        .map(|show| {
+            show.listed
+                .iter()
+                .map(|episode| (episode.metadata_path.as_str(), episode))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut listed = Vec::with_capacity(listed_urls.len());
+    for metadata_path in listed_urls {
+        let episode = match planned.get(metadata_path.as_str()) {
+            Some(episode) => {
+                let file = existing_output(
+                    &episode.metadata,
+                    &layout.folder(folder, &episode.metadata),
+                    layout.file_index(episode),
+                    naming,
+                )?;
+                show_file::ListedEpisode {
+                    metadata_path: metadata_path.clone(),
+                    title: Some(episode.metadata.title.clone()),
+                    file: file.map(|file| file.strip_prefix(folder).unwrap_or(&file).to_path_buf()),
+                }
+            }
+            None => known
+                .get(metadata_path.as_str())
+                .map(|episode| (*episode).clone())
+                .unwrap_or_else(|| show_file::ListedEpisode {
+                    metadata_path: metadata_path.clone(),
+                    title: None,
+                    file: None,
+                }),
+        };
+        listed.push(episode);
+    }
+    Ok(listed)
+}
+
+/// Reports the episodes of the last run that the show no longer lists, and returns all
+/// those removed so far for `show.json`. Their files are left alone, unless
+/// `--prune-removed` is given: they are then moved to `removed/` with their sidecars.
+fn track_removed(
+    previous: Option<&show_file::ShowFile>,
+    listed: &[show_file::ListedEpisode],
+    folder: &Path,
+    prune: bool,
+    summary: &ShowSummary,
+) -> Result<Vec<show_file::RemovedEpisode>> {
+    let Some(previous) = previous else {
+        return Ok(Vec::new());
+    };
+    let listed_paths: HashSet<&str> = listed
+        .iter()
+        .map(|episode| episode.metadata_path.as_str())
+        .collect();
+    // Episodes listed again are no longer removed.
+    let mut removed: Vec<show_file::RemovedEpisode> = previous
+        .removed
+        .iter()
+        .filter(|removed| !listed_paths.contains(removed.episode.metadata_path.as_str()))
+        .cloned()
+        .collect();
+    for (index, episode) in show_file::removed_since(previous, listed) {
+        let mut episode = episode.clone();
+        let title = episode
+            .title
+            .clone()
+            .unwrap_or_else(|| episode.metadata_path.clone());
+        episode.file = episode.file.filter(|file| folder.join(file).exists());
+        let note = match &episode.file {
+            Some(file) if prune => {
+                let moved = show_file::move_to_removed(folder, file)?;
+                let note = format!("moved to {}", moved.display());
+                episode.file = Some(moved);
+                note
+            }
+            Some(file) => format!("{} is the only copy left", file.display()),
+            None => "not in the folder".to_string(),
+        };
+        summary.add_removed(index, &title, &note);
+        removed.push(show_file::RemovedEpisode {
+            episode,
+            removed_at: chrono::Utc::now(),
+        });
+    }
+    if prune {
+        // Those reported by earlier runs are moved once asked to.
+        for removed in &mut removed {
+            let Some(file) = removed
+                .episode
+                .file
+                .as_ref()
+                .filter(|file| !file.starts_with(show_file::REMOVED_DIR))
+                .filter(|file| folder.join(file).exists())
+            else {
+                continue;
+            };
+            let moved = show_file::move_to_removed(folder, file)?;
+            outln!("Moved {} to {}", file.display(), moved.display());
+            removed.episode.file = Some(moved);
+        }
+    }
+    Ok(removed)
+}
+
+/// Describes the planned downloads of a show for `--save-plan`, with the paths of the
+/// files relative to the show folder.
+fn show_plan(
+    show_url: &str,
+    title: Option<String>,
+    episodes: &[Episode],
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+    args: &Args,
+) -> Result<plan::ShowPlan> {
+    let folder = std::path::absolute(folder)
+        .with_context(|| format!("Failed to resolve folder: {}", folder.display()))?;
+    let mut planned = Vec::with_capacity(episodes.len());
+    for episode in episodes {
+        let idx = layout.file_index(episode);
+        let path = output_path(
+            &episode.metadata,
+            &layout.folder(&folder, &episode.metadata),
+            idx,
+            naming,
+        )?;
+        planned.push(plan::PlannedEpisode {
+            index: episode.index,
+            file_index: idx,
+            title: episode.metadata.title.clone(),
+            date: episode.metadata.date,
+            metadata_path: episode.metadata.metadata_path.clone(),
+            audio_url: episode.metadata.url.clone(),
+            path: path.strip_prefix(&folder).unwrap_or(&path).to_path_buf(),
+        });
+    }
+    Ok(plan::ShowPlan {
+        url: show_url.to_string(),
+        title,
+        folder,
+        filters: args.filters(),
+        episodes: planned,
+    })
+}
+
+/// What became of an episode given to [`download_episode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EpisodeOutcome {
+    /// The audio was downloaded.
+    Downloaded,
+    /// The audio was already in the folder.
+    Present,
+    /// The episode is unavailable or out of the size range, and was recorded as such.
+    SetAside,
+    /// The episode failed and was recorded as such.
+    Failed,
+}
+
+/// Downloads the audio of an episode unless its file is there, then writes its tags and
+/// sidecars.
+#[allow(clippy::too_many_arguments)]
+async fn download_episode<F: Fetcher>(
+    client: &F,
+    metadata: &mut AudioMetadata,
+    index: usize,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: Naming,
+    cache: &dyn Cache,
+    args: &Args,
+    summary: &ShowSummary,
+) -> Result<EpisodeOutcome> {
+    if existing_output(metadata, folder, idx, &naming)?.is_none() {
+        start_download(summary, index, &metadata.title, folder).await;
+    }
+    let result = observer::scope(
+        summary.url(),
+        index,
+        download_fresh_audio(
+            client,
+            metadata,
+            folder,
+            idx,
+            cache,
+            &args.download_options(naming),
+        ),
+    )
+    .await
+    .and_then(|transfer| {
+        if let Some(transfer) = &transfer {
+            // A file moved aside counts as neither downloaded nor described.
+            check_duration(metadata, folder, idx, &naming, args)?;
+            record_download(summary, index, &metadata.title, transfer, args);
+            set_mtime(metadata, folder, idx, &naming, args)?;
+            write_info_json(metadata, folder, idx, &naming, transfer, args)?;
+        }
+        Ok(transfer.is_some())
+    });
+    let downloaded = match result {
+        Ok(downloaded) => downloaded,
+        Err(err) => return record_failure(summary, index, &metadata.title, err, args),
+    };
+    write_thumbnail(client, metadata, folder, idx, &naming, cache, args).await?;
+    Ok(if downloaded {
+        EpisodeOutcome::Downloaded
+    } else {
+        EpisodeOutcome::Present
+    })
+}
+
+/// Reports a failed episode and records it in the summary, as unavailable, out of the
+/// size range or failed; with `--fail-fast`, a failure ends the show instead.
+fn record_failure(
+    summary: &ShowSummary,
+    index: usize,
+    title: &str,
+    err: anyhow::Error,
+    args: &Args,
+) -> Result<EpisodeOutcome> {
+    report_failure(summary, index, title, &err);
+    match err {
+        err if skip_unavailable(&err, args) => {
+            summary.add_unavailable(index, title, &reason_of(&err))
+        }
+        err if out_of_size(&err) => summary.add_out_of_size(index, title, &reason_of(&err)),
+        err if args.fail_fast => return Err(err),
+        err => {
+            summary.add_failed(FailedEpisode::new(index, title, err));
+            return Ok(EpisodeOutcome::Failed);
+        }
+    }
+    Ok(EpisodeOutcome::SetAside)
+}
+
+/// Downloads the episodes of a saved plan that are not in their folder yet, under the
+/// names and from the audio URLs they were planned with.
+///
+/// Only the episode JSON is read again, from the cache when it is there, for the tags
+/// and sidecars.
+async fn resume_show<F: Fetcher>(
+    client: &F,
+    planned: &plan::ShowPlan,
+    cache: &dyn Cache,
+    args: &Args,
+    summary: &ShowSummary,
+) -> Result<Outcome> {
+    summary.add_found(planned.episodes.len());
+    if !same_folder(&planned.folder, &args.folder) {
+        summary.set_folder(&planned.folder);
+    }
+    // The planned names stand, even those taken from the CDN.
+    let naming = Naming {
+        original: false,
+        ..args.naming(planned.episodes.len())
+    };
+    let mut remaining = Vec::new();
+    for episode in &planned.episodes {
+        let path = planned.folder.join(&episode.path);
+        naming::confine(&planned.folder, &path)?;
+        let folder = path.parent().unwrap_or(&planned.folder);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let names: Vec<String> = relinker::EXTENSIONS
+            .iter()
+            .map(|extension| format!("{}.{}", stem, extension))
+            .collect();
+        if naming::find_any_case(folder, &names)?.is_some() {
+            summary.add_skipped();
+        } else {
+            remaining.push((episode, path));
+        }
+    }
+    outln!(
+        "Resuming {} of the {} planned episodes",
+        remaining.len(),
+        planned.episodes.len()
+    );
+
+    for (episode, path) in remaining {
+        let folder = path.parent().unwrap_or(&planned.folder);
+        let idx = episode.file_index;
+        let mut metadata = match fetch_audio_metadata(client, &episode.metadata_path, cache).await {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                record_failure(summary, episode.index, &episode.title, err, args)?;
+                continue;
+            }
+        };
+        metadata.url.clone_from(&episode.audio_url);
+        metadata.title.clone_from(&episode.title);
+        metadata.date = episode.date;
+        metadata.planned_path = Some(path.clone());
+        download_episode(
+            client,
+            &mut metadata,
+            episode.index,
+            folder,
+            idx,
+            naming,
+            cache,
+            args,
+            summary,
+        )
+        .await?;
+    }
+
+    if summary.has_failures() {
+        return Ok(Outcome::EpisodesFailed);
+    }
+    Ok(Outcome::Success)
+}
+
+/// Whether the show is saved in a subfolder: with `--show-subdirs`, and for the shows
+/// `--follow-shows` found on a landing page, while those given keep their folder.
+fn show_subdir(args: &Args, summary: &ShowSummary) -> bool {
+    args.show_subdirs || summary.is_found()
+}
+
+/// Returns the subfolder of `folder` named after the show for `--show-subdirs`, with
+/// the title sanitized like those of the episodes, creating it unless `dry_run`.
+fn subfolder(
+    folder: &Path,
+    title: Option<String>,
+    naming: &Naming,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let name = naming::sanitize(title.as_deref().unwrap_or_default(), naming);
+    let path = naming::long_path(folder.join(naming::windows_safe(&name)));
+    naming::confine(folder, &path)?;
+    if !dry_run {
+        create_dir_all(&path)
+            .with_context(|| format!("Failed to create show directory: {}", path.display()))?;
+    }
+    Ok(path)
+}
+
+/// Records the episodes missing from the folder for `--check`, without downloading, and
+/// tells when the folder was last synced according to its `show.json`.
+fn check_show(
+    episodes: &[Episode],
+    listed: usize,
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+    thumbnails: bool,
+    summary: &ShowSummary,
+) -> Result<Outcome> {
+    if let Some(show) = show_file::read(folder) {
+        let added = match listed.saturating_sub(show.episodes) {
+            0 => String::new(),
+            added => format!(", {} more listed now", added),
+        };
+        outln!(
+            "Last synced {} with rsnd {}, when the show listed {} episodes{}",
+            show.synced_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M"),
+            show.rsnd_version,
+            show.episodes,
+            added
+        );
+    }
+    for episode in episodes {
+        let folder = layout.folder(folder, &episode.metadata);
+        let idx = layout.file_index(episode);
+        match existing_output(&episode.metadata, &folder, idx, naming)? {
+            None => {
+                let path = output_path(&episode.metadata, &folder, idx, naming)?;
+                summary.add_missing(episode.index, &episode.metadata.title, &path);
+            }
+            Some(path) if thumbnails && episode.metadata.own_image().is_some() => {
+                let thumbnail = thumbnail::sidecar_path(&path);
+                if !thumbnail.exists() {
+                    summary.add_missing(episode.index, &episode.metadata.title, &thumbnail);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(if summary.has_missing() {
+        Outcome::Incomplete
+    } else {
+        Outcome::Success
+    })
+}
+
+/// Matches the files of the folder to the episodes for `retag`, then brings their ID3
+/// tags, `.info.json` and thumbnails up to date without downloading any audio.
+///
+/// A file is matched by its current name, by its `.info.json`, or by its number and a
+/// title close enough to the episode's. Files matching no episode are listed and left
+/// alone, and nothing is renamed unless `--rename-to-current-scheme` is given.
+#[allow(clippy::too_many_arguments)]
+async fn retag_show<F: Fetcher>(
+    client: &F,
+    episodes: &[Episode],
+    folder: &Path,
+    layout: Layout,
+    naming: &Naming,
+    cache: &dyn Cache,
+    retag: &RetagArgs,
+    args: &Args,
+) -> Result<Outcome> {
+    let mut files = HashMap::new();
+    let mut claimed = HashSet::new();
+    let (mut missing, mut misnamed, mut changed) = (0, 0, 0);
+    for episode in episodes {
+        let metadata = &episode.metadata;
+        let folder = layout.folder(folder, metadata);
+        let idx = layout.file_index(episode);
+        if !files.contains_key(&folder) {
+            files.insert(folder.clone(), fuzzy::audio_files(&folder)?);
+        }
+        let found = match existing_output(metadata, &folder, idx, naming)? {
+            Some(path) => Some(path),
+            None => archive::find_by_info(&folder, metadata.id.as_deref(), &metadata.url)?,
+        };
+        let track = idx.map(|idx| idx + naming.start_index - 1);
+        // Files carry the number unless they are named by date or without it.
+        let number = naming::file_number(idx, naming);
+        let found = found.filter(|path| !claimed.contains(path)).or_else(|| {
+            fuzzy::find_file(&files[&folder], &claimed, number, &metadata.title).cloned()
+        });
+        let Some(mut path) = found else {
+            missing += 1;
+            continue;
+        };
+        claimed.insert(path.clone());
+
+        // The container is not known without probing the stream: the file keeps its
+        // extension.
+        let mut target = output_path(metadata, &folder, idx, naming)?;
+        if let Some(extension) = path.extension() {
+            target.set_extension(extension);
+        }
+        if !naming.original && target.file_name() != path.file_name() {
+            if !retag.rename_to_current_scheme {
+                misnamed += 1;
+            } else if target.exists() {
+                errln!(
+                    "Warning: not renaming {}: {} exists",
+                    path.display(),
+                    target.display()
+                );
+            } else if args.dry_run {
+                outln!("Would rename {} to {}", path.display(), target.display());
+                changed += 1;
+            } else {
+                rename_with_sidecars(&path, &target)?;
+                outln!("Renamed {} to {}", path.display(), target.display());
+                path = target;
+                changed += 1;
+            }
+        }
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"))
+        {
+            let frames = episode_frames(metadata, track);
+            let frames: Vec<_> = frames
+                .iter()
+                .map(|(id, text)| (*id, text.as_str()))
+                .collect();
+            // The frames rsnd doesn't write, like the artwork, are kept.
+            let tagged = id3::read_tag(&path).and_then(|old| {
+                let tag = id3::merge(&old, &frames)?;
+                if old == tag {
+                    return Ok(false);
+                }
+                if args.dry_run {
+                    outln!("Would tag {}", path.display());
+                } else {
+                    id3::write_tag(&path, &tag)?;
+                    outln!("Tagged {}", path.display());
+                }
+                Ok(true)
+            });
+            match tagged {
+                Ok(tagged) => changed += tagged as usize,
+                Err(err) => errln!("Warning: not tagging {}: {:#}", path.display(), err),
+            }
+        }
+
+        if args.write_info_json {
+            let mut info = match episode_info(metadata, &path) {
+                Ok(info) => info,
+                Err(err) => {
+                    errln!("Warning: not describing {}: {:#}", path.display(), err);
+                    continue;
+                }
+            };
+            let old = info::read(&path);
+            // What is known of the download is kept, and left unknown otherwise.
+            info.downloaded_at = None;
+            if let Some(old) = &old {
+                info.media_url = old.media_url.clone().or(info.media_url);
+                info.downloaded_at = old.downloaded_at;
+                info.elapsed_secs = old.elapsed_secs;
+                info.throughput = old.throughput;
+                info.peak_throughput = old.peak_throughput;
+            }
+            if old.as_ref() != Some(&info) {
+                let sidecar = info::sidecar_path(&path);
+                if args.dry_run {
+                    outln!("Would write {}", sidecar.display());
+                } else {
+                    info::write(&path, &info)?;
+                    outln!("Wrote {}", sidecar.display());
+                }
+                changed += 1;
+            }
+        }
+
+        let image = metadata.own_image().filter(|_| args.write_thumbnails);
+        if let Some(image) = image {
+            let thumbnail = thumbnail::sidecar_path(&path);
+            if thumbnail.exists() {
+                continue;
+            }
+            if args.dry_run {
+                outln!("Would save {}", thumbnail.display());
+                changed += 1;
+                continue;
+            }
+            match thumbnail::write(client, cache, image, &path).await {
+                Ok(thumbnail) => {
+                    outln!("Saved {}", thumbnail.display());
+                    changed += 1;
+                }
+                Err(err) => errln!("Warning: no thumbnail for {}: {:#}", metadata.title, err),
+            }
+        }
+    }
+
+    let mut unmatched: Vec<&PathBuf> = files
+        .values()
+        .flatten()
+        .filter(|file| !claimed.contains(*file))
+        .collect();
+    unmatched.sort();
+    if !unmatched.is_empty() {
+        outln!(
+            "{} files match no episode and were left alone:",
+            unmatched.len()
+        );
+        for file in unmatched {
+            outln!("  {}", file.display());
+        }
+    }
+    if misnamed > 0 {
+        outln!(
+            "{} files are named after an older scheme: rename them with \
+             --rename-to-current-scheme",
+            misnamed
+        );
+    }
+    outln!(
+        "Matched {} of the {} episodes, {} {}",
+        episodes.len() - missing,
+        episodes.len(),
+        changed,
+        if args.dry_run {
+            "changes to make"
+        } else {
+            "changes made"
+        }
+    );
+    Ok(Outcome::Success)
+}
+
+/// Renames an audio file along with its `.info.json` and thumbnail.
+fn rename_with_sidecars(from: &Path, to: &Path) -> Result<()> {
+    let renames = [
+        (from.to_path_buf(), to.to_path_buf()),
+        (info::sidecar_path(from), info::sidecar_path(to)),
+        (thumbnail::sidecar_path(from), thumbnail::sidecar_path(to)),
+    ];
+    for (from, to) in renames {
+        if from.exists() {
+            std::fs::rename(&from, &to).with_context(|| {
+                format!("Failed to rename {} to {}", from.display(), to.display())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the ID3 frames of an episode: title, show as album and artist, number and
+/// publication date.
+fn episode_frames(metadata: &AudioMetadata, track: Option<usize>) -> Vec<(&'static str, String)> {
+    let mut frames = vec![
+        ("TIT2", metadata.title.clone()),
+        ("TALB", metadata.show.clone().unwrap_or_default()),
+        ("TPE1", metadata.show.clone().unwrap_or_default()),
+        (
+            "TRCK",
+            track.map(|track| track.to_string()).unwrap_or_default(),
+        ),
+    ];
+    if let Some(date) = metadata.date {
+        frames.push(("TYER", date.format("%Y").to_string()));
+        frames.push(("TDAT", date.format("%d%m").to_string()));
+    }
+    frames
+}
+
+/// Waits out the `--sleep` pause before a download, then tells the observer it starts.
+async fn start_download(summary: &ShowSummary, index: usize, title: &str, folder: &Path) {
+    summary.add_sleep(pace::before_download().await);
+    observer::emit(|| observer::Event::DownloadStarted {
+        show_url: summary.url().to_string(),
+        index,
+        title: title.to_string(),
+        folder: folder.to_path_buf(),
+    });
+}
+
+/// Tells the observer the download of an episode failed, or was skipped for a reason.
+fn report_failure(summary: &ShowSummary, index: usize, title: &str, err: &anyhow::Error) {
+    observer::emit(|| observer::Event::DownloadFailed {
+        show_url: summary.url().to_string(),
+        index,
+        title: title.to_string(),
+        reason: reason_of(err),
+    });
+}
+
+/// Records a finished download, flagging it when it is smaller than `--min-size`.
+fn record_download(
+    summary: &ShowSummary,
+    index: usize,
+    title: &str,
+    transfer: &Transfer,
+    args: &Args,
+) {
+    let bytes = transfer.bytes;
+    observer::emit(|| observer::Event::DownloadFinished {
+        show_url: summary.url().to_string(),
+        index,
+        title: title.to_string(),
+        bytes,
+        elapsed_secs: transfer.elapsed.as_secs_f64(),
+    });
+    summary.add_downloaded(bytes);
+    summary.add_stream(transfer.elapsed, transfer.wasted);
+    if let Some(host) = &transfer.mirror {
+        summary.add_mirrored(index, title, host);
+    }
+    if bytes < args.min_size {
+        summary.add_suspicious(
+            index,
+            title,
+            &format!(
+                "only {} downloaded, expected at least {}",
+                format_size(bytes),
+                format_size(args.min_size)
+            ),
+        );
+    }
+}
+
+/// Looks for an episode missing from its folder in the `--also-check-dir` folders, by
+/// file name or by `.info.json` sidecar, and puts it in its folder with
+/// `--link-existing`. Returns whether it was found, so that it is not downloaded.
+fn find_elsewhere(
+    metadata: &AudioMetadata,
+    index: usize,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    args: &Args,
+    summary: &ShowSummary,
+) -> Result<bool> {
+    if args.also_check_dir.is_empty() || existing_output(metadata, folder, idx, naming)?.is_some() {
+        return Ok(false);
+    }
+    for dir in &args.also_check_dir {
+        let found = match existing_output(metadata, dir, idx, naming)? {
+            Some(found) => Some(found),
+            None => archive::find_by_info(dir, metadata.id.as_deref(), &metadata.url)?,
+        };
+        let Some(found) = found else {
+            continue;
+        };
+        let placed = if args.link_existing && !args.dry_run {
+            let mut target = output_path(metadata, folder, idx, naming)?;
+            if let Some(extension) = found.extension() {
+                target.set_extension(extension);
+            }
+            create_dir_all(folder)
+                .with_context(|| format!("Failed to create folder: {}", folder.display()))?;
+            Some(match archive::link_or_copy(&found, &target)? {
+                archive::Placed::Linked => "linked",
+                archive::Placed::Copied => "copied",
+            })
+        } else {
+            None
+        };
+        summary.add_elsewhere(index, &metadata.title, &found, placed);
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Looks for the file of an episode missing under its name among those of the folder
+/// for `--match-existing fuzzy`, in case an older version named it. A file stands for
+/// one episode at most. The files of each folder are listed once, in `files`.
+fn find_fuzzy(
+    metadata: &AudioMetadata,
+    index: usize,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    files: &mut HashMap<PathBuf, Vec<PathBuf>>,
+    claimed: &mut HashSet<PathBuf>,
+) -> Result<bool> {
+    if existing_output(metadata, folder, idx, naming)?.is_some() {
+        return Ok(false);
+    }
+    if !files.contains_key(folder) {
+        files.insert(folder.to_path_buf(), fuzzy::audio_files(folder)?);
+    }
+    let number = naming::file_number(idx, naming);
+    let Some(found) = fuzzy::find_file(&files[folder], claimed, number, &metadata.title) else {
+        return Ok(false);
+    };
+    let expected = output_path(metadata, folder, idx, naming)?;
+    outln!(
+        "Skipping {:03} - {}: found as {} (fuzzy match for {})",
+        index,
+        metadata.title,
+        found.file_name().unwrap_or_default().to_string_lossy(),
+        expected.file_name().unwrap_or_default().to_string_lossy()
+    );
+    claimed.insert(found.clone());
+    Ok(true)
+}
+
+/// Sets the modification and access times of a finished download to the publication
+/// date of the episode with `--mtime source`. Files of unknown date keep the download
+/// time.
+fn set_mtime(
     metadata: &AudioMetadata,
     folder: &Path,
-    idx: usize,
+    idx: Option<usize>,
+    naming: &Naming,
+    args: &Args,
 ) -> Result<()> {
-    let re = Regex::new(r"[^\w\s-]")?;
-    let sanitized_title = re.replace_all(&metadata.title, "_").to_lowercase();
-    let output_path = folder.join(format!("{:03} - {}.mp3", idx, sanitized_title));
+    let (MtimeMode::Source, Some(date)) = (args.mtime, metadata.date) else {
+        return Ok(());
+    };
+    let Some(path) = existing_output(metadata, folder, idx, naming)? else {
+        return Ok(());
+    };
+    let time = dates::start_of_day(date);
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .and_then(|file| {
+            file.set_times(
+                std::fs::FileTimes::new()
+                    .set_accessed(time)
+                    .set_modified(time),
+            )
+        })
+        .with_context(|| format!("Failed to set the modification time of {}", path.display()))
+}
 
-    if output_path.exists() {
-        println!(
-            "File {} already exists. Skipping download.",
-            output_path.display()
-        );
+/// Writes the `.info.json` sidecar of a finished download with `--write-info-json`.
+fn write_info_json(
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    transfer: &Transfer,
+    args: &Args,
+) -> Result<()> {
+    if !args.write_info_json {
         return Ok(());
     }
+    let Some(path) = existing_output(metadata, folder, idx, naming)? else {
+        return Ok(());
+    };
+    let info = info::Info {
+        elapsed_secs: Some(transfer.elapsed.as_secs_f64()),
+        throughput: Some(transfer.average()),
+        peak_throughput: Some(transfer.peak),
+        ..episode_info(metadata, &path)?
+    };
+    let sidecar = info::write(&path, &info)?;
+    log::debug!("Wrote {}", sidecar.display());
+    Ok(())
+}
 
-    let response = client
-        .get(&metadata.url)
-        .send()
-        .await
-        .with_context(|| format!("Failed to fetch audio URL: {}", metadata.url))?;
+/// Describes the episode saved at `path` for its `.info.json`, leaving out the figures
+/// of the download.
+fn episode_info(metadata: &AudioMetadata, path: &Path) -> Result<info::Info> {
+    Ok(info::Info {
+        schema: info::SCHEMA_VERSION,
+        id: metadata.id.clone(),
+        title: metadata.title.clone(),
+        part: metadata.part(),
+        show: metadata.show.clone(),
+        date: metadata.date,
+        duration: metadata.duration,
+        description: metadata.description.clone(),
+        image: metadata.image.clone(),
+        metadata_url: metadata.metadata_url(),
+        audio_url: metadata.url.clone(),
+        media_url: metadata.media_url.clone(),
+        size: path.metadata()?.len(),
+        crc32: info::checksum(path)?,
+        downloaded_at: Some(chrono::Utc::now()),
+        elapsed_secs: None,
+        throughput: None,
+        peak_throughput: None,
+    })
+}
 
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "Failed to fetch audio URL: {}. Status: {}",
-            metadata.url,
-            response.status()
-        ));
+/// Saves the image of the episode next to its file with `--write-thumbnails`, unless it
+/// is there already. A dead image only earns a warning: the audio is what matters.
+async fn write_thumbnail<F: Fetcher>(
+    client: &F,
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    cache: &dyn Cache,
+    args: &Args,
+) -> Result<()> {
+    if !args.write_thumbnails || args.dry_run {
+        return Ok(());
+    }
+    let (Some(image), Some(path)) = (
+        metadata.own_image(),
+        existing_output(metadata, folder, idx, naming)?,
+    ) else {
+        return Ok(());
+    };
+    if thumbnail::sidecar_path(&path).exists() {
+        return Ok(());
     }
+    match thumbnail::write(client, cache, image, &path).await {
+        Ok(thumbnail) => log::debug!("Wrote {}", thumbnail.display()),
+        Err(err) => errln!("Warning: no thumbnail for {}: {:#}", metadata.title, err),
+    }
+    Ok(())
+}
 
-    let mut file = TokioFile::create(&output_path).await.with_context(|| {
-        format!(
-            "Failed to create file: {}. Error: {:?}",
-            output_path.display(),
-            std::io::Error::last_os_error()
-        )
-    })?;
-    file.write_all(&response.bytes().await?)
-        .await
-        .with_context(|| {
-            format!(
-                "Failed to write to file: {}. Error: {:?}",
-                output_path.display(),
-                std::io::Error::last_os_error()
-            )
-        })?;
-    println!("Downloaded {} to {}", metadata.title, output_path.display());
+/// Warns when the file numbered like the episode belongs to another one, which happens
+/// when episodes are added to or removed from the page between runs.
+fn check_drift(
+    episode: &Episode,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    summary: &ShowSummary,
+) -> Result<()> {
+    let Some(prefix) = naming::index_prefix(idx, naming) else {
+        return Ok(());
+    };
+    if naming.by_date || naming.sxxeyy {
+        return Ok(());
+    }
+    if existing_output(&episode.metadata, folder, idx, naming)?.is_some() {
+        return Ok(());
+    }
+    let stem = naming::file_stem(&episode.metadata.name_parts(idx), naming)?;
+    if let Some(other) = naming::drifted(folder, &prefix, &stem)? {
+        summary.add_drifted(episode.index, &episode.metadata.title, &other);
+    }
     Ok(())
 }
 
-fn get_client() -> Result<Client> {
-    let mut headers = HeaderMap::new();
-    headers.insert("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8".parse().unwrap());
-    headers.insert("accept-language", "en-US,en;q=0.7".parse().unwrap());
-    headers.insert("priority", "u=0, i".parse().unwrap());
-    headers.insert(
-        "sec-ch-ua",
-        "\"Not/A)Brand\";v=\"8\", \"Chromium\";v=\"126\", \"Brave\";v=\"126\""
-            .parse()
-            .unwrap(),
+/// Compares the length of the downloaded file with the duration in its metadata, when
+/// `--check-duration` is given, failing on a mismatch in strict mode after moving the
+/// file aside to `<name>.mismatch`, so that the next run downloads it again.
+fn check_duration(
+    metadata: &AudioMetadata,
+    folder: &Path,
+    idx: Option<usize>,
+    naming: &Naming,
+    args: &Args,
+) -> Result<()> {
+    let (Some(mode), Some(expected)) = (args.check_duration, metadata.duration) else {
+        return Ok(());
+    };
+    let Some(path) = existing_output(metadata, folder, idx, naming)? else {
+        return Ok(());
+    };
+    let Some(measured) = audio_length::measure(&path)? else {
+        log::debug!("Cannot measure the duration of {}", path.display());
+        return Ok(());
+    };
+    if audio_length::matches(measured, expected) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "{} lasts {}, but its metadata says {}",
+        path.display(),
+        duration::format_clock(measured.round() as u64),
+        duration::format_clock(expected)
     );
-    headers.insert("sec-ch-ua-mobile", "?0".parse().unwrap());
-    headers.insert("sec-ch-ua-platform", "\"Linux\"".parse().unwrap());
-    headers.insert("sec-fetch-dest", "document".parse().unwrap());
-    headers.insert("sec-fetch-mode", "navigate".parse().unwrap());
-    headers.insert("sec-fetch-site", "none".parse().unwrap());
-    headers.insert("sec-fetch-user", "?1".parse().unwrap());
-    headers.insert("sec-gpc", "1".parse().unwrap());
-    headers.insert("upgrade-insecure-requests", "1".parse().unwrap());
-    headers.insert("user-agent", "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36".parse().unwrap());
+    match mode {
+        DurationCheck::Warn => {
+            outln!("Warning: {}", message);
+            Ok(())
+        }
+        DurationCheck::Strict => {
+            let mut aside = path.clone().into_os_string();
+            aside.push(".mismatch");
+            std::fs::rename(&path, &aside)
+                .with_context(|| format!("Failed to move aside {}", path.display()))?;
+            Err(anyhow::anyhow!(
+                "{} (moved to {})",
+                message,
+                Path::new(&aside).display()
+            ))
+        }
+    }
+}
 
-    let client = Client::builder()
-        .default_headers(headers.clone())
-        .redirect(reqwest::redirect::Policy::limited(5))
-        .cookie_store(true)
-        .build()
-        .context("Failed to build HTTP client")?;
-    Ok(client)
+/// Returns whether the error is an unavailable episode that should be skipped rather
+/// than counted as a failure.
+fn skip_unavailable(err: &anyhow::Error, args: &Args) -> bool {
+    !args.strict_geo && matches!(RsndError::of(err), Some(RsndError::GeoBlocked { .. }))
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+/// Returns whether the error is an episode whose metadata links to its web page only,
+/// which is skipped as unavailable rather than counted as a failure.
+fn web_page_only(err: &anyhow::Error) -> bool {
+    matches!(RsndError::of(err), Some(RsndError::WebPageOnly { .. }))
+}
 
-    create_dir_all(&args.folder).with_context(|| {
-        format!(
-            "Failed to create folder directory: {}. Error: {:?}",
-            &args.folder.display(),
-            std::io::Error::last_os_error()
-        )
-    })?;
+/// Returns whether the error is an episode skipped because of its size.
+fn out_of_size(err: &anyhow::Error) -> bool {
+    matches!(RsndError::of(err), Some(RsndError::SizeLimit { .. }))
+}
+
+/// Returns why an unavailable episode was skipped.
+fn reason_of(err: &anyhow::Error) -> String {
+    match RsndError::of(err) {
+        Some(RsndError::GeoBlocked { reason, .. } | RsndError::SizeLimit { reason }) => {
+            reason.clone()
+        }
+        Some(err @ RsndError::WebPageOnly { .. }) => err.to_string(),
+        _ => format!("{:#}", err),
+    }
+}
+
+/// Replaces the landing pages among the shows by the shows they link to, for
+/// `--follow-shows`, or prints those shows for `--list-shows`, which takes nothing but
+/// landing pages.
+///
+/// Only one level is followed, so that a landing page never starts a crawl of the whole
+/// site, and shows already in the run are not added twice. Returns the shows with the
+/// landing page each followed one was found on.
+async fn follow_landing_pages<F: Fetcher>(
+    client: &F,
+    shows: Vec<(String, PathBuf)>,
+    cache: &dyn Cache,
+    args: &Args,
+) -> Result<(Vec<(String, PathBuf)>, HashMap<String, String>)> {
+    let mut seen: HashSet<String> = shows
+        .iter()
+        .map(|(url, _)| url.trim_end_matches('/').to_string())
+        .collect();
+    let is_landing =
+        |url: &str| local_page(url).is_none() && playlist::page_kind(url) == PageKind::Landing;
+    if args.list_shows {
+        if let Some((url, _)) = shows.iter().find(|(url, _)| !is_landing(url)) {
+            return Err(anyhow::anyhow!(
+                "{} is not a landing page: --list-shows lists the shows of landing pages, \
+                 like a genre or /audiolibri",
+                url
+            ));
+        }
+    }
+    let mut followed = Vec::with_capacity(shows.len());
+    let mut found_on = HashMap::new();
+    let mut listed = Vec::new();
+    for (landing_url, folder) in shows {
+        if !is_landing(&landing_url) {
+            followed.push((landing_url, folder));
+            continue;
+        }
+        let html = fetch_or_read_page(client, &landing_url, cache)
+            .await
+            .with_context(|| format!("Failed to fetch landing page: {}", landing_url))?;
+        let links: Vec<crawl::ShowLink> = crawl::landing_links(&html, &landing_url)
+            .into_iter()
+            .filter(|link| playlist::page_kind(&link.url) != PageKind::Landing)
+            .collect();
+        if args.list_shows {
+            if args.json {
+                listed.extend(links.into_iter().map(|link| crawl::FoundShow {
+                    link,
+                    found_on: landing_url.clone(),
+                }));
+            } else if links.is_empty() {
+                outln!("No shows linked from {}", landing_url);
+            } else {
+                outln!("{}", crawl::render_links(&links).trim_end_matches('\n'));
+            }
+            continue;
+        }
+        let linked = links.len();
+        let mut added = 0;
+        for link in links {
+            if seen.insert(link.url.clone()) {
+                found_on.insert(link.url.clone(), landing_url.clone());
+                followed.push((link.url, folder.clone()));
+                added += 1;
+            }
+        }
+        if added == linked {
+            outln!("Following the {} shows linked from {}", linked, landing_url);
+        } else {
+            outln!(
+                "Following {} of the {} shows linked from {} ({} already in this run)",
+                added,
+                linked,
+                landing_url,
+                linked - added
+            );
+        }
+    }
+    if args.list_shows && args.json {
+        let json = serde_json::to_string_pretty(&listed).context("Failed to serialize shows")?;
+        println!("{}", json);
+    }
+    Ok((followed, found_on))
+}
+
+/// Collects the shows linked from the catalog page and from the pages following it.
+///
+/// Pages are cached like show pages, so an interrupted crawl resumes with the same shows.
+async fn crawl_catalog<F: Fetcher>(
+    client: &F,
+    catalog_url: &str,
+    cache: &dyn Cache,
+) -> Result<Vec<crawl::ShowLink>> {
+    let mut links: Vec<crawl::ShowLink> = Vec::new();
+    let mut pages = HashSet::new();
+    let mut next = Some(catalog_url.to_string());
+    while let Some(page_url) = next.take() {
+        if pages.len() >= crawl::MAX_PAGES || !pages.insert(page_url.clone()) {
+            break;
+        }
+        let entry = cache::Entry::new(&page_url, crawl::cache_name(&page_url));
+        let html = fetch_cached(client, cache, &entry)
+            .await
+            .with_context(|| format!("Failed to fetch catalog page: {}", page_url))?;
+        for link in crawl::show_links(&html, &page_url) {
+            if !links.iter().any(|known| known.url == link.url) {
+                links.push(link);
+            }
+        }
+        next = crawl::next_page(&html, &page_url);
+    }
+    Ok(links)
+}
+
+/// Searches for shows matching the query and prints them, reading the results from the
+/// cache when the same search was made in the last hour, or at any time `offline`.
+async fn run_search<F: Fetcher>(
+    client: &F,
+    query: &str,
+    cache: &dyn Cache,
+    json: bool,
+    offline: bool,
+) -> Result<Vec<search::SearchResult>> {
+    let (url, cache_name) = search::search_url(query)?;
+    let entry = cache::Entry::new(&url, cache_name);
+    if !offline && cache.age(&entry).is_some_and(|age| age > search::MAX_AGE) {
+        cache.invalidate(&entry)?;
+    }
+    let body = fetch_cached(client, cache, &entry).await?;
+    let results = search::parse_results(&body)
+        .with_context(|| format!("Invalid search response: {}", url))?;
+    if json {
+        let json = serde_json::to_string_pretty(&results).context("Failed to serialize results")?;
+        println!("{}", json);
+    } else if results.is_empty() {
+        outln!("No shows found for {:?}", query);
+    } else {
+        print!("{}", search::render_results(&results));
+    }
+    Ok(results)
+}
 
-    let cache_dir = PathBuf::from(&args.cache);
-    create_dir_all(&cache_dir).with_context(|| {
-        format!(
-            "Failed to create cache directory: {}. Error: {:?}",
-            cache_dir.display(),
-            std::io::Error::last_os_error()
-        )
-    })?;
+/// Downloads the single episode whose metadata lives at `metadata_path`.
+///
+/// The file is named after the title, prefixed with `--index` when given.
+async fn run_episode<F: Fetcher>(
+    client: &F,
+    metadata_path: &str,
+    folder: &Path,
+    cache: &dyn Cache,
+    args: &Args,
+    summary: &ShowSummary,
+) -> Result<Outcome> {
+    summary.add_found(1);
+    let mut metadata = match fetch_audio_metadata(client, metadata_path, cache).await {
+        Ok(metadata) => metadata,
+        Err(err) if args.check => {
+            summary.add_unknown(args.index.unwrap_or(1), metadata_path, &reason_of(&err));
+            return Ok(Outcome::Success);
+        }
+        Err(err) if web_page_only(&err) => {
+            summary.add_unavailable(args.index.unwrap_or(1), metadata_path, &reason_of(&err));
+            return Ok(Outcome::Success);
+        }
+        Err(err) if args.fail_fast => return Err(err),
+        Err(err) => {
+            summary.add_failed(FailedEpisode::new(
+                args.index.unwrap_or(1),
+                metadata_path,
+                err,
+            ));
+            return Ok(Outcome::EpisodesFailed);
+        }
+    };
+    if !keep_media(args.index.unwrap_or(1), &metadata, args, summary) {
+        return Ok(Outcome::Success);
+    }
+    args.prepare_metadata(&mut metadata);
+    // Only the episode's own number has to fit in the pad width.
+    let naming = args.naming(args.index.unwrap_or(1));
+    let show_folder;
+    let folder = if show_subdir(args, summary) {
+        show_folder = subfolder(folder, metadata.show.clone(), &naming, args.dry_run)?;
+        summary.set_folder(&show_folder);
+        &show_folder
+    } else {
+        folder
+    };
 
-    let client = get_client().with_context(|| {
-        format!(
-            "Failed to create the reqwest client. Error: {:?}",
-            std::io::Error::last_os_error()
-        )
-    })?;
+    if args.list {
+        let existing = existing_output(&metadata, folder, args.index, &naming)?;
+        let row = EpisodeRow {
+            index: args.index.unwrap_or(1),
+            date: metadata.date,
+            duration: metadata.duration.map(duration::format_clock),
+            size: existing.as_deref().and_then(file_size),
+            present: existing.is_some(),
+            part: metadata.part(),
+            title: metadata.title,
+            variants: metadata.variants,
+        };
+        print_rows(vec![row], args.json, summary);
+        return Ok(Outcome::Success);
+    }
 
-    let page_html = fetch_or_read_page(&client, &args.url, &cache_dir).await?;
+    if args.check {
+        if existing_output(&metadata, folder, args.index, &naming)?.is_some() {
+            return Ok(Outcome::Success);
+        }
+        let path = output_path(&metadata, folder, args.index, &naming)?;
+        summary.add_missing(args.index.unwrap_or(1), &metadata.title, &path);
+        return Ok(Outcome::Incomplete);
+    }
 
-    let audio_urls = extract_options(&page_html);
+    if find_elsewhere(
+        &metadata,
+        args.index.unwrap_or(1),
+        folder,
+        args.index,
+        &naming,
+        args,
+        summary,
+    )? {
+        return Ok(if args.dry_run {
+            Outcome::NothingToDo
+        } else {
+            Outcome::Success
+        });
+    }
 
-    for (idx, audio_url) in audio_urls.iter().enumerate() {
-        let metadata = fetch_audio_metadata(&client, audio_url, &cache_dir).await?;
-        download_audio(&client, &metadata, &args.folder, idx + 1).await?;
+    let mut size = None;
+    if (args.estimate || (args.dry_run && args.sizes))
+        && existing_output(&metadata, folder, args.index, &naming)?.is_none()
+    {
+        let probe = probe_sizes(client, &mut [&mut metadata], 1).await.remove(0);
+        let mut estimate = estimate::Estimate::default();
+        estimate.add(args.index.unwrap_or(1), &metadata.title, &probe);
+        if !args.dry_run && !confirm_estimate(&estimate, args)? {
+            return Ok(Outcome::NothingToDo);
+        }
+        size = Some(probe);
     }
 
-    Ok(())
+    let index = args.index.unwrap_or(1);
+    let outcome = if args.dry_run {
+        match plan_audio(&metadata, folder, args.index, &naming, size.as_ref()) {
+            Ok(true) => EpisodeOutcome::Downloaded,
+            Ok(false) => EpisodeOutcome::Present,
+            Err(err) => record_failure(summary, index, &metadata.title, err, args)?,
+        }
+    } else {
+        download_episode(
+            client,
+            &mut metadata,
+            index,
+            folder,
+            args.index,
+            naming,
+            cache,
+            args,
+            summary,
+        )
+        .await?
+    };
+    Ok(match outcome {
+        EpisodeOutcome::Downloaded | EpisodeOutcome::SetAside => Outcome::Success,
+        EpisodeOutcome::Present => {
+            summary.add_skipped();
+            if args.dry_run {
+                Outcome::NothingToDo
+            } else {
+                Outcome::Success
+            }
+        }
+        EpisodeOutcome::Failed => Outcome::EpisodesFailed,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fetcher::MockFetcher;
     use std::env::temp_dir;
     use std::fs::File;
     use std::io::Write;
     use tokio::fs::{create_dir_all, remove_file};
 
+    const RELINKER_URL: &str =
+        "https://mediapolisvod.rai.it/relinker/relinkerServlet.htm?cont=jmC2BrdAhSIeeqqEEqual";
+
     #[tokio::test]
     async fn test_fetch_or_read_page() -> Result<()> {
+        let url = "https://www.raiplaysound.it/audiolibri/itremoschettieri";
+        let cache_dir = tempfile::tempdir()?;
+        let client = MockFetcher::default().serve(
+            url,
+            "text/html",
+            include_str!("../tests/fixtures/itremoschettieri.html"),
+        );
+
+        let cache = cache::FileCache::new(cache_dir.path());
+
+        let page = fetch_or_read_page(&client, url, &cache).await?;
+        assert!(cache_dir.path().join("itremoschettieri.html").exists());
+        // The second read comes from the cache.
+        assert_eq!(fetch_or_read_page(&client, url, &cache).await?, page);
+        assert_eq!(client.requests(), vec![format!("GET {}", url)]);
+
+        // Without a cache every read is a request.
+        fetch_or_read_page(&client, url, &cache::NoCache).await?;
+        assert_eq!(client.requests().len(), 2);
+
+        let missing = "https://www.raiplaysound.it/audiolibri/missing";
+        let err = fetch_or_read_page(&client, missing, &cache)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::Status { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "reaches raiplaysound.it"]
+    async fn test_fetch_or_read_page_live() -> Result<()> {
         let url = "https://www.raiplaysound.it/audiolibri/itremoschettieri";
         let cache_dir = temp_dir().join("test_cache");
         create_dir_all(&cache_dir).await?;
 
-        let client = get_client()?;
+        let client = HttpFetcher::new(get_client(&ClientOptions::default(), None)?);
 
         // Pulire il file di cache se esiste
         let cache_file = cache_dir.join("itremoschettieri.html");
@@ -310,7 +5077,7 @@ mod tests {
             remove_file(&cache_file).await?;
         }
 
-        let result = fetch_or_read_page(&client, url, &cache_dir).await;
+        let result = fetch_or_read_page(&client, url, &cache::FileCache::new(&cache_dir)).await;
         assert!(result.is_ok());
 
         // Check that the file was cached
@@ -328,7 +5095,7 @@ mod tests {
     #[test]
     fn test_extract_options() {
         let html = r#"<rps-play-with-labels options='{"url": "audio/2015/06/I-tre-moschettieri---Lettura-I-2c45793e-a289-42a8-97ae-656a2a94a71f.json"}'></rps-play-with-labels>"#;
-        let options = extract_options(html);
+        let options = extract_options(html).unwrap();
         assert_eq!(options.len(), 1);
         assert_eq!(options[0], "audio/2015/06/I-tre-moschettieri---Lettura-I-2c45793e-a289-42a8-97ae-656a2a94a71f.json");
     }
@@ -355,14 +5122,16 @@ mod tests {
         let mut file = File::create(&cache_file)?;
         file.write_all(json_response.as_bytes())?;
 
-        let client = get_client()?;
+        let client = MockFetcher::default();
 
-        let metadata = fetch_audio_metadata(&client, url, &cache_dir).await?;
+        let metadata =
+            fetch_audio_metadata(&client, url, &cache::FileCache::new(&cache_dir)).await?;
         assert_eq!(
             metadata.url,
             "https://mediapolisvod.rai.it/relinker/relinkerServlet.htm?cont=jmC2BrdAhSIeeqqEEqual"
         );
         assert_eq!(metadata.title, "I tre moschettieri - Lettura I");
+        assert_eq!(metadata.duration, Some(1155));
 
         // Pulire il file di cache
         if cache_file.exists() {
@@ -373,22 +5142,176 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_download_audio() -> Result<()> {
-        let metadata = AudioMetadata {
-            url: "https://mediapolisvod.rai.it/relinker/relinkerServlet.htm?cont=jmC2BrdAhSIeeqqEEqual".to_string(),
+    async fn test_resolve_metadata() -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let urls: Vec<String> = (1..=6).map(|n| format!("/audio/ep{}.json", n)).collect();
+        for (n, url) in urls.iter().enumerate().skip(1) {
+            let json = if n == 3 {
+                "not json".to_string()
+            } else {
+                format!(
+                    r#"{{"audio": {{"title": "Episode {}", "url": "https://example.com/{}.mp3"}}}}"#,
+                    n + 1,
+                    n + 1
+                )
+            };
+            std::fs::write(cache_dir.path().join(&url[7..]), json)?;
+        }
+        let mut prefetched = HashMap::new();
+        let mut listed = parse_audio_metadata(
+            &serde_json::json!({"audio": {"title": "Listed", "url": "https://example.com/1.mp3"}}),
+        )?;
+        listed.metadata_path = urls[0].clone();
+        prefetched.insert(urls[0].clone(), listed);
+
+        let numbered = number_episodes(urls.iter().collect(), true, 0, None);
+        let resolved = resolve_metadata(
+            &MockFetcher::default(),
+            "https://www.raiplaysound.it/audiolibri/itremoschettieri",
+            numbered,
+            &mut prefetched,
+            &(Arc::new(cache::FileCache::new(cache_dir.path())) as SharedCache),
+            2,
+        )
+        .await;
+        let indexes: Vec<usize> = resolved.iter().map(|(index, _, _)| *index).collect();
+        assert_eq!(indexes, vec![1, 2, 3, 4, 5, 6]);
+        let titles: Vec<String> = resolved
+            .iter()
+            .map(|(_, _, result)| match result {
+                Ok(metadata) => metadata.title.clone(),
+                Err(_) => "error".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                "Episode 6",
+                "Episode 5",
+                "error",
+                "Episode 3",
+                "Episode 2",
+                "Listed"
+            ]
+        );
+        assert!(prefetched.is_empty());
+        Ok(())
+    }
+
+    /// Metadata of an episode whose audio lives behind the relinker.
+    fn relinked_metadata() -> AudioMetadata {
+        AudioMetadata {
+            url: RELINKER_URL.to_string(),
             title: "Test Audio".to_string(),
-        };
+            date: None,
+            duration: None,
+            description: None,
+            image: None,
+            show_image: None,
+            id: None,
+            show: None,
+            source: AudioSource::AudioUrl,
+            variants: Vec::new(),
+            video: false,
+            media_url: None,
+            original_name: None,
+            media_size: None,
+            container: Container::Mp3,
+            metadata_path: String::new(),
+            cache_entry: None,
+            day_rank: None,
+            season: None,
+            episode: None,
+            duplicate: false,
+            planned_path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_audio() -> Result<()> {
+        let cdn_url = "https://creativemedia1-rai-it.akamaized.net/lettura-1.mp3";
+        let mut audio = b"ID3".to_vec();
+        audio.resize(32 * 1024, 0);
+        let client = MockFetcher::default()
+            .fixture(
+                RELINKER_URL,
+                reqwest::StatusCode::OK,
+                "audio/mpeg",
+                Vec::new(),
+                Some(cdn_url),
+            )
+            .serve(cdn_url, "audio/mpeg", audio);
+        let mut metadata = relinked_metadata();
+        let folder = tempfile::tempdir()?;
+
+        let transfer = download_audio(
+            &client,
+            &mut metadata,
+            folder.path(),
+            Some(1),
+            &DownloadOptions::default(),
+        )
+        .await?;
+        assert_eq!(transfer.map(|transfer| transfer.bytes), Some(32 * 1024));
+        assert_eq!(metadata.media_url.as_deref(), Some(cdn_url));
+        let output_path = output_path(&metadata, folder.path(), Some(1), &Naming::default())?;
+        assert_eq!(std::fs::metadata(output_path)?.len(), 32 * 1024);
+        assert_eq!(
+            client.requests(),
+            vec![format!("HEAD {}", RELINKER_URL), format!("GET {}", cdn_url)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_audio_geo_blocked() -> Result<()> {
+        let placeholder = "https://mediapolisvod.rai.it/video_no_available.mp4";
+        let client = MockFetcher::default()
+            .fixture(
+                RELINKER_URL,
+                reqwest::StatusCode::OK,
+                "video/mp4",
+                Vec::new(),
+                Some(placeholder),
+            )
+            .serve(placeholder, "video/mp4", vec![0; 1024]);
+        let folder = tempfile::tempdir()?;
+        let err = download_audio(
+            &client,
+            &mut relinked_metadata(),
+            folder.path(),
+            Some(1),
+            &DownloadOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::GeoBlocked { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "reaches the RAI relinker and CDN"]
+    async fn test_download_audio_live() -> Result<()> {
+        let mut metadata = relinked_metadata();
         let folder = temp_dir().join("test_audio");
         create_dir_all(&folder).await?;
 
-        let client = get_client()?;
+        let client = HttpFetcher::new(get_client(&ClientOptions::default(), None)?);
 
-        let result = download_audio(&client, &metadata, &folder, 1).await;
+        let result = download_audio(
+            &client,
+            &mut metadata,
+            &folder,
+            Some(1),
+            &DownloadOptions::default(),
+        )
+        .await;
         assert!(result.is_ok());
 
-        let re = Regex::new(r"[^\w\s-]")?;
-        let sanitized_title = re.replace_all(&metadata.title, "_").to_lowercase();
-        let output_path = folder.join(format!("{:03} - {}.mp3", 1, sanitized_title));
+        let output_path = output_path(&metadata, &folder, Some(1), &Naming::default())?;
 
         assert!(output_path.exists());
 
@@ -399,4 +5322,659 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_output_path() -> Result<()> {
+        let metadata = AudioMetadata {
+            url: String::new(),
+            title: "I tre moschettieri: Lettura I".to_string(),
+            date: None,
+            duration: None,
+            description: None,
+            image: None,
+            show_image: None,
+            id: None,
+            show: None,
+            source: AudioSource::AudioUrl,
+            variants: Vec::new(),
+            video: false,
+            media_url: None,
+            original_name: None,
+            media_size: None,
+            container: Container::Mp3,
+            metadata_path: String::new(),
+            cache_entry: None,
+            day_rank: None,
+            season: None,
+            episode: None,
+            duplicate: false,
+            planned_path: None,
+        };
+        let path = output_path(&metadata, Path::new("libri"), Some(7), &Naming::default())?;
+        assert_eq!(
+            path,
+            Path::new("libri/07 - i tre moschettieri lettura i.mp3")
+        );
+        let path = output_path(&metadata, Path::new("libri"), None, &Naming::default())?;
+        assert_eq!(path, Path::new("libri/i tre moschettieri lettura i.mp3"));
+
+        // A name served by the CDN is checked too.
+        let folder = tempfile::tempdir()?;
+        let naming = Naming {
+            original: true,
+            no_index: true,
+            ..Naming::default()
+        };
+        let hostile = AudioMetadata {
+            original_name: Some("..".to_string()),
+            ..metadata
+        };
+        let err = original_path(&hostile, folder.path(), Some(1), &naming).unwrap_err();
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::UnsafePath { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_overwrite() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = |name: &str| folder.path().join(name);
+        for name in [
+            "01 - lettura i.mp3",
+            "lettura ii.mp3",
+            "notes.mp3",
+            "cdn name.mp3",
+        ] {
+            std::fs::write(path(name), b"ID3")?;
+        }
+        std::fs::write(path("lettura ii.info.json"), b"{}")?;
+        std::fs::write(
+            path("names.json"),
+            r#"{"cdn name.mp3": {"key": "a", "title": "A"}}"#,
+        )?;
+
+        assert!(check_overwrite(&path("missing.mp3"), false).is_ok());
+        assert!(check_overwrite(&path("01 - lettura i.mp3"), false).is_ok());
+        assert!(check_overwrite(&path("lettura ii.mp3"), false).is_ok());
+        assert!(check_overwrite(&path("cdn name.mp3"), false).is_ok());
+        let err = check_overwrite(&path("notes.mp3"), false).unwrap_err();
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::Clobber { .. })
+        ));
+        assert!(check_overwrite(&path("notes.mp3"), true).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(2048), "2.0 KB");
+        assert_eq!(format_size(9 * 1024 * 1024 * 1024), "9.0 GB");
+    }
+
+    #[test]
+    fn test_limit_count() {
+        assert!(LimitCount::Downloads.counts(true));
+        assert!(!LimitCount::Downloads.counts(false));
+        assert!(LimitCount::All.counts(true));
+        assert!(LimitCount::All.counts(false));
+    }
+
+    #[test]
+    fn test_number_episodes() {
+        let audio_urls: Vec<String> = (1..=6).map(|n| format!("/audio/{}.json", n)).collect();
+        let numbered = |reverse, skip, select: Option<&str>| -> Vec<(usize, String)> {
+            let selection = select.map(|s| s.parse::<Selection>().unwrap());
+            number_episodes(audio_urls.clone(), reverse, skip, selection.as_ref())
+        };
+
+        assert_eq!(
+            numbered(false, 4, None),
+            vec![(5, "/audio/5.json".into()), (6, "/audio/6.json".into())]
+        );
+        assert_eq!(
+            numbered(true, 4, None),
+            vec![(5, "/audio/2.json".into()), (6, "/audio/1.json".into())]
+        );
+        assert_eq!(
+            numbered(true, 0, Some("1,3")),
+            vec![(1, "/audio/6.json".into()), (3, "/audio/4.json".into())]
+        );
+        assert_eq!(
+            numbered(true, 2, Some("1-4")),
+            vec![(3, "/audio/4.json".into()), (4, "/audio/3.json".into())]
+        );
+    }
+
+    #[test]
+    fn test_select_episodes() {
+        let mut episodes: Vec<Episode> = (1..=6)
+            .map(|index| Episode {
+                index,
+                metadata: parse_audio_metadata(&serde_json::json!({
+                    "audio": {"title": format!("Puntata {}", index), "url": "https://example.com/a.mp3"}
+                }))
+                .unwrap(),
+            })
+            .collect();
+        let show_url = "https://www.raiplaysound.it/programmi/battiti";
+        let args =
+            Args::try_parse_from(["rsnd", show_url, "--skip", "2", "--select", "1-4,6"]).unwrap();
+        select_episodes(&mut episodes, &args);
+        let indices: Vec<usize> = episodes.iter().map(|episode| episode.index).collect();
+        // --skip drops the first two, whatever --select picks.
+        assert_eq!(indices, [3, 4, 6]);
+    }
+
+    #[test]
+    fn test_sort_metadata() {
+        let metadata = |title: &str, date: Option<(i32, u32, u32)>| AudioMetadata {
+            url: String::new(),
+            title: title.to_string(),
+            date: date.and_then(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+            duration: None,
+            description: None,
+            image: None,
+            show_image: None,
+            id: None,
+            show: None,
+            source: AudioSource::AudioUrl,
+            variants: Vec::new(),
+            video: false,
+            media_url: None,
+            original_name: None,
+            media_size: None,
+            container: Container::Mp3,
+            metadata_path: String::new(),
+            cache_entry: None,
+            day_rank: None,
+            season: None,
+            episode: None,
+            duplicate: false,
+            planned_path: None,
+        };
+        let mut all = vec![
+            metadata("Lettura III", Some((2015, 6, 14))),
+            metadata("Speciale", None),
+            metadata("Lettura I", Some((2015, 6, 12))),
+            metadata("lettura II", Some((2015, 6, 12))),
+        ];
+
+        sort_metadata(&mut all, SortKey::Date);
+        let titles: Vec<&str> = all.iter().map(|m| m.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Lettura I", "lettura II", "Lettura III", "Speciale"]
+        );
+
+        sort_metadata(&mut all, SortKey::Title);
+        let titles: Vec<&str> = all.iter().map(|m| m.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Lettura I", "lettura II", "Lettura III", "Speciale"]
+        );
+
+        let numbered = number_episodes(all, false, 1, None);
+        assert_eq!(numbered[0].0, 2);
+        assert_eq!(numbered[0].1.title, "lettura II");
+
+        let mut all = vec![
+            metadata("Lettura XI", None),
+            metadata("Speciale", None),
+            metadata("Lettura IX", None),
+            metadata("Lettura 10", None),
+        ];
+        sort_metadata(&mut all, SortKey::Part);
+        let titles: Vec<&str> = all.iter().map(|m| m.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Lettura IX", "Lettura 10", "Lettura XI", "Speciale"]
+        );
+    }
+
+    #[test]
+    fn test_extract_options_duplicates() {
+        let html = r#"
+            <rps-play-with-labels options='{"url": "/audio/2015/06/lettura-1.json"}'></rps-play-with-labels>
+            <rps-play-with-labels options='{"url": "/audio/2015/06/lettura-2.json"}'></rps-play-with-labels>
+            <rps-play-with-labels options='{"url": "/audio/2015/06/lettura-1.json"}'></rps-play-with-labels>
+            <rps-play-with-labels options='{"url": "/audio/2015/06/lettura-3.json"}'></rps-play-with-labels>
+        "#;
+        let options = extract_options(html).unwrap();
+        assert_eq!(
+            options,
+            vec![
+                "/audio/2015/06/lettura-1.json",
+                "/audio/2015/06/lettura-2.json",
+                "/audio/2015/06/lettura-3.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedup_by_audio_url() {
+        let metadata = |url: &str, title: &str| AudioMetadata {
+            url: url.to_string(),
+            title: title.to_string(),
+            date: None,
+            duration: None,
+            description: None,
+            image: None,
+            show_image: None,
+            id: None,
+            show: None,
+            source: AudioSource::AudioUrl,
+            variants: Vec::new(),
+            video: false,
+            media_url: None,
+            original_name: None,
+            media_size: None,
+            container: Container::Mp3,
+            metadata_path: String::new(),
+            cache_entry: None,
+            day_rank: None,
+            season: None,
+            episode: None,
+            duplicate: false,
+            planned_path: None,
+        };
+        let all = vec![
+            metadata("https://example.com/a", "Lettura I"),
+            metadata("https://example.com/b", "Lettura II"),
+            metadata("https://example.com/a", "Lettura I (replica)"),
+        ];
+        let mut dropped = HashSet::new();
+        let titles: Vec<String> = dedup_by_audio_url(all, |m| m, &mut dropped)
+            .into_iter()
+            .map(|m| m.title)
+            .collect();
+        assert_eq!(titles, vec!["Lettura I", "Lettura II"]);
+        assert_eq!(dropped.len(), 1);
+    }
+
+    #[test]
+    fn test_episode_metadata_path() {
+        assert_eq!(
+            episode_metadata_path(
+                "https://www.raiplaysound.it/audio/2015/06/I-tre-moschettieri---Lettura-I-4d3f.html"
+            ),
+            Some("/audio/2015/06/I-tre-moschettieri---Lettura-I-4d3f.json".to_string())
+        );
+        assert_eq!(
+            episode_metadata_path("https://www.raiplaysound.it/audiolibri/itremoschettieri"),
+            None
+        );
+        assert_eq!(
+            episode_metadata_path("https://www.raiplaysound.it/audio/2015/06/lettura.json"),
+            None
+        );
+        // A page saved under an `audio` folder is still a show page.
+        assert_eq!(
+            episode_metadata_path("file:///audio/2015/06/lettura.html"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_local_page() {
+        assert_eq!(
+            local_page("file:///home/me/i%20tre%20moschettieri.html"),
+            Some(PathBuf::from("/home/me/i tre moschettieri.html"))
+        );
+        assert_eq!(
+            local_page("https://www.raiplaysound.it/audiolibri/itremoschettieri"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_options_shapes() {
+        let html = r#"
+            <rps-play-with-labels options='{&amp;quot;url&amp;quot;: &amp;quot;/audio/a.json&amp;quot;}'></rps-play-with-labels>
+            <rps-play-with-labels options='{"item": {"url": "/audio/b.json"}}'></rps-play-with-labels>
+            <rps-play-with-labels options='[{"url": "/audio/c.json"}, {"url": "/audio/d.json"}]'></rps-play-with-labels>
+            <rps-play-with-labels options='{"items": [{"url": "/audio/e.json"}]}'></rps-play-with-labels>
+            <rps-play-with-labels options='{"url": "/audio/f.json?a=1&amp;amp;b=2"}'></rps-play-with-labels>
+        "#;
+        assert_eq!(
+            extract_options(html).unwrap(),
+            vec![
+                "/audio/a.json",
+                "/audio/b.json",
+                "/audio/c.json",
+                "/audio/d.json",
+                "/audio/e.json",
+                // Valid JSON as the parser decoded it is not decoded again.
+                "/audio/f.json?a=1&amp;b=2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_options_malformed() {
+        let html = r#"
+            <rps-play-with-labels options='{"url": "/audio/a.json"'></rps-play-with-labels>
+            <rps-play-with-labels options='{"title": "No url"}'></rps-play-with-labels>
+            <rps-play-with-labels options='{"url": "/audio/b.json"}'></rps-play-with-labels>
+            <rps-play-with-labels></rps-play-with-labels>
+        "#;
+        assert_eq!(extract_options(html).unwrap(), vec!["/audio/b.json"]);
+
+        let html = r#"<rps-play-with-labels options='not json'></rps-play-with-labels>"#;
+        assert!(extract_options(html).is_err());
+        assert!(extract_options("<html></html>").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_audio_url_fallbacks() {
+        let cases = [
+            (
+                r#"{"audio": {"url": "https://a/1.mp3", "downloadUrl": "https://a/2.mp3"}}"#,
+                "https://a/1.mp3",
+                AudioSource::AudioUrl,
+            ),
+            (
+                r#"{"audio": {"downloadUrl": "https://a/2.mp3"}, "downloadable_url": "https://a/3.mp3"}"#,
+                "https://a/3.mp3",
+                AudioSource::DownloadableUrl,
+            ),
+            (
+                r#"{"audio": {"url": "", "downloadUrl": "https://a/2.mp3"}}"#,
+                "https://a/2.mp3",
+                AudioSource::DownloadUrl,
+            ),
+            (
+                r#"{"downloadable_audio": {"url": "https://a/4.mp3"}}"#,
+                "https://a/4.mp3",
+                AudioSource::DownloadableAudio,
+            ),
+        ];
+        for (json, url, source) in cases {
+            let value: Value = serde_json::from_str(json).unwrap();
+            assert_eq!(
+                audio_url(&value),
+                Some((url.to_string(), source)),
+                "{}",
+                json
+            );
+        }
+        assert_eq!(audio_url(&serde_json::json!({"audio": {}})), None);
+        // The web page of the episode is not audio.
+        assert_eq!(
+            audio_url(&serde_json::json!({"weblink": "/audio/2015/06/lettura-1.html"})),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_audio_metadata_without_url() {
+        let value = serde_json::json!({"audio": {"title": "Lettura I"}});
+        let err = parse_audio_metadata(&value).unwrap_err();
+        assert!(format!("{:#}", err).contains("\"Lettura I\""));
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::MissingAudioUrl { .. })
+        ));
+
+        let value = serde_json::json!({
+            "audio": {"title": "Lettura I"},
+            "weblink": "/audio/2015/06/lettura-1.html"
+        });
+        let err = parse_audio_metadata(&value).unwrap_err();
+        assert!(web_page_only(&err));
+        assert_eq!(
+            reason_of(&err),
+            "no audio, only the web page https://www.raiplaysound.it/audio/2015/06/lettura-1.html"
+        );
+    }
+
+    #[test]
+    fn test_parse_video_item() {
+        let value = serde_json::json!({
+            "title": "Videocast I",
+            "video": {"url": "https://example.com/videocast-1.mp4", "duration": "00:10:00"}
+        });
+        let metadata = parse_audio_metadata(&value).unwrap();
+        assert!(metadata.video);
+        assert_eq!(metadata.title, "Videocast I");
+        assert_eq!(metadata.source, AudioSource::VideoUrl);
+        assert_eq!(metadata.container, Container::Mp4);
+        assert_eq!(metadata.duration, Some(600));
+    }
+
+    #[test]
+    fn test_select_variant() {
+        let value = serde_json::json!({
+            "audio": {
+                "title": "Lettura I",
+                "url": "https://example.com/lettura-1.mp3",
+                "renditions": [
+                    {"url": "https://example.com/lettura-1_64.mp3", "bitrate": 64},
+                    {"url": "https://example.com/lettura-1_128.m4a", "bitrate": 128},
+                ],
+            }
+        });
+        let mut metadata = parse_audio_metadata(&value).unwrap();
+        assert_eq!(metadata.variants.len(), 3);
+        assert_eq!(
+            metadata.variants[0].url,
+            "https://example.com/lettura-1.mp3"
+        );
+
+        metadata.select_variant(&variant::Choice::default());
+        assert_eq!(metadata.url, "https://example.com/lettura-1.mp3");
+        assert_eq!(metadata.source, AudioSource::AudioUrl);
+
+        metadata.select_variant(&variant::Choice {
+            quality: Some(variant::Quality::Best),
+            format: None,
+        });
+        assert_eq!(metadata.url, "https://example.com/lettura-1_128.m4a");
+        assert_eq!(metadata.source, AudioSource::Rendition);
+        assert_eq!(metadata.container, Container::M4a);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_metadata_bodies() -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let cache = cache::FileCache::new(cache_dir.path());
+        let client = HttpFetcher::offline(get_client(&ClientOptions::default(), None)?);
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+        for fixture in ["lettura-1-bom.json", "lettura-1-xssi.json"] {
+            std::fs::copy(
+                fixtures.join(fixture),
+                cache_dir.path().join("lettura-1.json"),
+            )?;
+            let metadata =
+                fetch_audio_metadata(&client, "/audio/2015/06/lettura-1.json", &cache).await?;
+            assert_eq!(metadata.title, "Lettura I", "{}", fixture);
+        }
+
+        // The page cached in place of the JSON is fetched again, and served again.
+        let error_page = std::fs::read(fixtures.join("lettura-1-error.html"))?;
+        std::fs::write(cache_dir.path().join("lettura-1.json"), &error_page)?;
+        let client = MockFetcher::default().serve(
+            "https://www.raiplaysound.it/audio/2015/06/lettura-1.json",
+            "text/html",
+            error_page,
+        );
+        let err = fetch_audio_metadata(&client, "/audio/2015/06/lettura-1.json", &cache)
+            .await
+            .unwrap_err();
+        assert_eq!(client.requests().len(), 1);
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::MetadataHtml { .. })
+        ));
+        let message = err.to_string();
+        assert!(message.starts_with("Metadata endpoint returned HTML (status page?)"));
+        assert!(message.contains("<!DOCTYPE html> <html lang=\"it\"> <head>"));
+        // The page is dropped from the cache, so the next run fetches the JSON again.
+        assert!(!cache_dir.path().join("lettura-1.json").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_variants() -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let cache = cache::FileCache::new(cache_dir.path());
+        let client = HttpFetcher::new(get_client(&ClientOptions::default(), None)?);
+
+        let broken = MockFetcher::default().serve(
+            "https://www.raiplaysound.it/audio/broken.json",
+            "application/json",
+            "not json",
+        );
+        let err = fetch_audio_metadata(&broken, "/audio/broken.json", &cache)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            RsndError::of(&err),
+            Some(RsndError::MetadataParse { .. })
+        ));
+
+        let entry = cache::Entry::new("http://127.0.0.1:1/audiolibri/show", "show.html");
+        let err = fetch_cached(&client, &cache, &entry).await.unwrap_err();
+        let variant = RsndError::of(&err);
+        assert!(matches!(variant, Some(RsndError::PageFetch { .. })));
+        assert!(variant.is_some_and(RsndError::is_page_unavailable));
+
+        // A directory where the cache file should be cannot be read.
+        std::fs::create_dir(cache_dir.path().join("dir.html"))?;
+        let entry = cache::Entry::new("http://127.0.0.1:1/dir", "dir.html");
+        let err = fetch_cached(&client, &cache, &entry).await.unwrap_err();
+        let variant = RsndError::of(&err);
+        assert!(matches!(variant, Some(RsndError::Cache { .. })));
+        assert!(!variant.is_some_and(RsndError::is_page_unavailable));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_audio_metadata_shapes() {
+        let value: Value =
+            serde_json::from_str(include_str!("../tests/fixtures/lettura-1.json")).unwrap();
+        let metadata = parse_audio_metadata(&value).unwrap();
+        assert_eq!(metadata.date, NaiveDate::from_ymd_opt(2015, 6, 12));
+        assert_eq!(metadata.duration, Some(1155));
+        assert_eq!(metadata.id.as_deref(), Some("ContentItem-lettura-1"));
+        assert_eq!(metadata.show.as_deref(), Some("I tre moschettieri"));
+        assert_eq!(metadata.description, None);
+        assert_eq!(metadata.image, None);
+
+        let value = serde_json::json!({
+            "id": 4567,
+            "description": "  La prima puntata.  ",
+            "images": {"landscape": "", "square": "/dl/img/2023/01/square.jpg"},
+            "program": {"name": "Il ruggito del coniglio"},
+            "literal_publication_date": "3 Gennaio 2023",
+            "audio": {"title": "Puntata del 03/01/2023", "duration_in_seconds": 5400},
+            "downloadable_url": "https://example.com/puntata.mp3"
+        });
+        let metadata = parse_audio_metadata(&value).unwrap();
+        assert_eq!(metadata.date, NaiveDate::from_ymd_opt(2023, 1, 3));
+        assert_eq!(metadata.duration, Some(5400));
+        assert_eq!(metadata.id.as_deref(), Some("4567"));
+        assert_eq!(metadata.show.as_deref(), Some("Il ruggito del coniglio"));
+        assert_eq!(metadata.description.as_deref(), Some("La prima puntata."));
+        assert_eq!(
+            metadata.image.as_deref(),
+            Some("https://www.raiplaysound.it/dl/img/2023/01/square.jpg")
+        );
+
+        let value = serde_json::json!({
+            "uniquename": "",
+            "image": "https://img.example.com/cover.jpg",
+            "audio": {"title": "Senza data", "url": "https://example.com/a.mp3", "duration": ""}
+        });
+        let metadata = parse_audio_metadata(&value).unwrap();
+        assert_eq!(metadata.date, None);
+        assert_eq!(metadata.duration, None);
+        assert_eq!(metadata.id, None);
+        assert_eq!(metadata.show, None);
+        assert_eq!(
+            metadata.image.as_deref(),
+            Some("https://img.example.com/cover.jpg")
+        );
+    }
+
+    #[test]
+    fn test_existing_output_any_container() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let mut metadata = AudioMetadata {
+            url: String::new(),
+            title: "Lettura I".to_string(),
+            date: None,
+            duration: None,
+            description: None,
+            image: None,
+            show_image: None,
+            id: None,
+            show: None,
+            source: AudioSource::AudioUrl,
+            variants: Vec::new(),
+            video: false,
+            media_url: None,
+            original_name: None,
+            media_size: None,
+            container: Container::Mp3,
+            metadata_path: String::new(),
+            cache_entry: None,
+            day_rank: None,
+            season: None,
+            episode: None,
+            duplicate: false,
+            planned_path: None,
+        };
+        assert_eq!(
+            existing_output(&metadata, folder.path(), Some(1), &Naming::default())?,
+            None
+        );
+
+        metadata.container = Container::M4a;
+        let path = output_path(&metadata, folder.path(), Some(1), &Naming::default())?;
+        assert_eq!(path, folder.path().join("01 - lettura i.m4a"));
+        std::fs::write(&path, b"ftyp")?;
+
+        metadata.container = Container::Mp3;
+        assert_eq!(
+            existing_output(&metadata, folder.path(), Some(1), &Naming::default())?,
+            Some(path)
+        );
+
+        // Files saved with another pad width are found too.
+        let wide = Naming {
+            pad_width: 3,
+            ..Naming::default()
+        };
+        assert_eq!(
+            existing_output(&metadata, folder.path(), Some(1), &wide)?,
+            Some(folder.path().join("01 - lettura i.m4a"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_looks_expired() {
+        let status = |status| {
+            anyhow::Error::from(RsndError::Status {
+                url: "https://cdn.rai.it/lettura-1.mp3".to_string(),
+                status,
+                retry_after: None,
+            })
+        };
+        assert!(looks_expired(&status(reqwest::StatusCode::FORBIDDEN)));
+        assert!(looks_expired(&status(reqwest::StatusCode::NOT_FOUND)));
+        assert!(!looks_expired(&status(
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        )));
+        assert!(looks_expired(&anyhow::Error::from(RsndError::GeoBlocked {
+            reason: "status 403 Forbidden".to_string(),
+            status: reqwest::StatusCode::FORBIDDEN,
+        })));
+        assert!(!looks_expired(&anyhow::anyhow!("Connection reset")));
+    }
 }
@@ -0,0 +1,159 @@
+use crate::error::RsndError;
+use crate::retry::{self, RetryClass};
+use anyhow::{anyhow, Result};
+use reqwest::{StatusCode, Url};
+
+/// Mirrors known to serve the same paths as the mediapolis hosts, tried after those
+/// given with `--cdn-fallback`.
+const BUILT_IN: [(&str, &str); 2] = [
+    ("mediapolis.rai.it", "creativemedia1.rai.it"),
+    ("mediapolisvod.rai.it", "creativemedia3.rai.it"),
+];
+
+/// A host whose audio is also served by another one, as in
+/// `mediapolis.rai.it=creativemedia1.rai.it`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub from: String,
+    pub to: String,
+}
+
+/// Parses a `--cdn-fallback` value.
+pub fn parse_substitution(text: &str) -> Result<Substitution> {
+    let (from, to) = text.split_once('=').ok_or_else(|| {
+        anyhow!("expected FROM=TO, as in mediapolis.rai.it=creativemedia1.rai.it")
+    })?;
+    let host = |host: &str| {
+        let host = host.trim().to_lowercase();
+        match Url::parse(&format!("https://{}/", host)) {
+            Ok(url) if !host.is_empty() && url.host_str() == Some(host.as_str()) => Ok(host),
+            _ => Err(anyhow!("not a host name: {:?}", host)),
+        }
+    };
+    Ok(Substitution {
+        from: host(from)?,
+        to: host(to)?,
+    })
+}
+
+/// Returns the substitutions to try, those given first and then the built-in ones.
+pub fn substitutions(given: &[Substitution]) -> Vec<Substitution> {
+    let built_in = BUILT_IN.iter().map(|(from, to)| Substitution {
+        from: from.to_string(),
+        to: to.to_string(),
+    });
+    let mut substitutions = given.to_vec();
+    for substitution in built_in {
+        if !substitutions.contains(&substitution) {
+            substitutions.push(substitution);
+        }
+    }
+    substitutions
+}
+
+/// Returns the URLs of the mirrors of `url`, in the order of the substitutions that
+/// apply to its host.
+pub fn mirrors(url: &str, substitutions: &[Substitution]) -> Vec<String> {
+    let Ok(parsed) = Url::parse(url) else {
+        return Vec::new();
+    };
+    let host = parsed.host_str().unwrap_or_default().to_lowercase();
+    let mut mirrors: Vec<String> = Vec::new();
+    for substitution in substitutions.iter().filter(|sub| sub.from == host) {
+        let mut mirror = parsed.clone();
+        if mirror.set_host(Some(&substitution.to)).is_ok() && !mirrors.contains(&mirror.to_string())
+        {
+            mirrors.push(mirror.to_string());
+        }
+    }
+    mirrors
+}
+
+/// Returns the host of a URL, to tell which one served a download.
+pub fn host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Returns whether a failed download is worth trying on a mirror: the host could not be
+/// reached, or refused to serve the file. Placeholders served in place of the audio are
+/// not, since every mirror serves them alike.
+pub fn should_fall_back(err: &anyhow::Error) -> bool {
+    if retry::classify(err) == Some(RetryClass::Connect) {
+        return true;
+    }
+    match RsndError::of(err) {
+        Some(RsndError::Status { status, .. } | RsndError::GeoBlocked { status, .. }) => {
+            *status == StatusCode::FORBIDDEN
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CDN_URL: &str = "https://mediapolis.rai.it/audio/2015/06/lettura-1.mp3?x=1";
+
+    #[test]
+    fn test_parse_substitution() {
+        assert_eq!(
+            parse_substitution("Mediapolis.rai.it=creativemedia2.rai.it").unwrap(),
+            Substitution {
+                from: "mediapolis.rai.it".to_string(),
+                to: "creativemedia2.rai.it".to_string(),
+            }
+        );
+        assert!(parse_substitution("mediapolis.rai.it").is_err());
+        assert!(parse_substitution("mediapolis.rai.it=").is_err());
+        assert!(parse_substitution("mediapolis.rai.it=https://other.rai.it/").is_err());
+    }
+
+    #[test]
+    fn test_mirrors() {
+        let given = [parse_substitution("mediapolis.rai.it=creativemedia2.rai.it").unwrap()];
+        assert_eq!(
+            mirrors(CDN_URL, &substitutions(&given)),
+            [
+                "https://creativemedia2.rai.it/audio/2015/06/lettura-1.mp3?x=1",
+                "https://creativemedia1.rai.it/audio/2015/06/lettura-1.mp3?x=1",
+            ]
+        );
+        // Built-in substitutions are not repeated when given again.
+        let given = [parse_substitution("mediapolis.rai.it=creativemedia1.rai.it").unwrap()];
+        assert_eq!(mirrors(CDN_URL, &substitutions(&given)).len(), 1);
+        assert!(mirrors("https://cdn.rai.it/lettura-1.mp3", &substitutions(&[])).is_empty());
+        assert_eq!(host(CDN_URL), "mediapolis.rai.it");
+    }
+
+    #[test]
+    fn test_should_fall_back() {
+        let status = |status| {
+            anyhow::Error::from(RsndError::Status {
+                url: CDN_URL.to_string(),
+                status,
+                retry_after: None,
+            })
+        };
+        assert!(should_fall_back(&status(StatusCode::FORBIDDEN)));
+        assert!(!should_fall_back(&status(StatusCode::NOT_FOUND)));
+        let geo = |reason: &str, status| {
+            anyhow::Error::from(RsndError::GeoBlocked {
+                reason: reason.to_string(),
+                status,
+            })
+        };
+        assert!(should_fall_back(&geo(
+            "status 403 Forbidden",
+            StatusCode::FORBIDDEN
+        )));
+        assert!(!should_fall_back(&geo(
+            "redirected to the no_available placeholder https://cdn.rai.it/no_available.mp4",
+            StatusCode::OK
+        )));
+        assert!(!should_fall_back(&anyhow!("other")));
+    }
+}
@@ -0,0 +1,931 @@
+use crate::error::RsndError;
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+/// Pad width used when there are fewer than 100 episodes.
+const MIN_PAD_WIDTH: usize = 2;
+
+/// Device names Windows reserves, whatever the extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest path Windows opens without the extended-length prefix, the terminating NUL
+/// excluded.
+const MAX_PATH: usize = 259;
+
+/// Bytes set aside for the extension, dot included, when limiting the file name length.
+const EXTENSION_BYTES: usize = 4;
+
+/// Default limit of the file name length, below the 255 bytes most file systems allow.
+pub const DEFAULT_MAX_BYTES: usize = 200;
+
+/// How the output files are named.
+#[derive(Debug, Clone, Copy)]
+pub struct Naming {
+    /// Name files after the episode date (or id) and title instead of its index.
+    pub stable: bool,
+    /// Leave out the index prefix.
+    pub no_index: bool,
+    /// Number given to the episode with index 1.
+    pub start_index: usize,
+    /// Width the number is zero-padded to.
+    pub pad_width: usize,
+    /// Keep the casing of the title instead of lowercasing it.
+    pub keep_case: bool,
+    /// Character that stands for the characters dropped from titles.
+    pub replacement: char,
+    /// Longest file name, extension included, in bytes.
+    pub max_bytes: usize,
+    /// Extension every file is saved with, whatever its container.
+    pub extension: Option<&'static str>,
+    /// Keep the name the CDN serves the file under.
+    pub original: bool,
+    /// Prefix names with the publication date instead of the index.
+    pub by_date: bool,
+    /// Prefix names with the season and episode, like `S02E05`, instead of the index.
+    pub sxxeyy: bool,
+    /// Transliterate titles to ASCII.
+    pub ascii: bool,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Naming {
+            stable: false,
+            no_index: false,
+            start_index: 1,
+            pad_width: MIN_PAD_WIDTH,
+            keep_case: false,
+            replacement: ' ',
+            max_bytes: DEFAULT_MAX_BYTES,
+            extension: None,
+            original: false,
+            by_date: false,
+            sxxeyy: false,
+            ascii: false,
+        }
+    }
+}
+
+/// Returns the pad width that fits the last of `count` episodes numbered from
+/// `start_index`, and at least two digits.
+pub fn auto_pad_width(count: usize, start_index: usize) -> usize {
+    let last = (start_index + count).saturating_sub(1);
+    last.to_string().len().max(MIN_PAD_WIDTH)
+}
+
+/// Parses the `--replacement-char` argument, refusing characters that are not safe in
+/// file names themselves.
+pub fn parse_replacement(text: &str) -> Result<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_control() && !r#"/\<>:"|?*"#.contains(c) => Ok(c),
+        _ => Err(anyhow!(
+            "Invalid replacement {:?}: expected a single character that is safe in file names",
+            text
+        )),
+    }
+}
+
+/// Transliterates the text to ASCII, e.g. "è" to "e" and "’" to "'". Characters without
+/// a mapping become the replacement character.
+fn transliterate(text: &str, replacement: char) -> String {
+    let mut ascii = String::with_capacity(text.len());
+    for c in text.chars() {
+        match deunicode::deunicode_char(c) {
+            _ if c.is_ascii() => ascii.push(c),
+            Some(mapped) if !mapped.is_empty() => ascii.push_str(mapped),
+            _ => ascii.push(replacement),
+        }
+    }
+    ascii
+}
+
+/// Makes the text safe for file names, lowercasing it unless `keep_case` is set.
+///
+/// Letters (accented ones included, unless `ascii` transliterates them), digits, spaces
+/// and hyphens are kept; apostrophes and colons become spaces, and anything else becomes
+/// the replacement character. Runs of separators are collapsed and trimmed from both
+/// ends.
+pub fn sanitize(text: &str, naming: &Naming) -> String {
+    let replacement = naming.replacement;
+    let text = if naming.ascii {
+        Cow::Owned(transliterate(text, replacement))
+    } else {
+        Cow::Borrowed(text)
+    };
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        let c = match c {
+            c if c.is_alphanumeric() || c == '-' => c,
+            c if c.is_whitespace() => ' ',
+            '\'' | '’' | '‘' | 'ʼ' | '`' | ':' => ' ',
+            _ => replacement,
+        };
+        // Collapse runs of separators, preferring a space to the replacement.
+        if (c == ' ' || c == replacement) && sanitized.ends_with([' ', replacement]) {
+            if c == ' ' && sanitized.ends_with(replacement) {
+                sanitized.pop();
+                sanitized.push(' ');
+            }
+            continue;
+        }
+        sanitized.push(c);
+    }
+    let sanitized = sanitized.trim_matches(|c| c == ' ' || c == '-' || c == replacement);
+    let sanitized = if sanitized.is_empty() {
+        "untitled"
+    } else {
+        sanitized
+    };
+    if naming.keep_case {
+        sanitized.to_string()
+    } else {
+        sanitized.to_lowercase()
+    }
+}
+
+/// Sanitizes the text like older versions did, replacing every character but letters,
+/// digits, spaces and hyphens with an underscore.
+fn legacy_sanitize(text: &str, naming: &Naming) -> Result<String> {
+    let re = Regex::new(r"[^\w\s-]")?;
+    let sanitized = re.replace_all(text, "_");
+    Ok(if naming.keep_case {
+        sanitized.into_owned()
+    } else {
+        sanitized.to_lowercase()
+    })
+}
+
+/// What the file name of an episode is built from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NameParts<'a> {
+    pub title: &'a str,
+    pub date: Option<NaiveDate>,
+    pub id: Option<&'a str>,
+    pub idx: Option<usize>,
+    /// Position among the episodes published on the same day, when there are several.
+    pub day_rank: Option<usize>,
+    /// Season and episode within the season.
+    pub season: Option<(usize, usize)>,
+    /// What identifies the episode, like its id or audio URL.
+    pub key: Option<&'a str>,
+    /// Another episode of the show has the same name, so the name ends with a hash of
+    /// `key` to tell them apart.
+    pub duplicate: bool,
+}
+
+/// Returns the file name of an episode, without the extension.
+///
+/// Numbered names are prefixed with the index when there is one, shifted by
+/// `start_index` and padded to `pad_width`. Stable names do not
+/// depend on the page position: they are prefixed with the publication date, or
+/// suffixed with the episode id when the date is unknown. Names by date are prefixed
+/// with the date and the rank within the day, falling back to the index. Season names
+/// are prefixed like `S02E05`, episodes without a season counting as season 1.
+pub fn file_stem(parts: &NameParts, naming: &Naming) -> Result<String> {
+    Ok(windows_safe(&compose(
+        sanitize(parts.title, naming),
+        parts,
+        naming,
+    )))
+}
+
+/// Makes the file stem valid on Windows too, so that a library can be shared between
+/// systems: trailing dots and spaces are stripped and reserved device names get an
+/// underscore.
+pub fn windows_safe(stem: &str) -> String {
+    let stem = stem.trim_end_matches(['.', ' ']);
+    let base = stem.split('.').next().unwrap_or(stem);
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+    {
+        format!("{}_", stem)
+    } else {
+        stem.to_string()
+    }
+}
+
+/// Returns the extended-length form of an absolute Windows path longer than `MAX_PATH`.
+pub fn extended_length(path: &str) -> Option<String> {
+    if path.chars().count() <= MAX_PATH || path.starts_with(r"\\?\") {
+        return None;
+    }
+    Some(match path.strip_prefix(r"\\") {
+        Some(share) => format!(r"\\?\UNC\{}", share),
+        None => format!(r"\\?\{}", path),
+    })
+}
+
+/// Lets Windows open output paths longer than `MAX_PATH`; elsewhere the path is
+/// returned unchanged.
+pub fn long_path(path: PathBuf) -> PathBuf {
+    if !cfg!(windows) {
+        return path;
+    }
+    std::path::absolute(&path)
+        .ok()
+        .and_then(|absolute| extended_length(&absolute.to_string_lossy()))
+        .map_or(path, PathBuf::from)
+}
+
+/// Builds the file stem around an already sanitized title.
+fn compose(title: String, parts: &NameParts, naming: &Naming) -> String {
+    let (prefix, suffix) = match (naming.stable, parts.date, parts.id) {
+        (true, Some(date), _) => (format!("{} - ", date), String::new()),
+        (true, None, Some(id)) => (String::new(), format!(" - {}", sanitize(id, naming))),
+        (true, None, None) => (String::new(), String::new()),
+        (false, Some(date), _) if naming.by_date => {
+            let rank = parts
+                .day_rank
+                .map_or_else(String::new, |rank| format!("-{}", rank));
+            (format!("{}{} - ", date, rank), String::new())
+        }
+        (false, _, _) if naming.sxxeyy && !naming.no_index => {
+            let prefix = parts
+                .season
+                .or_else(|| Some((crate::season::DEFAULT_SEASON, parts.idx?)))
+                .map_or_else(String::new, |(season, episode)| {
+                    format!("{} - ", crate::season::code(season, episode))
+                });
+            (prefix, String::new())
+        }
+        (false, _, _) => (
+            index_prefix(parts.idx, naming).unwrap_or_default(),
+            String::new(),
+        ),
+    };
+    let suffix = match parts.key {
+        Some(key) if parts.duplicate => format!("{} ~{}", suffix, short_hash(key)),
+        _ => suffix,
+    };
+    let budget = naming
+        .max_bytes
+        .saturating_sub(prefix.len() + suffix.len() + EXTENSION_BYTES);
+    format!("{}{}{}", prefix, shorten(&title, budget), suffix)
+}
+
+/// Truncates the title to at most `budget` bytes on a character boundary, ending it
+/// with a hash of the full title so that truncated names stay unique.
+fn shorten(title: &str, budget: usize) -> String {
+    if title.len() <= budget {
+        return title.to_string();
+    }
+    let hash = format!("{:08x}", fnv1a(title.as_bytes()));
+    let mut end = budget.saturating_sub(hash.len() + 1).min(title.len());
+    while !title.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}~{}", title[..end].trim_end(), hash)
+}
+
+/// Returns a short hash of the episode key, which tells apart episodes with the same
+/// name in the same way on every run.
+pub fn short_hash(key: &str) -> String {
+    format!("{:06x}", fnv1a(key.as_bytes()) & 0xff_ffff)
+}
+
+/// 32-bit FNV-1a hash, stable across platforms and Rust versions.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Ranks the episodes published on the same day in index order, for the episodes given
+/// as index and date. Episodes alone on their day get no rank.
+pub fn day_ranks(episodes: &[(usize, Option<NaiveDate>)]) -> HashMap<usize, usize> {
+    let mut by_day: BTreeMap<NaiveDate, Vec<usize>> = BTreeMap::new();
+    for (index, date) in episodes {
+        if let Some(date) = date {
+            by_day.entry(*date).or_default().push(*index);
+        }
+    }
+    let mut ranks = HashMap::new();
+    for mut indices in by_day.into_values().filter(|indices| indices.len() > 1) {
+        indices.sort_unstable();
+        for (rank, index) in indices.into_iter().enumerate() {
+            ranks.insert(index, rank + 1);
+        }
+    }
+    ranks
+}
+
+/// Returns the number the file of an episode is named with, if its name has one.
+pub fn file_number(idx: Option<usize>, naming: &Naming) -> Option<usize> {
+    idx.filter(|_| !naming.stable && !naming.no_index)
+        .map(|idx| idx + naming.start_index - 1)
+}
+
+/// Returns the number prefix of a numbered file name, like `07 - `.
+pub fn index_prefix(idx: Option<usize>, naming: &Naming) -> Option<String> {
+    let number = file_number(idx, naming)?;
+    Some(format!("{:0width$} - ", number, width = naming.pad_width))
+}
+
+/// Checks that `path` stays inside `folder`: every component below the folder must be
+/// a plain name that Windows does not trim to `.` or `..`, and no symbolic link in the
+/// folder may lead out of it.
+pub fn confine(folder: &Path, path: &Path) -> Result<(), RsndError> {
+    let escape = || RsndError::UnsafePath {
+        path: path.to_path_buf(),
+        folder: folder.to_path_buf(),
+    };
+    let relative = path
+        .strip_prefix(folder)
+        .or_else(|_| path.strip_prefix(long_path(folder.to_path_buf())))
+        .map_err(|_| escape())?;
+    if relative.as_os_str().is_empty() {
+        return Err(escape());
+    }
+    for component in relative.components() {
+        let Component::Normal(name) = component else {
+            return Err(escape());
+        };
+        let name = name.to_string_lossy();
+        if name.trim_end_matches(['.', ' ']).is_empty() || name.contains(['/', '\\']) {
+            return Err(escape());
+        }
+    }
+    let (Ok(root), Some(parent)) = (folder.canonicalize(), path.parent()) else {
+        return Ok(());
+    };
+    let real = parent
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .and_then(|ancestor| ancestor.canonicalize().ok());
+    match real {
+        Some(real) if !real.starts_with(&root) => Err(escape()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns whether a file name starts like the names rsnd gives: a number, a date or a
+/// season code, then ` - `.
+pub fn scheme_prefixed(stem: &str) -> bool {
+    let Some((prefix, _)) = stem.split_once(" - ") else {
+        return false;
+    };
+    let numbered = prefix.starts_with(|c: char| c.is_ascii_digit())
+        && prefix.chars().all(|c| c.is_ascii_digit() || c == '-');
+    let season = prefix
+        .strip_prefix(['S', 's'])
+        .and_then(|rest| rest.split_once(['E', 'e']))
+        .is_some_and(|(season, episode)| {
+            [season, episode]
+                .iter()
+                .all(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+        });
+    numbered || season
+}
+
+/// Returns the other file names the episode may have been saved under, numbered with
+/// another pad width, sanitized like older versions did, with or without `--ascii-names`,
+/// or disambiguated from another episode with the same name, so that it is not
+/// downloaded again.
+pub fn alternatives(parts: &NameParts, naming: &Naming) -> Result<Vec<String>> {
+    let primary = file_stem(parts, naming)?;
+    let other_script = Naming {
+        ascii: !naming.ascii,
+        ..*naming
+    };
+    let titles = [
+        sanitize(parts.title, naming),
+        sanitize(parts.title, &other_script),
+        legacy_sanitize(parts.title, naming)?,
+    ];
+    let widths = if index_prefix(parts.idx, naming).is_some() {
+        1..=4
+    } else {
+        naming.pad_width..=naming.pad_width
+    };
+    // A name without the hash may belong to the other episode, but the hashed name of
+    // this one is always safe to reuse.
+    let variants = match parts.key {
+        Some(_) if !parts.duplicate => vec![
+            *parts,
+            NameParts {
+                duplicate: true,
+                ..*parts
+            },
+        ],
+        _ => vec![*parts],
+    };
+    let mut stems = Vec::new();
+    for parts in &variants {
+        for title in &titles {
+            for width in widths.clone() {
+                let naming = Naming {
+                    pad_width: width,
+                    ..*naming
+                };
+                let stem = windows_safe(&compose(title.clone(), parts, &naming));
+                if stem != primary && !stems.contains(&stem) {
+                    stems.push(stem);
+                }
+            }
+        }
+    }
+    Ok(stems)
+}
+
+/// Finds the first of the file names that is present in the folder, ignoring case so
+/// that files saved with and without `--keep-case` are both found.
+pub fn find_any_case(folder: &Path, names: &[String]) -> Result<Option<PathBuf>> {
+    if !folder.is_dir() {
+        return Ok(None);
+    }
+    let mut present = HashMap::new();
+    for entry in std::fs::read_dir(folder)
+        .with_context(|| format!("Failed to read folder: {}", folder.display()))?
+    {
+        if let Ok(name) = entry?.file_name().into_string() {
+            present.insert(name.to_lowercase(), name);
+        }
+    }
+    Ok(names
+        .iter()
+        .find_map(|name| present.get(&name.to_lowercase()))
+        .map(|name| folder.join(name)))
+}
+
+/// Looks for a file with the number `prefix` in the folder that belongs to another
+/// episode, a sign that episodes were added or removed since it was downloaded.
+///
+/// Returns the name of the first such file.
+pub fn drifted(folder: &Path, prefix: &str, stem: &str) -> Result<Option<String>> {
+    if !folder.is_dir() {
+        return Ok(None);
+    }
+    for entry in std::fs::read_dir(folder)
+        .with_context(|| format!("Failed to read folder: {}", folder.display()))?
+    {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !name.starts_with(prefix) || name.contains(".part") {
+            continue;
+        }
+        let same = path
+            .file_stem()
+            .and_then(|other| other.to_str())
+            .is_some_and(|other| other.to_lowercase() == stem.to_lowercase());
+        if !same {
+            return Ok(Some(name.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stem(
+        title: &str,
+        date: Option<NaiveDate>,
+        id: Option<&str>,
+        idx: Option<usize>,
+        naming: &Naming,
+    ) -> Result<String> {
+        let parts = NameParts {
+            title,
+            date,
+            id,
+            idx,
+            ..NameParts::default()
+        };
+        file_stem(&parts, naming)
+    }
+
+    #[test]
+    fn test_file_stem() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2015, 6, 12);
+        let numbered = Naming::default();
+        let stable = Naming {
+            stable: true,
+            ..Naming::default()
+        };
+        let title = "I tre moschettieri - Lettura I";
+
+        assert_eq!(
+            stem(title, date, None, Some(7), &numbered)?,
+            "07 - i tre moschettieri - lettura i"
+        );
+        let second_volume = Naming {
+            start_index: 27,
+            pad_width: 3,
+            ..Naming::default()
+        };
+        assert_eq!(
+            stem(title, date, None, Some(1), &second_volume)?,
+            "027 - i tre moschettieri - lettura i"
+        );
+        let no_index = Naming {
+            no_index: true,
+            ..Naming::default()
+        };
+        assert_eq!(
+            stem(title, date, None, Some(7), &no_index)?,
+            "i tre moschettieri - lettura i"
+        );
+        assert_eq!(
+            stem(title, date, None, None, &numbered)?,
+            "i tre moschettieri - lettura i"
+        );
+        assert_eq!(
+            stem(title, date, Some("ContentItem-1"), Some(7), &stable)?,
+            "2015-06-12 - i tre moschettieri - lettura i"
+        );
+        assert_eq!(
+            stem(title, None, Some("ContentItem-1"), Some(7), &stable)?,
+            "i tre moschettieri - lettura i - contentitem-1"
+        );
+        assert_eq!(
+            stem("Lettura: I?", None, None, Some(7), &stable)?,
+            "lettura i"
+        );
+        let keep_case = Naming {
+            keep_case: true,
+            ..Naming::default()
+        };
+        assert_eq!(
+            stem("I Promessi Sposi", None, None, Some(1), &keep_case)?,
+            "01 - I Promessi Sposi"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_drifted() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        std::fs::write(folder.path().join("001 - lettura i.mp3"), b"ID3")?;
+        std::fs::write(folder.path().join("002 - lettura iii.part"), b"ID3")?;
+
+        assert_eq!(drifted(folder.path(), "001 - ", "001 - lettura i")?, None);
+        assert_eq!(
+            drifted(folder.path(), "001 - ", "001 - lettura ii")?,
+            Some("001 - lettura i.mp3".to_string())
+        );
+        assert_eq!(drifted(folder.path(), "002 - ", "002 - lettura ii")?, None);
+        assert_eq!(drifted(&folder.path().join("missing"), "1 - ", "x")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_pad_width() {
+        assert_eq!(auto_pad_width(1, 1), 2);
+        assert_eq!(auto_pad_width(99, 1), 2);
+        assert_eq!(auto_pad_width(100, 1), 3);
+        assert_eq!(auto_pad_width(80, 27), 3);
+    }
+
+    #[test]
+    fn test_alternatives() -> Result<()> {
+        let naming = Naming::default();
+        assert_eq!(
+            alternatives(
+                &NameParts {
+                    title: "Lettura I",
+                    idx: Some(7),
+                    ..NameParts::default()
+                },
+                &naming
+            )?,
+            ["7 - lettura i", "007 - lettura i", "0007 - lettura i"]
+        );
+        assert!(alternatives(
+            &NameParts {
+                title: "Lettura I",
+                idx: None,
+                ..NameParts::default()
+            },
+            &naming
+        )?
+        .is_empty());
+        assert_eq!(
+            alternatives(
+                &NameParts {
+                    title: "L'isola",
+                    idx: None,
+                    ..NameParts::default()
+                },
+                &naming
+            )?,
+            ["l_isola"]
+        );
+        // Files saved with and without --ascii-names are found in either mode.
+        let accented = NameParts {
+            title: "Perché",
+            idx: None,
+            ..NameParts::default()
+        };
+        assert_eq!(alternatives(&accented, &naming)?, ["perche"]);
+        let ascii = Naming {
+            ascii: true,
+            ..naming
+        };
+        assert_eq!(alternatives(&accented, &ascii)?, ["perché"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let naming = Naming::default();
+        let cases = [
+            (
+                "I tre moschettieri - Lettura I",
+                "i tre moschettieri - lettura i",
+            ),
+            ("L'isola del tesoro", "l isola del tesoro"),
+            ("Perché è così: un'analisi", "perché è così un analisi"),
+            ("Dell’anno più bello", "dell anno più bello"),
+            ("\"Il Conte di Montecristo\"", "il conte di montecristo"),
+            ("Puntata del 03/01/2023", "puntata del 03 01 2023"),
+            ("Vol. 2 ... capitolo 3", "vol 2 capitolo 3"),
+            ("Chi è? Cosa fa?!", "chi è cosa fa"),
+            ("  Ritorno   a casa  ", "ritorno a casa"),
+            ("Tom & Jerry (1940)", "tom jerry 1940"),
+            ("«Ultime notizie» | RAI", "ultime notizie rai"),
+            ("- Prologo -", "prologo"),
+            ("???", "untitled"),
+        ];
+        for (title, expected) in cases {
+            assert_eq!(sanitize(title, &naming), expected, "{:?}", title);
+        }
+
+        let underscores = Naming {
+            replacement: '_',
+            keep_case: true,
+            ..Naming::default()
+        };
+        assert_eq!(sanitize("Chi è? Cosa fa?!", &underscores), "Chi è Cosa fa");
+        assert_eq!(sanitize("03/01/2023", &underscores), "03_01_2023");
+
+        let ascii = Naming {
+            ascii: true,
+            ..underscores
+        };
+        assert_eq!(sanitize("Perché è così", &ascii), "Perche e cosi");
+        assert_eq!(
+            sanitize("Dell’anno più bello", &ascii),
+            "Dell anno piu bello"
+        );
+        assert_eq!(sanitize("Straße «Nord»", &ascii), "Strasse Nord");
+        // Characters without a mapping do not leave runs of replacement characters.
+        assert_eq!(sanitize("Canto \u{10FFFD}\u{10FFFD} I", &ascii), "Canto I");
+        assert_eq!(sanitize("L'isola", &underscores), "L isola");
+    }
+
+    #[test]
+    fn test_parse_replacement() {
+        assert_eq!(parse_replacement("_").unwrap(), '_');
+        assert_eq!(parse_replacement(" ").unwrap(), ' ');
+        assert!(parse_replacement("/").is_err());
+        assert!(parse_replacement("__").is_err());
+        assert!(parse_replacement("").is_err());
+    }
+
+    #[test]
+    fn test_find_any_case() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        std::fs::write(folder.path().join("01 - I Promessi Sposi.mp3"), b"ID3")?;
+
+        let names = ["01 - i promessi sposi.m4a", "01 - i promessi sposi.mp3"].map(String::from);
+        assert_eq!(
+            find_any_case(folder.path(), &names)?,
+            Some(folder.path().join("01 - I Promessi Sposi.mp3"))
+        );
+        assert_eq!(find_any_case(folder.path(), &names[..1])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_safe() {
+        assert_eq!(windows_safe("con"), "con_");
+        assert_eq!(windows_safe("Com1"), "Com1_");
+        assert_eq!(windows_safe("nul.tar"), "nul.tar_");
+        assert_eq!(windows_safe("console"), "console");
+        assert_eq!(windows_safe("01 - con"), "01 - con");
+        assert_eq!(windows_safe("lettura i. ."), "lettura i");
+
+        let naming = Naming {
+            no_index: true,
+            ..Naming::default()
+        };
+        assert_eq!(stem("AUX", None, None, Some(1), &naming).unwrap(), "aux_");
+    }
+
+    #[test]
+    fn test_hostile_titles_stay_in_folder() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let naming = Naming {
+            keep_case: true,
+            ..Naming::default()
+        };
+        for title in [
+            "../../etc/passwd",
+            r"..\..\Windows\System32",
+            "a/b\\c",
+            "..",
+            ". .",
+            "...",
+            "/absolute",
+        ] {
+            for idx in [Some(1), None] {
+                let stem = stem(title, None, None, idx, &naming)?;
+                let path = folder.path().join(format!("{}.mp3", stem));
+                confine(folder.path(), &path)
+                    .unwrap_or_else(|err| panic!("{:?} gave {}: {}", title, stem, err));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_confine() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let folder = folder.path();
+        assert!(confine(folder, &folder.join("01 - lettura i.mp3")).is_ok());
+        assert!(confine(folder, &folder.join("Stagione 01/01 - lettura i.mp3")).is_ok());
+        for hostile in [
+            "../evil.mp3",
+            "sub/../../evil.mp3",
+            "..",
+            ". ./evil.mp3",
+            "sub/.../evil.mp3",
+            r"..\evil.mp3",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    confine(folder, &folder.join(hostile)),
+                    Err(RsndError::UnsafePath { .. })
+                ),
+                "{:?}",
+                hostile
+            );
+        }
+        assert!(confine(folder, Path::new("/etc/passwd")).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_confine_symlink() -> Result<()> {
+        let (folder, outside) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        std::os::unix::fs::symlink(outside.path(), folder.path().join("link"))?;
+        assert!(confine(folder.path(), &folder.path().join("link/evil.mp3")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scheme_prefixed() {
+        assert!(scheme_prefixed("01 - lettura i"));
+        assert!(scheme_prefixed("2015-06-01-2 - lettura i"));
+        assert!(scheme_prefixed("S02E05 - lettura i"));
+        assert!(!scheme_prefixed("lettura i"));
+        assert!(!scheme_prefixed("tesi - capitolo 1"));
+        assert!(!scheme_prefixed("SE - x"));
+    }
+
+    #[test]
+    fn test_extended_length() {
+        assert_eq!(extended_length(r"C:\libri\01 - lettura i.mp3"), None);
+        let long = format!(r"C:\libri\{}.mp3", "a".repeat(260));
+        assert_eq!(extended_length(&long), Some(format!(r"\\?\{}", long)));
+        let share = format!(r"\\server\libri\{}.mp3", "a".repeat(260));
+        assert_eq!(
+            extended_length(&share),
+            Some(format!(r"\\?\UNC\server\libri\{}.mp3", "a".repeat(260)))
+        );
+        let prefixed = format!(r"\\?\C:\{}.mp3", "a".repeat(260));
+        assert_eq!(extended_length(&prefixed), None);
+    }
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("lettura i", 20), "lettura i");
+        let shortened = shorten("perché è così lungo", 16);
+        assert_eq!(
+            shortened,
+            format!("perché~{:08x}", fnv1a("perché è così lungo".as_bytes()))
+        );
+        // The cut falls inside "é" and moves back to the character boundary.
+        assert_eq!(shorten("perché è così lungo", 15).len(), 14);
+        assert_ne!(shorten(&"a".repeat(300), 50), shorten(&"a".repeat(301), 50));
+    }
+
+    #[test]
+    fn test_file_stem_max_bytes() -> Result<()> {
+        let naming = Naming {
+            max_bytes: 40,
+            ..Naming::default()
+        };
+        let title = "Una puntata dal titolo davvero molto lungo che non finisce mai";
+        let short = stem(title, None, None, Some(7), &naming)?;
+        assert!(short.starts_with("07 - una puntata dal titolo"));
+        assert!(short.len() + EXTENSION_BYTES <= 40);
+        assert_eq!(short, stem(title, None, None, Some(7), &naming)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_by_date() -> Result<()> {
+        let naming = Naming {
+            by_date: true,
+            ..Naming::default()
+        };
+        let date = NaiveDate::from_ymd_opt(2024, 3, 15);
+        let parts = NameParts {
+            title: "Il titolo",
+            date,
+            idx: Some(3),
+            ..NameParts::default()
+        };
+        assert_eq!(file_stem(&parts, &naming)?, "2024-03-15 - il titolo");
+        let ranked = NameParts {
+            day_rank: Some(2),
+            ..parts
+        };
+        assert_eq!(file_stem(&ranked, &naming)?, "2024-03-15-2 - il titolo");
+        let undated = NameParts {
+            date: None,
+            ..parts
+        };
+        assert_eq!(file_stem(&undated, &naming)?, "03 - il titolo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_day_ranks() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d);
+        let ranks = day_ranks(&[
+            (1, day(15)),
+            (2, day(16)),
+            (3, day(15)),
+            (4, None),
+            (5, day(15)),
+        ]);
+        assert_eq!(ranks.len(), 3);
+        assert_eq!(ranks[&1], 1);
+        assert_eq!(ranks[&3], 2);
+        assert_eq!(ranks[&5], 3);
+        assert!(!ranks.contains_key(&2));
+    }
+
+    #[test]
+    fn test_name_sxxeyy() -> Result<()> {
+        let naming = Naming {
+            sxxeyy: true,
+            ..Naming::default()
+        };
+        let parts = NameParts {
+            title: "Il titolo",
+            idx: Some(12),
+            season: Some((2, 5)),
+            ..NameParts::default()
+        };
+        assert_eq!(file_stem(&parts, &naming)?, "S02E05 - il titolo");
+        let flat = NameParts {
+            season: None,
+            ..parts
+        };
+        assert_eq!(file_stem(&flat, &naming)?, "S01E12 - il titolo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_names() -> Result<()> {
+        let naming = Naming {
+            no_index: true,
+            ..Naming::default()
+        };
+        let parts = NameParts {
+            title: "Puntata del giorno",
+            key: Some("ContentItem-1"),
+            ..NameParts::default()
+        };
+        let duplicate = NameParts {
+            duplicate: true,
+            ..parts
+        };
+        let hash = short_hash("ContentItem-1");
+        assert_eq!(hash.len(), 6);
+        assert_eq!(
+            file_stem(&duplicate, &naming)?,
+            format!("puntata del giorno ~{}", hash)
+        );
+        assert!(alternatives(&parts, &naming)?.contains(&file_stem(&duplicate, &naming)?));
+        assert!(!alternatives(&duplicate, &naming)?.contains(&file_stem(&parts, &naming)?));
+        Ok(())
+    }
+}
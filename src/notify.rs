@@ -0,0 +1,103 @@
+use crate::summary::Report;
+
+/// The desktop notification sent at the end of a run with `--notify-desktop`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    /// Whether the run failed, which raises the urgency of the notification.
+    pub critical: bool,
+}
+
+impl Notification {
+    /// Summarizes the run, which `failed` when an episode or the run itself did.
+    pub fn new(report: &Report, failed: bool) -> Self {
+        let title = if report.interrupted {
+            "rsnd was interrupted"
+        } else if failed {
+            "rsnd finished with failures"
+        } else {
+            "rsnd finished"
+        };
+        let total = &report.total;
+        let mut parts = vec![
+            format!("{} downloaded", total.downloaded),
+            format!("{} skipped", total.skipped),
+        ];
+        if !total.failed.is_empty() {
+            parts.push(format!("{} failed", total.failed.len()));
+        }
+        Notification {
+            title: title.to_string(),
+            body: parts.join(", "),
+            critical: failed || !total.failed.is_empty(),
+        }
+    }
+}
+
+/// Shows the notification with the notifier of the desktop: `notify-send` on Linux and
+/// `osascript` on macOS. Without one, as over SSH, the notification is only logged.
+#[cfg(feature = "notify-desktop")]
+pub fn send(notification: &Notification) {
+    use std::process::{Command, Stdio};
+
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {:?} with title {:?}",
+            notification.body, notification.title
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command
+            .arg("--app-name=rsnd")
+            .arg("--urgency")
+            .arg(if notification.critical {
+                "critical"
+            } else {
+                "normal"
+            })
+            .arg(&notification.title)
+            .arg(&notification.body);
+        command
+    };
+    match command.stdout(Stdio::null()).stderr(Stdio::null()).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::debug!(
+            "Desktop notification not shown: notifier exited with {}",
+            status
+        ),
+        Err(err) => log::debug!("Desktop notification not shown: {}", err),
+    }
+}
+
+#[cfg(not(feature = "notify-desktop"))]
+pub fn send(_notification: &Notification) {
+    eprintln!(
+        "Warning: this rsnd was built without desktop notifications, rebuild it with --features notify-desktop"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::Summary;
+
+    #[test]
+    fn test_notification() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/audiolibri/itremoschettieri");
+        show.add_found(3);
+        show.add_downloaded(1024);
+        show.add_skipped();
+        let notification = Notification::new(&summary.report(false), false);
+        assert_eq!(notification.title, "rsnd finished");
+        assert_eq!(notification.body, "1 downloaded, 1 skipped");
+        assert!(!notification.critical);
+
+        let notification = Notification::new(&summary.report(true), true);
+        assert_eq!(notification.title, "rsnd was interrupted");
+        assert!(notification.critical);
+    }
+}
@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Name of the file recording the number given to each episode, for
+/// `--continue-numbering`.
+pub const NUMBERS_FILE: &str = "numbers.json";
+
+/// An audio file of the folder named with a number, like `041 - lettura i.mp3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberedFile {
+    pub number: usize,
+    /// Number of digits of the number, zero padding included.
+    pub width: usize,
+    /// The rest of the stem after the number, lowercased.
+    pub title: String,
+    pub name: String,
+}
+
+/// Returns the audio files of the folder whose name starts with a number, by number.
+pub fn scan(folder: &Path) -> Result<Vec<NumberedFile>> {
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(folder)
+        .with_context(|| format!("Failed to read folder: {}", folder.display()))?
+    {
+        let Ok(name) = entry?.file_name().into_string() else {
+            continue;
+        };
+        if let Some(file) = parse_name(&name) {
+            files.push(file);
+        }
+    }
+    files.sort_by(|a, b| (a.number, &a.name).cmp(&(b.number, &b.name)));
+    Ok(files)
+}
+
+fn parse_name(name: &str) -> Option<NumberedFile> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    if !crate::relinker::EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+        return None;
+    }
+    let (number, title) = stem.split_once(" - ")?;
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some(NumberedFile {
+        number: number.parse().ok()?,
+        width: number.len(),
+        title: title.to_lowercase(),
+        name: name.to_string(),
+    })
+}
+
+/// Describes the numbers given to several files and the numbers missing below the
+/// highest, which are reported but left alone.
+pub fn problems(files: &[NumberedFile]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut by_number: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for file in files {
+        by_number.entry(file.number).or_default().push(&file.name);
+    }
+    for (number, names) in &by_number {
+        if names.len() > 1 {
+            problems.push(format!(
+                "number {:03} is used by {} files: {}",
+                number,
+                names.len(),
+                names.join(", ")
+            ));
+        }
+    }
+    let Some((first, last)) = by_number.keys().next().zip(by_number.keys().last()) else {
+        return problems;
+    };
+    let missing: Vec<usize> = (*first..*last)
+        .filter(|number| !by_number.contains_key(number))
+        .collect();
+    if !missing.is_empty() {
+        problems.push(format!(
+            "numbers missing in the folder: {}",
+            format_ranges(&missing)
+        ));
+    }
+    problems
+}
+
+/// Formats ascending numbers as ranges, like "5, 7-9".
+fn format_ranges(numbers: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for number in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *number => *end = *number,
+            _ => ranges.push((*number, *number)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The numbers given to the episodes saved in a folder, by episode id, read from and
+/// saved to `numbers.json`.
+#[derive(Debug, Default)]
+pub struct NumberMap {
+    path: PathBuf,
+    numbers: BTreeMap<String, usize>,
+    /// Numbers given in this run, saved once the file of their episode is in the folder.
+    reserved: BTreeMap<String, usize>,
+}
+
+impl NumberMap {
+    /// Reads the map of the folder, which is empty when the folder has none yet.
+    pub fn load(folder: &Path) -> Result<Self> {
+        let path = folder.join(NUMBERS_FILE);
+        let numbers = if path.exists() {
+            let json = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse JSON: {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(NumberMap {
+            path,
+            numbers,
+            reserved: BTreeMap::new(),
+        })
+    }
+
+    /// Numbers the episodes, given as id and file stem without number, in order.
+    ///
+    /// An episode keeps the number it was given in an earlier run, or takes the number
+    /// of a file of the folder with its title. The others get the numbers after the
+    /// highest in the folder, in order. The numbers given are only reserved until
+    /// [`NumberMap::commit`].
+    pub fn assign(&mut self, files: &[NumberedFile], episodes: &[(String, String)]) -> Vec<usize> {
+        let mut taken: HashSet<usize> = self
+            .numbers
+            .values()
+            .chain(self.reserved.values())
+            .copied()
+            .collect();
+        let mut next = files
+            .iter()
+            .map(|file| file.number)
+            .chain(taken.iter().copied())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let mut numbers = Vec::with_capacity(episodes.len());
+        for (key, title) in episodes {
+            let number = match self.numbers.get(key).or(self.reserved.get(key)) {
+                Some(number) => *number,
+                None => {
+                    let title = title.to_lowercase();
+                    let found = files
+                        .iter()
+                        .find(|file| file.title == title && !taken.contains(&file.number))
+                        .map(|file| file.number);
+                    let number = found.unwrap_or_else(|| {
+                        next += 1;
+                        next - 1
+                    });
+                    taken.insert(number);
+                    self.reserved.insert(key.clone(), number);
+                    number
+                }
+            };
+            numbers.push(number);
+        }
+        numbers
+    }
+
+    /// Returns the numbers given to several episodes, which happens when the map is
+    /// edited by hand.
+    pub fn shared_numbers(&self) -> BTreeSet<usize> {
+        let mut seen = HashSet::new();
+        self.numbers
+            .values()
+            .filter(|number| !seen.insert(**number))
+            .copied()
+            .collect()
+    }
+
+    /// Saves the number reserved for the episode, once its file is in the folder, so that
+    /// an episode that failed to download does not hold on to its number.
+    pub fn commit(&mut self, key: &str) -> Result<()> {
+        let Some(number) = self.reserved.remove(key) else {
+            return Ok(());
+        };
+        self.numbers.insert(key.to_string(), number);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(&self.numbers).context("Failed to serialize numbers")?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write file: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(number: usize, title: &str) -> NumberedFile {
+        NumberedFile {
+            number,
+            width: 3,
+            title: title.to_string(),
+            name: format!("{:03} - {}.mp3", number, title),
+        }
+    }
+
+    fn episodes(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(key, title)| (key.to_string(), title.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_name() {
+        assert_eq!(
+            parse_name("041 - lettura xli.mp3"),
+            Some(file(41, "lettura xli"))
+        );
+        assert_eq!(
+            parse_name("041 - Lettura.M4A").map(|f| f.title),
+            Some("lettura".to_string())
+        );
+        assert_eq!(parse_name("041 - lettura xli.info.json"), None);
+        assert_eq!(parse_name("lettura - 041.mp3"), None);
+        assert_eq!(parse_name("2024-05-01 - lettura.mp3"), None);
+    }
+
+    #[test]
+    fn test_assign() {
+        let files = [
+            file(1, "lettura i"),
+            file(2, "lettura ii"),
+            file(41, "lettura xli"),
+        ];
+        let mut map = NumberMap::default();
+        // Episodes found in the folder keep their number, new ones follow the highest.
+        assert_eq!(
+            map.assign(
+                &files,
+                &episodes(&[
+                    ("b", "Lettura II"),
+                    ("x", "Lettura XLII"),
+                    ("y", "Lettura XLIII")
+                ])
+            ),
+            [2, 42, 43]
+        );
+        // Later runs find them by id, whatever their title has become.
+        assert_eq!(
+            map.assign(&files, &episodes(&[("z", "Nuova"), ("x", "Lettura 42")])),
+            [44, 42]
+        );
+        assert!(map.shared_numbers().is_empty());
+    }
+
+    #[test]
+    fn test_commit() -> Result<()> {
+        let folder = tempfile::TempDir::new()?;
+        let mut map = NumberMap::load(folder.path())?;
+        assert_eq!(
+            map.assign(&[], &episodes(&[("a", "Lettura I"), ("b", "Lettura II")])),
+            [1, 2]
+        );
+        assert!(!folder.path().join(NUMBERS_FILE).exists());
+
+        // Only the episode downloaded keeps its number.
+        map.commit("b")?;
+        map.commit("c")?;
+        let mut map = NumberMap::load(folder.path())?;
+        assert_eq!(
+            map.assign(&[], &episodes(&[("a", "Lettura I"), ("b", "Lettura II")])),
+            [3, 2]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_problems() {
+        let files = [
+            file(1, "lettura i"),
+            file(2, "lettura ii"),
+            file(2, "lettura bis"),
+            file(5, "lettura v"),
+            file(7, "lettura vii"),
+        ];
+        assert_eq!(
+            problems(&files),
+            [
+                "number 002 is used by 2 files: 002 - lettura ii.mp3, 002 - lettura bis.mp3",
+                "numbers missing in the folder: 3-4, 6",
+            ]
+        );
+        assert!(problems(&[]).is_empty());
+    }
+}
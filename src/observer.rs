@@ -0,0 +1,213 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Version of the events, raised when an event or field changes meaning or goes away.
+/// New events and new fields do not raise it, so observers should ignore what they do
+/// not know.
+pub const EVENT_VERSION: u32 = 1;
+
+/// How often the progress of a download is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The observer installed for the run, if any.
+static OBSERVER: RwLock<Option<Arc<dyn Observer>>> = RwLock::new(None);
+
+tokio::task_local! {
+    /// The show URL and index of the episode being downloaded, which progress is about.
+    static EPISODE: (String, usize);
+}
+
+/// What the download pipeline went through, for a front end to follow the run without
+/// reading its output.
+///
+/// Events own their data, so they can be sent to another thread or kept. This is a
+/// semi-stable API: see [`EVENT_VERSION`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The episodes of the show were listed.
+    ShowResolved {
+        show_url: String,
+        title: Option<String>,
+        episodes: usize,
+    },
+    /// An episode was found on the page, numbered by its position there.
+    EpisodeDiscovered {
+        show_url: String,
+        position: usize,
+        metadata_url: String,
+    },
+    /// The metadata of an episode was read, as it arrives. The index is the final one,
+    /// or the position on the page when the episodes are sorted or reordered afterwards.
+    MetadataFetched {
+        show_url: String,
+        index: usize,
+        title: String,
+        date: Option<NaiveDate>,
+        /// Duration in seconds.
+        duration: Option<u64>,
+    },
+    DownloadStarted {
+        show_url: String,
+        index: usize,
+        title: String,
+        folder: PathBuf,
+    },
+    /// Bytes received so far for the file, reported a few times per second.
+    DownloadProgressed {
+        show_url: String,
+        index: usize,
+        path: PathBuf,
+        received: u64,
+        expected: Option<u64>,
+    },
+    DownloadFinished {
+        show_url: String,
+        index: usize,
+        title: String,
+        bytes: u64,
+        elapsed_secs: f64,
+    },
+    DownloadFailed {
+        show_url: String,
+        index: usize,
+        title: String,
+        reason: String,
+    },
+    /// The run is over; the same totals as the summary.
+    RunFinished {
+        found: usize,
+        downloaded: usize,
+        downloaded_bytes: u64,
+        skipped: usize,
+        failed: usize,
+        elapsed_secs: f64,
+        interrupted: bool,
+    },
+}
+
+/// Receives the events of the run.
+///
+/// Events are sent from the task that does the work, so observers should return
+/// quickly, handing the event to another thread if they need time.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+/// Writes each event as a line of JSON to stderr, for `--progress-json`.
+#[derive(Debug, Default)]
+pub struct JsonLines;
+
+/// An event as `--progress-json` writes it, with the version of the events.
+#[derive(Serialize)]
+struct Line<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Observer for JsonLines {
+    fn on_event(&self, event: &Event) {
+        let line = Line {
+            version: EVENT_VERSION,
+            event,
+        };
+        match serde_json::to_string(&line) {
+            Ok(json) => eprintln!("{}", json),
+            Err(err) => log::debug!("Cannot serialize event {:?}: {}", event, err),
+        }
+    }
+}
+
+/// Installs the observer the events of the run are sent to, if any, in place of the one
+/// of an earlier run of `--watch`.
+pub fn install(observer: Option<Box<dyn Observer>>) {
+    *OBSERVER.write().unwrap_or_else(|err| err.into_inner()) = observer.map(Arc::from);
+}
+
+/// Returns the observer of the run, if any.
+fn observer() -> Option<Arc<dyn Observer>> {
+    OBSERVER
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// Sends the event built by `event` to the observer. Without one, the event is not
+/// even built.
+pub fn emit(event: impl FnOnce() -> Event) {
+    if let Some(observer) = observer() {
+        observer.on_event(&event());
+    }
+}
+
+/// Runs the download of an episode, which its progress events are reported for.
+pub async fn scope<F: Future>(show_url: &str, index: usize, future: F) -> F::Output {
+    EPISODE.scope((show_url.to_string(), index), future).await
+}
+
+/// Reports the progress of a download at most every [`PROGRESS_INTERVAL`].
+#[derive(Debug)]
+pub struct Progress {
+    path: PathBuf,
+    expected: Option<u64>,
+    last: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(path: &Path, expected: Option<u64>) -> Self {
+        Progress {
+            path: path.to_path_buf(),
+            expected,
+            last: None,
+        }
+    }
+
+    /// Reports the bytes received so far, unless it was done moments ago.
+    pub fn update(&mut self, received: u64) {
+        if observer().is_none()
+            || self
+                .last
+                .is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.last = Some(Instant::now());
+        let (show_url, index) = EPISODE.try_with(Clone::clone).unwrap_or_default();
+        emit(|| Event::DownloadProgressed {
+            show_url,
+            index,
+            path: self.path.clone(),
+            received,
+            expected: self.expected,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_line() {
+        let event = Event::DownloadFailed {
+            show_url: "https://www.raiplaysound.it/audiolibri/itremoschettieri".to_string(),
+            index: 3,
+            title: "Lettura III".to_string(),
+            reason: "HTTP 404".to_string(),
+        };
+        let json = serde_json::to_value(Line {
+            version: EVENT_VERSION,
+            event: &event,
+        })
+        .unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["event"], "download_failed");
+        assert_eq!(json["index"], 3);
+        assert_eq!(json["reason"], "HTTP 404");
+    }
+}
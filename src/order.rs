@@ -0,0 +1,102 @@
+use chrono::NaiveDate;
+use clap::ValueEnum;
+
+/// Order `--order` puts the episodes in before numbering them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PageOrder {
+    /// Oldest episode first, numbered 1
+    OldestFirst,
+    /// Newest episode first, numbered 1
+    NewestFirst,
+    /// As listed on the page
+    #[default]
+    Page,
+}
+
+/// The order a list of episodes turns out to be in by date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detected {
+    OldestFirst,
+    NewestFirst,
+    /// Too few dates, or no clear majority.
+    Unknown,
+}
+
+/// Tells the order of the dates from the consecutive dated episodes: the order that more
+/// than two thirds of them follow wins, so a few pinned or misplaced episodes do not
+/// change the outcome. Episodes without a date are skipped.
+pub fn detect(dates: &[Option<NaiveDate>]) -> Detected {
+    let dated: Vec<NaiveDate> = dates.iter().flatten().copied().collect();
+    let (mut ascending, mut descending) = (0, 0);
+    for pair in dated.windows(2) {
+        if pair[0] < pair[1] {
+            ascending += 1;
+        } else if pair[0] > pair[1] {
+            descending += 1;
+        }
+    }
+    let total = ascending + descending;
+    if total < 2 {
+        Detected::Unknown
+    } else if ascending * 3 > total * 2 {
+        Detected::OldestFirst
+    } else if descending * 3 > total * 2 {
+        Detected::NewestFirst
+    } else {
+        Detected::Unknown
+    }
+}
+
+/// Returns whether items in the `detected` order have to be reversed to be in the
+/// `wanted` one.
+pub fn needs_reverse(detected: Detected, wanted: PageOrder) -> bool {
+    matches!(
+        (detected, wanted),
+        (Detected::NewestFirst, PageOrder::OldestFirst)
+            | (Detected::OldestFirst, PageOrder::NewestFirst)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dates of episodes published on the given days of June 2015, or undated for 0.
+    fn days(days: &[u32]) -> Vec<Option<NaiveDate>> {
+        days.iter()
+            .map(|day| NaiveDate::from_ymd_opt(2015, 6, *day))
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(&days(&[1, 2, 3, 4, 5])), Detected::OldestFirst);
+        assert_eq!(detect(&days(&[5, 4, 3, 2, 1])), Detected::NewestFirst);
+        // A pinned episode on top and an undated one do not change the outcome.
+        assert_eq!(
+            detect(&days(&[30, 1, 2, 0, 3, 4, 5, 6])),
+            Detected::OldestFirst
+        );
+        assert_eq!(
+            detect(&days(&[1, 20, 19, 18, 17, 16, 15])),
+            Detected::NewestFirst
+        );
+        // Same-day episodes count for neither order.
+        assert_eq!(detect(&days(&[3, 3, 2, 2, 1])), Detected::NewestFirst);
+        assert_eq!(detect(&days(&[1, 5, 2, 6, 3, 7])), Detected::Unknown);
+        assert_eq!(detect(&days(&[1, 2])), Detected::Unknown);
+        assert_eq!(detect(&days(&[0, 0, 0])), Detected::Unknown);
+    }
+
+    #[test]
+    fn test_needs_reverse() {
+        assert!(needs_reverse(Detected::NewestFirst, PageOrder::OldestFirst));
+        assert!(needs_reverse(Detected::OldestFirst, PageOrder::NewestFirst));
+        assert!(!needs_reverse(
+            Detected::OldestFirst,
+            PageOrder::OldestFirst
+        ));
+        assert!(!needs_reverse(Detected::Unknown, PageOrder::OldestFirst));
+        assert!(!needs_reverse(Detected::NewestFirst, PageOrder::Page));
+    }
+}
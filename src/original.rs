@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the sidecar file that maps original file names to episodes.
+pub const NAMES_FILE: &str = "names.json";
+
+/// The episode a file saved under its original name belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameEntry {
+    /// The episode id, or its audio URL when it has none.
+    pub key: String,
+    pub title: String,
+}
+
+/// Reads the file name from a Content-Disposition header value, preferring the
+/// RFC 5987 `filename*` parameter.
+fn disposition_filename(disposition: &str) -> Option<String> {
+    let mut plain = None;
+    for parameter in disposition.split(';').map(str::trim) {
+        let Some((name, value)) = parameter.split_once('=') else {
+            continue;
+        };
+        match name.trim().to_lowercase().as_str() {
+            "filename*" => {
+                let value = value.trim();
+                // charset'language'value
+                let encoded = value.splitn(3, '\'').nth(2).unwrap_or(value);
+                return Some(percent_decode(encoded));
+            }
+            "filename" => plain = Some(value.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// Decodes `%XX` escapes, leaving malformed ones as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the name RAI serves the file under: the Content-Disposition file name, or the
+/// last segment of the URL path.
+pub fn original_name(url: &str, disposition: Option<&str>) -> Option<String> {
+    let name = disposition.and_then(disposition_filename).or_else(|| {
+        let path = url.split(['?', '#']).next()?;
+        let (_, name) = path.rsplit_once('/')?;
+        Some(percent_decode(name))
+    })?;
+    let name = safe_name(&name);
+    (!name.is_empty()).then_some(name)
+}
+
+/// Replaces only what cannot appear in a file name, so that the name stays as close as
+/// possible to the original.
+fn safe_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"/\<>:"|?*"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    crate::naming::windows_safe(name.trim())
+}
+
+/// The original file names given out in a folder, read from and saved to `names.json`.
+#[derive(Debug, Default)]
+pub struct NameMap {
+    path: PathBuf,
+    names: BTreeMap<String, NameEntry>,
+}
+
+impl NameMap {
+    /// Reads the map of the folder, which is empty when the folder has none yet.
+    pub fn load(folder: &Path) -> Result<Self> {
+        let path = folder.join(NAMES_FILE);
+        let names = if path.exists() {
+            let json = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse JSON: {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(NameMap { path, names })
+    }
+
+    /// Returns the file name given to the episode, if any.
+    pub fn find(&self, key: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, entry)| entry.key == key)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Returns whether the name was given to an episode.
+    pub fn lists(&self, name: &str) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /// Gives the episode a file name based on `name`, adding `-2`, `-3`… before the
+    /// extension when another episode has it already. An episode keeps the name it
+    /// was given first.
+    pub fn assign(&mut self, name: &str, entry: NameEntry) -> String {
+        if let Some(existing) = self.find(&entry.key) {
+            return existing.to_string();
+        }
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+            _ => (name, String::new()),
+        };
+        let name = (1..)
+            .map(|n| match n {
+                1 => name.to_string(),
+                n => format!("{}-{}{}", stem, n, extension),
+            })
+            .find(|name| !self.names.contains_key(name))
+            .unwrap();
+        self.names.insert(name.clone(), entry);
+        name
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(&self.names).context("Failed to serialize names")?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write file: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_original_name() {
+        assert_eq!(
+            original_name(
+                "https://cdn.example.com/podcast/Lettura%201.mp3?token=x",
+                None
+            ),
+            Some("Lettura 1.mp3".to_string())
+        );
+        assert_eq!(
+            original_name(
+                "https://cdn.example.com/get",
+                Some(r#"attachment; filename="I tre moschettieri: 1.mp3""#)
+            ),
+            Some("I tre moschettieri_ 1.mp3".to_string())
+        );
+        assert_eq!(
+            original_name(
+                "https://cdn.example.com/get",
+                Some("attachment; filename=\"fallback.mp3\"; filename*=UTF-8''perch%C3%A9.mp3")
+            ),
+            Some("perché.mp3".to_string())
+        );
+        assert_eq!(original_name("https://cdn.example.com/", None), None);
+    }
+
+    #[test]
+    fn test_name_map() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let entry = |key: &str| NameEntry {
+            key: key.to_string(),
+            title: format!("Episode {}", key),
+        };
+
+        let mut names = NameMap::load(folder.path())?;
+        assert_eq!(names.assign("audio.mp3", entry("a")), "audio.mp3");
+        assert_eq!(names.assign("audio.mp3", entry("b")), "audio-2.mp3");
+        assert_eq!(names.assign("audio.mp3", entry("c")), "audio-3.mp3");
+        assert_eq!(names.assign("other.mp3", entry("b")), "audio-2.mp3");
+        names.save()?;
+
+        let names = NameMap::load(folder.path())?;
+        assert_eq!(names.find("c"), Some("audio-3.mp3"));
+        assert_eq!(names.find("d"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::future::Future;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the lines of `outln!` go to stderr, set with `--json` to keep stdout for the
+/// JSON document.
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    /// What the lines printed for a show start with, when shows run at the same time.
+    static PREFIX: String;
+}
+
+/// Runs the processing of a show, prefixing the lines it prints with `prefix`.
+pub async fn scope<F: Future>(prefix: String, future: F) -> F::Output {
+    PREFIX.scope(prefix, future).await
+}
+
+/// Returns the prefix of the lines of a show processed with `--show-jobs`: its slug.
+pub fn show_prefix(show_url: &str) -> String {
+    let slug = show_url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(show_url)
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|slug| !slug.is_empty())
+        .unwrap_or(show_url);
+    format!("[{}] ", slug)
+}
+
+/// Sends the lines of `outln!` to stderr from now on when `json`, leaving stdout to the
+/// JSON printed with `--json`, and to stdout otherwise.
+pub fn install_json(json: bool) {
+    TO_STDERR.store(json, Ordering::Relaxed);
+}
+
+/// Prints a line of `outln!`, to stdout unless [`install_json`] sent them to stderr.
+pub fn print_line(line: &str) {
+    if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Prints a progress line over the previous one, where `outln!` prints its lines.
+pub fn print_progress(line: &str) {
+    let line = prefixed(line);
+    if TO_STDERR.load(Ordering::Relaxed) {
+        eprint!("\r{}", line);
+    } else {
+        print!("\r{}", line);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Prefixes each line of `text` with the prefix of the show being processed, if any.
+pub fn prefixed(text: &str) -> String {
+    PREFIX
+        .try_with(|prefix| {
+            if prefix.is_empty() || text.is_empty() {
+                return text.to_string();
+            }
+            text.split('\n')
+                .map(|line| format!("{}{}", prefix, line))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_else(|_| text.to_string())
+}
+
+/// Like `println!`, with the prefix of the show being processed. With `--json` the
+/// lines go to stderr.
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::output::print_line("")
+    };
+    ($($arg:tt)*) => {
+        $crate::output::print_line(&$crate::output::prefixed(&format!($($arg)*)))
+    };
+}
+
+/// Like `eprintln!`, with the prefix of the show being processed.
+#[macro_export]
+macro_rules! errln {
+    () => {
+        eprintln!()
+    };
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::output::prefixed(&format!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_show_prefix() {
+        assert_eq!(
+            show_prefix("https://www.raiplaysound.it/audiolibri/itremoschettieri/"),
+            "[itremoschettieri] "
+        );
+        assert_eq!(
+            show_prefix("https://www.raiplaysound.it/programmi/battiti?season=2"),
+            "[battiti] "
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefixed() {
+        assert_eq!(prefixed("Downloading"), "Downloading");
+        let text = scope("[battiti] ".to_string(), async {
+            prefixed("Downloading\n  more")
+        })
+        .await;
+        assert_eq!(text, "[battiti] Downloading\n[battiti]   more");
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// The pause installed for the run by `--sleep`, if any.
+static PACE: RwLock<Option<Arc<Pace>>> = RwLock::new(None);
+
+/// Pauses between consecutive audio downloads, to pace a long archive run like a person
+/// would. Unlike `--rps`, it leaves the requests for pages and metadata alone.
+#[derive(Debug)]
+pub struct Pace {
+    sleep: Duration,
+    /// Up to this much is added to each pause, at random.
+    jitter: Duration,
+    /// A download was started already, so the next one waits.
+    started: AtomicBool,
+}
+
+impl Pace {
+    pub fn new(sleep: Duration, jitter: Duration) -> Self {
+        Pace {
+            sleep,
+            jitter,
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns how long to wait before the next download: nothing before the first one.
+    pub fn next_delay(&self) -> Duration {
+        if !self.started.swap(true, Ordering::Relaxed) {
+            return Duration::ZERO;
+        }
+        self.sleep + self.jitter.mul_f64(random_fraction())
+    }
+}
+
+/// A number in `[0, 1]`, random enough to spread pauses.
+fn random_fraction() -> f64 {
+    RandomState::new().hash_one(std::time::Instant::now()) as f64 / u64::MAX as f64
+}
+
+/// Installs the pause every audio download of the run waits for, if any, in place of
+/// the one of an earlier run of `--watch`, whose first download does not wait either.
+pub fn install(sleep: Option<Duration>, jitter: Duration) {
+    *PACE.write().unwrap_or_else(|err| err.into_inner()) =
+        sleep.map(|sleep| Arc::new(Pace::new(sleep, jitter)));
+}
+
+/// Waits before an audio download, unless it is the first of the run or `--sleep` is not
+/// given, and returns how long it waited.
+///
+/// The wait is a plain sleep, so Ctrl+C stops it right away.
+pub async fn before_download() -> Duration {
+    let pace = PACE.read().unwrap_or_else(|err| err.into_inner()).clone();
+    let delay = pace.map_or(Duration::ZERO, |pace| pace.next_delay());
+    if !delay.is_zero() {
+        log::debug!(
+            "Sleeping {:.1}s before the next download",
+            delay.as_secs_f64()
+        );
+        tokio::time::sleep(delay).await;
+    }
+    delay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let pace = Pace::new(Duration::from_secs(30), Duration::from_secs(10));
+        assert_eq!(pace.next_delay(), Duration::ZERO);
+        for _ in 0..20 {
+            let delay = pace.next_delay();
+            assert!(delay >= Duration::from_secs(30) && delay <= Duration::from_secs(40));
+        }
+
+        let pace = Pace::new(Duration::from_secs(5), Duration::ZERO);
+        pace.next_delay();
+        assert_eq!(pace.next_delay(), Duration::from_secs(5));
+    }
+}
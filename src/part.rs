@@ -0,0 +1,220 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Highest part written in roman numerals, above which words like "di" (501) are more
+/// likely than a part number.
+const MAX_ROMAN_PART: usize = 100;
+
+/// Italian ordinals up to twenty, as in "Prima parte" or "Parte seconda".
+const ORDINALS: [&str; 20] = [
+    "prima",
+    "seconda",
+    "terza",
+    "quarta",
+    "quinta",
+    "sesta",
+    "settima",
+    "ottava",
+    "nona",
+    "decima",
+    "undicesima",
+    "dodicesima",
+    "tredicesima",
+    "quattordicesima",
+    "quindicesima",
+    "sedicesima",
+    "diciassettesima",
+    "diciottesima",
+    "diciannovesima",
+    "ventesima",
+];
+
+/// Reads the part number from an audiobook title like "Lettura XIV", "Parte 3",
+/// "Capitolo 12 di 40", "3ª puntata" or "Seconda parte".
+///
+/// The number has to follow or precede a word naming the part, so that titles starting
+/// with a roman-looking word, like "I tre moschettieri", are not mistaken for parts.
+pub fn part_number(title: &str) -> Option<usize> {
+    static PART: OnceLock<Regex> = OnceLock::new();
+    let re = PART.get_or_init(|| {
+        Regex::new(
+            r"(?ix)
+            \b(?:lettura|parte|capitolo|cap\.|puntata|episodio|ep\.|part|chapter)\s*
+                (?:n\.\s*|nr\.\s*|\#\s*)?(\d{1,4}|[ivxlcdm]{1,8}|[a-z]+)\b
+            | \b(\d{1,4})\s*[ªa°]?\s+(?:lettura|parte|puntata)\b
+            | \b([a-z]+)\s+(?:lettura|parte|puntata)\b",
+        )
+        .expect("Invalid regex")
+    });
+    let part = re.captures_iter(title).find_map(|captures| {
+        let word = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .or_else(|| captures.get(3))?
+            .as_str()
+            .to_lowercase();
+        word.parse()
+            .ok()
+            .or_else(|| ordinal(&word))
+            .or_else(|| roman(&word).filter(|part| *part <= MAX_ROMAN_PART))
+            .filter(|part| *part > 0)
+    });
+    part
+}
+
+fn ordinal(word: &str) -> Option<usize> {
+    ORDINALS
+        .iter()
+        .position(|ordinal| *ordinal == word)
+        .map(|position| position + 1)
+}
+
+/// Parses a roman numeral in its canonical form, rejecting words like "il" or "dim"
+/// that only look like one.
+pub fn roman(text: &str) -> Option<usize> {
+    let value = |c: char| match c {
+        'i' => Some(1),
+        'v' => Some(5),
+        'x' => Some(10),
+        'l' => Some(50),
+        'c' => Some(100),
+        'd' => Some(500),
+        'm' => Some(1000),
+        _ => None,
+    };
+    let digits: Vec<usize> = text
+        .to_lowercase()
+        .chars()
+        .map(value)
+        .collect::<Option<_>>()?;
+    if digits.is_empty() {
+        return None;
+    }
+    let mut total = 0;
+    for (position, digit) in digits.iter().enumerate() {
+        match digits.get(position + 1) {
+            Some(next) if next > digit => total -= *digit as isize,
+            _ => total += *digit as isize,
+        }
+    }
+    let total = usize::try_from(total).ok().filter(|total| *total > 0)?;
+    (to_roman(total) == text.to_lowercase()).then_some(total)
+}
+
+fn to_roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            roman.push_str(numeral);
+            number -= value;
+        }
+    }
+    roman
+}
+
+/// Returns the parts missing from 1 to the highest part found, in order.
+///
+/// Parts are only checked when at least half of the episodes have one, so that a show
+/// with a stray "Parte 2" in a title does not report a hole.
+pub fn missing_parts(parts: &[Option<usize>]) -> Vec<usize> {
+    let found: Vec<usize> = parts.iter().flatten().copied().collect();
+    if found.len() < 2 || found.len() * 2 < parts.len() {
+        return Vec::new();
+    }
+    let highest = found.iter().copied().max().unwrap_or(0);
+    (1..=highest).filter(|part| !found.contains(part)).collect()
+}
+
+/// Formats parts as ranges, like `3, 7-9`.
+pub fn format_parts(parts: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &part in parts {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == part => *end = part,
+            _ => ranges.push((part, part)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_number() {
+        let titles = [
+            ("Lettura I", Some(1)),
+            ("Lettura XIV", Some(14)),
+            ("I tre moschettieri - Lettura XLIV", Some(44)),
+            ("Lettura 12", Some(12)),
+            ("Parte 3", Some(3)),
+            ("Il conte di Montecristo. Parte IX", Some(9)),
+            ("Capitolo 12 di 40", Some(12)),
+            ("Cap. 7 - La partenza", Some(7)),
+            ("Puntata n. 5", Some(5)),
+            ("3ª puntata", Some(3)),
+            ("2a parte", Some(2)),
+            ("Seconda parte", Some(2)),
+            ("Parte prima: l'arrivo", Some(1)),
+            ("Chapter 21", Some(21)),
+            ("Episodio 4 - Il ritorno", Some(4)),
+            // Nothing names a part.
+            ("I tre moschettieri", None),
+            ("Il mix della settimana", None),
+            ("Lettura integrale", None),
+            ("Lettura di Paolo Poli", None),
+            ("Parte del tutto", None),
+            ("Puntata del 12/03/2024", None),
+        ];
+        for (title, part) in titles {
+            assert_eq!(part_number(title), part, "{}", title);
+        }
+    }
+
+    #[test]
+    fn test_roman() {
+        assert_eq!(roman("iv"), Some(4));
+        assert_eq!(roman("XIX"), Some(19));
+        assert_eq!(roman("mcmxc"), Some(1990));
+        assert_eq!(roman("iiii"), None);
+        assert_eq!(roman("il"), None);
+        assert_eq!(roman("dim"), None);
+        assert_eq!(roman(""), None);
+    }
+
+    #[test]
+    fn test_missing_parts() {
+        let parts = [Some(1), Some(2), Some(4), None, Some(7)];
+        assert_eq!(missing_parts(&parts), vec![3, 5, 6]);
+        assert_eq!(format_parts(&missing_parts(&parts)), "3, 5-6");
+        assert!(missing_parts(&[Some(1), Some(2), Some(3)]).is_empty());
+        // Too few titles name a part to tell.
+        assert!(missing_parts(&[Some(2), None, None, Some(5), None]).is_empty());
+    }
+}
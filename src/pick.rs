@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Context, Result};
+use inquire::MultiSelect;
+use std::fmt;
+use std::io::IsTerminal;
+
+/// An episode offered in the interactive picker.
+#[derive(Debug)]
+pub struct PickItem {
+    pub index: usize,
+    pub title: String,
+    pub duration: Option<String>,
+    pub present: bool,
+}
+
+impl fmt::Display for PickItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} — {}", self.index, self.title)?;
+        if let Some(duration) = &self.duration {
+            write!(f, " ({})", duration)?;
+        }
+        if self.present {
+            write!(f, " [present]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fails unless stdin is a terminal the picker can read from.
+pub fn ensure_terminal() -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "--interactive needs a terminal on stdin; use --select to choose episodes in scripts"
+        ));
+    }
+    Ok(())
+}
+
+/// Lets the user choose which episodes to download, pre-checking those not yet on disk.
+///
+/// Returns the 1-based indices of the chosen episodes; an empty list when the prompt is cancelled.
+pub fn pick_episodes(items: Vec<PickItem>) -> Result<Vec<usize>> {
+    let defaults: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !item.present)
+        .map(|(position, _)| position)
+        .collect();
+
+    let chosen = MultiSelect::new("Episodes to download (type to filter):", items)
+        .with_default(&defaults)
+        .with_page_size(15)
+        .prompt_skippable()
+        .context("Failed to read the episode selection")?;
+
+    Ok(chosen
+        .unwrap_or_default()
+        .into_iter()
+        .map(|item| item.index)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_item_label() {
+        let item = PickItem {
+            index: 3,
+            title: "I tre moschettieri - Lettura III".to_string(),
+            duration: Some("00:19:15".to_string()),
+            present: false,
+        };
+        assert_eq!(
+            item.to_string(),
+            "3 — I tre moschettieri - Lettura III (00:19:15)"
+        );
+
+        let item = PickItem {
+            index: 12,
+            title: "Speciale".to_string(),
+            duration: None,
+            present: true,
+        };
+        assert_eq!(item.to_string(), "12 — Speciale [present]");
+    }
+}
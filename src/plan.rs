@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Version of the plan file schema, raised when a field changes meaning or goes away.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The plan being saved for `--save-plan`, once installed.
+static SAVING: RwLock<Option<Arc<Saving>>> = RwLock::new(None);
+
+/// The plan being resumed for `--resume-plan`, once installed.
+static RESUMED: RwLock<Option<Arc<Plan>>> = RwLock::new(None);
+
+/// The downloads a run planned, in order, so that an interrupted run can carry on
+/// without fetching the listings and planning again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub schema: u32,
+    pub created_at: DateTime<Utc>,
+    /// Version of rsnd that wrote the plan.
+    pub rsnd_version: String,
+    pub shows: Vec<ShowPlan>,
+}
+
+/// The planned episodes of a show.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShowPlan {
+    pub url: String,
+    pub title: Option<String>,
+    /// The folder the episodes are saved in, including the show subfolder.
+    pub folder: PathBuf,
+    /// The filters the episodes were picked with, as given on the command line.
+    pub filters: Vec<String>,
+    pub episodes: Vec<PlannedEpisode>,
+}
+
+/// An episode to download, with the name it was planned under.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedEpisode {
+    /// Position of the episode on the page when the plan was made.
+    pub index: usize,
+    /// The number its file is prefixed with, if any.
+    pub file_index: Option<usize>,
+    pub title: String,
+    pub date: Option<NaiveDate>,
+    /// Path of the episode JSON, relative to the site.
+    pub metadata_path: String,
+    pub audio_url: String,
+    /// Path of the audio file, relative to the show folder.
+    pub path: PathBuf,
+}
+
+impl Plan {
+    fn new() -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            created_at: Utc::now(),
+            rsnd_version: env!("CARGO_PKG_VERSION").to_string(),
+            shows: Vec::new(),
+        }
+    }
+
+    /// Returns how long ago the plan was made.
+    pub fn age(&self) -> Duration {
+        (Utc::now() - self.created_at).to_std().unwrap_or_default()
+    }
+}
+
+/// A plan written again each time a show is planned, so that it holds the shows planned
+/// so far if the run dies.
+struct Saving {
+    path: PathBuf,
+    plan: Mutex<Plan>,
+}
+
+/// Reads the plan file at `path`.
+pub fn read(path: &Path) -> Result<Plan> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read plan file: {}", path.display()))?;
+    let plan: Plan = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse plan file: {}", path.display()))?;
+    if plan.schema != SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "{} was written by rsnd {} with plan schema {}, this version reads schema {}",
+            path.display(),
+            plan.rsnd_version,
+            plan.schema,
+            SCHEMA_VERSION
+        ));
+    }
+    Ok(plan)
+}
+
+/// Writes the plan through a temporary file, so that it is never left half written.
+pub fn write(path: &Path, plan: &Plan) -> Result<()> {
+    let json = serde_json::to_string_pretty(plan)?;
+    crate::atomic::write(path, (json + "\n").as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Saves the plan of each show of the run to `path`, if any, starting a new plan rather
+/// than adding to the one of an earlier run of `--watch`.
+pub fn install_saving(path: Option<&Path>) {
+    *SAVING.write().unwrap_or_else(|err| err.into_inner()) = path.map(|path| {
+        Arc::new(Saving {
+            path: path.to_path_buf(),
+            plan: Mutex::new(Plan::new()),
+        })
+    });
+}
+
+/// Adds the plan of a show to the plan file, when one is being saved.
+pub fn record(show: ShowPlan) -> Result<()> {
+    let saving = SAVING.read().unwrap_or_else(|err| err.into_inner()).clone();
+    let Some(saving) = saving else {
+        return Ok(());
+    };
+    let mut plan = saving.plan.lock().unwrap_or_else(|err| err.into_inner());
+    plan.shows.retain(|planned| planned.url != show.url);
+    plan.shows.push(show);
+    write(&saving.path, &plan)
+}
+
+/// Resumes `plan`, if any, instead of planning the shows it holds, in place of the plan
+/// of an earlier run of `--watch`.
+pub fn install_resumed(plan: Option<Plan>) {
+    *RESUMED.write().unwrap_or_else(|err| err.into_inner()) = plan.map(Arc::new);
+}
+
+/// Returns the plan of the show, when a plan holding it is being resumed.
+pub fn resumed(show_url: &str) -> Option<ShowPlan> {
+    RESUMED
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()?
+        .shows
+        .iter()
+        .find(|show| show.url == show_url)
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show() -> ShowPlan {
+        ShowPlan {
+            url: "https://www.raiplaysound.it/audiolibri/itremoschettieri".to_string(),
+            title: Some("I tre moschettieri".to_string()),
+            folder: PathBuf::from("/music/moschettieri"),
+            filters: vec!["--match lettura".to_string()],
+            episodes: vec![PlannedEpisode {
+                index: 2,
+                file_index: Some(2),
+                title: "Lettura II".to_string(),
+                date: NaiveDate::from_ymd_opt(2015, 6, 2),
+                metadata_path: "/audio/2015/06/lettura-2.json".to_string(),
+                audio_url: "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=2"
+                    .to_string(),
+                path: PathBuf::from("02 - lettura ii.mp3"),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_and_read() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("plan.json");
+        let mut plan = Plan::new();
+        plan.shows.push(show());
+        write(&path, &plan)?;
+        assert!(!folder.path().join("plan.json.part").exists());
+        assert_eq!(read(&path)?, plan);
+        assert!(plan.age() < Duration::from_secs(60));
+
+        plan.schema = SCHEMA_VERSION + 1;
+        write(&path, &plan)?;
+        let err = read(&path).unwrap_err();
+        assert!(format!("{}", err).contains("plan schema 2"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_age() {
+        let mut plan = Plan::new();
+        plan.created_at = Utc::now() - chrono::Duration::hours(7);
+        assert!(plan.age() > Duration::from_secs(6 * 3600));
+        // A clock set back does not make the plan older than new.
+        plan.created_at = Utc::now() + chrono::Duration::hours(1);
+        assert_eq!(plan.age(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_install_replaces_the_earlier_run() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let (first, second) = (folder.path().join("1.json"), folder.path().join("2.json"));
+        install_saving(Some(&first));
+        record(show())?;
+        // The next run of --watch starts a plan of its own.
+        install_saving(Some(&second));
+        let mut other = show();
+        other.url = "https://www.raiplaysound.it/audiolibri/ilconte".to_string();
+        record(other)?;
+        install_saving(None);
+        record(show())?;
+        assert_eq!(read(&first)?.shows.len(), 1);
+        let plan = read(&second)?;
+        assert_eq!(plan.shows.len(), 1);
+        assert!(plan.shows[0].url.ends_with("/ilconte"));
+
+        install_resumed(Some(read(&first)?));
+        assert!(resumed(&show().url).is_some());
+        install_resumed(None);
+        assert!(resumed(&show().url).is_none());
+        Ok(())
+    }
+}
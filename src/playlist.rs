@@ -0,0 +1,118 @@
+use crate::errln;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// The kinds of page a show URL can point to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    /// A programme or audiobook, listing episodes in `rps-play-with-labels` elements.
+    Programme,
+    /// A playlist, listing its items in a JSON blob embedded in the page.
+    Playlist,
+    /// A section or genre page, like `/audiolibri` or `/generi/gialli`, linking to
+    /// programme pages instead of listing episodes.
+    Landing,
+}
+
+/// Sections of the site whose own page is a landing page.
+const SECTIONS: [&str; 4] = ["audiolibri", "programmi", "podcast", "generi"];
+
+/// Detects the kind of page from the path of the URL.
+pub fn page_kind(url: &str) -> PageKind {
+    let Ok(url) = Url::parse(url) else {
+        return PageKind::Programme;
+    };
+    let segments: Vec<&str> = url
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match segments.as_slice() {
+        ["playlist", ..] => PageKind::Playlist,
+        ["generi", ..] => PageKind::Landing,
+        [section] if SECTIONS.contains(section) => PageKind::Landing,
+        _ => PageKind::Programme,
+    }
+}
+
+/// Collects the metadata paths of audio items found anywhere in the JSON value, in order.
+pub fn collect_paths(value: &Value, paths: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(path) = map.get("path_id").and_then(Value::as_str) {
+                if path.starts_with("/audio/") && path.ends_with(".json") {
+                    paths.push(path.to_string());
+                }
+            }
+            for child in map.values() {
+                collect_paths(child, paths);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_paths(item, paths);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Extracts the metadata paths of the items of a playlist page.
+///
+/// The items are read from the `application/json` scripts embedded in the page; items
+/// repeated in several places of the page are only kept once.
+pub fn extract_playlist_items(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(r#"script[type="application/json"]"#).expect("Invalid selector");
+    let mut paths = Vec::new();
+    for script in document.select(&selector) {
+        let text: String = script.text().collect();
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => collect_paths(&value, &mut paths),
+            Err(err) => errln!("Warning: skipping unparsable playlist data: {}", err),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_kind() {
+        assert_eq!(
+            page_kind("https://www.raiplaysound.it/playlist/leletturedellestate"),
+            PageKind::Playlist
+        );
+        assert_eq!(
+            page_kind("https://www.raiplaysound.it/audiolibri/itremoschettieri"),
+            PageKind::Programme
+        );
+        for landing in [
+            "https://www.raiplaysound.it/audiolibri",
+            "https://www.raiplaysound.it/programmi/",
+            "https://www.raiplaysound.it/generi/gialli",
+        ] {
+            assert_eq!(page_kind(landing), PageKind::Landing, "{}", landing);
+        }
+    }
+
+    #[test]
+    fn test_extract_playlist_items() {
+        let html = include_str!("../tests/fixtures/playlist.html");
+        assert_eq!(
+            extract_playlist_items(html),
+            vec![
+                "/audio/2015/06/lettura-1.json",
+                "/audio/2015/06/lettura-2.json",
+                "/audio/2019/03/il-conte-di-montecristo-puntata-1.json",
+            ]
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// The limiter installed for the run by `--rps`, if any.
+static LIMITER: RwLock<Option<Arc<RateLimiter>>> = RwLock::new(None);
+
+/// Caps the number of requests sent to each host per second.
+///
+/// Each host has a token bucket holding a single token, refilled every `1 / rps`
+/// seconds. The lock is only held to reserve a slot, never while waiting for it, so
+/// any number of concurrent requests can wait at the same time.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    /// When the next request to each host may be sent.
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(rps: f64) -> Self {
+        RateLimiter {
+            interval: Duration::from_secs_f64(1.0 / rps),
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserves the next slot of the host of `url` and waits for it.
+    pub async fn wait(&self, url: &str) {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|err| err.into_inner());
+            let now = Instant::now();
+            let slot = next.get(&host).map_or(now, |next| (*next).max(now));
+            next.insert(host, slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// Installs the limiter every request of the run goes through, if any, in place of the
+/// one of an earlier run of `--watch`.
+pub fn install(rps: Option<f64>) {
+    *LIMITER.write().unwrap_or_else(|err| err.into_inner()) =
+        rps.map(|rps| Arc::new(RateLimiter::new(rps)));
+}
+
+/// Waits until a request to `url` may be sent, which is right away without `--rps`.
+pub async fn throttle(url: &str) {
+    let limiter = LIMITER
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    if let Some(limiter) = limiter {
+        limiter.wait(url).await;
+    }
+}
+
+/// Parses the `--rps` value, a positive number of requests per second.
+pub fn parse_rps(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rps) if rps.is_finite() && rps > 0.0 => Ok(rps),
+        _ => Err(format!("expected a positive number, got {:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(50.0);
+        let start = Instant::now();
+        for _ in 0..6 {
+            limiter
+                .wait("https://www.raiplaysound.it/audio/a.json")
+                .await;
+        }
+        // Five intervals of 20ms after the first request, which is sent right away.
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let start = Instant::now();
+        limiter.wait("https://mediapolisvod.rai.it/relinker").await;
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_parse_rps() {
+        assert_eq!(parse_rps("2"), Ok(2.0));
+        assert_eq!(parse_rps("0.5"), Ok(0.5));
+        assert!(parse_rps("0").is_err());
+        assert!(parse_rps("-1").is_err());
+        assert!(parse_rps("fast").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::fetcher::Fetcher;
+use crate::format_size;
+use crate::hls;
+use crate::naming::{sanitize, Naming};
+use crate::relinker::{self, Container};
+use crate::retry;
+use crate::transfer::{Meter, Transfer};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+/// How often the playlist of an HLS channel is fetched again for new segments.
+const REFRESH: Duration = Duration::from_secs(2);
+
+/// A live channel, as described by its JSON on the site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    pub name: String,
+    /// The relinker or CDN URL of the live stream.
+    pub stream_url: String,
+}
+
+/// Reads the channel from the JSON of its live page, such as `/dirette/radio3.json`.
+pub fn parse_channel(slug: &str, json: &Value) -> Result<Channel> {
+    let stream_url = [
+        &json["audio"]["url"],
+        &json["video"]["content_url"],
+        &json["content_url"],
+    ]
+    .into_iter()
+    .find_map(Value::as_str)
+    .filter(|url| !url.is_empty())
+    .ok_or_else(|| anyhow!("No live stream in the page of channel {}", slug))?;
+    let name = [&json["channel"]["name"], &json["title"]]
+        .into_iter()
+        .find_map(Value::as_str)
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or(slug);
+    Ok(Channel {
+        name: name.trim().to_string(),
+        stream_url: crate::site::absolute_url(stream_url),
+    })
+}
+
+pub async fn fetch_channel<F: Fetcher>(client: &F, slug: &str) -> Result<Channel> {
+    let url = crate::site::absolute_url(&format!("/dirette/{}.json", slug));
+    let text = client
+        .get_text(&url)
+        .await
+        .with_context(|| format!("Failed to fetch channel {}", slug))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid JSON for channel {}: {}", slug, url))?;
+    parse_channel(slug, &json)
+}
+
+/// Name of the recording: the channel and the minute it started.
+pub fn output_name(channel: &Channel, start: DateTime<Local>, container: Container) -> String {
+    let name = format!("{} {}", channel.name, start.format("%Y-%m-%d %H%M"));
+    format!(
+        "{}.{}",
+        sanitize(&name, &Naming::default()),
+        container.extension()
+    )
+}
+
+/// ID3 frames of the recording, which players show instead of the file name.
+fn tag(channel: &Channel, start: DateTime<Local>) -> Vec<u8> {
+    let title = format!("{} {}", channel.name, start.format("%Y-%m-%d %H:%M"));
+    let year = start.format("%Y").to_string();
+    let date = start.format("%d%m").to_string();
+    let time = start.format("%H%M").to_string();
+    crate::id3::tag(&[
+        ("TIT2", &title),
+        ("TPE1", &channel.name),
+        ("TYER", &year),
+        ("TDAT", &date),
+        ("TIME", &time),
+    ])
+}
+
+/// Why a recording stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested duration elapsed.
+    Duration,
+    /// Ctrl+C was pressed.
+    Interrupted,
+    /// The server ended the stream.
+    Ended,
+}
+
+/// A finished recording.
+#[derive(Debug)]
+pub struct Recording {
+    pub path: PathBuf,
+    pub transfer: Transfer,
+    pub stop: Stop,
+}
+
+/// Records the live stream of the channel into a new file of `folder` until `duration`
+/// elapses or Ctrl+C is pressed.
+///
+/// Audio is written as it arrives, so that an interrupted recording is still playable,
+/// and a failed one keeps what it captured.
+pub async fn record<F: Fetcher>(
+    client: &F,
+    channel: &Channel,
+    folder: &Path,
+    duration: Duration,
+    accept_any_content: bool,
+) -> Result<Recording> {
+    let resolved = relinker::resolve(client, &channel.stream_url).await?;
+    let (playlist, container) = if resolved.container == Container::Hls {
+        let (playlist, _, container) = hls::media_playlist(client, &resolved.url).await?;
+        (Some(playlist), container)
+    } else {
+        (None, resolved.container)
+    };
+    // The duration counts from the moment the stream is found.
+    let stop = tokio::time::sleep(duration);
+    let start = Local::now();
+
+    let path = folder.join(output_name(channel, start, container));
+    let mut file = File::create(&path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    if matches!(container, Container::Mp3 | Container::Aac) {
+        file.write_all(&tag(channel, start))
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    let mut meter = Meter::start();
+    let capture = async {
+        match &playlist {
+            Some(playlist) => {
+                capture_hls(client, playlist, &mut file, &mut meter, accept_any_content).await
+            }
+            None => {
+                capture_stream(
+                    client,
+                    &resolved.url,
+                    &mut file,
+                    &mut meter,
+                    accept_any_content,
+                )
+                .await
+            }
+        }
+    };
+    let stop = tokio::select! {
+        result = capture => result.map(|()| Stop::Ended),
+        _ = stop => Ok(Stop::Duration),
+        _ = tokio::signal::ctrl_c() => Ok(Stop::Interrupted),
+    };
+    // Flushing also waits for the write in flight when the recording stopped.
+    file.flush()
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    let transfer = meter.finish();
+    let stop = stop.with_context(|| {
+        format!(
+            "Recording of {} failed, the {} captured so far are kept in {}",
+            channel.name,
+            format_size(transfer.bytes),
+            path.display()
+        )
+    })?;
+    Ok(Recording {
+        path,
+        transfer,
+        stop,
+    })
+}
+
+/// Appends the segments of a live playlist as they are published, starting from the
+/// newest one. Failed requests are tried again as `--retries` allows.
+async fn capture_hls<F: Fetcher>(
+    client: &F,
+    playlist: &str,
+    file: &mut File,
+    meter: &mut Meter,
+    accept_any_content: bool,
+) -> Result<()> {
+    let policy = retry::policy();
+    let refresh = || policy.run(playlist, || hls::refresh_segments(client, playlist));
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut segments = refresh().await?;
+    if let Some(newest) = segments.len().checked_sub(1) {
+        seen.extend(segments.drain(..newest));
+    }
+    loop {
+        for segment in segments {
+            if !seen.insert(segment.clone()) {
+                continue;
+            }
+            let data = policy
+                .run(&segment, || {
+                    hls::fetch_segment(client, &segment, accept_any_content)
+                })
+                .await?;
+            file.write_all(&data).await?;
+            meter.add(data.len() as u64);
+        }
+        tokio::time::sleep(REFRESH).await;
+        segments = refresh().await?;
+    }
+}
+
+/// Appends a progressive stream, such as Icecast, until the server closes it.
+async fn capture_stream<F: Fetcher>(
+    client: &F,
+    url: &str,
+    file: &mut File,
+    meter: &mut Meter,
+    accept_any_content: bool,
+) -> Result<()> {
+    let mut response = client
+        .get_stream(url)
+        .await
+        .with_context(|| format!("Failed to open stream: {}", url))?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to open stream: {}. Status: {}",
+            url,
+            response.status()
+        ));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let mut checked = accept_any_content;
+    while let Some(chunk) = response.chunk().await? {
+        if !checked {
+            crate::sniff::check_audio(content_type.as_deref(), &chunk[..chunk.len().min(512)])
+                .with_context(|| format!("Refusing to record {}", url))?;
+            checked = true;
+        }
+        file.write_all(&chunk).await?;
+        meter.add(chunk.len() as u64);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::MockFetcher;
+    use chrono::TimeZone;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn radio3() -> Channel {
+        Channel {
+            name: "Rai Radio 3".to_string(),
+            stream_url: "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=radio3"
+                .to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_channel() -> Result<()> {
+        let json = json!({
+            "title": "Radio 3 in diretta",
+            "channel": {"name": "Rai Radio 3"},
+            "audio": {"url": "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=radio3"},
+        });
+        assert_eq!(parse_channel("radio3", &json)?, radio3());
+
+        let json = json!({"content_url": "/live/radio2.mp3"});
+        let channel = parse_channel("radio2", &json)?;
+        assert_eq!(channel.name, "radio2");
+        assert_eq!(
+            channel.stream_url,
+            "https://www.raiplaysound.it/live/radio2.mp3"
+        );
+
+        assert!(parse_channel("radio1", &json!({"title": "Radio 1"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_name() {
+        let start = Local.with_ymd_and_hms(2024, 3, 9, 21, 5, 30).unwrap();
+        assert_eq!(
+            output_name(&radio3(), start, Container::Mp3),
+            "rai radio 3 2024-03-09 2105.mp3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_hls_starts_from_newest_segment() -> Result<()> {
+        let folder = TempDir::new()?;
+        let playlist = "https://cdn.rai.it/live/radio3.m3u8";
+        let client = MockFetcher::default()
+            .fixture(
+                playlist,
+                StatusCode::OK,
+                "application/vnd.apple.mpegurl",
+                "#EXTM3U\n#EXTINF:6,\n1.ts\n#EXTINF:6,\n2.ts\n",
+                None,
+            )
+            .fixture(
+                "https://cdn.rai.it/live/1.ts",
+                StatusCode::OK,
+                "video/mp2t",
+                "old",
+                None,
+            )
+            .fixture(
+                "https://cdn.rai.it/live/2.ts",
+                StatusCode::OK,
+                "video/mp2t",
+                "new",
+                None,
+            );
+        let channel = Channel {
+            name: "Rai Radio 3".to_string(),
+            stream_url: playlist.to_string(),
+        };
+
+        let recording = record(
+            &client,
+            &channel,
+            folder.path(),
+            Duration::from_millis(200),
+            true,
+        )
+        .await?;
+        assert_eq!(recording.stop, Stop::Duration);
+        assert_eq!(recording.path.extension().unwrap(), "ts");
+        assert_eq!(std::fs::read(&recording.path)?, b"new");
+        assert_eq!(recording.transfer.bytes, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_recording_keeps_the_file() -> Result<()> {
+        let folder = TempDir::new()?;
+        let playlist = "https://cdn.rai.it/live/radio3.m3u8";
+        let client = MockFetcher::default()
+            .fixture(
+                playlist,
+                StatusCode::OK,
+                "application/vnd.apple.mpegurl",
+                "#EXTM3U\n#EXTINF:6,\n1.ts\n",
+                None,
+            )
+            .fixture(
+                "https://cdn.rai.it/live/1.ts",
+                StatusCode::NOT_FOUND,
+                "text/html",
+                "gone",
+                None,
+            );
+        let channel = Channel {
+            name: "Rai Radio 3".to_string(),
+            stream_url: playlist.to_string(),
+        };
+
+        let err = record(
+            &client,
+            &channel,
+            folder.path(),
+            Duration::from_secs(60),
+            true,
+        )
+        .await
+        .unwrap_err();
+        let kept = std::fs::read_dir(folder.path())?.next().unwrap()?.path();
+        assert!(
+            format!("{:#}", err).contains(&format!("kept in {}", kept.display())),
+            "{:#}",
+            err
+        );
+        Ok(())
+    }
+}
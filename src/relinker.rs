@@ -0,0 +1,214 @@
+use crate::fetcher::Fetcher;
+use anyhow::{Context, Result};
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use reqwest::Response;
+
+/// Audio containers served by the CDN, and MP4 for video items.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Container {
+    #[default]
+    Mp3,
+    M4a,
+    Aac,
+    /// MPEG-TS segments of an HLS stream, concatenated.
+    Ts,
+    /// An HLS playlist, whose segments are downloaded instead.
+    Hls,
+    /// MP4 video, for `--include-video`.
+    Mp4,
+}
+
+/// Extensions of all the containers, used to find files downloaded by earlier runs.
+pub const EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "ts", "mp4"];
+
+impl Container {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Mp3 => "mp3",
+            Container::M4a => "m4a",
+            Container::Aac => "aac",
+            // Playlists are saved as the segments they list.
+            Container::Ts | Container::Hls => "ts",
+            Container::Mp4 => "mp4",
+        }
+    }
+
+    /// The container of a video item: MP4 boxes hold video rather than audio.
+    pub fn for_video(self) -> Self {
+        match self {
+            Container::M4a => Container::Mp4,
+            container => container,
+        }
+    }
+
+    /// Detects the container from a Content-Type header value.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        if crate::hls::is_playlist(content_type) {
+            return Some(Container::Hls);
+        }
+        let mime = content_type.split(';').next()?.trim().to_lowercase();
+        match mime.as_str() {
+            "audio/mpeg" | "audio/mp3" | "audio/mpeg3" => Some(Container::Mp3),
+            "audio/mp4" | "audio/m4a" | "audio/x-m4a" => Some(Container::M4a),
+            "audio/aac" | "audio/aacp" | "audio/x-aac" => Some(Container::Aac),
+            "video/mp4" => Some(Container::Mp4),
+            _ => None,
+        }
+    }
+
+    /// Detects the container from the first bytes of the body.
+    pub fn from_magic(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"ID3") {
+            return Some(Container::Mp3);
+        }
+        if head.len() >= 8 && &head[4..8] == b"ftyp" {
+            return Some(Container::M4a);
+        }
+        if head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE0 == 0xE0 {
+            // ADTS frames have the layer bits of MPEG audio set to zero.
+            return Some(if head[1] & 0x06 == 0 {
+                Container::Aac
+            } else {
+                Container::Mp3
+            });
+        }
+        if head.first() == Some(&0x47) && head.get(188).is_none_or(|byte| *byte == 0x47) {
+            return Some(Container::Ts);
+        }
+        None
+    }
+
+    /// Detects the container from the extension of a URL path.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split(['?', '#']).next()?;
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "mp3" => Some(Container::Mp3),
+            "m4a" | "mp4" => Some(Container::M4a),
+            "aac" => Some(Container::Aac),
+            "ts" => Some(Container::Ts),
+            "m3u8" => Some(Container::Hls),
+            _ => None,
+        }
+    }
+}
+
+/// The media a relinker URL points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    /// The CDN URL at the end of the redirect chain.
+    pub url: String,
+    pub container: Container,
+    /// The name the CDN serves the file under.
+    pub filename: Option<String>,
+    /// Size of the file the CDN advertises; unknown for HLS playlists.
+    pub size: Option<u64>,
+}
+
+fn resolved_from(response: &Response) -> Resolved {
+    let url = response.url().to_string();
+    let container = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Container::from_content_type)
+        .or_else(|| Container::from_url(&url))
+        .unwrap_or_default();
+    let disposition = response
+        .headers()
+        .get(CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok());
+    let filename = crate::original::original_name(&url, disposition);
+    let size = crate::fetcher::content_length(response).filter(|_| container != Container::Hls);
+    Resolved {
+        url,
+        container,
+        filename,
+        size,
+    }
+}
+
+/// Follows the redirects of the relinker URL and returns the media it ends up at.
+///
+/// Relinker URLs expire, so the result is only meant to be used within the current run.
+pub async fn resolve<F: Fetcher>(client: &F, url: &str) -> Result<Resolved> {
+    // The relinker does not always answer HEAD requests; a GET whose body is dropped
+    // still reveals the final URL and its headers.
+    if let Ok(response) = client.head(url).await {
+        if response.status().is_success() {
+            crate::dump::relinker(url, &response);
+            return Ok(resolved_from(&response));
+        }
+    }
+    let response = client
+        .get_stream(url)
+        .await
+        .with_context(|| format!("Failed to resolve audio URL: {}", url))?;
+    crate::dump::relinker(url, &response);
+    Ok(resolved_from(&response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_from_content_type() {
+        assert_eq!(
+            Container::from_content_type("audio/mpeg"),
+            Some(Container::Mp3)
+        );
+        assert_eq!(
+            Container::from_content_type("audio/x-m4a; charset=binary"),
+            Some(Container::M4a)
+        );
+        assert_eq!(
+            Container::from_content_type("audio/aacp"),
+            Some(Container::Aac)
+        );
+        assert_eq!(
+            Container::from_content_type("video/mp4"),
+            Some(Container::Mp4)
+        );
+        assert_eq!(Container::from_content_type("text/html"), None);
+    }
+
+    #[test]
+    fn test_container_from_url() {
+        assert_eq!(
+            Container::from_url("https://cdn.rai.it/podcastcdn/lettura-1.m4a?token=abc.mp3"),
+            Some(Container::M4a)
+        );
+        assert_eq!(
+            Container::from_url("https://cdn.rai.it/podcastcdn/lettura-1.MP3"),
+            Some(Container::Mp3)
+        );
+        assert_eq!(
+            Container::from_url("https://mediapolisvod.rai.it/relinker/relinkerServlet.htm?cont=x"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_magic() {
+        assert_eq!(Container::from_magic(b"ID3\x04\x00"), Some(Container::Mp3));
+        assert_eq!(
+            Container::from_magic(&[0xFF, 0xFB, 0x90, 0x64]),
+            Some(Container::Mp3)
+        );
+        assert_eq!(
+            Container::from_magic(&[0xFF, 0xF1, 0x50, 0x80]),
+            Some(Container::Aac)
+        );
+        assert_eq!(
+            Container::from_magic(b"\x00\x00\x00\x20ftypM4A "),
+            Some(Container::M4a)
+        );
+        assert_eq!(Container::from_magic(&[0x47; 200]), Some(Container::Ts));
+        assert_eq!(
+            Container::from_magic(b"\x00\x00\x00\x20ftypisom").map(Container::for_video),
+            Some(Container::Mp4)
+        );
+        assert_eq!(Container::from_magic(b"<html>"), None);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::fetcher::Fetcher;
+use crate::relinker::{self, Resolved};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// The resolutions of the run, once installed.
+static RESOLUTIONS: OnceLock<Resolutions> = OnceLock::new();
+
+/// What the relinker resolved the audio of each episode to, by the URL of the episode
+/// metadata, so that the size estimate, the size check and the download of an episode
+/// reach the relinker once between them.
+///
+/// Relinker URLs expire, so resolutions are only kept in memory for the run.
+#[derive(Debug, Default)]
+pub struct Resolutions {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    /// The audio URL that was resolved, which changes with the rendition picked.
+    audio_url: String,
+    resolved: Resolved,
+}
+
+impl Resolutions {
+    fn get(&self, metadata_url: &str, audio_url: &str) -> Option<Resolved> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries
+            .get(metadata_url)
+            .filter(|entry| entry.audio_url == audio_url)
+            .map(|entry| entry.resolved.clone())
+    }
+
+    fn put(&self, metadata_url: &str, audio_url: &str, resolved: &Resolved) {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries.insert(
+            metadata_url.to_string(),
+            Entry {
+                audio_url: audio_url.to_string(),
+                resolved: resolved.clone(),
+            },
+        );
+    }
+
+    /// Returns the resolution of the audio of the episode, resolving it the first time.
+    pub async fn resolve<F: Fetcher>(
+        &self,
+        client: &F,
+        metadata_url: &str,
+        audio_url: &str,
+    ) -> Result<Resolved> {
+        if let Some(resolved) = self.get(metadata_url, audio_url) {
+            log::debug!("Reusing the resolution of {}", audio_url);
+            return Ok(resolved);
+        }
+        let resolved = relinker::resolve(client, audio_url).await?;
+        self.put(metadata_url, audio_url, &resolved);
+        Ok(resolved)
+    }
+
+    /// Completes the resolution of the episode with the size a HEAD request found.
+    pub fn set_size(&self, metadata_url: &str, size: u64) {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(entry) = entries.get_mut(metadata_url) {
+            entry.resolved.size = Some(size);
+        }
+    }
+
+    /// Drops the resolution of the episode, whose CDN URL has expired.
+    pub fn forget(&self, metadata_url: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries.remove(metadata_url);
+    }
+}
+
+/// Keeps the resolutions of the run from now on, dropping those of an earlier run of
+/// `--watch`, which have expired since.
+pub fn install() {
+    let resolutions = RESOLUTIONS.get_or_init(Resolutions::default);
+    let mut entries = resolutions
+        .entries
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    entries.clear();
+}
+
+/// Resolves the audio of the episode through the relinker, or returns the resolution
+/// made earlier in the run.
+pub async fn resolve<F: Fetcher>(
+    client: &F,
+    metadata_url: &str,
+    audio_url: &str,
+) -> Result<Resolved> {
+    match RESOLUTIONS.get() {
+        Some(resolutions) => resolutions.resolve(client, metadata_url, audio_url).await,
+        None => relinker::resolve(client, audio_url).await,
+    }
+}
+
+/// Records the size of the audio of the episode, found after it was resolved.
+pub fn set_size(metadata_url: &str, size: u64) {
+    if let Some(resolutions) = RESOLUTIONS.get() {
+        resolutions.set_size(metadata_url, size);
+    }
+}
+
+/// Drops the resolution of the episode, so that the next attempt resolves it again.
+pub fn forget(metadata_url: &str) {
+    if let Some(resolutions) = RESOLUTIONS.get() {
+        resolutions.forget(metadata_url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::MockFetcher;
+    use reqwest::StatusCode;
+
+    const METADATA: &str = "https://www.raiplaysound.it/audio/2015/06/lettura-1.json";
+    const RELINKER: &str = "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=1";
+    const OTHER: &str = "https://mediapolis.rai.it/relinker/relinkerServlet.htm?cont=2";
+
+    fn client() -> MockFetcher {
+        MockFetcher::default()
+            .fixture(
+                RELINKER,
+                StatusCode::OK,
+                "audio/mpeg",
+                "",
+                Some("https://cdn.rai.it/lettura-1.mp3"),
+            )
+            .fixture(
+                OTHER,
+                StatusCode::OK,
+                "audio/mp4",
+                "",
+                Some("https://cdn.rai.it/lettura-1.m4a"),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_resolved_once() {
+        let client = client();
+        let resolutions = Resolutions::default();
+        let first = resolutions
+            .resolve(&client, METADATA, RELINKER)
+            .await
+            .unwrap();
+        let second = resolutions
+            .resolve(&client, METADATA, RELINKER)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.url, "https://cdn.rai.it/lettura-1.mp3");
+        assert_eq!(client.requests().len(), 1);
+
+        resolutions.set_size(METADATA, 1024);
+        let sized = resolutions
+            .resolve(&client, METADATA, RELINKER)
+            .await
+            .unwrap();
+        assert_eq!(sized.size, Some(1024));
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_forget_and_other_rendition() {
+        let client = client();
+        let resolutions = Resolutions::default();
+        resolutions
+            .resolve(&client, METADATA, RELINKER)
+            .await
+            .unwrap();
+        // Another rendition of the same episode is resolved on its own.
+        let other = resolutions.resolve(&client, METADATA, OTHER).await.unwrap();
+        assert_eq!(other.url, "https://cdn.rai.it/lettura-1.m4a");
+        assert_eq!(client.requests().len(), 2);
+
+        resolutions.forget(METADATA);
+        resolutions.resolve(&client, METADATA, OTHER).await.unwrap();
+        assert_eq!(client.requests().len(), 3);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::error::RsndError;
+use crate::outln;
+use anyhow::Result;
+use clap::ValueEnum;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+/// The policy installed for the run by `--retries` and `--retry-on`, if any.
+static POLICY: RwLock<Option<Policy>> = RwLock::new(None);
+
+/// The longest `Retry-After` waited for; a server asking for more is given up on.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// The kinds of failure `--retry-on` can retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RetryClass {
+    /// The connection could not be made, or was reset
+    Connect,
+    /// The server took too long to answer
+    Timeout,
+    /// The server answered with a 5xx status
+    #[value(name = "5xx")]
+    ServerError,
+    /// The server answered 429 Too Many Requests
+    #[value(name = "429")]
+    TooManyRequests,
+    /// The relinker served a placeholder, usually because of geo-blocking
+    Geo,
+    /// The download ended before its advertised size, or was empty
+    ShortRead,
+    /// The download stayed below --stall-speed for --stall-timeout
+    Stall,
+}
+
+impl RetryClass {
+    /// The classes retried without `--retry-on`: the downloads that ended early or
+    /// stalled, which were retried before the classes existed. Failed requests are only
+    /// retried when asked for, so that a server in trouble is not sent more of them.
+    pub const DEFAULT: [RetryClass; 2] = [RetryClass::ShortRead, RetryClass::Stall];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Tells which class the failure belongs to, if any; the others, such as a 404, are
+/// never retried.
+pub fn classify(err: &anyhow::Error) -> Option<RetryClass> {
+    match RsndError::of(err) {
+        Some(RsndError::PageFetch { source, .. } | RsndError::Download { source, .. }) => {
+            Some(classify_request(source))
+        }
+        Some(RsndError::Status { status, .. }) => classify_status(*status),
+        Some(RsndError::GeoBlocked { .. }) => Some(RetryClass::Geo),
+        Some(RsndError::ShortRead { .. }) => Some(RetryClass::ShortRead),
+        Some(RsndError::Stalled { .. }) => Some(RetryClass::Stall),
+        Some(_) => None,
+        // Errors reading a body arrive as they are.
+        None => err.downcast_ref::<reqwest::Error>().map(classify_request),
+    }
+}
+
+fn classify_request(err: &reqwest::Error) -> RetryClass {
+    if err.is_timeout() {
+        RetryClass::Timeout
+    } else {
+        RetryClass::Connect
+    }
+}
+
+/// Returns the wait the server asked for after a 429 or a 503, if any.
+fn retry_after(err: &anyhow::Error) -> Option<Duration> {
+    match RsndError::of(err) {
+        Some(RsndError::Status { retry_after, .. }) => *retry_after,
+        _ => None,
+    }
+}
+
+/// Parses a `Retry-After` header: a number of seconds, or the date to wait until.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let date = SystemTime::from(date);
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+fn classify_status(status: StatusCode) -> Option<RetryClass> {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        Some(RetryClass::TooManyRequests)
+    } else if status.is_server_error() {
+        Some(RetryClass::ServerError)
+    } else {
+        None
+    }
+}
+
+/// How many times, and after which failures, a request is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Extra attempts after the first.
+    pub retries: usize,
+    classes: u8,
+    /// Wait before the first retry, doubled before each of the next ones.
+    pub backoff: Duration,
+}
+
+impl Default for Policy {
+    /// No retries, as in tests and before the policy of the run is installed.
+    fn default() -> Self {
+        Policy::new(0, &RetryClass::DEFAULT)
+    }
+}
+
+impl Policy {
+    pub fn new(retries: usize, classes: &[RetryClass]) -> Self {
+        Policy {
+            retries,
+            classes: classes.iter().fold(0, |bits, class| bits | class.bit()),
+            backoff: Duration::from_millis(500),
+        }
+    }
+
+    pub fn retries_on(&self, class: RetryClass) -> bool {
+        self.classes & class.bit() != 0
+    }
+
+    /// Runs `operation`, trying it again after the failures of the retried classes, and
+    /// returns the outcome of the last attempt. `what` names it in the messages.
+    pub async fn run<T, F, Fut>(&self, what: &str, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let err = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let retried = classify(&err).filter(|class| self.retries_on(*class));
+            if attempt >= self.retries || retried.is_none() {
+                return Err(err);
+            }
+            // The server is not asked again before the time it gave.
+            let mut delay = self.backoff * 2u32.saturating_pow(attempt as u32);
+            if let Some(after) = retry_after(&err) {
+                if after > MAX_RETRY_AFTER {
+                    return Err(err.context(format!(
+                        "The server asked to wait {}s before trying again",
+                        after.as_secs()
+                    )));
+                }
+                delay = delay.max(after);
+            }
+            attempt += 1;
+            outln!(
+                "Retrying {} ({}/{}): {:#}",
+                what,
+                attempt,
+                self.retries,
+                err
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Installs the policy the requests of the run follow, in place of the one of an earlier
+/// run of `--watch`.
+pub fn install(policy: Policy) {
+    *POLICY.write().unwrap_or_else(|err| err.into_inner()) = Some(policy);
+}
+
+/// Returns the policy of the run, which does not retry until one is installed.
+pub fn policy() -> Policy {
+    POLICY
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .unwrap_or_default()
+}
+
+/// Prints the classes `--retry-on` accepts, marking those retried by default.
+pub fn print_classes() {
+    for class in RetryClass::value_variants() {
+        let Some(value) = class.to_possible_value() else {
+            continue;
+        };
+        let default = if RetryClass::DEFAULT.contains(class) {
+            " (default)"
+        } else {
+            ""
+        };
+        outln!(
+            "{:<12}{}{}",
+            value.get_name(),
+            value
+                .get_help()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            default
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::{Fetcher, HttpFetcher, MockFetcher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const URL: &str = "https://www.raiplaysound.it/audio/2015/06/lettura-1.json";
+
+    fn policy(classes: &[RetryClass]) -> Policy {
+        Policy {
+            backoff: Duration::ZERO,
+            ..Policy::new(2, classes)
+        }
+    }
+
+    /// Fetches `URL` from the fixture with the given status under the policy and
+    /// returns the number of requests sent.
+    async fn requests_for(status: StatusCode, policy: Policy) -> usize {
+        let fetcher = MockFetcher::default().fixture(URL, status, "text/html", "", None);
+        let result = policy
+            .run("lettura-1", || async { Ok(fetcher.get_text(URL).await?) })
+            .await;
+        assert!(result.is_err());
+        fetcher.requests().len()
+    }
+
+    /// Runs an operation failing with `err` under the policy and returns the number of
+    /// attempts.
+    async fn attempts_for(err: impl Fn() -> RsndError, policy: Policy) -> usize {
+        let attempts = AtomicUsize::new(0);
+        let result: Result<()> = policy
+            .run("lettura-1", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(err().into())
+            })
+            .await;
+        assert!(result.is_err());
+        attempts.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_status_classes() {
+        // Failed requests are only retried when asked for.
+        let default = policy(&RetryClass::DEFAULT);
+        assert_eq!(
+            requests_for(StatusCode::SERVICE_UNAVAILABLE, default).await,
+            1
+        );
+        let statuses = policy(&[RetryClass::ServerError, RetryClass::TooManyRequests]);
+        assert_eq!(
+            requests_for(StatusCode::SERVICE_UNAVAILABLE, statuses).await,
+            3
+        );
+        assert_eq!(
+            requests_for(StatusCode::TOO_MANY_REQUESTS, statuses).await,
+            3
+        );
+        // A missing page never comes back.
+        assert_eq!(requests_for(StatusCode::NOT_FOUND, statuses).await, 1);
+        let only_connect = policy(&[RetryClass::Connect]);
+        assert_eq!(requests_for(StatusCode::BAD_GATEWAY, only_connect).await, 1);
+        assert_eq!(
+            requests_for(StatusCode::TOO_MANY_REQUESTS, only_connect).await,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_geo_and_short_read_classes() {
+        let geo = || RsndError::GeoBlocked {
+            reason: "placeholder".to_string(),
+            status: StatusCode::OK,
+        };
+        let short = || RsndError::ShortRead {
+            received: 20,
+            expected: Some(100),
+        };
+        let default = policy(&RetryClass::DEFAULT);
+        assert_eq!(attempts_for(geo, default).await, 1);
+        assert_eq!(attempts_for(geo, policy(&[RetryClass::Geo])).await, 3);
+        assert_eq!(attempts_for(short, default).await, 3);
+        assert_eq!(attempts_for(short, policy(&[RetryClass::Geo])).await, 1);
+        assert_eq!(attempts_for(short, Policy::default()).await, 1);
+
+        let stalled = || RsndError::Stalled {
+            received: 4096,
+            speed: 100,
+            secs: 30,
+        };
+        assert_eq!(attempts_for(stalled, default).await, 3);
+        assert_eq!(
+            attempts_for(stalled, policy(&[RetryClass::Connect])).await,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_timeout_classes() -> Result<()> {
+        // Nothing listens on port 1.
+        let refused = HttpFetcher::new(reqwest::Client::new());
+        let err = anyhow::Error::from(refused.get_text("http://127.0.0.1:1/").await.unwrap_err());
+        assert_eq!(classify(&err), Some(RetryClass::Connect));
+
+        // A listener that never answers.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/", listener.local_addr()?);
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()?;
+        let err = anyhow::Error::from(HttpFetcher::new(client).get_text(&url).await.unwrap_err());
+        assert_eq!(classify(&err), Some(RetryClass::Timeout));
+
+        let attempts = AtomicUsize::new(0);
+        let result: Result<String> = policy(&[RetryClass::Timeout])
+            .run("lettura-1", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Ok(refused.get_text("http://127.0.0.1:1/").await?)
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.into_inner(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let later = chrono::Utc::now() + chrono::Duration::seconds(90);
+        let wait = parse_retry_after(&later.to_rfc2822()).unwrap();
+        assert!(wait > Duration::from_secs(85) && wait <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        let busy = |retry_after| {
+            move || RsndError::Status {
+                url: URL.to_string(),
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: Some(retry_after),
+            }
+        };
+        let policy = policy(&[RetryClass::TooManyRequests]);
+        let start = std::time::Instant::now();
+        assert_eq!(
+            attempts_for(busy(Duration::from_millis(50)), policy).await,
+            3
+        );
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        // A wait too long is not worth it.
+        assert_eq!(
+            attempts_for(busy(Duration::from_secs(3600)), policy).await,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_success_after_retry() {
+        let attempts = AtomicUsize::new(0);
+        let result = policy(&RetryClass::DEFAULT)
+            .run("lettura-1", || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(RsndError::ShortRead {
+                        received: 0,
+                        expected: None,
+                    }
+                    .into())
+                } else {
+                    Ok(42)
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.into_inner(), 2);
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::Result;
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use regex::Regex;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// How long after the expected publication time `--interval auto` polls, since episodes
+/// go up some time after they air.
+pub const POLL_DELAY: Duration = Duration::minutes(30);
+
+/// Interval of `--interval auto` for shows whose schedule is unknown.
+pub const FALLBACK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// How long `--watch` waits between runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    /// After the next expected publication of the shows, read from their schedule.
+    Auto,
+    Fixed(std::time::Duration),
+}
+
+/// Parses `--interval`: `auto`, or a duration like `30m`.
+pub fn parse_interval(text: &str) -> Result<Interval> {
+    if text.eq_ignore_ascii_case("auto") {
+        return Ok(Interval::Auto);
+    }
+    crate::duration::parse_duration(text).map(Interval::Fixed)
+}
+
+/// When a show publishes new episodes, as read from its schedule string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Days of publication, in week order.
+    pub days: Vec<Weekday>,
+    /// Time of publication, when the schedule gives one.
+    pub time: Option<NaiveTime>,
+}
+
+const WEEK: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Returns the weekday a word names, in Italian or English, singular or plural.
+fn weekday(word: &str) -> Option<Weekday> {
+    let word = deunicode::deunicode(word).to_lowercase();
+    let day = match word.as_str() {
+        "lunedi" | "monday" | "mondays" => Weekday::Mon,
+        "martedi" | "tuesday" | "tuesdays" => Weekday::Tue,
+        "mercoledi" | "wednesday" | "wednesdays" => Weekday::Wed,
+        "giovedi" | "thursday" | "thursdays" => Weekday::Thu,
+        "venerdi" | "friday" | "fridays" => Weekday::Fri,
+        "sabato" | "sabati" | "saturday" | "saturdays" => Weekday::Sat,
+        "domenica" | "domeniche" | "sunday" | "sundays" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(day)
+}
+
+/// Returns the days from `first` to `last`, wrapping around the end of the week.
+fn day_range(first: Weekday, last: Weekday) -> Vec<Weekday> {
+    let mut days = vec![first];
+    let mut day = first;
+    while day != last {
+        day = day.succ();
+        days.push(day);
+    }
+    days
+}
+
+/// Reads the first time of day in the schedule, like `alle 9.00`, `ore 21` or `14:30`.
+fn first_time(text: &str) -> Option<NaiveTime> {
+    static TIME: OnceLock<Regex> = OnceLock::new();
+    let time = TIME.get_or_init(|| {
+        Regex::new(
+            r"(?:\b(?:alle|dalle|ore|at)\s+(\d{1,2})(?:[:.](\d{2}))?\b)|\b(\d{1,2})[:.](\d{2})\b",
+        )
+        .expect("Invalid regex")
+    });
+    let captures = time.captures(text)?;
+    let hour = captures.get(1).or(captures.get(3))?.as_str().parse().ok()?;
+    let minute = captures
+        .get(2)
+        .or(captures.get(4))
+        .map_or(Some(0), |minute| minute.as_str().parse().ok())?;
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// Parses the schedule strings of the site, like `Dal lunedì al venerdì alle 9.00`,
+/// `Ogni sabato alle 14:00`, `Tutti i giorni ore 7`, `Lunedì e giovedì` or the
+/// `daily`, `weekdays` and `weekly on <day>` hints. Returns `None` when the days of
+/// publication cannot be told, as with a bare `settimanale` (weekly).
+pub fn parse(text: &str) -> Option<Schedule> {
+    let lower = deunicode::deunicode(text).to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let time = first_time(&lower);
+
+    let every_day = ["quotidiano", "quotidiana", "daily", "everyday"];
+    if words.iter().any(|word| every_day.contains(word))
+        || lower.contains("tutti i giorni")
+        || lower.contains("ogni giorno")
+        || lower.contains("every day")
+    {
+        return Some(Schedule {
+            days: WEEK.to_vec(),
+            time,
+        });
+    }
+    if words
+        .iter()
+        .any(|word| ["feriale", "feriali", "weekdays"].contains(word))
+    {
+        return Some(Schedule {
+            days: day_range(Weekday::Mon, Weekday::Fri),
+            time,
+        });
+    }
+
+    // "dal lunedì al venerdì", "from monday to friday", "lunedì-venerdì"
+    let mut days = Vec::new();
+    let mut position = 0;
+    while position < words.len() {
+        let Some(first) = weekday(words[position]) else {
+            position += 1;
+            continue;
+        };
+        // The last day of the range and the number of words it spans.
+        let range_end = match words.get(position + 1) {
+            Some(&"al" | &"alla" | &"to" | &"a") => words
+                .get(position + 2)
+                .and_then(|word| weekday(word))
+                .map(|last| (last, 3)),
+            Some(word) if lower.contains(&format!("{}-{}", words[position], word)) => {
+                weekday(word).map(|last| (last, 2))
+            }
+            _ => None,
+        };
+        match range_end {
+            Some((last, span)) => {
+                days.extend(day_range(first, last));
+                position += span;
+            }
+            None => {
+                days.push(first);
+                position += 1;
+            }
+        }
+    }
+    if days.is_empty() {
+        return None;
+    }
+    days.sort_by_key(|day| day.num_days_from_monday());
+    days.dedup();
+    Some(Schedule { days, time })
+}
+
+/// Returns when `--watch` runs next, `now` being the end of the run, and why: with
+/// `--interval auto`, shortly after the first expected publication among the
+/// schedules of the shows, those without one being polled every hour.
+pub fn next_poll(
+    schedules: &[Option<&str>],
+    interval: Interval,
+    now: NaiveDateTime,
+) -> (NaiveDateTime, String) {
+    let fallback = |interval: std::time::Duration| {
+        let next = now + Duration::from_std(interval).unwrap_or(Duration::MAX);
+        (
+            next,
+            format!(
+                "every {}",
+                crate::duration::format_clock(interval.as_secs())
+            ),
+        )
+    };
+    if let Interval::Fixed(interval) = interval {
+        return fallback(interval);
+    }
+    schedules
+        .iter()
+        .map(|text| match text.and_then(parse) {
+            Some(schedule) => (schedule.next_after(now) + POLL_DELAY, schedule.to_string()),
+            None => {
+                let (next, every) = fallback(FALLBACK_INTERVAL);
+                (next, format!("no schedule, {}", every))
+            }
+        })
+        .min_by_key(|(next, _)| *next)
+        .unwrap_or_else(|| fallback(FALLBACK_INTERVAL))
+}
+
+impl Schedule {
+    /// Returns the first expected publication after `now`, at the start of the day when
+    /// the schedule gives no time.
+    pub fn next_after(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let time = self.time.unwrap_or(NaiveTime::MIN);
+        (0..=7)
+            .map(|ahead| (now.date() + Duration::days(ahead)).and_time(time))
+            .find(|candidate| *candidate > now && self.days.contains(&candidate.weekday()))
+            .unwrap_or(now + Duration::days(7))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days.len() == 7 {
+            write!(f, "every day")?;
+        } else if self.days == day_range(Weekday::Mon, Weekday::Fri) {
+            write!(f, "weekdays")?;
+        } else {
+            let days: Vec<String> = self.days.iter().map(|day| day.to_string()).collect();
+            write!(f, "{}", days.join(", "))?;
+        }
+        if let Some(time) = self.time {
+            write!(f, " at {}", time.format("%H:%M"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    #[test]
+    fn test_parse() {
+        let weekdays = day_range(Weekday::Mon, Weekday::Fri);
+        assert_eq!(
+            parse("Dal lunedì al venerdì alle 9.00"),
+            Some(Schedule {
+                days: weekdays.clone(),
+                time: at(9, 0)
+            })
+        );
+        assert_eq!(
+            parse("dal lunedi al venerdi dalle 6.00 alle 7.00"),
+            Some(Schedule {
+                days: weekdays.clone(),
+                time: at(6, 0)
+            })
+        );
+        assert_eq!(
+            parse("Lunedì-Venerdì, 14:30"),
+            Some(Schedule {
+                days: weekdays.clone(),
+                time: at(14, 30)
+            })
+        );
+        assert_eq!(
+            parse("Lunedì-Mercoledì, venerdì"),
+            Some(Schedule {
+                days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Fri],
+                time: None
+            })
+        );
+        assert_eq!(
+            parse("Ogni sabato alle 14:00"),
+            Some(Schedule {
+                days: vec![Weekday::Sat],
+                time: at(14, 0)
+            })
+        );
+        assert_eq!(
+            parse("Tutti i sabati ore 21"),
+            Some(Schedule {
+                days: vec![Weekday::Sat],
+                time: at(21, 0)
+            })
+        );
+        assert_eq!(
+            parse("Lunedì, mercoledì e venerdì"),
+            Some(Schedule {
+                days: vec![Weekday::Mon, Weekday::Wed, Weekday::Fri],
+                time: None
+            })
+        );
+        assert_eq!(
+            parse("Dal venerdì alla domenica"),
+            Some(Schedule {
+                days: vec![Weekday::Fri, Weekday::Sat, Weekday::Sun],
+                time: None
+            })
+        );
+        assert_eq!(
+            parse("Tutti i giorni alle 7.05"),
+            Some(Schedule {
+                days: WEEK.to_vec(),
+                time: at(7, 5)
+            })
+        );
+        assert_eq!(parse("daily").map(|schedule| schedule.days.len()), Some(7));
+        assert_eq!(
+            parse("weekdays").map(|schedule| schedule.days),
+            Some(weekdays)
+        );
+        assert_eq!(
+            parse("weekly on Sunday at 8:00"),
+            Some(Schedule {
+                days: vec![Weekday::Sun],
+                time: at(8, 0)
+            })
+        );
+        assert_eq!(parse("Settimanale"), None);
+        assert_eq!(parse("weekly"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Wednesday 15 October 2025.
+        let now = NaiveDate::from_ymd_opt(2025, 10, 15)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let weekdays_at_nine = parse("Dal lunedì al venerdì alle 9.00").unwrap();
+        assert_eq!(
+            weekdays_at_nine.next_after(now).to_string(),
+            "2025-10-16 09:00:00"
+        );
+        let weekdays_at_noon = parse("Dal lunedì al venerdì alle 12.00").unwrap();
+        assert_eq!(
+            weekdays_at_noon.next_after(now).to_string(),
+            "2025-10-15 12:00:00"
+        );
+        let saturdays = parse("Ogni sabato").unwrap();
+        assert_eq!(saturdays.next_after(now).to_string(), "2025-10-18 00:00:00");
+        let wednesdays = parse("il mercoledì alle 10:00").unwrap();
+        assert_eq!(
+            wednesdays.next_after(now).to_string(),
+            "2025-10-22 10:00:00"
+        );
+    }
+
+    #[test]
+    fn test_next_poll() {
+        let now = NaiveDate::from_ymd_opt(2025, 10, 15)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let poll = |schedules: &[Option<&str>], interval| {
+            let (next, reason) = next_poll(schedules, interval, now);
+            (next.to_string(), reason)
+        };
+        assert_eq!(
+            poll(
+                &[
+                    Some("Ogni sabato alle 14:00"),
+                    Some("Tutti i giorni alle 12.00")
+                ],
+                Interval::Auto
+            ),
+            (
+                "2025-10-15 12:30:00".to_string(),
+                "every day at 12:00".to_string()
+            )
+        );
+        assert_eq!(
+            poll(&[Some("Ogni sabato alle 14:00"), None], Interval::Auto),
+            (
+                "2025-10-15 11:00:00".to_string(),
+                "no schedule, every 01:00:00".to_string()
+            )
+        );
+        // A manual interval wins over the schedules.
+        assert_eq!(
+            poll(
+                &[Some("Tutti i giorni alle 10.05")],
+                Interval::Fixed(std::time::Duration::from_secs(4 * 3600))
+            ),
+            (
+                "2025-10-15 14:00:00".to_string(),
+                "every 04:00:00".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            parse("Dal lunedì al venerdì alle 9.00")
+                .unwrap()
+                .to_string(),
+            "weekdays at 09:00"
+        );
+        assert_eq!(parse("Ogni giorno").unwrap().to_string(), "every day");
+        assert_eq!(
+            parse("Lunedì e giovedì alle 20:00").unwrap().to_string(),
+            "Mon, Thu at 20:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("auto").unwrap(), Interval::Auto);
+        assert_eq!(
+            parse_interval("30m").unwrap(),
+            Interval::Fixed(std::time::Duration::from_secs(1800))
+        );
+        assert!(parse_interval("soon").is_err());
+    }
+}
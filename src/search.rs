@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Path of the RaiPlaySound search endpoint on the site.
+const SEARCH_PATH: &str = "/atomatic/raiplay-search-service/api/v1/msearch";
+
+/// Age after which cached results are searched again, so that new shows turn up.
+pub const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A show found by the search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchResult {
+    pub title: String,
+    /// What the show is, e.g. "audiobook" or "programme".
+    pub kind: String,
+    /// Number of episodes, when the result carries it.
+    pub episodes: Option<u64>,
+    /// The show URL, as given to `--url`.
+    pub url: String,
+}
+
+/// Returns the URL of the search for `query`, along with the name of its cache file.
+pub fn search_url(query: &str) -> Result<(String, String)> {
+    let url = Url::parse_with_params(&crate::site::absolute_url(SEARCH_PATH), [("q", query)])
+        .with_context(|| format!("Invalid search query: {}", query))?;
+    let slug: String = query
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    Ok((url.to_string(), format!("search_{}.json", slug)))
+}
+
+/// Returns the kind of show from the first segment of its path.
+fn kind_of(path: &str) -> Option<&'static str> {
+    match path.trim_start_matches('/').split('/').next()? {
+        "audiolibri" => Some("audiobook"),
+        "programmi" => Some("programme"),
+        "podcast" => Some("podcast"),
+        "playlist" => Some("playlist"),
+        _ => None,
+    }
+}
+
+/// Reads an episode count from the fields results are known to carry it in.
+fn episode_count(map: &serde_json::Map<String, Value>) -> Option<u64> {
+    ["episodes_count", "episode_count", "count", "numero_episodi"]
+        .iter()
+        .filter_map(|key| map.get(*key))
+        .find_map(|value| {
+            value
+                .as_u64()
+                .or_else(|| value.as_str()?.trim().parse().ok())
+        })
+}
+
+/// Collects the shows found anywhere in the JSON value, in order.
+///
+/// A show is an object with a title whose `weblink` or `path_id` points to a programme,
+/// audiobook, podcast or playlist page.
+fn collect_results(value: &Value, results: &mut Vec<SearchResult>) {
+    match value {
+        Value::Object(map) => {
+            let path = ["weblink", "path_id"]
+                .iter()
+                .filter_map(|key| map.get(*key).and_then(Value::as_str))
+                .find(|path| kind_of(path).is_some());
+            let title = map.get("title").and_then(Value::as_str).map(str::trim);
+            if let (Some(path), Some(title)) = (path, title.filter(|title| !title.is_empty())) {
+                let page = path.trim_end_matches(".json").trim_end_matches(".html");
+                results.push(SearchResult {
+                    title: title.to_string(),
+                    kind: kind_of(path).unwrap_or_default().to_string(),
+                    episodes: episode_count(map),
+                    url: crate::site::absolute_url(page),
+                });
+                return;
+            }
+            for child in map.values() {
+                collect_results(child, results);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_results(item, results);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses the search response into the shows it lists, without duplicates.
+pub fn parse_results(json: &str) -> Result<Vec<SearchResult>> {
+    let value: Value = serde_json::from_str(json).context("Failed to parse search results")?;
+    let mut results = Vec::new();
+    collect_results(&value, &mut results);
+    let mut seen = HashSet::new();
+    results.retain(|result| seen.insert(result.url.clone()));
+    Ok(results)
+}
+
+/// Renders the results as a numbered list, each with the URL to pass to `--url`.
+pub fn render_results(results: &[SearchResult]) -> String {
+    let mut text = String::new();
+    for (position, result) in results.iter().enumerate() {
+        let episodes = result
+            .episodes
+            .map_or_else(String::new, |count| format!(", {} episodes", count));
+        text.push_str(&format!(
+            "{:>2}. {} ({}{})\n    {}\n",
+            position + 1,
+            result.title,
+            result.kind,
+            episodes,
+            result.url
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &str = r#"{
+        "agg": {"titoli": {"cards": [
+            {"title": "I tre moschettieri", "weblink": "/audiolibri/itremoschettieri", "episodes_count": 45},
+            {"title": "Lettura I", "weblink": "/audio/2015/06/lettura-1.html"},
+            {"title": "Moschettieri a Radio2", "path_id": "/programmi/moschettieri.json", "count": "12"},
+            {"title": "I tre moschettieri", "weblink": "/audiolibri/itremoschettieri"}
+        ]}}
+    }"#;
+
+    #[test]
+    fn test_parse_results() {
+        let results = parse_results(RESPONSE).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                SearchResult {
+                    title: "I tre moschettieri".to_string(),
+                    kind: "audiobook".to_string(),
+                    episodes: Some(45),
+                    url: "https://www.raiplaysound.it/audiolibri/itremoschettieri".to_string(),
+                },
+                SearchResult {
+                    title: "Moschettieri a Radio2".to_string(),
+                    kind: "programme".to_string(),
+                    episodes: Some(12),
+                    url: "https://www.raiplaysound.it/programmi/moschettieri".to_string(),
+                },
+            ]
+        );
+        assert!(parse_results("<html>").is_err());
+    }
+
+    #[test]
+    fn test_search_url() {
+        let (url, cache_name) = search_url("tre moschettieri").unwrap();
+        assert!(url.ends_with("/msearch?q=tre+moschettieri"));
+        assert_eq!(cache_name, "search_tre-moschettieri.json");
+    }
+
+    #[test]
+    fn test_render_results() {
+        let results = parse_results(RESPONSE).unwrap();
+        assert_eq!(
+            render_results(&results),
+            " 1. I tre moschettieri (audiobook, 45 episodes)\n    https://www.raiplaysound.it/audiolibri/itremoschettieri\n 2. Moschettieri a Radio2 (programme, 12 episodes)\n    https://www.raiplaysound.it/programmi/moschettieri\n"
+        );
+    }
+}
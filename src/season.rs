@@ -0,0 +1,124 @@
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Season given to episodes of shows that are not split into seasons.
+pub const DEFAULT_SEASON: usize = 1;
+
+/// Reads the season number from a block title like "Stagione 2", "2ª stagione" or
+/// "Season 2".
+pub fn season_number(label: &str) -> Option<usize> {
+    let re = Regex::new(
+        r"(?i)\b(?:stagione|season|serie)\s*(\d{1,3})\b|\b(\d{1,3})\s*[ªa°]?\s+stagione\b",
+    )
+    .expect("Invalid regex");
+    let captures = re.captures(label)?;
+    captures
+        .get(1)
+        .or_else(|| captures.get(2))?
+        .as_str()
+        .parse()
+        .ok()
+        .filter(|season| *season > 0)
+}
+
+/// Reads a positive number from a JSON number or numeric string.
+fn number(value: &Value) -> Option<usize> {
+    value
+        .as_u64()
+        .map(|number| number as usize)
+        .or_else(|| value.as_str()?.trim().parse().ok())
+        .filter(|number| *number > 0)
+}
+
+/// Returns the season and episode numbers found in an episode JSON, if any.
+pub fn from_metadata(json: &Value) -> (Option<usize>, Option<usize>) {
+    let season = [
+        &json["season"],
+        &json["season_number"],
+        &json["track_info"]["season"],
+        &json["audio"]["season"],
+    ]
+    .into_iter()
+    .find_map(number);
+    let episode = [
+        &json["episode"],
+        &json["episode_number"],
+        &json["track_info"]["episode"],
+        &json["audio"]["episode"],
+    ]
+    .into_iter()
+    .find_map(number);
+    (season, episode)
+}
+
+/// Numbers the episodes within their season, given in processing order as their season
+/// and episode number when known.
+///
+/// Episodes without a season go to `DEFAULT_SEASON`. Known episode numbers are kept,
+/// the others follow the previous episode of the same season.
+pub fn number_episodes(episodes: &[(Option<usize>, Option<usize>)]) -> Vec<(usize, usize)> {
+    let mut last: HashMap<usize, usize> = HashMap::new();
+    episodes
+        .iter()
+        .map(|(season, episode)| {
+            let season = season.unwrap_or(DEFAULT_SEASON);
+            let counter = last.entry(season).or_default();
+            *counter = episode.unwrap_or(*counter + 1);
+            (season, *counter)
+        })
+        .collect()
+}
+
+/// Formats the season and episode as `S02E05`.
+pub fn code(season: usize, episode: usize) -> String {
+    format!("S{:02}E{:02}", season, episode)
+}
+
+/// Name of the folder holding the episodes of a season.
+pub fn dir_name(season: usize) -> String {
+    format!("Stagione {:02}", season)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_season_number() {
+        assert_eq!(season_number("Stagione 2"), Some(2));
+        assert_eq!(season_number("Il conte - season 12"), Some(12));
+        assert_eq!(season_number("3ª stagione"), Some(3));
+        assert_eq!(season_number("Stagione 0"), None);
+        assert_eq!(season_number("Puntate 3-4"), None);
+        assert_eq!(season_number("Stagionale 2"), None);
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        assert_eq!(
+            from_metadata(&json!({"season": "2", "episode": 5})),
+            (Some(2), Some(5))
+        );
+        assert_eq!(
+            from_metadata(&json!({"track_info": {"season": 3}})),
+            (Some(3), None)
+        );
+        assert_eq!(from_metadata(&json!({"season": ""})), (None, None));
+    }
+
+    #[test]
+    fn test_number_episodes() {
+        let numbered = number_episodes(&[
+            (None, None),
+            (Some(2), None),
+            (None, None),
+            (Some(2), Some(7)),
+            (Some(2), None),
+        ]);
+        assert_eq!(numbered, vec![(1, 1), (2, 1), (1, 2), (2, 7), (2, 8)]);
+        assert_eq!(code(2, 5), "S02E05");
+        assert_eq!(dir_name(2), "Stagione 02");
+    }
+}
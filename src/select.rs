@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Context, Result};
+use std::str::FromStr;
+
+/// An inclusive range of 1-based episode indices; a missing end means "up to the last episode".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct IndexRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+/// A set of episode indices parsed from a string like `1,3,12-15,40-`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    ranges: Vec<IndexRange>,
+}
+
+impl Selection {
+    /// Returns whether the 1-based index is part of the selection.
+    pub fn contains(&self, idx: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| idx >= range.start && range.end.is_none_or(|end| idx <= end))
+    }
+
+    /// Returns the parts of the selection that fall outside `1..=total`, formatted as given.
+    pub fn out_of_bounds(&self, total: usize) -> Vec<String> {
+        self.ranges
+            .iter()
+            .filter(|range| range.start > total || range.end.is_some_and(|end| end > total))
+            .map(|range| match range.end {
+                Some(end) if end == range.start => format!("{}", end),
+                Some(end) => format!("{}-{}", range.start, end),
+                None => format!("{}-", range.start),
+            })
+            .collect()
+    }
+}
+
+/// Parses a 1-based index, rejecting zero.
+fn parse_index(text: &str) -> Result<usize> {
+    let idx: usize = text
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid episode index: {:?}", text.trim()))?;
+    if idx == 0 {
+        return Err(anyhow!("Episode indices start at 1"));
+    }
+    Ok(idx)
+}
+
+impl FromStr for Selection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let range = match part.split_once('-') {
+                Some((start, end)) => {
+                    let start = if start.trim().is_empty() {
+                        1
+                    } else {
+                        parse_index(start)?
+                    };
+                    let end = if end.trim().is_empty() {
+                        None
+                    } else {
+                        Some(parse_index(end)?)
+                    };
+                    if end.is_some_and(|end| end < start) {
+                        return Err(anyhow!("Invalid range {:?}: end is before start", part));
+                    }
+                    IndexRange { start, end }
+                }
+                None => {
+                    let idx = parse_index(part)?;
+                    IndexRange {
+                        start: idx,
+                        end: Some(idx),
+                    }
+                }
+            };
+            ranges.push(range);
+        }
+        if ranges.is_empty() {
+            return Err(anyhow!("Empty episode selection"));
+        }
+        Ok(Selection { ranges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(selection: &str, total: usize) -> Vec<usize> {
+        let selection: Selection = selection.parse().unwrap();
+        (1..=total).filter(|idx| selection.contains(*idx)).collect()
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(
+            selected("1,3,12-15,40-", 42),
+            vec![1, 3, 12, 13, 14, 15, 40, 41, 42]
+        );
+        assert_eq!(selected("-3", 10), vec![1, 2, 3]);
+        assert_eq!(selected(" 2 , 4 - 5 ", 10), vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn test_parse_selection_overlapping() {
+        assert_eq!(selected("1-5,3-7,5", 10), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(selected("8-,9-", 10), vec![8, 9, 10]);
+    }
+
+    #[test]
+    fn test_parse_selection_invalid() {
+        assert!("".parse::<Selection>().is_err());
+        assert!("0".parse::<Selection>().is_err());
+        assert!("5-3".parse::<Selection>().is_err());
+        assert!("a-b".parse::<Selection>().is_err());
+        assert!("1,,x".parse::<Selection>().is_err());
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let selection: Selection = "1,3-5,8,12-,20-30".parse().unwrap();
+        assert_eq!(selection.out_of_bounds(10), vec!["12-", "20-30"]);
+        assert_eq!(selected("1,3-5,8,12-,20-30", 10), vec![1, 3, 4, 5, 8]);
+        let selection: Selection = "3-12".parse().unwrap();
+        assert_eq!(selection.out_of_bounds(10), vec!["3-12"]);
+    }
+}
@@ -0,0 +1,186 @@
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+/// Site names RAI appends to the titles of its pages.
+const SITE_SUFFIXES: [&str; 3] = [" - RaiPlay Sound", " | RaiPlay Sound", " - RaiPlaySound"];
+
+/// Returns the title of the programme on its page: the `og:title` meta tag, or the
+/// page header.
+pub fn page_title(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let meta = Selector::parse(r#"meta[property="og:title"]"#).expect("Invalid selector");
+    let header = Selector::parse("h1").expect("Invalid selector");
+    let og_title = document
+        .select(&meta)
+        .filter_map(|element| element.value().attr("content"))
+        .map(str::to_string)
+        .next();
+    og_title
+        .or_else(|| {
+            document
+                .select(&header)
+                .map(|element| element.text().collect::<String>())
+                .next()
+        })
+        .map(|title| strip_site(&title))
+        .filter(|title| !title.is_empty())
+}
+
+/// Returns the title of the programme in its JSON API.
+pub fn json_title(json: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    let title = value["title"].as_str()?.trim();
+    (!title.is_empty()).then(|| strip_site(title))
+}
+
+/// Returns the image of the programme on its page: the `og:image` meta tag.
+pub fn page_image(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let meta = Selector::parse(r#"meta[property="og:image"]"#).expect("Invalid selector");
+    document
+        .select(&meta)
+        .filter_map(|element| element.value().attr("content"))
+        .map(str::trim)
+        .find(|image| !image.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the image of the programme in its JSON API, as the site gives it.
+pub fn json_image(json: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    first_image(&value)
+}
+
+fn first_image(value: &Value) -> Option<String> {
+    first_text(&[
+        &value["image"],
+        &value["images"]["landscape"],
+        &value["images"]["square"],
+        &value["podcast_info"]["image"],
+    ])
+}
+
+/// Returns the first of the fields that holds some text.
+fn first_text(fields: &[&Value]) -> Option<String> {
+    fields
+        .iter()
+        .filter_map(|field| field.as_str())
+        .map(str::trim)
+        .find(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the description of the programme on its page: the `og:description` or
+/// `description` meta tag.
+pub fn page_description(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let meta = Selector::parse(r#"meta[property="og:description"], meta[name="description"]"#)
+        .expect("Invalid selector");
+    document
+        .select(&meta)
+        .filter_map(|element| element.value().attr("content"))
+        .map(str::trim)
+        .find(|description| !description.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the description of the programme in its JSON API.
+pub fn json_description(json: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    first_text(&[
+        &value["description"],
+        &value["podcast_info"]["description"],
+        &value["program_info"]["description"],
+    ])
+}
+
+/// Returns the publication schedule of the programme in its JSON API, like `Dal lunedì
+/// al venerdì alle 9.00`, when it gives one.
+pub fn json_schedule(json: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    first_text(&[
+        &value["schedule"],
+        &value["podcast_info"]["schedule"],
+        &value["program_info"]["schedule"],
+        &value["podcast_info"]["orario"],
+        &value["program_info"]["orario"],
+    ])
+}
+
+/// Returns the last segment of the show URL, e.g. `itremoschettieri`.
+pub fn url_slug(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    url.path_segments()?
+        .rfind(|segment| !segment.is_empty())
+        .map(str::to_string)
+}
+
+fn strip_site(title: &str) -> String {
+    let title = title.trim();
+    SITE_SUFFIXES
+        .iter()
+        .find_map(|suffix| title.strip_suffix(suffix))
+        .unwrap_or(title)
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_title() {
+        let html = include_str!("../tests/fixtures/itremoschettieri.html");
+        assert_eq!(page_title(html).as_deref(), Some("I tre moschettieri"));
+        let og = r#"<meta property="og:title" content="Il conte - RaiPlay Sound"><h1>Other</h1>"#;
+        assert_eq!(page_title(og).as_deref(), Some("Il conte"));
+        assert_eq!(page_title("<p>No title</p>"), None);
+    }
+
+    #[test]
+    fn test_json_title() {
+        let json = include_str!("../tests/fixtures/itremoschettieri.json");
+        assert_eq!(json_title(json).as_deref(), Some("I tre moschettieri"));
+        assert_eq!(json_title(r#"{"title": " "}"#), None);
+    }
+
+    #[test]
+    fn test_show_image() {
+        let og = r#"<meta property="og:image" content="/cropgd/1200x1200/moschettieri.jpg">"#;
+        assert_eq!(
+            page_image(og).as_deref(),
+            Some("/cropgd/1200x1200/moschettieri.jpg")
+        );
+        assert_eq!(page_image("<p>No image</p>"), None);
+        let json = r#"{"title": "Il conte", "images": {"square": "/img/ilconte.jpg"}}"#;
+        assert_eq!(json_image(json).as_deref(), Some("/img/ilconte.jpg"));
+        assert_eq!(json_image(r#"{"image": ""}"#), None);
+    }
+
+    #[test]
+    fn test_show_description() {
+        let html = r#"<meta name="description" content=" Il romanzo di Dumas. "><meta property="og:description" content="">"#;
+        assert_eq!(
+            page_description(html).as_deref(),
+            Some("Il romanzo di Dumas.")
+        );
+        assert_eq!(page_description("<p>No description</p>"), None);
+        let json = r#"{"podcast_info": {"description": "Letto da Paolo Poli"}}"#;
+        assert_eq!(
+            json_description(json).as_deref(),
+            Some("Letto da Paolo Poli")
+        );
+        assert_eq!(json_description(r#"{"description": " "}"#), None);
+    }
+
+    #[test]
+    fn test_url_slug() {
+        assert_eq!(
+            url_slug("https://www.raiplaysound.it/audiolibri/itremoschettieri/").as_deref(),
+            Some("itremoschettieri")
+        );
+        assert_eq!(url_slug("https://www.raiplaysound.it/"), None);
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Name of the file describing the show in its folder.
+pub const SHOW_FILE: &str = "show.json";
+
+/// Subfolder the files of episodes no longer listed are moved to with `--prune-removed`.
+pub const REMOVED_DIR: &str = "removed";
+
+/// Version of the `show.json` schema, raised when a field changes meaning or goes away.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The show a folder was downloaded from, as of the last run that completed.
+///
+/// Like `.info.json` sidecars, unknown fields are written as `null`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShowFile {
+    pub schema: u32,
+    pub title: Option<String>,
+    /// Last segment of the show URL, e.g. `itremoschettieri`.
+    pub slug: Option<String>,
+    pub url: String,
+    pub description: Option<String>,
+    /// Absolute URL of the show image.
+    pub image: Option<String>,
+    /// Number of episodes the show listed.
+    pub episodes: usize,
+    /// Version of rsnd that wrote the file.
+    pub rsnd_version: String,
+    pub synced_at: DateTime<Utc>,
+    /// The episodes the show listed, in page order.
+    #[serde(default)]
+    pub listed: Vec<ListedEpisode>,
+    /// Episodes listed by an earlier run that the show no longer lists.
+    #[serde(default)]
+    pub removed: Vec<RemovedEpisode>,
+}
+
+/// An episode listed by the show.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListedEpisode {
+    /// Path of the episode JSON, relative to the site, which identifies the episode.
+    pub metadata_path: String,
+    pub title: Option<String>,
+    /// The audio file of the episode, relative to the folder, when it was there.
+    pub file: Option<PathBuf>,
+}
+
+/// An episode the show stopped listing, whose file may be the only copy left.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemovedEpisode {
+    #[serde(flatten)]
+    pub episode: ListedEpisode,
+    /// When a run first found it missing from the show.
+    pub removed_at: DateTime<Utc>,
+}
+
+/// Returns the episodes of the last run that the show no longer lists, with their
+/// 1-based position in that run.
+pub fn removed_since<'a>(
+    previous: &'a ShowFile,
+    listed: &[ListedEpisode],
+) -> Vec<(usize, &'a ListedEpisode)> {
+    let current: HashSet<&str> = listed
+        .iter()
+        .map(|episode| episode.metadata_path.as_str())
+        .collect();
+    previous
+        .listed
+        .iter()
+        .enumerate()
+        .filter(|(_, episode)| !current.contains(episode.metadata_path.as_str()))
+        .map(|(position, episode)| (position + 1, episode))
+        .collect()
+}
+
+/// Moves the audio file of a removed episode, with the sidecars named like it, to the
+/// `removed` subfolder of the folder. Returns its new path, relative to the folder.
+pub fn move_to_removed(folder: &Path, file: &Path) -> Result<PathBuf> {
+    let source = folder.join(file);
+    let (Some(dir), Some(stem), Some(name)) =
+        (source.parent(), source.file_stem(), source.file_name())
+    else {
+        return Err(anyhow::anyhow!("Not a file name: {}", source.display()));
+    };
+    let removed = folder.join(REMOVED_DIR);
+    std::fs::create_dir_all(&removed)
+        .with_context(|| format!("Failed to create folder: {}", removed.display()))?;
+    let prefix = format!("{}.", stem.to_string_lossy());
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read folder: {}", dir.display()))?
+    {
+        let entry = entry?;
+        let entry_name = entry.file_name();
+        if entry_name.to_string_lossy().starts_with(&prefix) && entry.path().is_file() {
+            let target = removed.join(&entry_name);
+            std::fs::rename(entry.path(), &target).with_context(|| {
+                format!(
+                    "Failed to move {} to {}",
+                    entry.path().display(),
+                    target.display()
+                )
+            })?;
+        }
+    }
+    Ok(Path::new(REMOVED_DIR).join(name))
+}
+
+/// Reads the show file of the folder, if it has a readable one.
+pub fn read(folder: &Path) -> Option<ShowFile> {
+    let json = std::fs::read_to_string(folder.join(SHOW_FILE)).ok()?;
+    match serde_json::from_str(&json) {
+        Ok(show) => Some(show),
+        Err(err) => {
+            log::debug!("Ignoring unreadable {}: {}", SHOW_FILE, err);
+            None
+        }
+    }
+}
+
+/// Writes the show file of the folder through a temporary file, so that it is never
+/// left half written.
+pub fn write(folder: &Path, show: &ShowFile) -> Result<PathBuf> {
+    let path = folder.join(SHOW_FILE);
+    let json = serde_json::to_string_pretty(show)?;
+    crate::atomic::write(&path, (json + "\n").as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        assert_eq!(read(folder.path()), None);
+        let show = ShowFile {
+            schema: SCHEMA_VERSION,
+            title: Some("I tre moschettieri".to_string()),
+            slug: Some("itremoschettieri".to_string()),
+            url: "https://www.raiplaysound.it/audiolibri/itremoschettieri".to_string(),
+            description: None,
+            image: None,
+            episodes: 3,
+            rsnd_version: "0.1.0".to_string(),
+            synced_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            listed: Vec::new(),
+            removed: Vec::new(),
+        };
+        let path = write(folder.path(), &show)?;
+        assert_eq!(path, folder.path().join("show.json"));
+        assert!(!folder.path().join("show.json.part").exists());
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        assert_eq!(written["description"], serde_json::Value::Null);
+        assert_eq!(written["synced_at"], "2023-11-14T22:13:20Z");
+        assert_eq!(read(folder.path()), Some(show));
+
+        std::fs::write(folder.path().join("show.json"), "{")?;
+        assert_eq!(read(folder.path()), None);
+        Ok(())
+    }
+
+    fn listed(metadata_path: &str, file: Option<&str>) -> ListedEpisode {
+        ListedEpisode {
+            metadata_path: metadata_path.to_string(),
+            title: None,
+            file: file.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn test_removed_since() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        // Files written before the episodes were tracked have no list.
+        std::fs::write(
+            folder.path().join("show.json"),
+            r#"{"schema": 1, "title": null, "slug": null, "url": "https://www.raiplaysound.it/audiolibri/itremoschettieri", "description": null, "image": null, "episodes": 2, "rsnd_version": "0.1.0", "synced_at": "2023-11-14T22:13:20Z"}"#,
+        )?;
+        let mut previous = read(folder.path()).unwrap();
+        assert!(removed_since(&previous, &[]).is_empty());
+
+        previous.listed = vec![
+            listed("/audio/lettura-1.json", Some("01 - lettura i.mp3")),
+            listed("/audio/lettura-2.json", None),
+            listed("/audio/lettura-3.json", Some("03 - lettura iii.mp3")),
+        ];
+        let now = [
+            listed("/audio/lettura-4.json", None),
+            listed("/audio/lettura-2.json", None),
+        ];
+        let removed = removed_since(&previous, &now);
+        assert_eq!(
+            removed,
+            vec![(1, &previous.listed[0]), (3, &previous.listed[2])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_to_removed() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        for name in [
+            "01 - lettura i.mp3",
+            "01 - lettura i.info.json",
+            "01 - lettura i.jpg",
+            "01 - lettura ii.mp3",
+        ] {
+            std::fs::write(folder.path().join(name), b"")?;
+        }
+        let moved = move_to_removed(folder.path(), Path::new("01 - lettura i.mp3"))?;
+        assert_eq!(moved, Path::new("removed/01 - lettura i.mp3"));
+        let removed = folder.path().join("removed");
+        assert!(removed.join("01 - lettura i.mp3").exists());
+        assert!(removed.join("01 - lettura i.info.json").exists());
+        assert!(removed.join("01 - lettura i.jpg").exists());
+        assert!(!folder.path().join("01 - lettura i.mp3").exists());
+        assert!(folder.path().join("01 - lettura ii.mp3").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use reqwest::Url;
+use std::sync::RwLock;
+
+/// The site metadata paths and relative links resolve against without `--base-url`.
+pub const DEFAULT_BASE_URL: &str = "https://www.raiplaysound.it";
+
+/// The base URL installed for the run by `--base-url`, if any.
+static BASE_URL: RwLock<Option<String>> = RwLock::new(None);
+
+/// Installs the base URL every relative path of the run resolves against, in place of
+/// the one of an earlier run of `--watch`.
+pub fn install(base_url: String) {
+    *BASE_URL.write().unwrap_or_else(|err| err.into_inner()) = Some(base_url);
+}
+
+/// Returns the base URL of the run, without a trailing slash.
+pub fn base_url() -> String {
+    BASE_URL
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_deref()
+        .unwrap_or(DEFAULT_BASE_URL)
+        .to_string()
+}
+
+/// Makes a path of the site absolute, leaving URLs that already are untouched.
+pub fn absolute_url(link: &str) -> String {
+    resolve(&base_url(), link)
+}
+
+fn resolve(base_url: &str, link: &str) -> String {
+    if link.starts_with('/') && !link.starts_with("//") {
+        format!("{}{}", base_url, link)
+    } else {
+        link.to_string()
+    }
+}
+
+/// Parses the `--base-url` value, an http or https URL without query or fragment, which
+/// may have a path for mirrors that serve the site under a prefix.
+pub fn parse_base_url(value: &str) -> Result<String, String> {
+    let url = Url::parse(value).map_err(|err| format!("invalid URL {:?}: {}", value, err))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!("expected an http or https URL, got {:?}", value));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(format!(
+            "expected a URL without query or fragment, got {:?}",
+            value
+        ));
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let path = "/audio/2015/06/lettura-1.json";
+        assert_eq!(
+            resolve(DEFAULT_BASE_URL, path),
+            "https://www.raiplaysound.it/audio/2015/06/lettura-1.json"
+        );
+        assert_eq!(
+            resolve("http://127.0.0.1:8080/rai", path),
+            "http://127.0.0.1:8080/rai/audio/2015/06/lettura-1.json"
+        );
+        // Paths that are already absolute URLs are not prefixed again.
+        let url = "https://www.raiplaysound.it/audio/2015/06/lettura-1.json";
+        assert_eq!(resolve("http://127.0.0.1:8080", url), url);
+        assert_eq!(
+            resolve(DEFAULT_BASE_URL, "//cdn.example.com/a.jpg"),
+            "//cdn.example.com/a.jpg"
+        );
+    }
+
+    #[test]
+    fn test_parse_base_url() {
+        assert_eq!(
+            parse_base_url("http://127.0.0.1:8080/").as_deref(),
+            Ok("http://127.0.0.1:8080")
+        );
+        assert_eq!(
+            parse_base_url("https://mirror.example.com/rai/").as_deref(),
+            Ok("https://mirror.example.com/rai")
+        );
+        assert!(parse_base_url("www.raiplaysound.it").is_err());
+        assert!(parse_base_url("ftp://mirror.example.com").is_err());
+        assert!(parse_base_url("https://mirror.example.com/?a=1").is_err());
+    }
+}
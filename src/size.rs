@@ -0,0 +1,118 @@
+use crate::format_size;
+use anyhow::{anyhow, Context, Result};
+
+/// The sizes `--min-filesize` and `--max-filesize` allow to download.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    /// Skip downloads whose size is not advertised, instead of downloading them.
+    pub strict: bool,
+}
+
+impl Bounds {
+    /// Returns whether any bound is set, so that sizes need to be probed.
+    pub fn is_set(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+
+    /// Returns why a download of the given size, if known, is skipped.
+    pub fn check(&self, size: Option<u64>) -> Option<String> {
+        if !self.is_set() {
+            return None;
+        }
+        let Some(size) = size else {
+            return self
+                .strict
+                .then(|| "size unknown (--strict-size)".to_string());
+        };
+        if let Some(max) = self.max.filter(|max| size > *max) {
+            return Some(format!(
+                "{} is larger than --max-filesize {}",
+                format_size(size),
+                format_size(max)
+            ));
+        }
+        if let Some(min) = self.min.filter(|min| size < *min) {
+            return Some(format!(
+                "{} is smaller than --min-filesize {}",
+                format_size(size),
+                format_size(min)
+            ));
+        }
+        None
+    }
+}
+
+/// Parses a size in bytes, optionally with a binary unit suffix: `500000`, `300K`,
+/// `1.5M`, `2G` (case-insensitive, a trailing `B` is allowed).
+pub fn parse_size(text: &str) -> Result<u64> {
+    let trimmed = text.trim();
+    let upper = trimmed.to_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, multiplier) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1024u64),
+        Some('M') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('G') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid size: {:?}", trimmed))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(anyhow!("Invalid size: {:?}", trimmed));
+    }
+    Ok((value * multiplier as f64).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("500000").unwrap(), 500_000);
+        assert_eq!(parse_size("300K").unwrap(), 300 * 1024);
+        assert_eq!(parse_size("300kb").unwrap(), 300 * 1024);
+        assert_eq!(parse_size("1.5M").unwrap(), 1_572_864);
+        assert_eq!(parse_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("-1K").is_err());
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn test_bounds() {
+        let bounds = Bounds {
+            min: Some(1024),
+            max: Some(1024 * 1024),
+            strict: false,
+        };
+        assert_eq!(bounds.check(Some(4096)), None);
+        assert_eq!(
+            bounds.check(Some(2 * 1024 * 1024)).as_deref(),
+            Some("2.0 MB is larger than --max-filesize 1.0 MB")
+        );
+        assert_eq!(
+            bounds.check(Some(512)).as_deref(),
+            Some("512 B is smaller than --min-filesize 1.0 KB")
+        );
+        assert_eq!(bounds.check(None), None);
+        let strict = Bounds {
+            strict: true,
+            ..bounds
+        };
+        assert_eq!(
+            strict.check(None).as_deref(),
+            Some("size unknown (--strict-size)")
+        );
+        // Without bounds, --strict-size alone skips nothing.
+        let unbounded = Bounds {
+            strict: true,
+            ..Bounds::default()
+        };
+        assert_eq!(unbounded.check(None), None);
+    }
+}
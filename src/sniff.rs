@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+
+/// Prefixes some endpoints send before their JSON, so that it cannot be run as a script.
+const XSSI_PREFIXES: [&str; 3] = [")]}'", "while(1);", "for(;;);"];
+
+/// Number of characters of an HTML page quoted when it was expected to be JSON.
+const SNIPPET_LEN: usize = 200;
+
+/// Describes what the body looks like when it is a text document rather than audio.
+fn text_kind(content_type: Option<&str>, head: &[u8]) -> Option<&'static str> {
+    let mime = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().to_lowercase());
+    match mime.as_deref() {
+        Some("text/html") | Some("application/xhtml+xml") => return Some("HTML"),
+        Some("application/json") => return Some("JSON"),
+        Some("text/xml") | Some("application/xml") => return Some("XML"),
+        _ => {}
+    }
+
+    let start = head
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .map_or(&[][..], |position| &head[position..]);
+    let lower: Vec<u8> = start.iter().take(16).map(u8::to_ascii_lowercase).collect();
+    if lower.starts_with(b"<!doctype html") || lower.starts_with(b"<html") {
+        Some("HTML")
+    } else if lower.starts_with(b"<?xml") || lower.starts_with(b"<") {
+        Some("XML")
+    } else if lower.starts_with(b"{") || lower.starts_with(b"[") {
+        Some("JSON")
+    } else {
+        None
+    }
+}
+
+/// Returns whether the first bytes match a known audio container.
+pub fn is_audio(head: &[u8]) -> bool {
+    head.starts_with(b"ID3")
+        // MPEG audio and ADTS AAC frame sync.
+        || (head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE0 == 0xE0)
+        || (head.len() >= 8 && &head[4..8] == b"ftyp")
+        // MPEG-TS packets start with a sync byte.
+        || head.first() == Some(&0x47)
+}
+
+/// Checks that the body is audio before it is written, failing with a description of
+/// what was received instead.
+pub fn check_audio(content_type: Option<&str>, head: &[u8]) -> Result<()> {
+    if is_audio(head) {
+        return Ok(());
+    }
+    if let Some(kind) = text_kind(content_type, head) {
+        let preview: String = String::from_utf8_lossy(&head[..head.len().min(60)])
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        return Err(anyhow!(
+            "Received {} instead of audio ({}): {:?}",
+            kind,
+            content_type.unwrap_or("no content type"),
+            preview
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the JSON in the body, without the byte order mark and anti-XSSI prefix some
+/// endpoints send before it.
+pub fn json_text(body: &str) -> &str {
+    let body = body.trim_start_matches('\u{feff}').trim_start();
+    XSSI_PREFIXES
+        .iter()
+        .find_map(|prefix| body.strip_prefix(prefix))
+        .map_or(body, |rest| {
+            rest.strip_prefix(',').unwrap_or(rest).trim_start()
+        })
+}
+
+/// Returns the start of the body, on one line, when it is markup rather than JSON, like
+/// the HTML status pages served in place of the metadata.
+pub fn html_snippet(body: &str) -> Option<String> {
+    let body = json_text(body);
+    if !body.starts_with('<') {
+        return None;
+    }
+    let snippet: String = body.chars().take(SNIPPET_LEN).collect();
+    Some(snippet.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_magic() {
+        assert!(check_audio(Some("audio/mpeg"), b"ID3\x04\x00\x00").is_ok());
+        assert!(check_audio(None, &[0xFF, 0xFB, 0x90, 0x64]).is_ok());
+        assert!(check_audio(None, b"\x00\x00\x00\x20ftypM4A ").is_ok());
+        assert!(check_audio(None, &[0x47, 0x40, 0x00, 0x10]).is_ok());
+        // Unknown binary data is given the benefit of the doubt.
+        assert!(check_audio(Some("application/octet-stream"), &[0x00, 0x01]).is_ok());
+    }
+
+    #[test]
+    fn test_text_bodies() {
+        let err = check_audio(
+            Some("text/html; charset=utf-8"),
+            b"<!DOCTYPE html>\n<html><body>Error 503</body></html>",
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Received HTML instead of audio (text/html; charset=utf-8)"));
+        assert!(check_audio(Some("audio/mpeg"), b"  <html><body>").is_err());
+        assert!(check_audio(None, b"<?xml version=\"1.0\"?><error/>").is_err());
+        assert!(check_audio(None, b"{\"error\": \"not found\"}").is_err());
+    }
+
+    #[test]
+    fn test_json_text() {
+        assert_eq!(json_text("{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(json_text("\u{feff}{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(json_text(")]}'\n{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(json_text(")]}',\n{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(json_text("\u{feff}while(1);[1]"), "[1]");
+        assert_eq!(html_snippet("{\"a\": 1}"), None);
+    }
+
+    #[test]
+    fn test_html_snippet() {
+        let page = format!(
+            "\u{feff}<!DOCTYPE html>\n<html>\n  <body>Servizio non disponibile{}</body></html>",
+            " ".repeat(10) + &"x".repeat(300)
+        );
+        let snippet = html_snippet(&page).unwrap();
+        assert!(snippet.starts_with("<!DOCTYPE html> <html> <body>Servizio non disponibile x"));
+        assert!(snippet.chars().count() < SNIPPET_LEN);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::error::RsndError;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How slow a download may get, and for how long, before it is given up as stalled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limit {
+    /// Lowest acceptable speed, in bytes per second.
+    pub speed: u64,
+    /// How long the download may stay below `speed`; zero never gives up.
+    pub timeout: Duration,
+}
+
+impl Limit {
+    pub fn is_set(&self) -> bool {
+        self.speed > 0 && !self.timeout.is_zero()
+    }
+}
+
+/// Follows the speed of a download over the last `timeout` of its chunks.
+#[derive(Debug)]
+pub struct Watch {
+    limit: Limit,
+    received: u64,
+    /// When the byte count was seen, oldest first; the first one is at or before the
+    /// start of the window.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Watch {
+    pub fn start(limit: Limit) -> Self {
+        Watch::start_at(limit, Instant::now())
+    }
+
+    fn start_at(limit: Limit, now: Instant) -> Self {
+        Watch {
+            limit,
+            received: 0,
+            samples: VecDeque::from([(now, 0)]),
+        }
+    }
+
+    /// Returns how long to wait for the next chunk: a whole window without a byte is a
+    /// stall whatever the speed.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.limit.is_set().then_some(self.limit.timeout)
+    }
+
+    /// Counts a chunk, failing when the speed over the last window is below the limit.
+    pub fn add(&mut self, bytes: u64) -> Result<(), RsndError> {
+        self.add_at(bytes, Instant::now())
+    }
+
+    fn add_at(&mut self, bytes: u64, now: Instant) -> Result<(), RsndError> {
+        self.received += bytes;
+        if !self.limit.is_set() {
+            return Ok(());
+        }
+        self.samples.push_back((now, self.received));
+        let Some(window_start) = now.checked_sub(self.limit.timeout) else {
+            return Ok(());
+        };
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|(seen, _)| *seen <= window_start)
+        {
+            self.samples.pop_front();
+        }
+        let (since, from) = self.samples[0];
+        if since > window_start {
+            // The download has not run for a whole window yet.
+            return Ok(());
+        }
+        let speed = (self.received - from) as f64 / (now - since).as_secs_f64();
+        if speed < self.limit.speed as f64 {
+            return Err(self.stalled(speed as u64));
+        }
+        Ok(())
+    }
+
+    /// Returns the error of a download that received nothing for a whole window.
+    pub fn silent(&self) -> RsndError {
+        self.stalled(0)
+    }
+
+    fn stalled(&self, speed: u64) -> RsndError {
+        RsndError::Stalled {
+            received: self.received,
+            speed,
+            secs: self.limit.timeout.as_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Limit = Limit {
+        speed: 10 * 1024,
+        timeout: Duration::from_secs(30),
+    };
+
+    #[test]
+    fn test_fast_download() {
+        let start = Instant::now();
+        let mut watch = Watch::start_at(LIMIT, start);
+        for second in 1..=120 {
+            let now = start + Duration::from_secs(second);
+            assert!(watch.add_at(64 * 1024, now).is_ok());
+        }
+        assert_eq!(watch.timeout(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_slow_download() {
+        let start = Instant::now();
+        let mut watch = Watch::start_at(LIMIT, start);
+        // Fast at first, then down to 2 KB/s: the first 30 seconds are still fast on
+        // average, the stall shows once the slow part fills the window.
+        for second in 1..=10 {
+            let now = start + Duration::from_secs(second);
+            assert!(watch.add_at(100 * 1024, now).is_ok());
+        }
+        let mut stalled_at = None;
+        for second in 11..=60 {
+            let now = start + Duration::from_secs(second);
+            if let Err(err) = watch.add_at(2 * 1024, now) {
+                stalled_at = Some((second, err));
+                break;
+            }
+        }
+        let (second, err) = stalled_at.expect("the download should stall");
+        assert!((30..=41).contains(&second), "stalled at {}s", second);
+        assert!(matches!(
+            err,
+            RsndError::Stalled {
+                speed: 0..=10239,
+                secs: 30,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unset_limit() {
+        let start = Instant::now();
+        let mut watch = Watch::start_at(Limit::default(), start);
+        assert_eq!(watch.timeout(), None);
+        assert!(watch.add_at(1, start + Duration::from_secs(600)).is_ok());
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Name of the file recording the newest episode of each show, for `--incremental`.
+pub const STATE_FILE: &str = "rsnd-state.json";
+
+/// Known episodes processed again after the newest one, in case the page was reordered.
+pub const OVERLAP: usize = 3;
+
+/// The newest episode of a show at the end of the last incremental run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShowState {
+    /// Metadata path of the episode listed first on the page.
+    pub newest: String,
+    /// Its publication date.
+    pub date: Option<NaiveDate>,
+}
+
+/// The state of the shows saved in a folder, by show URL.
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    shows: BTreeMap<String, ShowState>,
+}
+
+impl StateFile {
+    /// Reads the state file of the folder, which is empty before the first run.
+    pub fn load(folder: &Path) -> Result<Self> {
+        let path = folder.join(STATE_FILE);
+        let shows = if path.exists() {
+            let json = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse JSON: {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(StateFile { path, shows })
+    }
+
+    pub fn get(&self, show_url: &str) -> Option<&ShowState> {
+        self.shows.get(show_url)
+    }
+
+    pub fn set(&mut self, show_url: &str, state: ShowState) {
+        self.shows.insert(show_url.to_string(), state);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(&self.shows).context("Failed to serialize state")?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write file: {}", self.path.display()))
+    }
+}
+
+/// Serializes the updates of state files by shows processed at the same time.
+static UPDATE: Mutex<()> = Mutex::new(());
+
+/// Records the state of the show in the state file of the folder. The file is read
+/// again first, so that the states saved by other shows in the meantime are kept.
+pub fn update(folder: &Path, show_url: &str, state: ShowState) -> Result<()> {
+    let _guard = UPDATE.lock().unwrap_or_else(|err| err.into_inner());
+    let mut file = StateFile::load(folder)?;
+    file.set(show_url, state);
+    file.save()
+}
+
+/// Returns the number of episodes listed before the newest one of the last run, or
+/// `None` when it is no longer listed.
+///
+/// Pages list the newest episode first, so these are the new ones.
+pub fn new_count(audio_urls: &[String], state: &ShowState) -> Option<usize> {
+    audio_urls.iter().position(|url| *url == state.newest)
+}
+
+/// Returns the episode to record as the newest one: the newest of the episodes new
+/// since `known` that the run handled with every older new one, or `None` when the
+/// oldest new episode was not handled, as in a run cut short by `--limit`.
+pub fn newest_handled(
+    audio_urls: &[String],
+    known: Option<&ShowState>,
+    handled: &HashSet<String>,
+) -> Option<String> {
+    let new = known
+        .and_then(|known| new_count(audio_urls, known))
+        .unwrap_or(audio_urls.len());
+    audio_urls[..new]
+        .iter()
+        .rev()
+        .take_while(|url| handled.contains(*url))
+        .last()
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_count() {
+        let urls: Vec<String> = ["/audio/c.json", "/audio/b.json", "/audio/a.json"]
+            .iter()
+            .map(|url| url.to_string())
+            .collect();
+        let state = |newest: &str| ShowState {
+            newest: newest.to_string(),
+            date: None,
+        };
+        assert_eq!(new_count(&urls, &state("/audio/b.json")), Some(1));
+        assert_eq!(new_count(&urls, &state("/audio/c.json")), Some(0));
+        assert_eq!(new_count(&urls, &state("/audio/z.json")), None);
+    }
+
+    #[test]
+    fn test_newest_handled() {
+        let urls: Vec<String> = [
+            "/audio/d.json",
+            "/audio/c.json",
+            "/audio/b.json",
+            "/audio/a.json",
+        ]
+        .iter()
+        .map(|url| url.to_string())
+        .collect();
+        let known = ShowState {
+            newest: "/audio/b.json".to_string(),
+            date: None,
+        };
+        let handled = |urls: &[&str]| urls.iter().map(|url| url.to_string()).collect();
+        assert_eq!(
+            newest_handled(
+                &urls,
+                Some(&known),
+                &handled(&["/audio/d.json", "/audio/c.json"])
+            ),
+            Some("/audio/d.json".to_string())
+        );
+        // The newest episode alone was handled: the other new one is still new.
+        assert_eq!(
+            newest_handled(
+                &urls,
+                Some(&known),
+                &handled(&["/audio/d.json", "/audio/b.json"])
+            ),
+            None
+        );
+        assert_eq!(
+            newest_handled(&urls, None, &handled(&["/audio/b.json", "/audio/a.json"])),
+            Some("/audio/b.json".to_string())
+        );
+    }
+
+    #[test]
+    fn test_state_file() -> Result<()> {
+        let folder = tempfile::tempdir()?;
+        let mut file = StateFile::load(folder.path())?;
+        assert_eq!(file.get("https://example.com/show"), None);
+        let state = ShowState {
+            newest: "/audio/c.json".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 5, 1),
+        };
+        file.set("https://example.com/show", state.clone());
+        file.save()?;
+        let file = StateFile::load(folder.path())?;
+        assert_eq!(file.get("https://example.com/show"), Some(&state));
+        Ok(())
+    }
+}
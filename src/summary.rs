@@ -0,0 +1,1203 @@
+use crate::format_size;
+use crate::list::EpisodeRow;
+use crate::{errln, outln};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// An episode that could not be processed.
+#[derive(Debug)]
+pub struct FailedEpisode {
+    pub index: usize,
+    /// The title, or the metadata URL when the metadata itself could not be fetched.
+    pub label: String,
+    pub error: anyhow::Error,
+}
+
+impl FailedEpisode {
+    /// Logs the failure with its full error chain and returns it.
+    pub fn new(index: usize, label: &str, error: anyhow::Error) -> Self {
+        errln!("Failed {:03} - {}: {:#}", index, label, error);
+        FailedEpisode {
+            index,
+            label: label.to_string(),
+            error,
+        }
+    }
+}
+
+/// Counters collected during a run, shared by all the shows it processes.
+#[derive(Debug)]
+pub struct Summary {
+    started: Instant,
+    shows: Mutex<Vec<Arc<ShowSummary>>>,
+}
+
+/// Counters of a single show, safe to update from concurrent downloads.
+#[derive(Debug)]
+pub struct ShowSummary {
+    url: String,
+    found: AtomicUsize,
+    downloaded: AtomicUsize,
+    downloaded_bytes: AtomicU64,
+    /// Time spent downloading, summed over the downloads, in milliseconds.
+    stream_millis: AtomicU64,
+    wasted_bytes: AtomicU64,
+    /// Time spent in `--sleep` pauses, in milliseconds.
+    slept_millis: AtomicU64,
+    skipped: AtomicUsize,
+    filtered: AtomicUsize,
+    videos: AtomicUsize,
+    failed: Mutex<Vec<FailedEpisode>>,
+    unavailable: Mutex<Vec<FailureReport>>,
+    out_of_size: Mutex<Vec<FailureReport>>,
+    elsewhere: Mutex<Vec<FailureReport>>,
+    suspicious: Mutex<Vec<FailureReport>>,
+    drifted: Mutex<Vec<FailureReport>>,
+    missing: Mutex<Vec<FailureReport>>,
+    unknown: Mutex<Vec<FailureReport>>,
+    removed: Mutex<Vec<FailureReport>>,
+    mirrored: Mutex<Vec<MirrorReport>>,
+    /// The folder the episodes are saved in, when it is not the one given.
+    folder: Mutex<Option<PathBuf>>,
+    new_episodes: Mutex<Option<NewEpisodes>>,
+    limit_reached: Mutex<Option<LimitReached>>,
+    /// The landing page the show was found on, with `--follow-shows`.
+    found_on: Mutex<Option<String>>,
+    /// The publication schedule the show JSON gives.
+    schedule: Mutex<Option<String>>,
+    /// The episodes listed with `--list --json`, printed once every show is listed.
+    listed: Mutex<Vec<EpisodeRow>>,
+}
+
+/// Episodes listed since the last incremental run.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewEpisodes {
+    pub count: usize,
+    /// Publication date of the newest episode of the last run.
+    pub since: Option<NaiveDate>,
+}
+
+impl fmt::Display for NewEpisodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.since {
+            Some(date) => write!(f, "{} new episodes since {}", self.count, date),
+            None => write!(f, "{} new episodes since the last run", self.count),
+        }
+    }
+}
+
+/// The `--limit` that stopped a show, with the episodes it left for the next run.
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitReached {
+    pub limit: usize,
+    pub remaining: usize,
+}
+
+impl fmt::Display for LimitReached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Limit of {} episodes reached, {} episodes remaining",
+            self.limit, self.remaining
+        )
+    }
+}
+
+/// A failed episode as written to the summary.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureReport {
+    pub index: usize,
+    pub title: String,
+    pub error: String,
+}
+
+/// An episode served by a `--cdn-fallback` mirror, as written to the summary.
+#[derive(Debug, Clone, Serialize)]
+pub struct MirrorReport {
+    pub index: usize,
+    pub title: String,
+    pub host: String,
+}
+
+/// A snapshot of the counters of one show, or of the whole run.
+#[derive(Debug, Default, Serialize)]
+pub struct Counts {
+    pub found: usize,
+    pub downloaded: usize,
+    pub downloaded_bytes: u64,
+    /// Time spent downloading, summed over the downloads.
+    pub stream_secs: f64,
+    /// Bytes received by download attempts that were retried.
+    pub wasted_bytes: u64,
+    /// Time spent in `--sleep` pauses between downloads.
+    pub slept_secs: f64,
+    pub skipped: usize,
+    pub filtered: usize,
+    /// Video items among the episodes, only downloaded with `--include-video`.
+    pub videos: usize,
+    pub failed: Vec<FailureReport>,
+    /// Episodes skipped because the relinker served a placeholder.
+    pub unavailable: Vec<FailureReport>,
+    /// Episodes skipped because of `--min-filesize`, `--max-filesize` or `--strict-size`.
+    pub out_of_size: Vec<FailureReport>,
+    /// Episodes found in an `--also-check-dir` folder instead of being downloaded.
+    pub elsewhere: Vec<FailureReport>,
+    /// Downloads smaller than `--min-size`.
+    pub suspicious: Vec<FailureReport>,
+    /// Episodes whose index is taken by another episode's file.
+    pub drifted: Vec<FailureReport>,
+    /// Episodes `--check` found no file for, with the expected file.
+    pub missing: Vec<FailureReport>,
+    /// Episodes `--check` could not read the metadata of.
+    pub unknown: Vec<FailureReport>,
+    /// Episodes listed by an earlier run that the show no longer lists.
+    pub removed: Vec<FailureReport>,
+    /// Episodes a `--cdn-fallback` mirror served, with its host.
+    pub mirrored: Vec<MirrorReport>,
+}
+
+/// A snapshot of the counters of one show.
+#[derive(Debug, Serialize)]
+pub struct ShowReport {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_episodes: Option<NewEpisodes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_reached: Option<LimitReached>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found_on: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(flatten)]
+    pub counts: Counts,
+}
+
+/// A snapshot of the run counters, with the totals and the per-show breakdown.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub total: Counts,
+    pub shows: Vec<ShowReport>,
+    pub elapsed_secs: f64,
+    /// Average download speed in bytes per second.
+    pub throughput: f64,
+    /// Average number of downloads running at the same time.
+    pub concurrency: f64,
+    pub interrupted: bool,
+    /// When `--watch` runs next.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_poll: Option<DateTime<Local>>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Summary {
+            started: Instant::now(),
+            shows: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Starts collecting the counters of a new show.
+    pub fn start_show(&self, url: &str) -> Arc<ShowSummary> {
+        let show = Arc::new(ShowSummary {
+            url: url.to_string(),
+            found: AtomicUsize::new(0),
+            downloaded: AtomicUsize::new(0),
+            downloaded_bytes: AtomicU64::new(0),
+            stream_millis: AtomicU64::new(0),
+            wasted_bytes: AtomicU64::new(0),
+            slept_millis: AtomicU64::new(0),
+            skipped: AtomicUsize::new(0),
+            filtered: AtomicUsize::new(0),
+            videos: AtomicUsize::new(0),
+            failed: Mutex::new(Vec::new()),
+            unavailable: Mutex::new(Vec::new()),
+            out_of_size: Mutex::new(Vec::new()),
+            elsewhere: Mutex::new(Vec::new()),
+            suspicious: Mutex::new(Vec::new()),
+            drifted: Mutex::new(Vec::new()),
+            missing: Mutex::new(Vec::new()),
+            unknown: Mutex::new(Vec::new()),
+            removed: Mutex::new(Vec::new()),
+            mirrored: Mutex::new(Vec::new()),
+            folder: Mutex::new(None),
+            new_episodes: Mutex::new(None),
+            limit_reached: Mutex::new(None),
+            found_on: Mutex::new(None),
+            schedule: Mutex::new(None),
+            listed: Mutex::new(Vec::new()),
+        });
+        self.shows.lock().unwrap().push(show.clone());
+        show
+    }
+
+    /// Takes the episodes every show listed, in the order the shows were started.
+    pub fn take_listed(&self) -> Vec<(String, Vec<EpisodeRow>)> {
+        self.shows
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|show| {
+                let rows = std::mem::take(&mut *show.listed.lock().unwrap());
+                (show.url.clone(), rows)
+            })
+            .collect()
+    }
+
+    /// Takes a snapshot of the counters.
+    pub fn report(&self, interrupted: bool) -> Report {
+        let elapsed_secs = self.started.elapsed().as_secs_f64();
+        let shows: Vec<ShowReport> = self
+            .shows
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|show| show.report())
+            .collect();
+
+        let mut total = Counts::default();
+        for show in &shows {
+            total.found += show.counts.found;
+            total.downloaded += show.counts.downloaded;
+            total.downloaded_bytes += show.counts.downloaded_bytes;
+            total.stream_secs += show.counts.stream_secs;
+            total.wasted_bytes += show.counts.wasted_bytes;
+            total.slept_secs += show.counts.slept_secs;
+            total.skipped += show.counts.skipped;
+            total.filtered += show.counts.filtered;
+            total.videos += show.counts.videos;
+            total.failed.extend(show.counts.failed.iter().cloned());
+            total
+                .unavailable
+                .extend(show.counts.unavailable.iter().cloned());
+            total
+                .out_of_size
+                .extend(show.counts.out_of_size.iter().cloned());
+            total
+                .elsewhere
+                .extend(show.counts.elsewhere.iter().cloned());
+            total
+                .suspicious
+                .extend(show.counts.suspicious.iter().cloned());
+            total.drifted.extend(show.counts.drifted.iter().cloned());
+            total.missing.extend(show.counts.missing.iter().cloned());
+            total.unknown.extend(show.counts.unknown.iter().cloned());
+            total.removed.extend(show.counts.removed.iter().cloned());
+            total.mirrored.extend(show.counts.mirrored.iter().cloned());
+        }
+
+        Report {
+            throughput: if elapsed_secs > 0.0 {
+                total.downloaded_bytes as f64 / elapsed_secs
+            } else {
+                0.0
+            },
+            concurrency: if elapsed_secs > 0.0 {
+                total.stream_secs / elapsed_secs
+            } else {
+                0.0
+            },
+            total,
+            shows,
+            elapsed_secs,
+            interrupted,
+            next_poll: None,
+        }
+    }
+}
+
+impl ShowSummary {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn add_found(&self, count: usize) {
+        self.found.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_downloaded(&self, bytes: u64) {
+        self.downloaded.fetch_add(1, Ordering::Relaxed);
+        self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Adds the time a download took, and the bytes of its attempts that were retried.
+    pub fn add_stream(&self, elapsed: Duration, wasted: u64) {
+        self.stream_millis
+            .fetch_add(elapsed.as_millis() as u64, Ordering::Relaxed);
+        self.wasted_bytes.fetch_add(wasted, Ordering::Relaxed);
+    }
+
+    /// Adds a `--sleep` pause taken before a download.
+    pub fn add_sleep(&self, slept: Duration) {
+        self.slept_millis
+            .fetch_add(slept.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_filtered(&self, count: usize) {
+        self.filtered.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_video(&self) {
+        self.videos.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_failed(&self, failure: FailedEpisode) {
+        self.failed.lock().unwrap().push(failure);
+    }
+
+    /// Records an episode the relinker served a placeholder for.
+    pub fn add_unavailable(&self, index: usize, title: &str, reason: &str) {
+        outln!(
+            "Skipping {:03} - {}: unavailable (geo-blocked?): {}",
+            index,
+            title,
+            reason
+        );
+        self.unavailable.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: reason.to_string(),
+        });
+    }
+
+    /// Records an episode skipped because of its size.
+    pub fn add_out_of_size(&self, index: usize, title: &str, reason: &str) {
+        outln!("Skipping {:03} - {}: {}", index, title, reason);
+        self.out_of_size.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: reason.to_string(),
+        });
+    }
+
+    /// Records an episode found in another folder, and how it was put in the output
+    /// folder, if it was.
+    pub fn add_elsewhere(&self, index: usize, title: &str, path: &Path, placed: Option<&str>) {
+        let note = match placed {
+            Some(placed) => format!("{} {}", placed, path.display()),
+            None => format!("found {}", path.display()),
+        };
+        outln!("Skipping {:03} - {}: {}", index, title, note);
+        self.elsewhere.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: note,
+        });
+    }
+
+    /// Records a download that is suspiciously small.
+    pub fn add_suspicious(&self, index: usize, title: &str, reason: &str) {
+        errln!(
+            "Warning: {:03} - {} is suspicious: {}",
+            index,
+            title,
+            reason
+        );
+        self.suspicious.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: reason.to_string(),
+        });
+    }
+
+    /// Records an episode whose index is taken by the file of another episode.
+    pub fn add_drifted(&self, index: usize, title: &str, other: &str) {
+        errln!(
+            "Warning: {:03} - {} would be numbered like {}: episodes may have shifted",
+            index,
+            title,
+            other
+        );
+        self.drifted.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: format!("index taken by {}", other),
+        });
+    }
+
+    /// Records the folder the episodes are saved in, e.g. the show subfolder.
+    pub fn set_folder(&self, folder: &Path) {
+        *self.folder.lock().unwrap() = Some(folder.to_path_buf());
+    }
+
+    /// Keeps the listed episodes until every show is listed.
+    pub fn add_listed(&self, rows: Vec<EpisodeRow>) {
+        self.listed.lock().unwrap().extend(rows);
+    }
+
+    /// Records an episode that is not in the folder.
+    pub fn add_missing(&self, index: usize, title: &str, path: &Path) {
+        self.missing.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: format!("missing {}", path.display()),
+        });
+    }
+
+    /// Records an episode whose metadata could not be read, so it is not known whether
+    /// it is in the folder.
+    pub fn add_unknown(&self, index: usize, label: &str, reason: &str) {
+        errln!("Warning: {:03} - {} is unknown: {}", index, label, reason);
+        self.unknown.lock().unwrap().push(FailureReport {
+            index,
+            title: label.to_string(),
+            error: format!("unknown: {}", reason),
+        });
+    }
+
+    /// Records an episode listed by an earlier run that the show no longer lists, with
+    /// what became of its file.
+    pub fn add_removed(&self, index: usize, title: &str, note: &str) {
+        errln!(
+            "Warning: {:03} - {} is no longer available upstream: {}",
+            index,
+            title,
+            note
+        );
+        self.removed.lock().unwrap().push(FailureReport {
+            index,
+            title: title.to_string(),
+            error: format!("no longer available upstream: {}", note),
+        });
+    }
+
+    /// Records an episode downloaded from a mirror because its CDN host failed.
+    pub fn add_mirrored(&self, index: usize, title: &str, host: &str) {
+        self.mirrored.lock().unwrap().push(MirrorReport {
+            index,
+            title: title.to_string(),
+            host: host.to_string(),
+        });
+    }
+
+    pub fn has_missing(&self) -> bool {
+        !self.missing.lock().unwrap().is_empty()
+    }
+
+    /// Records how many episodes were listed since the last incremental run.
+    pub fn set_new_episodes(&self, new_episodes: NewEpisodes) {
+        *self.new_episodes.lock().unwrap() = Some(new_episodes);
+    }
+
+    /// Records that `--limit` stopped the show with `remaining` episodes left.
+    pub fn set_limit_reached(&self, limit: usize, remaining: usize) {
+        *self.limit_reached.lock().unwrap() = Some(LimitReached { limit, remaining });
+    }
+
+    /// Records the landing page the show was found on.
+    pub fn set_found_on(&self, landing_url: &str) {
+        *self.found_on.lock().unwrap() = Some(landing_url.to_string());
+    }
+
+    /// Whether the show was found on a landing page rather than given.
+    pub fn is_found(&self) -> bool {
+        self.found_on.lock().unwrap().is_some()
+    }
+
+    /// Records the publication schedule of the show.
+    pub fn set_schedule(&self, schedule: &str) {
+        *self.schedule.lock().unwrap() = Some(schedule.to_string());
+    }
+
+    pub fn has_failures(&self) -> bool {
+        !self.failed.lock().unwrap().is_empty()
+    }
+
+    fn report(&self) -> ShowReport {
+        let mut failed: Vec<FailureReport> = self
+            .failed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|failure| FailureReport {
+                index: failure.index,
+                title: failure.label.clone(),
+                error: format!("{:#}", failure.error),
+            })
+            .collect();
+        failed.sort_by_key(|failure| failure.index);
+        let mut unavailable = self.unavailable.lock().unwrap().clone();
+        unavailable.sort_by_key(|episode| episode.index);
+        let mut out_of_size = self.out_of_size.lock().unwrap().clone();
+        out_of_size.sort_by_key(|episode| episode.index);
+        let mut elsewhere = self.elsewhere.lock().unwrap().clone();
+        elsewhere.sort_by_key(|episode| episode.index);
+        let mut suspicious = self.suspicious.lock().unwrap().clone();
+        suspicious.sort_by_key(|episode| episode.index);
+        let mut drifted = self.drifted.lock().unwrap().clone();
+        drifted.sort_by_key(|episode| episode.index);
+        let mut missing = self.missing.lock().unwrap().clone();
+        missing.sort_by_key(|episode| episode.index);
+        let mut unknown = self.unknown.lock().unwrap().clone();
+        unknown.sort_by_key(|episode| episode.index);
+        let mut removed = self.removed.lock().unwrap().clone();
+        removed.sort_by_key(|episode| episode.index);
+        let mut mirrored = self.mirrored.lock().unwrap().clone();
+        mirrored.sort_by_key(|episode| episode.index);
+
+        ShowReport {
+            url: self.url.clone(),
+            folder: self.folder.lock().unwrap().clone(),
+            new_episodes: self.new_episodes.lock().unwrap().clone(),
+            limit_reached: self.limit_reached.lock().unwrap().clone(),
+            found_on: self.found_on.lock().unwrap().clone(),
+            schedule: self.schedule.lock().unwrap().clone(),
+            counts: Counts {
+                found: self.found.load(Ordering::Relaxed),
+                downloaded: self.downloaded.load(Ordering::Relaxed),
+                downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+                stream_secs: self.stream_millis.load(Ordering::Relaxed) as f64 / 1000.0,
+                wasted_bytes: self.wasted_bytes.load(Ordering::Relaxed),
+                slept_secs: self.slept_millis.load(Ordering::Relaxed) as f64 / 1000.0,
+                skipped: self.skipped.load(Ordering::Relaxed),
+                filtered: self.filtered.load(Ordering::Relaxed),
+                videos: self.videos.load(Ordering::Relaxed),
+                failed,
+                unavailable,
+                out_of_size,
+                elsewhere,
+                suspicious,
+                drifted,
+                missing,
+                unknown,
+                removed,
+                mirrored,
+            },
+        }
+    }
+}
+
+/// The totals of the shows saved in one folder.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Destination<'a> {
+    pub folder: &'a Path,
+    pub shows: usize,
+    pub downloaded: usize,
+    pub downloaded_bytes: u64,
+    pub failed: usize,
+}
+
+impl Report {
+    /// Groups the shows by the folder they were saved in, in the order of their first
+    /// show. Empty unless several shows share a folder and there is more than one.
+    pub fn by_folder(&self) -> Vec<Destination<'_>> {
+        let mut destinations: Vec<Destination> = Vec::new();
+        for show in &self.shows {
+            let Some(folder) = &show.folder else {
+                continue;
+            };
+            let position = match destinations
+                .iter()
+                .position(|destination| destination.folder == folder.as_path())
+            {
+                Some(position) => position,
+                None => {
+                    destinations.push(Destination {
+                        folder,
+                        shows: 0,
+                        downloaded: 0,
+                        downloaded_bytes: 0,
+                        failed: 0,
+                    });
+                    destinations.len() - 1
+                }
+            };
+            let destination = &mut destinations[position];
+            destination.shows += 1;
+            destination.downloaded += show.counts.downloaded;
+            destination.downloaded_bytes += show.counts.downloaded_bytes;
+            destination.failed += show.counts.failed.len();
+        }
+        if destinations.len() < 2 || destinations.len() == self.shows.len() {
+            destinations.clear();
+        }
+        destinations
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize summary")
+    }
+
+    /// Writes the report as JSON to the given path.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let json = self.to_json()?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write summary: {}", path.display()))
+    }
+}
+
+fn write_mirrored(f: &mut fmt::Formatter<'_>, mirrored: &[MirrorReport]) -> fmt::Result {
+    for episode in mirrored {
+        writeln!(
+            f,
+            "    {:03} - {}: served by mirror {}",
+            episode.index, episode.title, episode.host
+        )?;
+    }
+    Ok(())
+}
+
+fn write_failures(f: &mut fmt::Formatter<'_>, failed: &[FailureReport]) -> fmt::Result {
+    for failure in failed {
+        writeln!(
+            f,
+            "    {:03} - {}: {}",
+            failure.index, failure.title, failure.error
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elapsed = self.elapsed_secs as u64;
+        writeln!(
+            f,
+            "Summary{}:",
+            if self.interrupted {
+                " (interrupted)"
+            } else {
+                ""
+            }
+        )?;
+        let per_show = self.shows.len() > 1;
+        if per_show {
+            for show in &self.shows {
+                writeln!(
+                    f,
+                    "  {}: {} found, {} downloaded ({}), {} skipped, {} filtered, {} failed",
+                    show.url,
+                    show.counts.found,
+                    show.counts.downloaded,
+                    format_size(show.counts.downloaded_bytes),
+                    show.counts.skipped,
+                    show.counts.filtered,
+                    show.counts.failed.len()
+                )?;
+                if let Some(landing_url) = &show.found_on {
+                    writeln!(f, "    Found on {}", landing_url)?;
+                }
+                if let Some(folder) = &show.folder {
+                    writeln!(f, "    Saved in {}", folder.display())?;
+                }
+                if let Some(new_episodes) = &show.new_episodes {
+                    writeln!(f, "    {}", new_episodes)?;
+                }
+                if let Some(limit_reached) = &show.limit_reached {
+                    writeln!(f, "    {}", limit_reached)?;
+                }
+                write_failures(f, &show.counts.failed)?;
+                write_failures(f, &show.counts.unavailable)?;
+                write_failures(f, &show.counts.out_of_size)?;
+                write_failures(f, &show.counts.elsewhere)?;
+                write_failures(f, &show.counts.suspicious)?;
+                write_failures(f, &show.counts.drifted)?;
+                write_failures(f, &show.counts.missing)?;
+                write_failures(f, &show.counts.unknown)?;
+                write_failures(f, &show.counts.removed)?;
+                write_mirrored(f, &show.counts.mirrored)?;
+            }
+            let destinations = self.by_folder();
+            if !destinations.is_empty() {
+                writeln!(f, "  By folder:")?;
+            }
+            for destination in destinations {
+                writeln!(
+                    f,
+                    "    {}: {} shows, {} downloaded ({}), {} failed",
+                    destination.folder.display(),
+                    destination.shows,
+                    destination.downloaded,
+                    format_size(destination.downloaded_bytes),
+                    destination.failed
+                )?;
+            }
+        }
+        if let (false, Some(folder)) =
+            (per_show, self.shows.first().and_then(|s| s.folder.as_ref()))
+        {
+            writeln!(f, "  Folder:         {}", folder.display())?;
+        }
+        writeln!(f, "  Episodes found: {}", self.total.found)?;
+        if let (false, Some(new_episodes)) = (
+            per_show,
+            self.shows.first().and_then(|s| s.new_episodes.as_ref()),
+        ) {
+            writeln!(f, "  {}", new_episodes)?;
+        }
+        if let (false, Some(limit_reached)) = (
+            per_show,
+            self.shows.first().and_then(|s| s.limit_reached.as_ref()),
+        ) {
+            writeln!(f, "  {}", limit_reached)?;
+        }
+        writeln!(
+            f,
+            "  Downloaded:     {} ({})",
+            self.total.downloaded,
+            format_size(self.total.downloaded_bytes)
+        )?;
+        writeln!(
+            f,
+            "  Skipped:        {} (already present)",
+            self.total.skipped
+        )?;
+        writeln!(f, "  Filtered:       {}", self.total.filtered)?;
+        if self.total.videos > 0 {
+            writeln!(f, "  Video items:    {}", self.total.videos)?;
+        }
+        writeln!(f, "  Failed:         {}", self.total.failed.len())?;
+        if !self.total.unavailable.is_empty() {
+            writeln!(
+                f,
+                "  Unavailable:    {} (geo-blocked?)",
+                self.total.unavailable.len()
+            )?;
+        }
+        if !self.total.out_of_size.is_empty() {
+            writeln!(
+                f,
+                "  Size skipped:   {} (outside --min/--max-filesize)",
+                self.total.out_of_size.len()
+            )?;
+        }
+        if !self.total.elsewhere.is_empty() {
+            writeln!(
+                f,
+                "  Elsewhere:      {} (in --also-check-dir)",
+                self.total.elsewhere.len()
+            )?;
+        }
+        if !self.total.suspicious.is_empty() {
+            writeln!(
+                f,
+                "  Suspicious:     {} (smaller than --min-size)",
+                self.total.suspicious.len()
+            )?;
+        }
+        if !self.total.drifted.is_empty() {
+            writeln!(
+                f,
+                "  Index drift:    {} (consider --stable-names)",
+                self.total.drifted.len()
+            )?;
+        }
+        if !self.total.missing.is_empty() {
+            writeln!(f, "  Missing:        {}", self.total.missing.len())?;
+        }
+        if !self.total.unknown.is_empty() {
+            writeln!(
+                f,
+                "  Unknown:        {} (metadata unavailable)",
+                self.total.unknown.len()
+            )?;
+        }
+        if !self.total.removed.is_empty() {
+            writeln!(
+                f,
+                "  Removed:        {} (no longer available upstream)",
+                self.total.removed.len()
+            )?;
+        }
+        if !self.total.mirrored.is_empty() {
+            writeln!(
+                f,
+                "  Mirrored:       {} (CDN host failed)",
+                self.total.mirrored.len()
+            )?;
+        }
+        writeln!(
+            f,
+            "  Elapsed:        {:02}:{:02}:{:02} ({}/s)",
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60,
+            format_size(self.throughput as u64)
+        )?;
+        if self.total.stream_secs > 0.0 {
+            writeln!(
+                f,
+                "  Streams:        {:.1} at a time on average",
+                self.concurrency
+            )?;
+        }
+        if self.total.wasted_bytes > 0 {
+            writeln!(
+                f,
+                "  Wasted:         {} (retried downloads)",
+                format_size(self.total.wasted_bytes)
+            )?;
+        }
+        if self.total.slept_secs > 0.0 {
+            let slept = self.total.slept_secs as u64;
+            writeln!(
+                f,
+                "  Slept:          {:02}:{:02}:{:02} (--sleep)",
+                slept / 3600,
+                slept / 60 % 60,
+                slept % 60
+            )?;
+        }
+        if !per_show {
+            write_failures(f, &self.total.failed)?;
+            write_failures(f, &self.total.unavailable)?;
+            write_failures(f, &self.total.out_of_size)?;
+            write_failures(f, &self.total.elsewhere)?;
+            write_failures(f, &self.total.suspicious)?;
+            write_failures(f, &self.total.drifted)?;
+            write_failures(f, &self.total.missing)?;
+            write_failures(f, &self.total.unknown)?;
+            write_failures(f, &self.total.removed)?;
+            write_mirrored(f, &self.total.mirrored)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_report() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/audiolibri/itremoschettieri");
+        show.add_found(5);
+        show.add_downloaded(1024);
+        show.add_downloaded(2048);
+        show.add_skipped();
+        show.add_filtered(1);
+        show.add_failed(FailedEpisode {
+            index: 5,
+            label: "Lettura V".to_string(),
+            error: anyhow!("Status: 404").context("Failed to fetch audio URL"),
+        });
+
+        let report = summary.report(true);
+        assert_eq!(report.total.found, 5);
+        assert_eq!(report.total.downloaded, 2);
+        assert_eq!(report.total.downloaded_bytes, 3072);
+        assert_eq!(report.total.skipped, 1);
+        assert_eq!(report.total.filtered, 1);
+        assert_eq!(
+            report.total.failed[0].error,
+            "Failed to fetch audio URL: Status: 404"
+        );
+        assert!(show.has_failures());
+
+        let text = report.to_string();
+        assert!(text.starts_with("Summary (interrupted):\n  Episodes found: 5\n"));
+        assert!(text.contains("  Downloaded:     2 (3.0 KB)\n"));
+        assert!(text.contains("    005 - Lettura V: Failed to fetch audio URL: Status: 404\n"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["failed"][0]["title"], "Lettura V");
+        assert_eq!(json["shows"][0]["found"], 5);
+        assert_eq!(json["interrupted"], true);
+    }
+
+    #[test]
+    fn test_report_streams() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/audiolibri/itremoschettieri");
+        show.add_downloaded(1024);
+        show.add_stream(Duration::from_millis(1500), 2048);
+        show.add_downloaded(1024);
+        show.add_stream(Duration::from_millis(500), 0);
+        show.add_sleep(Duration::from_secs(90));
+
+        let report = summary.report(false);
+        assert_eq!(report.total.stream_secs, 2.0);
+        assert_eq!(report.total.wasted_bytes, 2048);
+        assert!(report.concurrency > 0.0);
+        let text = report.to_string();
+        assert!(text.contains("  Streams:        "));
+        assert!(text.contains("  Wasted:         2.0 KB (retried downloads)\n"));
+        assert!(text.contains("  Slept:          00:01:30 (--sleep)\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["stream_secs"], 2.0);
+        assert_eq!(json["wasted_bytes"], 2048);
+        assert_eq!(json["slept_secs"], 90.0);
+    }
+
+    #[test]
+    fn test_report_per_show() {
+        let summary = Summary::new();
+        let first = summary.start_show("https://www.raiplaysound.it/programmi/first");
+        first.add_found(3);
+        first.add_downloaded(1024);
+        let second = summary.start_show("https://www.raiplaysound.it/programmi/second");
+        second.add_found(2);
+        second.add_failed(FailedEpisode {
+            index: 2,
+            label: "Puntata 2".to_string(),
+            error: anyhow!("Status: 403"),
+        });
+
+        let report = summary.report(false);
+        assert_eq!(report.total.found, 5);
+        assert_eq!(report.total.failed.len(), 1);
+        assert_eq!(report.shows.len(), 2);
+
+        let text = report.to_string();
+        assert!(text.contains(
+            "  https://www.raiplaysound.it/programmi/first: 3 found, 1 downloaded (1.0 KB), 0 skipped, 0 filtered, 0 failed\n"
+        ));
+        assert!(!text.contains("Saved in"));
+        assert!(text.contains("failed\n    002 - Puntata 2: Status: 403\n  Episodes found: 5\n"));
+    }
+
+    #[test]
+    fn test_report_unavailable() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_found(1);
+        show.add_unavailable(1, "Puntata 1", "status 403 Forbidden");
+
+        let report = summary.report(false);
+        assert!(!show.has_failures());
+        assert_eq!(report.total.unavailable.len(), 1);
+        let text = report.to_string();
+        assert!(text.contains("  Unavailable:    1 (geo-blocked?)\n"));
+        assert!(text.contains("    001 - Puntata 1: status 403 Forbidden\n"));
+    }
+
+    #[test]
+    fn test_report_removed() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_removed(3, "Puntata 3", "kept in 03 - puntata 3.mp3");
+
+        let report = summary.report(false);
+        assert!(!show.has_failures());
+        let text = report.to_string();
+        assert!(text.contains("  Removed:        1 (no longer available upstream)\n"));
+        assert!(text.contains(
+            "    003 - Puntata 3: no longer available upstream: kept in 03 - puntata 3.mp3\n"
+        ));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["removed"][0]["title"], "Puntata 3");
+    }
+
+    #[test]
+    fn test_report_mirrored() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_mirrored(2, "Puntata 2", "creativemedia1.rai.it");
+
+        let report = summary.report(false);
+        assert!(!show.has_failures());
+        let text = report.to_string();
+        assert!(text.contains("  Mirrored:       1 (CDN host failed)\n"));
+        assert!(text.contains("    002 - Puntata 2: served by mirror creativemedia1.rai.it\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["mirrored"][0]["index"], 2);
+        assert_eq!(json["mirrored"][0]["host"], "creativemedia1.rai.it");
+    }
+
+    #[test]
+    fn test_report_out_of_size() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_found(1);
+        show.add_out_of_size(
+            1,
+            "Maratona",
+            "2.0 GB is larger than --max-filesize 500.0 MB",
+        );
+
+        let report = summary.report(false);
+        assert!(!show.has_failures());
+        let text = report.to_string();
+        assert!(text.contains("  Size skipped:   1 (outside --min/--max-filesize)\n"));
+        assert!(text.contains("    001 - Maratona: 2.0 GB is larger than --max-filesize"));
+    }
+
+    #[test]
+    fn test_report_elsewhere() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/audiolibri/itremoschettieri");
+        show.add_found(2);
+        show.add_elsewhere(1, "Lettura I", Path::new("/nas/01 - lettura i.mp3"), None);
+        show.add_elsewhere(
+            2,
+            "Lettura II",
+            Path::new("/nas/02 - lettura ii.mp3"),
+            Some("linked"),
+        );
+
+        let text = summary.report(false).to_string();
+        assert!(text.contains("  Elsewhere:      2 (in --also-check-dir)\n"));
+        assert!(text.contains("    001 - Lettura I: found /nas/01 - lettura i.mp3\n"));
+        assert!(text.contains("    002 - Lettura II: linked /nas/02 - lettura ii.mp3\n"));
+    }
+
+    #[test]
+    fn test_report_drifted() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/audiolibri/itremoschettieri");
+        show.add_found(2);
+        show.add_drifted(2, "Lettura II", "002 - lettura iii.mp3");
+
+        let report = summary.report(false);
+        assert!(!show.has_failures());
+        let text = report.to_string();
+        assert!(text.contains("  Index drift:    1 (consider --stable-names)\n"));
+        assert!(text.contains("    002 - Lettura II: index taken by 002 - lettura iii.mp3\n"));
+    }
+
+    #[test]
+    fn test_report_folder() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/audiolibri/itremoschettieri");
+        show.set_folder(Path::new("libri/i tre moschettieri"));
+
+        let report = summary.report(false);
+        assert!(report
+            .to_string()
+            .contains("  Folder:         libri/i tre moschettieri\n  Episodes found: 0\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["shows"][0]["folder"], "libri/i tre moschettieri");
+
+        let other = summary.start_show("https://www.raiplaysound.it/programmi/other");
+        other.set_folder(Path::new("libri/other"));
+        assert!(summary
+            .report(false)
+            .to_string()
+            .contains("0 failed\n    Saved in libri/other\n"));
+    }
+
+    #[test]
+    fn test_report_found_on() {
+        let summary = Summary::new();
+        for slug in ["itremoschettieri", "ilconte"] {
+            let show =
+                summary.start_show(&format!("https://www.raiplaysound.it/audiolibri/{}", slug));
+            show.set_found_on("https://www.raiplaysound.it/audiolibri");
+        }
+
+        let report = summary.report(false);
+        assert!(report.to_string().contains(
+            "/audiolibri/ilconte: 0 found, 0 downloaded (0 B), 0 skipped, 0 filtered, 0 failed\n    Found on https://www.raiplaysound.it/audiolibri\n"
+        ));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["shows"][0]["found_on"],
+            "https://www.raiplaysound.it/audiolibri"
+        );
+    }
+
+    #[test]
+    fn test_report_by_folder() {
+        let summary = Summary::new();
+        for (url, folder, downloaded) in [
+            (
+                "https://www.raiplaysound.it/audiolibri/itremoschettieri",
+                "/mnt/books",
+                2,
+            ),
+            ("https://www.raiplaysound.it/programmi/gr1", "/mnt/news", 1),
+            (
+                "https://www.raiplaysound.it/audiolibri/ilgattopardo",
+                "/mnt/books",
+                1,
+            ),
+        ] {
+            let show = summary.start_show(url);
+            show.set_folder(Path::new(folder));
+            for _ in 0..downloaded {
+                show.add_downloaded(1000);
+            }
+        }
+        let report = summary.report(false);
+        let destinations = report.by_folder();
+        assert_eq!(destinations.len(), 2);
+        assert_eq!(destinations[0].folder, Path::new("/mnt/books"));
+        assert_eq!(destinations[0].shows, 2);
+        assert_eq!(destinations[0].downloaded, 3);
+        assert!(report.to_string().contains(
+            "  By folder:\n    /mnt/books: 2 shows, 3 downloaded (2.9 KB), 0 failed\n    /mnt/news: 1 shows"
+        ));
+
+        // Each show in its own folder, as with --show-subdirs, says nothing new.
+        let summary = Summary::new();
+        summary
+            .start_show("https://www.raiplaysound.it/programmi/gr1")
+            .set_folder(Path::new("a"));
+        summary
+            .start_show("https://www.raiplaysound.it/programmi/gr2")
+            .set_folder(Path::new("b"));
+        assert!(summary.report(false).by_folder().is_empty());
+    }
+
+    #[test]
+    fn test_report_new_episodes() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_found(40);
+        show.set_new_episodes(NewEpisodes {
+            count: 2,
+            since: NaiveDate::from_ymd_opt(2024, 5, 1),
+        });
+
+        let report = summary.report(false);
+        assert!(report
+            .to_string()
+            .contains("  Episodes found: 40\n  2 new episodes since 2024-05-01\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["shows"][0]["new_episodes"]["count"], 2);
+    }
+
+    #[test]
+    fn test_report_limit_reached() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_found(5);
+        show.set_limit_reached(2, 3);
+
+        let report = summary.report(false);
+        assert!(report
+            .to_string()
+            .contains("  Limit of 2 episodes reached, 3 episodes remaining\n"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["shows"][0]["limit_reached"]["limit"], 2);
+        assert_eq!(json["shows"][0]["limit_reached"]["remaining"], 3);
+        let other = summary.start_show("https://www.raiplaysound.it/programmi/gr2");
+        other.add_found(1);
+        assert!(summary.report(false).to_string().contains(
+            "programmi/show: 5 found, 0 downloaded (0 B), 0 skipped, 0 filtered, 0 failed\n    Limit of 2 episodes reached"
+        ));
+    }
+
+    #[test]
+    fn test_report_missing() {
+        let summary = Summary::new();
+        let show = summary.start_show("https://www.raiplaysound.it/programmi/show");
+        show.add_found(3);
+        show.add_missing(2, "Puntata 2", Path::new("show/02 - puntata 2.mp3"));
+        show.add_unknown(3, "/audio/p3.json", "Status: 404");
+
+        assert!(show.has_missing());
+        assert!(!show.has_failures());
+        let report = summary.report(false);
+        let text = report.to_string();
+        assert!(text.contains("  Missing:        1\n  Unknown:        1 (metadata unavailable)\n"));
+        assert!(text.contains("    002 - Puntata 2: missing show/02 - puntata 2.mp3\n"));
+        assert!(text.contains("    003 - /audio/p3.json: unknown: Status: 404\n"));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["missing"][0]["title"], "Puntata 2");
+    }
+}
@@ -0,0 +1,240 @@
+use crate::cache::{Cache, Entry};
+use crate::error::RsndError;
+use crate::fetcher::Fetcher;
+use crate::retry;
+use anyhow::{anyhow, Context, Result};
+#[cfg(feature = "convert-thumbnails")]
+use image::ImageFormat;
+#[cfg(feature = "convert-thumbnails")]
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Quality of the jpeg images converted from other formats.
+#[cfg(feature = "convert-thumbnails")]
+const JPEG_QUALITY: u8 = 90;
+
+/// Returns the path of the thumbnail saved next to an audio file: `<name>.jpg`.
+pub fn sidecar_path(audio: &Path) -> PathBuf {
+    let mut name = audio.file_stem().unwrap_or_default().to_owned();
+    name.push(".jpg");
+    audio.with_file_name(name)
+}
+
+/// Returns the image of the episode unless it is the image of the show, which many
+/// episodes repeat instead of having their own.
+///
+/// Images are compared by file name, since the site serves the same picture cropped to
+/// several sizes under different folders.
+pub fn own_image<'a>(image: Option<&'a str>, show_image: Option<&str>) -> Option<&'a str> {
+    let image = image?;
+    match show_image {
+        Some(show_image) if file_name(image) == file_name(show_image) => None,
+        _ => Some(image),
+    }
+}
+
+/// Returns the last segment of the path of an image URL, without its query.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Returns the cache entry of an image, already converted to jpeg, named with a hash of
+/// its URL, which several episodes can share.
+pub fn cache_entry(url: &str) -> Entry {
+    let name = format!("image-{}.jpg", crate::cache::digest(url));
+    Entry::new(url, name)
+}
+
+/// Returns the cached image as jpeg, or fetches it and caches it once it converted, so
+/// that an error page served in its place is fetched again next time.
+pub async fn fetch<F: Fetcher>(client: &F, cache: &dyn Cache, url: &str) -> Result<Vec<u8>> {
+    let entry = cache_entry(url);
+    if let Some(jpeg) = cache.get_bytes(&entry)? {
+        return Ok(jpeg);
+    }
+    let body = retry::policy()
+        .run(url, || async {
+            let response = client.get_stream(url).await?;
+            if !response.status().is_success() {
+                return Err(RsndError::status(url, &response).into());
+            }
+            let body = response
+                .bytes()
+                .await
+                .map_err(|source| RsndError::Download {
+                    url: url.to_string(),
+                    source,
+                })?;
+            Ok(body.to_vec())
+        })
+        .await?;
+    let jpeg = to_jpeg(body).with_context(|| format!("Invalid image: {}", url))?;
+    cache.put_bytes(&entry, &jpeg)?;
+    Ok(jpeg)
+}
+
+/// Returns the image as jpeg, converting it when it is in another format, like the
+/// webp images the site serves for some episodes.
+#[cfg(feature = "convert-thumbnails")]
+pub fn to_jpeg(body: Vec<u8>) -> Result<Vec<u8>> {
+    let format = image::guess_format(&body).context("Unknown image format")?;
+    if format == ImageFormat::Jpeg {
+        return Ok(body);
+    }
+    let decoded = image::load_from_memory_with_format(&body, format)
+        .with_context(|| format!("Failed to decode {:?} image", format))?;
+    let mut jpeg = Cursor::new(Vec::new());
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY);
+    // Jpeg has no transparency.
+    decoded
+        .to_rgb8()
+        .write_with_encoder(encoder)
+        .map_err(|err| anyhow!("Failed to encode jpeg image: {}", err))?;
+    Ok(jpeg.into_inner())
+}
+
+/// Returns the image when it is a jpeg; converting the other formats needs the
+/// convert-thumbnails feature.
+#[cfg(not(feature = "convert-thumbnails"))]
+pub fn to_jpeg(body: Vec<u8>) -> Result<Vec<u8>> {
+    if body.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Ok(body);
+    }
+    Err(anyhow!(
+        "Not a jpeg image, and this rsnd was built without converting the others: rebuild it with --features convert-thumbnails"
+    ))
+}
+
+/// Saves the image at `url` as the jpeg thumbnail of the audio file and returns its path.
+pub async fn write<F: Fetcher>(
+    client: &F,
+    cache: &dyn Cache,
+    url: &str,
+    audio: &Path,
+) -> Result<PathBuf> {
+    let jpeg = fetch(client, cache, url).await?;
+    let path = sidecar_path(audio);
+    std::fs::write(&path, jpeg).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::fetcher::MockFetcher;
+
+    /// Encodes a small picture in the given format.
+    #[cfg(feature = "convert-thumbnails")]
+    fn picture(format: ImageFormat) -> Vec<u8> {
+        let picture = image::RgbImage::from_pixel(4, 4, image::Rgb([200, 30, 30]));
+        let mut body = Cursor::new(Vec::new());
+        picture.write_to(&mut body, format).unwrap();
+        body.into_inner()
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("show/01 - lettura i.mp3")),
+            Path::new("show/01 - lettura i.jpg")
+        );
+        assert_eq!(
+            sidecar_path(Path::new("show/02 - vol. 2.m4a")),
+            Path::new("show/02 - vol. 2.jpg")
+        );
+    }
+
+    #[test]
+    fn test_own_image() {
+        let show = Some("https://www.raiplaysound.it/cropgd/1200x1200/moschettieri.jpg");
+        let cropped = "https://www.raiplaysound.it/cropgd/600x600/moschettieri.jpg?v=2";
+        let own = "https://www.raiplaysound.it/cropgd/600x600/lettura-1.webp";
+        assert_eq!(own_image(Some(cropped), show), None);
+        assert_eq!(own_image(Some(own), show), Some(own));
+        assert_eq!(own_image(Some(own), None), Some(own));
+        assert_eq!(own_image(None, show), None);
+    }
+
+    #[test]
+    fn test_cache_entry() {
+        let entry = cache_entry("https://img.example.com/a/cover.webp?w=600");
+        assert!(entry.name.starts_with("image-") && entry.name.ends_with(".jpg"));
+        assert_ne!(
+            entry,
+            cache_entry("https://img.example.com/b/cover.webp?w=600")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "convert-thumbnails")]
+    fn test_to_jpeg() {
+        let jpeg = picture(ImageFormat::Jpeg);
+        assert_eq!(to_jpeg(jpeg.clone()).unwrap(), jpeg);
+        for format in [ImageFormat::WebP, ImageFormat::Png] {
+            let converted = to_jpeg(picture(format)).unwrap();
+            assert_eq!(
+                image::guess_format(&converted).unwrap(),
+                ImageFormat::Jpeg,
+                "{:?}",
+                format
+            );
+        }
+        assert!(to_jpeg(b"<html>Not found</html>".to_vec()).is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "convert-thumbnails")]
+    async fn test_write_through_cache() {
+        let url = "https://img.example.com/lettura-1.webp";
+        let client = MockFetcher::default().serve(url, "image/webp", picture(ImageFormat::WebP));
+        let cache = MemoryCache::default();
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("01 - lettura i.mp3");
+
+        let path = write(&client, &cache, url, &audio).await.unwrap();
+        assert_eq!(path, dir.path().join("01 - lettura i.jpg"));
+        let saved = std::fs::read(&path).unwrap();
+        assert_eq!(image::guess_format(&saved).unwrap(), ImageFormat::Jpeg);
+
+        // The second time, the image comes from the cache.
+        std::fs::remove_file(&path).unwrap();
+        let offline = MockFetcher::default();
+        write(&offline, &cache, url, &audio).await.unwrap();
+        assert!(path.exists());
+        assert!(offline.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dead_image() {
+        let url = "https://img.example.com/gone.jpg";
+        let client = MockFetcher::default();
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("01 - lettura i.mp3");
+        let result = write(&client, &MemoryCache::default(), url, &audio).await;
+        assert!(result.is_err());
+        assert!(!sidecar_path(&audio).exists());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_image_is_not_cached() {
+        let url = "https://img.example.com/lettura-1.jpg";
+        let client =
+            MockFetcher::default().serve(url, "text/html", b"<html>Not found</html>".to_vec());
+        let cache = MemoryCache::default();
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("01 - lettura i.mp3");
+
+        let err = write(&client, &cache, url, &audio).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid image"), "{:#}", err);
+        assert!(!cache.contains(&cache_entry(url)));
+
+        // A jpeg needs no conversion.
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10];
+        let client = MockFetcher::default().serve(url, "image/jpeg", jpeg.clone());
+        let path = write(&client, &cache, url, &audio).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), jpeg);
+        assert!(cache.contains(&cache_entry(url)));
+    }
+}
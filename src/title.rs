@@ -0,0 +1,123 @@
+use regex::Regex;
+
+/// Which part of a title split with `--title-split` is kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TitlePart {
+    First,
+    #[default]
+    Last,
+    /// The part at this 1-based position.
+    Nth(usize),
+}
+
+/// Parses the `--title-part` value: `first`, `last` or a 1-based position.
+pub fn parse_title_part(value: &str) -> Result<TitlePart, String> {
+    match value {
+        "first" => Ok(TitlePart::First),
+        "last" => Ok(TitlePart::Last),
+        _ => value
+            .parse()
+            .ok()
+            .filter(|position| *position > 0)
+            .map(TitlePart::Nth)
+            .ok_or_else(|| format!("expected first, last or a position from 1, got {:?}", value)),
+    }
+}
+
+/// How episode titles are shortened before they are used anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct TitleTrim {
+    /// Matches removed from the title.
+    pub patterns: Vec<Regex>,
+    /// Delimiter the title is split at, keeping only `part`.
+    pub split: Option<String>,
+    pub part: TitlePart,
+}
+
+impl TitleTrim {
+    /// Returns the shortened title, or the whole title when nothing would be left of it.
+    ///
+    /// The title is split first, so that the patterns only see the part that is kept.
+    /// A title without the delimiter, or with fewer parts than asked for, is kept whole.
+    pub fn apply(&self, title: &str) -> String {
+        let mut trimmed = title.to_string();
+        if let Some(delimiter) = self.split.as_deref().filter(|d| !d.is_empty()) {
+            let parts: Vec<&str> = title.split(delimiter).collect();
+            let part = match self.part {
+                TitlePart::First => parts.first(),
+                TitlePart::Last => parts.last(),
+                TitlePart::Nth(position) => parts.get(position - 1),
+            };
+            if let Some(part) = part.filter(|_| parts.len() > 1) {
+                trimmed = part.trim().to_string();
+            }
+        }
+        for pattern in &self.patterns {
+            trimmed = pattern.replace_all(&trimmed, "").into_owned();
+        }
+        let trimmed = trimmed.split_whitespace().collect::<Vec<_>>().join(" ");
+        if trimmed.is_empty() {
+            title.to_string()
+        } else {
+            trimmed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::parse_title_regex;
+
+    const TITLE: &str = "Ad alta voce | I Promessi Sposi | Puntata 12";
+
+    fn split(part: TitlePart) -> TitleTrim {
+        TitleTrim {
+            split: Some("|".to_string()),
+            part,
+            ..TitleTrim::default()
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split(TitlePart::Last).apply(TITLE), "Puntata 12");
+        assert_eq!(split(TitlePart::First).apply(TITLE), "Ad alta voce");
+        assert_eq!(split(TitlePart::Nth(2)).apply(TITLE), "I Promessi Sposi");
+        assert_eq!(split(TitlePart::Nth(4)).apply(TITLE), TITLE);
+        assert_eq!(split(TitlePart::Last).apply("Puntata 12"), "Puntata 12");
+    }
+
+    #[test]
+    fn test_patterns() {
+        let trim = TitleTrim {
+            patterns: vec![
+                parse_title_regex(r"^ad alta voce \|").unwrap(),
+                parse_title_regex(r"\| puntata \d+$").unwrap(),
+            ],
+            ..TitleTrim::default()
+        };
+        assert_eq!(trim.apply(TITLE), "I Promessi Sposi");
+        // Nothing is left of the title, which is kept whole.
+        let trim = TitleTrim {
+            patterns: vec![parse_title_regex(".*").unwrap()],
+            ..TitleTrim::default()
+        };
+        assert_eq!(trim.apply(TITLE), TITLE);
+
+        let trim = TitleTrim {
+            patterns: vec![parse_title_regex(r"^puntata\s*").unwrap()],
+            ..split(TitlePart::Last)
+        };
+        assert_eq!(trim.apply(TITLE), "12");
+    }
+
+    #[test]
+    fn test_parse_title_part() {
+        assert_eq!(parse_title_part("first"), Ok(TitlePart::First));
+        assert_eq!(parse_title_part("last"), Ok(TitlePart::Last));
+        assert_eq!(parse_title_part("2"), Ok(TitlePart::Nth(2)));
+        assert!(parse_title_part("0").is_err());
+        assert!(parse_title_part("middle").is_err());
+    }
+}
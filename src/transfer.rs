@@ -0,0 +1,140 @@
+use crate::format_size;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Length of the windows the peak throughput is measured over.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// How a finished download went.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transfer {
+    /// Bytes of the successful attempt.
+    pub bytes: u64,
+    /// From the request to the last byte of the successful attempt.
+    pub elapsed: Duration,
+    /// Highest throughput over a second of the download, in bytes per second.
+    pub peak: f64,
+    /// Bytes received by the attempts that were retried.
+    pub wasted: u64,
+    /// Host of the `--cdn-fallback` mirror that served the file, when the CDN failed.
+    pub mirror: Option<String>,
+}
+
+impl Transfer {
+    /// Average throughput in bytes per second.
+    pub fn average(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {:.1}s, {}/s, peak {}/s",
+            format_size(self.bytes),
+            self.elapsed.as_secs_f64(),
+            format_size(self.average() as u64),
+            format_size(self.peak as u64)
+        )
+    }
+}
+
+/// Measures a download as its chunks arrive.
+#[derive(Debug)]
+pub struct Meter {
+    started: Instant,
+    bytes: u64,
+    window_start: Instant,
+    window_bytes: u64,
+    peak: f64,
+}
+
+impl Meter {
+    pub fn start() -> Self {
+        Meter::start_at(Instant::now())
+    }
+
+    fn start_at(now: Instant) -> Self {
+        Meter {
+            started: now,
+            bytes: 0,
+            window_start: now,
+            window_bytes: 0,
+            peak: 0.0,
+        }
+    }
+
+    /// Counts bytes that just arrived.
+    pub fn add(&mut self, bytes: u64) {
+        self.add_at(bytes, Instant::now());
+    }
+
+    fn add_at(&mut self, bytes: u64, now: Instant) {
+        self.bytes += bytes;
+        self.window_bytes += bytes;
+        let window = now - self.window_start;
+        if window >= WINDOW {
+            self.peak = self
+                .peak
+                .max(self.window_bytes as f64 / window.as_secs_f64());
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+    }
+
+    pub fn finish(self) -> Transfer {
+        self.finish_at(Instant::now())
+    }
+
+    /// Downloads shorter than a window have their average as peak.
+    fn finish_at(self, now: Instant) -> Transfer {
+        let mut transfer = Transfer {
+            bytes: self.bytes,
+            elapsed: now - self.started,
+            peak: self.peak,
+            wasted: 0,
+            mirror: None,
+        };
+        if transfer.peak == 0.0 {
+            transfer.peak = transfer.average();
+        }
+        transfer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut meter = Meter::start_at(start);
+        meter.add_at(100_000, at(500));
+        meter.add_at(100_000, at(1000));
+        // Three times as fast in the second window.
+        meter.add_at(600_000, at(2000));
+        meter.add_at(200_000, at(4000));
+        let transfer = meter.finish_at(at(4000));
+        assert_eq!(transfer.bytes, 1_000_000);
+        assert_eq!(transfer.elapsed, Duration::from_secs(4));
+        assert_eq!(transfer.average(), 250_000.0);
+        assert_eq!(transfer.peak, 600_000.0);
+
+        let mut meter = Meter::start_at(start);
+        meter.add_at(1000, at(250));
+        let transfer = meter.finish_at(at(500));
+        assert_eq!(transfer.peak, 2000.0);
+        assert_eq!(
+            transfer.to_string(),
+            "1000 B in 0.5s, 2.0 KB/s, peak 2.0 KB/s"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use crate::relinker::Container;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Arrays of alternative renditions in the episode JSON, by path.
+const RENDITION_FIELDS: &[(&str, &str)] = &[
+    ("audio", "renditions"),
+    ("audio", "variants"),
+    ("audio", "alternatives"),
+    ("", "renditions"),
+];
+
+/// Audio format a rendition can be preferred by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Mp3,
+    M4a,
+}
+
+impl Format {
+    fn from_container(container: Container) -> Option<Self> {
+        match container {
+            Container::Mp3 => Some(Format::Mp3),
+            Container::M4a => Some(Format::M4a),
+            _ => None,
+        }
+    }
+}
+
+/// An audio rendition of an episode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Variant {
+    pub url: String,
+    /// From the declared format or MIME type, or else the extension of the URL.
+    pub format: Option<Format>,
+    /// Bitrate in kbit/s.
+    pub bitrate: Option<u32>,
+}
+
+impl Variant {
+    /// A rendition known only by its URL, like the main `audio.url`.
+    pub fn from_url(url: &str) -> Self {
+        Variant {
+            url: url.to_string(),
+            format: Container::from_url(url).and_then(Format::from_container),
+            bitrate: None,
+        }
+    }
+}
+
+/// Which rendition `--quality` asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quality {
+    Best,
+    Worst,
+    /// The rendition closest to this bitrate, in kbit/s.
+    Bitrate(u32),
+}
+
+/// Parses the `--quality` value: `best`, `worst` or a bitrate like `128` or `128k`.
+pub fn parse_quality(value: &str) -> Result<Quality, String> {
+    match value.to_lowercase().as_str() {
+        "best" => Ok(Quality::Best),
+        "worst" => Ok(Quality::Worst),
+        other => other
+            .trim_end_matches(['k', 'K'])
+            .parse()
+            .ok()
+            .filter(|bitrate| *bitrate > 0)
+            .map(Quality::Bitrate)
+            .ok_or_else(|| {
+                format!(
+                    "expected best, worst or a bitrate in kbit/s, got {:?}",
+                    value
+                )
+            }),
+    }
+}
+
+/// How to pick among the renditions of an episode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Choice {
+    pub quality: Option<Quality>,
+    pub format: Option<Format>,
+}
+
+impl Choice {
+    /// Returns the rendition to download, or `None` to keep the main audio URL.
+    ///
+    /// Renditions of the preferred format win when there are any. Among those, the first
+    /// is taken without `--quality`, which keeps the main URL when it qualifies.
+    pub fn pick<'a>(&self, variants: &'a [Variant]) -> Option<&'a Variant> {
+        if *self == Choice::default() {
+            return None;
+        }
+        let preferred: Vec<&Variant> = variants
+            .iter()
+            .filter(|variant| self.format.is_some() && variant.format == self.format)
+            .collect();
+        let candidates = if preferred.is_empty() {
+            variants.iter().collect()
+        } else {
+            preferred
+        };
+        match self.quality {
+            None => candidates.first().copied(),
+            // Renditions of unknown bitrate only win when no other is known.
+            Some(Quality::Best) => candidates
+                .into_iter()
+                .rev()
+                .max_by_key(|variant| variant.bitrate),
+            Some(Quality::Worst) => candidates
+                .into_iter()
+                .min_by_key(|variant| (variant.bitrate.is_none(), variant.bitrate)),
+            Some(Quality::Bitrate(target)) => candidates.into_iter().min_by_key(|variant| {
+                (
+                    variant.bitrate.is_none(),
+                    variant.bitrate.map(|bitrate| bitrate.abs_diff(target)),
+                )
+            }),
+        }
+    }
+}
+
+/// Reads the alternative renditions listed in the episode JSON, in order and without
+/// duplicates. Entries without a URL are left out.
+pub fn parse_variants(json_value: &Value) -> Vec<Variant> {
+    let mut variants: Vec<Variant> = Vec::new();
+    for (parent, field) in RENDITION_FIELDS {
+        let parent = if parent.is_empty() {
+            json_value
+        } else {
+            &json_value[*parent]
+        };
+        let Some(items) = parent[*field].as_array() else {
+            continue;
+        };
+        for item in items {
+            let Some(variant) = parse_variant(item) else {
+                continue;
+            };
+            if !variants.iter().any(|known| known.url == variant.url) {
+                variants.push(variant);
+            }
+        }
+    }
+    variants
+}
+
+fn parse_variant(item: &Value) -> Option<Variant> {
+    let url = ["url", "src", "downloadUrl"]
+        .iter()
+        .find_map(|key| item[*key].as_str())
+        .map(str::trim)
+        .filter(|url| !url.is_empty())?;
+    let mut variant = Variant::from_url(url);
+    let declared = ["format", "container", "mime_type", "mimeType", "type"]
+        .iter()
+        .filter_map(|key| item[*key].as_str())
+        .find_map(|format| match format.to_lowercase().as_str() {
+            "mp3" | "audio/mpeg" | "audio/mp3" => Some(Format::Mp3),
+            "m4a" | "mp4" | "aac" | "audio/mp4" | "audio/x-m4a" | "audio/aac" => Some(Format::M4a),
+            _ => None,
+        });
+    variant.format = declared.or(variant.format);
+    variant.bitrate = ["bitrate", "bit_rate", "bitRate", "quality"]
+        .iter()
+        .find_map(|key| parse_bitrate(&item[*key]));
+    Some(variant)
+}
+
+/// Reads a bitrate given as a number or a string like "128" or "128 kbps", in kbit/s or,
+/// from 1000 up, in bit/s.
+fn parse_bitrate(value: &Value) -> Option<u32> {
+    let bitrate = match value {
+        Value::Number(number) => number.as_u64()?,
+        Value::String(text) => {
+            let digits: String = text
+                .trim()
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            digits.parse().ok()?
+        }
+        _ => return None,
+    };
+    let kbps = if bitrate >= 1000 {
+        bitrate / 1000
+    } else {
+        bitrate
+    };
+    u32::try_from(kbps).ok().filter(|kbps| *kbps > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn variant(url: &str, format: Option<Format>, bitrate: Option<u32>) -> Variant {
+        Variant {
+            url: url.to_string(),
+            format,
+            bitrate,
+        }
+    }
+
+    #[test]
+    fn test_parse_variants() {
+        let value = json!({
+            "audio": {
+                "url": "https://example.com/a.mp3",
+                "renditions": [
+                    {"url": "https://example.com/a_64.mp3", "bitrate": 64},
+                    {"url": "https://example.com/a_128", "mime_type": "audio/mp4", "bitrate": "128 kbps"},
+                    {"bitrate": 256},
+                ],
+                "variants": [
+                    {"src": "https://example.com/a_64.mp3", "bitrate": 64},
+                    {"url": "https://example.com/a_192.m4a", "bit_rate": 192000},
+                ],
+            }
+        });
+        assert_eq!(
+            parse_variants(&value),
+            vec![
+                variant("https://example.com/a_64.mp3", Some(Format::Mp3), Some(64)),
+                variant("https://example.com/a_128", Some(Format::M4a), Some(128)),
+                variant(
+                    "https://example.com/a_192.m4a",
+                    Some(Format::M4a),
+                    Some(192)
+                ),
+            ]
+        );
+        assert!(parse_variants(&json!({"audio": {"url": "https://example.com/a.mp3"}})).is_empty());
+    }
+
+    #[test]
+    fn test_parse_quality() {
+        assert_eq!(parse_quality("best"), Ok(Quality::Best));
+        assert_eq!(parse_quality("Worst"), Ok(Quality::Worst));
+        assert_eq!(parse_quality("128"), Ok(Quality::Bitrate(128)));
+        assert_eq!(parse_quality("96k"), Ok(Quality::Bitrate(96)));
+        assert!(parse_quality("0").is_err());
+        assert!(parse_quality("high").is_err());
+    }
+
+    #[test]
+    fn test_pick() {
+        let variants = [
+            variant("https://example.com/a.mp3", Some(Format::Mp3), None),
+            variant("https://example.com/a_64.mp3", Some(Format::Mp3), Some(64)),
+            variant(
+                "https://example.com/a_128.m4a",
+                Some(Format::M4a),
+                Some(128),
+            ),
+            variant(
+                "https://example.com/a_192.mp3",
+                Some(Format::Mp3),
+                Some(192),
+            ),
+        ];
+        let pick = |quality, format| {
+            Choice { quality, format }
+                .pick(&variants)
+                .map(|variant| variant.url.as_str())
+        };
+        assert_eq!(pick(None, None), None);
+        assert_eq!(
+            pick(Some(Quality::Best), None),
+            Some("https://example.com/a_192.mp3")
+        );
+        assert_eq!(
+            pick(Some(Quality::Worst), None),
+            Some("https://example.com/a_64.mp3")
+        );
+        assert_eq!(
+            pick(Some(Quality::Bitrate(120)), None),
+            Some("https://example.com/a_128.m4a")
+        );
+        assert_eq!(
+            pick(Some(Quality::Bitrate(120)), Some(Format::Mp3)),
+            Some("https://example.com/a_64.mp3")
+        );
+        // Without --quality, the main URL is kept when it has the preferred format.
+        assert_eq!(
+            pick(None, Some(Format::Mp3)),
+            Some("https://example.com/a.mp3")
+        );
+        assert_eq!(
+            pick(None, Some(Format::M4a)),
+            Some("https://example.com/a_128.m4a")
+        );
+        // A format no rendition has is ignored.
+        let mp3_only = &variants[..2];
+        assert_eq!(
+            Choice {
+                quality: Some(Quality::Best),
+                format: Some(Format::M4a),
+            }
+            .pick(mp3_only)
+            .map(|variant| variant.url.as_str()),
+            Some("https://example.com/a_64.mp3")
+        );
+    }
+}
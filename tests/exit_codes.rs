@@ -0,0 +1,2847 @@
+use assert_cmd::Command;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const SHOW_URL: &str = "https://www.raiplaysound.it/audiolibri/itremoschettieri";
+
+/// An address nothing listens on, so requests fail immediately.
+const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+/// Writes a cached show page with one episode whose audio lives at `audio_url`.
+fn cache_show(cache: &Path, audio_url: &str) {
+    fs::write(
+        cache.join("itremoschettieri.html"),
+        r#"<rps-play-with-labels options='{"url": "/audio/2015/06/lettura-1.json"}'></rps-play-with-labels>"#,
+    )
+    .unwrap();
+    fs::write(
+        cache.join("lettura-1.json"),
+        format!(
+            r#"{{"audio": {{"title": "Lettura I", "url": "{}", "duration": "00:19:15"}}}}"#,
+            audio_url
+        ),
+    )
+    .unwrap();
+}
+
+fn rsnd_json(cache: &TempDir, folder: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("rsnd").unwrap();
+    cmd.arg("--cache")
+        .arg(cache.path())
+        .arg("--folder")
+        .arg(folder.path());
+    cmd
+}
+
+/// Reads the cached HTML pages, so the tests never reach the live JSON API.
+fn rsnd(cache: &TempDir, folder: &TempDir) -> Command {
+    let mut cmd = rsnd_json(cache, folder);
+    cmd.arg("--scrape-html");
+    cmd
+}
+
+#[test]
+fn test_success_exits_0() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(folder.path().join("001 - lettura i.mp3"), b"ID3").unwrap();
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn test_usage_errors_exit_1() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    rsnd(&cache, &folder).assert().code(1);
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--select", "0"])
+        .assert()
+        .code(1);
+    Command::cargo_bin("rsnd")
+        .unwrap()
+        .arg("--help")
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn test_page_fetch_failure_exits_2() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    rsnd(&cache, &folder)
+        .args(["--url", &format!("{}/programmi/show", UNREACHABLE)])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_episode_failure_exits_3() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_nothing_to_do_exits_4() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    fs::write(
+        cache.path().join("itremoschettieri.html"),
+        "<html><body>Nothing here</body></html>",
+    )
+    .unwrap();
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .assert()
+        .code(4);
+
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(folder.path().join("001 - lettura i.mp3"), b"ID3").unwrap();
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_failing_show_does_not_stop_others() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(folder.path().join("001 - lettura i.mp3"), b"ID3").unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", &format!("{}/programmi/show", UNREACHABLE)])
+        .arg(SHOW_URL)
+        .assert()
+        .code(2);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("already exists. Skipping download."));
+    assert!(stdout.contains("Episodes found: 1"));
+}
+
+#[test]
+fn test_batch_file_folder_override() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    let override_folder = folder.path().join("moschettieri");
+    fs::create_dir(&override_folder).unwrap();
+    fs::write(override_folder.join("001 - lettura i.mp3"), b"ID3").unwrap();
+    let batch = cache.path().join("shows.txt");
+    fs::write(
+        &batch,
+        format!(
+            "# shows\nnot a url\n{}\t{}\n{}\n",
+            SHOW_URL,
+            override_folder.display(),
+            SHOW_URL
+        ),
+    )
+    .unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .arg("--batch-file")
+        .arg(&batch)
+        .assert()
+        .code(0);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("skipping batch line 2"));
+    assert!(stderr.contains("skipping batch line 4: duplicate URL"));
+}
+
+#[test]
+fn test_batch_file_folders_are_checked_first() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    let relative = folder.path().join("libri/moschettieri");
+    fs::create_dir_all(&relative).unwrap();
+    fs::write(relative.join("001 - lettura i.mp3"), b"ID3").unwrap();
+    let batch = cache.path().join("shows.txt");
+    fs::write(
+        &batch,
+        format!(
+            "{}\tlibri/moschettieri\n{}\t{}\n",
+            SHOW_URL,
+            "https://www.raiplaysound.it/programmi/ilruggitodelconiglio",
+            folder.path().join("missing/news").display()
+        ),
+    )
+    .unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .arg("--batch-file")
+        .arg(&batch)
+        .assert()
+        .code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Invalid output folders"));
+    assert!(stderr.contains("batch line 2: neither "));
+    assert!(!stdout.contains("already exists"));
+
+    // Relative folders are under --folder.
+    fs::write(&batch, format!("{}\tlibri/moschettieri\n", SHOW_URL)).unwrap();
+    let assert = rsnd(&cache, &folder)
+        .arg("--batch-file")
+        .arg(&batch)
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Skipped:        1"));
+}
+
+#[test]
+fn test_empty_show_page_is_diagnosed() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // A show that no longer exists redirects to the home page.
+    let server = serve(|_, path| {
+        if path == "/audiolibri/itremoshettieri" {
+            return b"HTTP/1.1 302 Found\r\nLocation: /\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec();
+        }
+        let body = "<html><head><title>RaiPlay Sound</title></head></html>";
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    });
+    let show_url = format!("{}/audiolibri/itremoshettieri", server);
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", &show_url])
+        .assert()
+        .code(4);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains(&format!("No episodes found on page {}", show_url)));
+    assert!(stderr.contains(&format!("Final URL:  {}/ (status 200)", server)));
+    assert!(stderr.contains("Title:      \"RaiPlay Sound\""));
+    assert!(stderr.contains("no rps-play-with-labels elements"));
+    assert!(stderr.contains("Looks like: the home page"));
+
+    rsnd(&cache, &folder)
+        .args(["--url", &show_url, "--allow-empty"])
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn test_show_url_variants_share_the_cached_page() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(folder.path().join("01 - lettura i.mp3"), b"ID3").unwrap();
+
+    for url in [
+        format!("{}/", SHOW_URL),
+        format!("{}#episodi", SHOW_URL),
+        SHOW_URL.replace("www.raiplaysound.it", "WWW.RAIPLAYSOUND.IT"),
+    ] {
+        rsnd(&cache, &folder).args(["--url", &url]).assert().code(0);
+    }
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", "www.raiplaysound.it/audiolibri/itremoschettieri"])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Invalid URL: www.raiplaysound.it/audiolibri/itremoschettieri"));
+}
+
+const EPISODE_URL: &str = "https://www.raiplaysound.it/audio/2015/06/lettura-1.html";
+
+/// Copies the show page and episode metadata fixtures into the cache.
+fn cache_fixtures(cache: &Path) {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for name in ["itremoschettieri.html", "lettura-1.json"] {
+        fs::copy(fixtures.join(name), cache.join(name)).unwrap();
+    }
+}
+
+#[test]
+fn test_show_fixture() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--keep-case"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - Lettura I.mp3 (would download)"));
+
+    // Files saved without --keep-case are not downloaded again.
+    fs::write(folder.path().join("01 - lettura i.mp3"), b"ID3").unwrap();
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--keep-case"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_numbering_controls() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--start-index", "27"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/27 - lettura i.mp3 (would download)"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--no-index"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/lettura i.mp3 (would download)"));
+
+    fs::write(folder.path().join("01 - lettura i.mp3"), b"ID3").unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--pad-width", "3"])
+        .assert()
+        .code(4);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("01 - lettura i.mp3 is numbered with another pad width"));
+}
+
+#[test]
+fn test_stable_names_and_index_drift() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+    fs::write(folder.path().join("01 - lettura zero.mp3"), b"ID3").unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("001 - Lettura I would be numbered like 01 - lettura zero.mp3"));
+    assert!(stdout.contains("Index drift:    1 (consider --stable-names)"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--stable-names"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("2015-06-12 - lettura i.mp3 (would download)"));
+    assert!(!stdout.contains("Index drift"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--name-by-date"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("2015-06-12 - lettura i.mp3 (would download)"));
+    assert!(!stdout.contains("Index drift"));
+}
+
+#[test]
+fn test_day_ranks_ignore_selection() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let card = |n: usize| {
+        format!(
+            r#"{{"path_id": "/audio/p{n}.json", "create_date": "15-03-2024", "audio": {{"title": "Puntata {n}", "url": "{}/p{n}.mp3"}}}}"#,
+            UNREACHABLE
+        )
+    };
+    fs::write(
+        cache.path().join("audiolibri_itremoschettieri.json"),
+        format!(r#"{{"block": {{"cards": [{}, {}]}}}}"#, card(1), card(2)),
+    )
+    .unwrap();
+
+    for skip in ["0", "1"] {
+        let assert = rsnd_json(&cache, &folder)
+            .args([
+                "--url",
+                SHOW_URL,
+                "--dry-run",
+                "--name-by-date",
+                "--skip",
+                skip,
+            ])
+            .assert()
+            .code(0);
+        let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+        assert!(
+            stdout.contains("/2024-03-15-2 - puntata 2.mp3 (would download)"),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn test_duplicate_titles() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let card = |n: usize| {
+        format!(
+            r#"{{"path_id": "/audio/p{n}.json", "uniquename": "ContentItem-{n}", "audio": {{"title": "Puntata del giorno", "url": "{}/p{n}.mp3"}}}}"#,
+            UNREACHABLE
+        )
+    };
+    fs::write(
+        cache.path().join("audiolibri_itremoschettieri.json"),
+        format!(r#"{{"block": {{"cards": [{}, {}]}}}}"#, card(1), card(2)),
+    )
+    .unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--no-index"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("002 - Puntata del giorno has the same file name as another episode"));
+    assert!(stdout.contains("2 of 2 episodes would be downloaded"));
+    assert!(!stdout.contains("/puntata del giorno.mp3"));
+
+    // The name is the same when the other episode is not selected.
+    let assert = rsnd_json(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--dry-run",
+            "--no-index",
+            "--select",
+            "2",
+        ])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("1 of 1 episodes would be downloaded"));
+    assert!(stdout.contains("/puntata del giorno ~"), "{}", stdout);
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(!stderr.contains("same file name"));
+}
+
+#[test]
+fn test_audiobook_parts() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let card = |n: usize, title: &str| {
+        format!(
+            r#"{{"path_id": "/audio/p{n}.json", "uniquename": "ContentItem-{n}", "audio": {{"title": "{title}", "url": "{}/p{n}.mp3"}}}}"#,
+            UNREACHABLE
+        )
+    };
+    fs::write(
+        cache.path().join("audiolibri_itremoschettieri.json"),
+        format!(
+            r#"{{"block": {{"cards": [{}, {}, {}]}}}}"#,
+            card(1, "Lettura III"),
+            card(2, "Lettura I"),
+            card(3, "Lettura IV")
+        ),
+    )
+    .unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--sort", "part"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Warning: part 2 is missing from the page"));
+    assert!(stdout.contains("/01 - lettura i.mp3 (would download)"));
+    assert!(stdout.contains("/03 - lettura iv.mp3 (would download)"));
+}
+
+#[test]
+fn test_check_reports_missing_episodes() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    fs::copy(
+        fixtures.join("itremoschettieri.json"),
+        cache.path().join("audiolibri_itremoschettieri.json"),
+    )
+    .unwrap();
+    // The metadata of the second episode is neither in the card nor in the cache.
+    fs::write(cache.path().join("lettura-2.json"), "not json").unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--check", "--json"])
+        .assert()
+        .code(5);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    // Progress goes to stderr, leaving stdout to the report alone.
+    let report: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["missing"][0]["title"], "Lettura I");
+    assert_eq!(report["unknown"][0]["index"], 2);
+    assert_eq!(report["failed"].as_array().unwrap().len(), 0);
+    assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 0);
+
+    fs::write(folder.path().join("01 - lettura i.mp3"), b"ID3").unwrap();
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--check"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("  Unknown:        1 (metadata unavailable)"));
+    assert!(!stdout.contains("Missing"));
+}
+
+#[test]
+fn test_search() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+    fs::write(
+        cache.path().join("search_tre-moschettieri.json"),
+        r#"{"cards": [{"title": "I tre moschettieri", "weblink": "/audiolibri/itremoschettieri"}]}"#,
+    )
+    .unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["search", "tre moschettieri"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert_eq!(
+        stdout,
+        " 1. I tre moschettieri (audiobook)\n    https://www.raiplaysound.it/audiolibri/itremoschettieri\n"
+    );
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["search", "tre moschettieri", "--json"])
+        .assert()
+        .code(0);
+    let results: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(results[0]["url"], SHOW_URL);
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--dry-run", "search", "tre moschettieri", "--download", "1"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+
+    rsnd(&cache, &folder)
+        .args(["search", "tre moschettieri", "--download", "2"])
+        .assert()
+        .code(1);
+
+    // Results older than an hour are searched again, unless offline.
+    let search = cache.path().join("search_tre-moschettieri.json");
+    let two_hours_ago = std::time::SystemTime::now() - Duration::from_secs(2 * 3600);
+    fs::File::options()
+        .write(true)
+        .open(&search)
+        .and_then(|file| file.set_modified(two_hours_ago))
+        .unwrap();
+    rsnd_json(&cache, &folder)
+        .args(["--offline", "search", "tre moschettieri"])
+        .assert()
+        .code(0);
+    let server = serve(|_, _| {
+        let body = r#"{"cards": [{"title": "Il conte", "weblink": "/audiolibri/ilconte"}]}"#;
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    });
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--base-url", &format!("{}/", server)])
+        .args(["search", "tre moschettieri"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Il conte"));
+}
+
+#[test]
+fn test_crawl_catalog() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+    fs::write(
+        cache.path().join("catalog_generi_audiolibri.html"),
+        r#"<a href="/audiolibri/ilconte">Il conte di Montecristo</a><a rel="next" href="?page=2">2</a>"#,
+    )
+    .unwrap();
+    fs::write(
+        cache.path().join("catalog_generi_audiolibri_page_2.html"),
+        r#"<a href="/audiolibri/itremoschettieri">I tre moschettieri</a>"#,
+    )
+    .unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--crawl",
+            "https://www.raiplaysound.it/generi/audiolibri",
+            "--crawl-match",
+            "moschettieri",
+            "--dry-run",
+        ])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Crawling 1 of the 2 shows"));
+    assert!(stdout.contains("/i tre moschettieri/01 - lettura i.mp3 (would download)"));
+}
+
+#[test]
+fn test_show_subdirs() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--show-subdirs"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/i tre moschettieri/01 - lettura i.mp3 (would download)"));
+    assert!(stdout.contains("  Folder:         "));
+    // A dry run leaves the folder untouched.
+    assert!(!folder.path().join("i tre moschettieri").exists());
+}
+
+#[test]
+fn test_single_episode_url() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", EPISODE_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/lettura i.mp3 (would download)"));
+
+    fs::write(folder.path().join("005 - lettura i.mp3"), b"ID3").unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", EPISODE_URL, "--index", "5"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Skipped:        1 (already present)"));
+
+    // A failed download is recorded like one of a show.
+    fs::remove_file(folder.path().join("005 - lettura i.mp3")).unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", EPISODE_URL, "--index", "5"])
+        .assert()
+        .code(3);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Failed:         1"));
+    assert!(stdout.contains("    005 - Lettura I: "));
+}
+
+#[test]
+fn test_multiblock_programme() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for (fixture, name) in [
+        ("multiblock.html", "ilconte.html"),
+        ("ilconte-set-2.json", "programmi_ilconte_puntate_set-2.json"),
+        ("ilconte-set-3.json", "programmi_ilconte_puntate_set-3.json"),
+    ] {
+        fs::copy(fixtures.join(fixture), cache.path().join(name)).unwrap();
+    }
+    for episode in 1..=5 {
+        fs::write(
+            cache.path().join(format!("ilconte-{}.json", episode)),
+            format!(
+                r#"{{"audio": {{"title": "Puntata {}", "url": "{}/ilconte-{}.mp3"}}}}"#,
+                episode, UNREACHABLE, episode
+            ),
+        )
+        .unwrap();
+    }
+
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--url",
+            "https://www.raiplaysound.it/programmi/ilconte",
+            "--dry-run",
+        ])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Fetched 3 more episodes from 2 episode sets"));
+    assert!(stdout.contains("05 - puntata 5.mp3 (would download)"));
+    assert!(stdout.contains("Episodes found: 5"));
+}
+
+#[test]
+fn test_show_json_api() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    fs::copy(
+        fixtures.join("itremoschettieri.json"),
+        cache.path().join("audiolibri_itremoschettieri.json"),
+    )
+    .unwrap();
+    // The second card has no audio, so its own metadata file is read.
+    fs::write(
+        cache.path().join("lettura-2.json"),
+        format!(
+            r#"{{"audio": {{"title": "Lettura II", "url": "{}/lettura-2.mp3"}}}}"#,
+            UNREACHABLE
+        ),
+    )
+    .unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+    assert!(stdout.contains("02 - lettura ii.mp3 (would download)"));
+    assert!(stdout.contains("Episodes found: 2"));
+}
+
+#[test]
+fn test_season_names() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let card = |n: usize| {
+        format!(
+            r#"{{"path_id": "/audio/p{n}.json", "audio": {{"title": "Puntata {n}", "url": "{}/p{n}.mp3"}}}}"#,
+            UNREACHABLE
+        )
+    };
+    fs::write(
+        cache.path().join("audiolibri_itremoschettieri.json"),
+        format!(
+            r#"{{"blocks": [{{"title": "Stagione 1", "cards": [{}, {}]}}, {{"title": "Stagione 2", "cards": [{}]}}]}}"#,
+            card(1),
+            card(2),
+            card(3)
+        ),
+    )
+    .unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--name-scheme", "sxxeyy"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/S01E02 - puntata 2.mp3 (would download)"));
+    assert!(stdout.contains("/S02E01 - puntata 3.mp3 (would download)"));
+
+    // Skipping earlier episodes does not renumber the later ones.
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--name-scheme", "sxxeyy"])
+        .args(["--skip", "1"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!stdout.contains("puntata 1.mp3"));
+    assert!(stdout.contains("/S01E02 - puntata 2.mp3 (would download)"));
+    assert!(stdout.contains("/S02E01 - puntata 3.mp3 (would download)"));
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--season-dirs"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/Stagione 01/02 - puntata 2.mp3 (would download)"));
+    assert!(stdout.contains("/Stagione 02/01 - puntata 3.mp3 (would download)"));
+
+    fs::create_dir(folder.path().join("Stagione 02")).unwrap();
+    fs::write(folder.path().join("Stagione 02/03 - puntata 3.mp3"), b"ID3").unwrap();
+    let assert = rsnd_json(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--dry-run",
+            "--season-dirs",
+            "--global-index",
+        ])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("2 of 3 episodes would be downloaded"));
+}
+
+/// Serves every request on a local port with the response built by `respond` from the
+/// request method and path, and returns the base URL of the server.
+fn serve(respond: fn(&str, &str) -> Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            let mut parts = request_line.split_whitespace();
+            let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+            let _ = stream.write_all(&respond(method, path));
+        }
+    });
+    base
+}
+
+/// The answer of a CDN to a request for an MP3 of 100000 bytes, of which it sends
+/// `sent`, the headers only to HEAD requests.
+fn audio_response(method: &str, sent: usize) -> Vec<u8> {
+    let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nContent-Length: 100000\r\nConnection: close\r\n\r\n".to_vec();
+    if method == "GET" {
+        response.extend_from_slice(b"ID3");
+        response.resize(response.len() + sent - 3, 0);
+    }
+    response
+}
+
+/// Serves the whole 100000 bytes of MP3 for every path.
+fn serve_audio() -> String {
+    serve(|method, _| audio_response(method, 100_000))
+}
+
+/// Writes a cached show page listing `count` episodes, titled "Lettura 1" on, whose
+/// audio is served by `server` as `/1.mp3` on.
+fn cache_episodes(cache: &Path, count: usize, server: &str) {
+    fs::write(
+        cache.join("itremoschettieri.html"),
+        (1..=count)
+            .map(|n| {
+                format!(
+                    r#"<rps-play-with-labels options='{{"url": "/audio/2015/06/lettura-{}.json"}}'></rps-play-with-labels>"#,
+                    n
+                )
+            })
+            .collect::<String>(),
+    )
+    .unwrap();
+    for n in 1..=count {
+        fs::write(
+            cache.join(format!("lettura-{}.json", n)),
+            format!(
+                r#"{{"audio": {{"title": "Lettura {}", "url": "{}/{}.mp3"}}}}"#,
+                n, server, n
+            ),
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn test_short_download_is_retried_and_reported() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // Advertises 100000 bytes but closes the connection after 20000.
+    let server = serve(|method, _| audio_response(method, 20_000));
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--retries", "1"])
+        .assert()
+        .code(3);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stdout.contains("Retrying Lettura I (1/1)"));
+    // Both attempts failed, so nothing counts as wasted by a retry that succeeded.
+    assert!(!stdout.contains("Wasted:"));
+    assert!(stderr.contains("Incomplete download: received 20000 of 100000 bytes"));
+    assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_retry_on_limits_the_retried_failures() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| audio_response(method, 20_000));
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--retries",
+            "1",
+            "--retry-on",
+            "connect,5xx",
+        ])
+        .assert()
+        .code(3);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!stdout.contains("Retrying"));
+
+    let assert = Command::cargo_bin("rsnd")
+        .unwrap()
+        .arg("--list-retry-classes")
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("short-read  The download ended before its advertised size"));
+    assert!(!stdout.contains("Episodes found"));
+    // It stands alone, like --help.
+    Command::cargo_bin("rsnd")
+        .unwrap()
+        .args(["--list-retry-classes", "--url", SHOW_URL])
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_empty_download_fails() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|_, _| {
+        b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec()
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--retries", "1"])
+        .assert()
+        .code(3);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Empty download: received no data"));
+    assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_tiny_download_is_suspicious() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| {
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 1021, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Suspicious:     1 (smaller than --min-size)"));
+    assert!(stdout.contains("001 - Lettura I: only 1.0 KB downloaded"));
+    assert!(folder.path().join("01 - lettura i.mp3").exists());
+
+    fs::remove_file(folder.path().join("01 - lettura i.mp3")).unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!stdout.contains("Suspicious"));
+}
+
+#[test]
+fn test_check_duration_mismatch() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // Ten MPEG-1 layer III frames, a quarter of a second against the 19 minutes advertised.
+    let server = serve(|method, _| {
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            for _ in 0..10 {
+                let start = response.len();
+                response.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+                response.resize(start + 417, 0);
+            }
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0", "--check-duration"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("lasts 00:00:00, but its metadata says 00:19:15"));
+
+    fs::remove_file(folder.path().join("01 - lettura i.mp3")).unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--min-size",
+            "0",
+            "--check-duration",
+            "strict",
+            "--write-info-json",
+        ])
+        .assert()
+        .code(3);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("but its metadata says 00:19:15"));
+    // The rejected file is moved aside rather than kept as if it were complete, and
+    // counts as failed only.
+    assert!(!folder.path().join("01 - lettura i.mp3").exists());
+    assert!(folder.path().join("01 - lettura i.mp3.mismatch").is_file());
+    assert!(!folder.path().join("01 - lettura i.info.json").exists());
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Downloaded:     0"));
+    assert!(stdout.contains("Failed:         1"));
+}
+
+#[test]
+fn test_extension_follows_content() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // Served as a generic binary from a URL ending in .mp3, but the body is an MP4.
+    let server = serve(|method, _| {
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"\x00\x00\x00\x20ftypM4A ");
+            response.resize(response.len() + 1012, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0"])
+        .assert()
+        .code(0);
+    assert!(folder.path().join("01 - lettura i.m4a").exists());
+
+    let forced = TempDir::new().unwrap();
+    rsnd(&cache, &forced)
+        .args(["--url", SHOW_URL, "--min-size", "0", "--extension", "mp3"])
+        .assert()
+        .code(0);
+    assert!(forced.path().join("01 - lettura i.mp3").exists());
+}
+
+#[test]
+fn test_original_names() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| {
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 1021, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/Lettura_1.mp3?auth=x", server));
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0", "--original-names"])
+        .assert()
+        .code(0);
+    assert!(folder.path().join("01 - Lettura_1.mp3").exists());
+    let names = fs::read_to_string(folder.path().join("names.json")).unwrap();
+    assert!(names.contains("\"01 - Lettura_1.mp3\""));
+    assert!(names.contains("\"title\": \"Lettura I\""));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--original-names"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - Lettura_1.mp3 already exists"));
+}
+
+#[test]
+fn test_incremental() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, path| {
+        if path.starts_with("/programmi/") {
+            let mut response =
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n".to_vec();
+            for n in 1..=5 {
+                response.extend_from_slice(
+                    format!(r#"<rps-play-with-labels options='{{"url": "/audio/2024/05/p{}.json"}}'></rps-play-with-labels>"#, n)
+                        .as_bytes(),
+                );
+            }
+            return response;
+        }
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 1021, 0);
+        }
+        response
+    });
+    for n in 1..=5 {
+        fs::write(
+            cache.path().join(format!("p{}.json", n)),
+            format!(
+                r#"{{"track_info": {{"date": "2024-05-0{}"}}, "audio": {{"title": "Puntata {}", "url": "{}/p{}.mp3"}}}}"#,
+                6 - n,
+                n,
+                server,
+                n
+            ),
+        )
+        .unwrap();
+    }
+    // A stale page listing no episodes, which --incremental must not read.
+    fs::write(cache.path().join("show.html"), "<html></html>").unwrap();
+    let show_url = format!("{}/programmi/show", server);
+    let state = format!(
+        r#"{{"{}": {{"newest": "/audio/2024/05/p2.json", "date": "2024-05-04"}}}}"#,
+        show_url
+    );
+    fs::write(folder.path().join("rsnd-state.json"), state).unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", &show_url, "--incremental", "--min-size", "0"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("1 new episodes since 2024-05-04"));
+    assert!(stdout.contains("Downloaded:     4"));
+    assert!(!folder.path().join("05 - puntata 5.mp3").exists());
+    let state = fs::read_to_string(folder.path().join("rsnd-state.json")).unwrap();
+    assert!(state.contains(r#""newest": "/audio/2024/05/p1.json""#));
+    assert!(state.contains(r#""date": "2024-05-05""#));
+
+    // A run cut short leaves the episodes it did not reach new for the next one.
+    let state = format!(
+        r#"{{"{}": {{"newest": "/audio/2024/05/p3.json", "date": "2024-05-03"}}}}"#,
+        show_url
+    );
+    fs::write(folder.path().join("rsnd-state.json"), &state).unwrap();
+    fs::remove_file(folder.path().join("01 - puntata 1.mp3")).unwrap();
+    fs::remove_file(folder.path().join("02 - puntata 2.mp3")).unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", &show_url, "--incremental", "--min-size", "0"])
+        .args(["--limit", "1"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("  Limit of 1 episodes reached"));
+    assert!(folder.path().join("01 - puntata 1.mp3").exists());
+    assert!(!folder.path().join("02 - puntata 2.mp3").exists());
+    assert_eq!(
+        fs::read_to_string(folder.path().join("rsnd-state.json")).unwrap(),
+        state
+    );
+
+    // An episode filtered out on purpose does not hold back the newer ones.
+    rsnd(&cache, &folder)
+        .args(["--url", &show_url, "--incremental", "--min-size", "0"])
+        .args(["--reject", "puntata 2"])
+        .assert()
+        .code(0);
+    assert!(!folder.path().join("02 - puntata 2.mp3").exists());
+    let state = fs::read_to_string(folder.path().join("rsnd-state.json")).unwrap();
+    assert!(state.contains(r#""newest": "/audio/2024/05/p1.json""#));
+}
+
+/// When the rate-limit test server received each request.
+static REQUEST_TIMES: std::sync::Mutex<Vec<std::time::Instant>> = std::sync::Mutex::new(Vec::new());
+
+#[test]
+fn test_rps_limits_requests() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, path| {
+        REQUEST_TIMES
+            .lock()
+            .unwrap()
+            .push(std::time::Instant::now());
+        if path.starts_with("/programmi/") {
+            let mut response =
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n".to_vec();
+            for n in 1..=3 {
+                response.extend_from_slice(
+                    format!(r#"<rps-play-with-labels options='{{"url": "/audio/2024/05/r{}.json"}}'></rps-play-with-labels>"#, n)
+                        .as_bytes(),
+                );
+            }
+            return response;
+        }
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 1021, 0);
+        }
+        response
+    });
+    for n in 1..=3 {
+        fs::write(
+            cache.path().join(format!("r{}.json", n)),
+            format!(
+                r#"{{"audio": {{"title": "Puntata {}", "url": "{}/r{}.mp3"}}}}"#,
+                n, server, n
+            ),
+        )
+        .unwrap();
+    }
+
+    rsnd(&cache, &folder)
+        .args(["--url", &format!("{}/programmi/show", server)])
+        .args(["--rps", "5", "--min-size", "0"])
+        .assert()
+        .code(0);
+    let times = REQUEST_TIMES.lock().unwrap();
+    // The page, then the relinker and the audio of each episode.
+    assert!(times.len() >= 4, "only {} requests", times.len());
+    for pair in times.windows(2) {
+        let gap = pair[1] - pair[0];
+        assert!(
+            gap >= std::time::Duration::from_millis(150),
+            "requests {:?} apart",
+            gap
+        );
+    }
+}
+
+#[test]
+fn test_offline_reads_only_the_cache() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+
+    // The show JSON is not cached, so the cached page is scraped instead.
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--offline", "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains(
+        "Not cached, offline: https://www.raiplaysound.it/audiolibri/itremoschettieri.json"
+    ));
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+
+    rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--offline", "--check"])
+        .assert()
+        .code(5);
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--offline"])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Cannot download audio with --offline"));
+
+    // Uncached metadata fails its episode instead of reaching the network.
+    fs::remove_file(cache.path().join("lettura-1.json")).unwrap();
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--offline", "--dry-run"])
+        .assert()
+        .code(3);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout
+        .contains("Not cached, offline: https://www.raiplaysound.it/audio/2015/06/lettura-1.json"));
+}
+
+#[test]
+fn test_base_url_points_at_a_mirror() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // Nothing is cached: the page and the episode metadata both come from the mirror.
+    let server = serve(|_, path| {
+        let body = match path {
+            "/audiolibri/itremoschettieri" => include_str!("fixtures/itremoschettieri.html"),
+            "/audio/2015/06/lettura-1.json" => include_str!("fixtures/lettura-1.json"),
+            _ => return b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n".to_vec(),
+        };
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    });
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", &format!("{}/audiolibri/itremoschettieri", server)])
+        .args(["--base-url", &format!("{}/", server), "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--url", SHOW_URL, "--base-url", "raiplaysound.it"])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("invalid value 'raiplaysound.it' for '--base-url <URL>'"));
+}
+
+/// Serves the bodies `respond` returns by path, and 404 for the others, keeping
+/// connections open between requests. Returns the base URL and the number of
+/// connections accepted so far.
+fn serve_keep_alive(respond: fn(&str) -> Option<String>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    let mut header = String::new();
+                    while reader.read_line(&mut header).unwrap_or(0) > 2 {
+                        header.clear();
+                    }
+                    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                    let response = match respond(path) {
+                        Some(body) => {
+                            format!(
+                                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                                body.len(),
+                                body
+                            )
+                        }
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+                    };
+                    if stream.write_all(response.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (base, connections)
+}
+
+/// Lists 60 episodes, whose metadata is fetched one small request each.
+fn many_episodes(path: &str) -> Option<String> {
+    if path == "/audiolibri/itremoschettieri" {
+        return Some(
+            (1..=60)
+                .map(|n| {
+                    format!(
+                        r#"<rps-play-with-labels options='{{"url": "/audio/2015/06/lettura-{}.json"}}'></rps-play-with-labels>"#,
+                        n
+                    )
+                })
+                .collect(),
+        );
+    }
+    let n = path
+        .strip_prefix("/audio/2015/06/lettura-")?
+        .strip_suffix(".json")?;
+    Some(format!(
+        r#"{{"audio": {{"title": "Lettura {}", "url": "http://127.0.0.1:1/{}.mp3", "duration": "00:19:15"}}}}"#,
+        n, n
+    ))
+}
+
+#[test]
+fn test_connection_pool_reuses_connections() {
+    // Runs a dry run against the mirror and returns the connections it opened.
+    let measure = |extra: &[&str]| {
+        let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let (server, connections) = serve_keep_alive(many_episodes);
+        rsnd_json(&cache, &folder)
+            .args(["--url", &format!("{}/audiolibri/itremoschettieri", server)])
+            .args(["--base-url", &format!("{}/", server), "--dry-run"])
+            .args(extra)
+            .assert()
+            .code(0);
+        connections.load(Ordering::SeqCst)
+    };
+
+    let tuned = measure(&[]);
+    // What reqwest does left to itself: no cap on idle connections and no keepalive.
+    let max_idle = usize::MAX.to_string();
+    let reqwest_defaults = measure(&["--pool-max-idle", &max_idle, "--tcp-keepalive", "0"]);
+    let http1 = measure(&["--http1-only"]);
+    // The 61 requests share a few connections, no more than with reqwest's defaults.
+    assert!(tuned * 4 <= 61, "{} connections", tuned);
+    assert!(
+        tuned <= reqwest_defaults,
+        "{} connections, {} with reqwest's defaults",
+        tuned,
+        reqwest_defaults
+    );
+    assert!(http1 * 4 <= 61, "{} connections", http1);
+}
+
+#[test]
+fn test_video_items_are_skipped_unless_included() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+    fs::write(
+        cache.path().join("itremoschettieri.html"),
+        r#"<rps-play-with-labels options='{"url": "/audio/2015/06/lettura-1.json"}'></rps-play-with-labels>
+           <rps-play-with-labels options='{"url": "/audio/2015/06/videocast-1.json"}'></rps-play-with-labels>"#,
+    )
+    .unwrap();
+    fs::write(
+        cache.path().join("videocast-1.json"),
+        r#"{"video": {"title": "Videocast I", "url": "http://127.0.0.1:1/videocast-1.mp4"}}"#,
+    )
+    .unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Skipping video item 002 - Videocast I (use --include-video"));
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+    assert!(stdout.contains("Video items:    1"));
+    assert!(stdout.contains("Failed:         0"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--include-video"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("02 - videocast i.mp4 (would download)"));
+}
+
+#[test]
+fn test_title_trim() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--title-trim", "^lettura "])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/01 - i.mp3 (would download)"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--title-split", " "])
+        .args(["--title-part", "first"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/01 - lettura.mp3 (would download)"));
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--title-trim", "(unclosed"])
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_write_info_json() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| {
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 1021, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0", "--write-info-json"])
+        .assert()
+        .code(0);
+    let info: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(folder.path().join("01 - lettura i.info.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(info["schema"], 1);
+    assert_eq!(info["title"], "Lettura I");
+    assert_eq!(info["duration"], 1155);
+    assert_eq!(info["size"], 1024);
+    assert_eq!(
+        info["metadata_url"],
+        "https://www.raiplaysound.it/audio/2015/06/lettura-1.json"
+    );
+    assert_eq!(info["audio_url"], format!("{}/lettura-1.mp3", server));
+    assert_eq!(info["crc32"].as_str().unwrap().len(), 8);
+    assert!(info["peak_throughput"].as_f64().unwrap() > 0.0);
+}
+
+#[test]
+fn test_also_check_dir_links_archived_episodes() {
+    let (cache, folder, archive) = (
+        TempDir::new().unwrap(),
+        TempDir::new().unwrap(),
+        TempDir::new().unwrap(),
+    );
+    // Nothing listens there: the episode must not be downloaded.
+    cache_show(cache.path(), "http://127.0.0.1:9/lettura-1.mp3");
+    fs::write(archive.path().join("01 - lettura i.mp3"), b"ID3").unwrap();
+
+    let output = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--also-check-dir"])
+        .arg(archive.path())
+        .arg("--link-existing")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("Elsewhere:      1 (in --also-check-dir)"));
+    assert!(stdout.contains("001 - Lettura I: linked "));
+    assert_eq!(
+        fs::read(folder.path().join("01 - lettura i.mp3")).unwrap(),
+        b"ID3"
+    );
+}
+
+#[test]
+fn test_input_file_reads_a_saved_page() {
+    let (cache, folder, saved) = (
+        TempDir::new().unwrap(),
+        TempDir::new().unwrap(),
+        TempDir::new().unwrap(),
+    );
+    // The episode metadata still comes from the cache, standing in for the network.
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    let page = saved.path().join("i tre moschettieri.html");
+    fs::copy(cache.path().join("itremoschettieri.html"), &page).unwrap();
+    fs::remove_file(cache.path().join("itremoschettieri.html")).unwrap();
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--dry-run", "--input-file"])
+        .arg(&page)
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+
+    let url = format!("file://{}", page.display()).replace(' ', "%20");
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--dry-run", "--url", &url])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download)"));
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--dry-run", "--input-file"])
+        .arg(saved.path().join("missing.html"))
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("No such file:"));
+}
+
+#[test]
+fn test_dump_pages() {
+    let (cache, folder, dump) = (
+        TempDir::new().unwrap(),
+        TempDir::new().unwrap(),
+        TempDir::new().unwrap(),
+    );
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--dump-pages"])
+        .arg(dump.path())
+        .assert()
+        .code(0);
+    let show = dump.path().join("itremoschettieri");
+    let index: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(show.join("index.json")).unwrap()).unwrap();
+    assert_eq!(index[0]["file"], "001-itremoschettieri.html");
+    assert_eq!(index[0]["kind"], "page");
+    assert_eq!(index[0]["cached"], true);
+    assert_eq!(
+        index[1]["url"],
+        "https://www.raiplaysound.it/audio/2015/06/lettura-1.json"
+    );
+    assert!(fs::read_to_string(show.join("002-lettura-1.json"))
+        .unwrap()
+        .contains("Lettura I"));
+}
+
+#[test]
+fn test_max_filesize_skips_large_episodes() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| {
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nContent-Length: 307200\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 307_197, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--max-filesize", "100K"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout
+        .contains("Skipping 001 - Lettura I: 300.0 KB is larger than --max-filesize 100.0 KB"));
+    assert!(stdout.contains("Size skipped:   1 (outside --min/--max-filesize)"));
+    // Only the description of the show was written.
+    let names: Vec<_> = fs::read_dir(folder.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["show.json"]);
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--max-filesize", "1M"])
+        .assert()
+        .code(0);
+    assert!(folder.path().join("01 - lettura i.mp3").exists());
+}
+
+#[test]
+fn test_estimate_sums_probed_sizes() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| {
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nContent-Length: 307200\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 307_197, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--sizes"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("01 - lettura i.mp3 (would download, 300.0 KB)"));
+    assert!(stdout.contains("About 300.0 KB across 1 new episodes"));
+
+    // Without a terminal to ask in, the downloads go ahead.
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--estimate"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("About 300.0 KB across 1 new episodes"));
+    assert!(folder.path().join("01 - lettura i.mp3").exists());
+}
+
+#[test]
+fn test_list_probe_sizes() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|_, _| {
+        b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nContent-Length: 307200\r\nConnection: close\r\n\r\n".to_vec()
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--list"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("  ?  missing  Lettura I"), "{}", stdout);
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--list", "--probe-sizes", "1"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(
+        stdout.contains("300.0 KB  missing  Lettura I"),
+        "{}",
+        stdout
+    );
+
+    // The probed size is remembered, so the next list does not probe again.
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--list", "--json"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("\"size\": 307200"), "{}", stdout);
+}
+
+#[test]
+fn test_list_json_of_several_shows() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    for show in ["itremoschettieri", "ilgattopardo"] {
+        fs::write(
+            cache.path().join(format!("{}.html", show)),
+            format!(
+                r#"<rps-play-with-labels options='{{"url": "/audio/2015/06/{}-1.json"}}'></rps-play-with-labels>"#,
+                show
+            ),
+        )
+        .unwrap();
+        fs::write(
+            cache.path().join(format!("{}-1.json", show)),
+            format!(
+                r#"{{"audio": {{"title": "Lettura {}", "url": "{}/{}.mp3"}}}}"#,
+                show, UNREACHABLE, show
+            ),
+        )
+        .unwrap();
+    }
+    let other = "https://www.raiplaysound.it/audiolibri/ilgattopardo";
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--url", other, "--list", "--json"])
+        .assert()
+        .code(0);
+    // The episodes of both shows make up a single JSON array.
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let rows: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let rows = rows.as_array().unwrap();
+    assert_eq!(rows.len(), 2, "{}", stdout);
+    assert_eq!(rows[0]["show"], SHOW_URL);
+    assert_eq!(rows[0]["title"], "Lettura itremoschettieri");
+    assert_eq!(rows[1]["show"], other);
+    assert_eq!(rows[1]["title"], "Lettura ilgattopardo");
+}
+
+/// Serves the audio to requests for the `localhost` host, and refuses to serve it under
+/// any other host name, as a CDN host blocked from abroad.
+fn serve_by_host() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut host = String::new();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                if let Some(value) = header.to_lowercase().strip_prefix("host:") {
+                    host = value.trim().to_string();
+                }
+                header.clear();
+            }
+            let method = request_line.split_whitespace().next().unwrap_or("");
+            let response = if method == "GET" && !host.starts_with("localhost") {
+                b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+            } else {
+                audio_response(method, 100_000)
+            };
+            let _ = stream.write_all(&response);
+        }
+    });
+    format!("http://127.0.0.1:{}", port)
+}
+
+#[test]
+fn test_cdn_fallback() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve_by_host();
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0"])
+        .args(["--cdn-fallback", "127.0.0.1=localhost"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Trying mirror localhost"), "{}", stdout);
+    assert!(
+        stdout.contains("  Mirrored:       1 (CDN host failed)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("01 - Lettura I: served by mirror localhost"),
+        "{}",
+        stdout
+    );
+    assert!(folder.path().join("01 - lettura i.mp3").exists());
+}
+
+#[test]
+fn test_damaged_cache_is_fetched_again() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|_, path| {
+        let body = if path == "/audio/2015/06/lettura-1.json" {
+            r#"{"audio": {"title": "Lettura I", "url": "/lettura-1.mp3", "duration": "00:19:15"}}"#
+        } else {
+            ""
+        };
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    });
+    cache_show(cache.path(), UNREACHABLE);
+    // Cut short, as by a run killed while writing it.
+    fs::write(
+        cache.path().join("lettura-1.json"),
+        r#"{"audio": {"title": "Lett"#,
+    )
+    .unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--base-url", &server, "--list"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(
+        stderr.contains("Warning: ignoring damaged cache file"),
+        "{}",
+        stderr
+    );
+    assert!(stdout.contains("missing  Lettura I"), "{}", stdout);
+    let cached = fs::read_to_string(cache.path().join("lettura-1.json")).unwrap();
+    assert!(cached.contains("00:19:15"), "{}", cached);
+}
+
+#[test]
+fn test_continue_numbering() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_fixtures(cache.path());
+    fs::write(folder.path().join("040 - lettura xl.mp3"), b"").unwrap();
+    fs::write(folder.path().join("041 - lettura xli.mp3"), b"").unwrap();
+    fs::write(folder.path().join("038 - lettura xxxviii.mp3"), b"").unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--continue-numbering"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stdout.contains("/042 - lettura i.mp3 (would download)"));
+    assert!(stderr.contains("Warning: numbers missing in the folder: 39"));
+    // A dry run gives out no numbers.
+    assert!(!folder.path().join("numbers.json").exists());
+
+    // Once numbered, the episode keeps its number.
+    fs::write(folder.path().join("042 - lettura i.mp3"), b"").unwrap();
+    fs::write(folder.path().join("043 - lettura xliii.mp3"), b"").unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--continue-numbering"])
+        .assert()
+        .code(4);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("/042 - lettura i.mp3 (exists, would skip)"));
+}
+
+#[test]
+fn test_continue_numbering_keeps_downloaded_numbers() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(folder.path().join("041 - lettura xli.mp3"), b"").unwrap();
+
+    // A failed download gives its number back.
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--continue-numbering"])
+        .assert()
+        .code(3);
+    assert!(!folder.path().join("numbers.json").exists());
+
+    let server = serve_audio();
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--continue-numbering", "--min-size", "0"])
+        .assert()
+        .code(0);
+    assert!(folder.path().join("042 - lettura i.mp3").exists());
+    let numbers = fs::read_to_string(folder.path().join("numbers.json")).unwrap();
+    assert!(numbers.contains(": 42"), "{}", numbers);
+}
+
+#[test]
+fn test_mtime_follows_publication_date() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, _| {
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        if method == "GET" {
+            response.extend_from_slice(b"ID3");
+            response.resize(response.len() + 1021, 0);
+        }
+        response
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+    fs::write(
+        cache.path().join("lettura-1.json"),
+        format!(
+            r#"{{"create_date": "12-06-2015", "audio": {{"title": "Lettura I", "url": "{}/lettura-1.mp3"}}}}"#,
+            server
+        ),
+    )
+    .unwrap();
+    let path = folder.path().join("01 - lettura i.mp3");
+    let year = |path: &Path| {
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        chrono::DateTime::<chrono::Local>::from(modified)
+            .format("%Y")
+            .to_string()
+    };
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0"])
+        .assert()
+        .code(0);
+    assert_eq!(year(&path), "2015");
+
+    fs::remove_file(&path).unwrap();
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0", "--mtime", "now"])
+        .assert()
+        .code(0);
+    assert_ne!(year(&path), "2015");
+}
+
+#[test]
+fn test_record_live_channel() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // A stream that the server closes early, which ends the recording before its
+    // duration.
+    let server = serve(|method, path| {
+        let (content_type, body): (&str, &[u8]) = match path {
+            "/dirette/radio3.json" => (
+                "application/json",
+                br#"{"channel": {"name": "Rai Radio 3"}, "audio": {"url": "/live/radio3.mp3"}}"#,
+            ),
+            "/live/radio3.mp3" => ("audio/mpeg", b"\xFF\xFBlive audio"),
+            _ => return b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n".to_vec(),
+        };
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        if method == "GET" {
+            response.extend_from_slice(body);
+        }
+        response
+    });
+
+    let assert = rsnd_json(&cache, &folder)
+        .args(["--base-url", &server, "record", "--channel", "radio3"])
+        .args(["--duration", "1h"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stdout.contains("Recording Rai Radio 3 for 01:00:00"));
+    assert!(stderr.contains("the stream of Rai Radio 3 ended early"));
+    let recording = fs::read_dir(folder.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|extension| extension == "mp3"))
+        .expect("No recording");
+    assert!(recording
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("rai radio 3 "));
+    let data = fs::read(&recording).unwrap();
+    assert!(data.starts_with(b"ID3"));
+    assert!(data.ends_with(b"\xFF\xFBlive audio"));
+}
+
+/// Like `serve`, answering each connection on its own thread so that requests sent at
+/// the same time are served at the same time.
+fn serve_concurrently(respond: fn(&str, &str) -> Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                let _ = stream.write_all(&respond(method, path));
+            });
+        }
+    });
+    base
+}
+
+/// Requests received by the slow CDN of `test_next_episodes_resolve_during_download`.
+static CDN_LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[test]
+fn test_next_episodes_resolve_during_download() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // Resolving and downloading each take a while, as on a distant CDN.
+    let server = serve_concurrently(|method, path| {
+        CDN_LOG.lock().unwrap().push(format!("{} {}", method, path));
+        thread::sleep(Duration::from_millis(300));
+        let response = audio_response(method, 100_000);
+        if method == "GET" {
+            CDN_LOG.lock().unwrap().push(format!("done {}", path));
+        }
+        response
+    });
+    cache_episodes(cache.path(), 3, &server);
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0"])
+        .assert()
+        .code(0);
+
+    let log = CDN_LOG.lock().unwrap().clone();
+    let position = |request: &str| log.iter().position(|line| line == request).unwrap();
+    // The next two episodes were resolved while the first one downloaded, and not again
+    // before their own downloads.
+    assert!(position("HEAD /2.mp3") < position("done /1.mp3"));
+    assert!(position("HEAD /3.mp3") < position("done /1.mp3"));
+    assert_eq!(
+        log.iter().filter(|line| line.starts_with("HEAD")).count(),
+        3
+    );
+    for n in 1..=3 {
+        assert!(folder
+            .path()
+            .join(format!("{:02} - lettura {}.mp3", n, n))
+            .exists());
+    }
+}
+
+#[test]
+fn test_sleep_between_downloads() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve_concurrently(|method, _| audio_response(method, 100_000));
+    cache_episodes(cache.path(), 3, &server);
+    fs::write(folder.path().join("02 - lettura 2.mp3"), b"ID3").unwrap();
+
+    let start = Instant::now();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--min-size", "0", "--sleep", "1"])
+        .assert()
+        .code(0);
+    let elapsed = start.elapsed();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    // One pause, between the first and third episodes: none for the one already present
+    // and none after the last.
+    assert!(stdout.contains("  Slept:          00:00:01 (--sleep)"));
+    assert!(elapsed >= Duration::from_secs(1));
+}
+
+#[test]
+fn test_cookies_from_missing_browser_profile() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let home = TempDir::new().unwrap();
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+
+    let assert = rsnd(&cache, &folder)
+        .env("HOME", home.path())
+        .args(["--url", SHOW_URL, "--cookies-from-browser", "firefox:work"])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    if cfg!(feature = "cookies-from-browser") {
+        assert!(stderr.contains("No Firefox profile \"work\" with cookies found"));
+    } else {
+        assert!(stderr.contains("rebuild it with --features cookies-from-browser"));
+    }
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--cookies-from-browser", "safari"])
+        .assert()
+        .code(1);
+}
+
+/// Requests received by the slow CDN of `test_shows_run_at_the_same_time`.
+static SHOW_JOBS_LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[test]
+fn test_shows_run_at_the_same_time() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve_concurrently(|method, path| {
+        SHOW_JOBS_LOG
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", method, path));
+        thread::sleep(Duration::from_millis(300));
+        let response = audio_response(method, 100_000);
+        if method == "GET" {
+            SHOW_JOBS_LOG.lock().unwrap().push(format!("done {}", path));
+        }
+        response
+    });
+    for show in ["itremoschettieri", "ilgattopardo"] {
+        fs::write(
+            cache.path().join(format!("{}.html", show)),
+            format!(
+                r#"<rps-play-with-labels options='{{"url": "/audio/2015/06/{}-1.json"}}'></rps-play-with-labels>"#,
+                show
+            ),
+        )
+        .unwrap();
+        fs::write(
+            cache.path().join(format!("{}-1.json", show)),
+            format!(
+                r#"{{"audio": {{"title": "Lettura {}", "url": "{}/{}.mp3"}}}}"#,
+                show, server, show
+            ),
+        )
+        .unwrap();
+    }
+
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--url",
+            "https://www.raiplaysound.it/audiolibri/ilgattopardo",
+            "--show-jobs",
+            "2",
+            "--min-size",
+            "0",
+        ])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("[itremoschettieri] "));
+    assert!(stdout.contains("[ilgattopardo] "));
+    assert!(!stdout.contains("==> "));
+    assert!(stdout.contains("  Downloaded:     2 "));
+
+    let log = SHOW_JOBS_LOG.lock().unwrap().clone();
+    let position = |request: &str| log.iter().position(|line| line == request).unwrap();
+    // The second show started downloading before the first one was done.
+    assert!(position("GET /ilgattopardo.mp3") < position("done /itremoschettieri.mp3"));
+    assert!(position("GET /itremoschettieri.mp3") < position("done /ilgattopardo.mp3"));
+
+    // Questions cannot be asked of several shows at once.
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--url",
+            "https://www.raiplaysound.it/audiolibri/ilgattopardo",
+            "--show-jobs",
+            "2",
+            "--estimate",
+        ])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Cannot run several shows at once with --estimate without --yes"));
+
+    // Shows run at the same time dump what they fetch in their own folders.
+    let dump = TempDir::new().unwrap();
+    rsnd(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--url",
+            "https://www.raiplaysound.it/audiolibri/ilgattopardo",
+            "--show-jobs",
+            "2",
+            "--dry-run",
+            "--dump-pages",
+        ])
+        .arg(dump.path())
+        .assert()
+        .code(4);
+    for show in ["itremoschettieri", "ilgattopardo"] {
+        let index = fs::read_to_string(dump.path().join(show).join("index.json")).unwrap();
+        assert!(index.contains(&format!("{}.html", show)));
+        assert!(index.contains(&format!("{}-1.json", show)));
+    }
+}
+
+#[test]
+fn test_progress_json_events() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve_audio();
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--progress-json"])
+        .assert()
+        .code(0);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    assert!(events.iter().all(|event| event["version"] == 1));
+    let names: Vec<&str> = events
+        .iter()
+        .filter_map(|event| event["event"].as_str())
+        .filter(|name| *name != "download_progressed")
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "show_resolved",
+            "episode_discovered",
+            "metadata_fetched",
+            "download_started",
+            "download_finished",
+            "run_finished"
+        ]
+    );
+    let progressed = events
+        .iter()
+        .find(|event| event["event"] == "download_progressed")
+        .unwrap();
+    assert_eq!(progressed["show_url"], SHOW_URL);
+    assert_eq!(progressed["index"], 1);
+    let finished = &events[events.len() - 2];
+    assert_eq!(finished["title"], "Lettura I");
+    assert_eq!(finished["bytes"], 100000);
+    assert_eq!(events[events.len() - 1]["downloaded"], 1);
+}
+
+static THUMBNAIL_LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[test]
+fn test_write_thumbnails() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|_, path| {
+        THUMBNAIL_LOG.lock().unwrap().push(path.to_string());
+        if path == "/lettura-1.jpg" {
+            let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: 8\r\nConnection: close\r\n\r\n".to_vec();
+            response.extend_from_slice(b"\xff\xd8\xff\xe0JFIF");
+            return response;
+        }
+        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+    });
+    cache_episodes(cache.path(), 3, UNREACHABLE);
+    // The first episode has its own image, the second a dead one, and the third repeats
+    // the image of the show.
+    for (n, image) in [
+        (1, "lettura-1.jpg"),
+        (2, "gone.jpg"),
+        (3, "moschettieri.jpg"),
+    ] {
+        fs::write(
+            cache.path().join(format!("lettura-{}.json", n)),
+            format!(
+                r#"{{"image": "{server}/{image}", "podcast_info": {{"image": "{server}/show/moschettieri.jpg"}}, "audio": {{"title": "Lettura {n}", "url": "{UNREACHABLE}/{n}.mp3"}}}}"#,
+            ),
+        )
+        .unwrap();
+        fs::write(
+            folder.path().join(format!("0{} - lettura {}.mp3", n, n)),
+            b"ID3",
+        )
+        .unwrap();
+    }
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--check", "--write-thumbnails"])
+        .assert()
+        .code(5);
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--write-thumbnails"])
+        .assert()
+        .code(0);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Warning: no thumbnail for Lettura 2"));
+    assert_eq!(
+        fs::read(folder.path().join("01 - lettura 1.jpg")).unwrap(),
+        b"\xff\xd8\xff\xe0JFIF"
+    );
+    assert!(!folder.path().join("02 - lettura 2.jpg").exists());
+    assert!(!folder.path().join("03 - lettura 3.jpg").exists());
+
+    // The image is read from the cache the next time it is needed.
+    fs::remove_file(folder.path().join("01 - lettura 1.jpg")).unwrap();
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--write-thumbnails"])
+        .assert()
+        .code(0);
+    assert!(folder.path().join("01 - lettura 1.jpg").exists());
+    let log = THUMBNAIL_LOG.lock().unwrap();
+    assert_eq!(
+        log.iter().filter(|path| *path == "/lettura-1.jpg").count(),
+        1
+    );
+    assert!(!log.iter().any(|path| path.contains("moschettieri")));
+}
+
+#[test]
+fn test_stalled_download_is_retried_and_reported() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    // Sends the first 20000 bytes of 100000, then nothing until the client gives up.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let method = request_line.split_whitespace().next().unwrap_or("");
+                let _ = stream.write_all(&audio_response(method, 20_000));
+                let _ = stream.flush();
+                thread::sleep(Duration::from_secs(5));
+            });
+        }
+    });
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+
+    let start = Instant::now();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--retries", "1", "--stall-timeout", "1"])
+        .assert()
+        .code(3);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(stdout.contains("Retrying Lettura I (1/1)"));
+    assert!(stderr.contains("Download stalled: 0 B/s over the last 1s, below --stall-speed"));
+    assert!(!stderr.contains("Incomplete download"));
+    assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_landing_page_shows() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let landing = "https://www.raiplaysound.it/audiolibri";
+    fs::write(
+        cache.path().join("audiolibri.html"),
+        r#"<header><a href="/programmi/radio2social">Radio2 Social</a></header>
+        <main>
+            <rps-card><a href="/audiolibri/itremoschettieri">I tre moschettieri</a></rps-card>
+            <rps-card><a href="/audiolibri/ilconte">Il conte</a></rps-card>
+        </main>"#,
+    )
+    .unwrap();
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::copy(
+        cache.path().join("itremoschettieri.html"),
+        cache.path().join("ilconte.html"),
+    )
+    .unwrap();
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", landing])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("is a landing page linking 2 shows, not a show"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", landing, "--list-shows"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains(
+        " 1. I tre moschettieri\n    https://www.raiplaysound.it/audiolibri/itremoschettieri\n"
+    ));
+    assert!(stdout.contains(" 2. Il conte\n"));
+    assert!(!stdout.contains("radio2social"));
+
+    // Several landing pages make one JSON document, telling where each show was found.
+    let genre = "https://www.raiplaysound.it/generi/gialli";
+    fs::write(
+        cache.path().join("gialli.html"),
+        r#"<main><rps-card><a href="/programmi/ilcommissario">Il commissario</a></rps-card></main>"#,
+    )
+    .unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", landing, "--url", genre, "--list-shows", "--json"])
+        .assert()
+        .code(0);
+    let shows: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(shows.as_array().unwrap().len(), 3);
+    assert_eq!(shows[0]["title"], "I tre moschettieri");
+    assert_eq!(shows[0]["found_on"], landing);
+    assert_eq!(
+        shows[2]["url"],
+        "https://www.raiplaysound.it/programmi/ilcommissario"
+    );
+    assert_eq!(shows[2]["found_on"], genre);
+
+    // A show is not a landing page to list.
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", landing, "--url", SHOW_URL, "--list-shows"])
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains(&format!("{} is not a landing page", SHOW_URL)));
+
+    // The show given on its own is not processed twice.
+    let assert = rsnd(&cache, &folder)
+        .args([
+            "--url",
+            SHOW_URL,
+            "--url",
+            landing,
+            "--follow-shows",
+            "--dry-run",
+        ])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains(
+        "Following 1 of the 2 shows linked from https://www.raiplaysound.it/audiolibri (1 already in this run)"
+    ));
+    assert_eq!(stdout.matches("==> ").count(), 2);
+    assert!(stdout.contains("==> https://www.raiplaysound.it/audiolibri/ilconte"));
+    assert!(stdout.contains("    Found on https://www.raiplaysound.it/audiolibri\n"));
+    // Only the show found on the landing page goes to a subfolder.
+    let given = folder.path().join("01 - lettura i.mp3");
+    let found = folder.path().join("ilconte").join("01 - lettura i.mp3");
+    assert!(stdout.contains(&format!("{} (would download)", given.display())));
+    assert!(stdout.contains(&format!("{} (would download)", found.display())));
+}
+
+#[test]
+fn test_retag_existing_files() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    fs::write(
+        cache.path().join("itremoschettieri.html"),
+        (1..=3)
+            .map(|n| {
+                format!(
+                    r#"<rps-play-with-labels options='{{"url": "/audio/2015/06/lettura-{}.json"}}'></rps-play-with-labels>"#,
+                    n
+                )
+            })
+            .collect::<String>(),
+    )
+    .unwrap();
+    for n in 1..=3 {
+        fs::write(
+            cache.path().join(format!("lettura-{}.json", n)),
+            format!(
+                r#"{{"uniquename": "lettura-{n}", "date_published": "0{n}-06-2015", "podcast_info": {{"title": "I tre moschettieri"}}, "audio": {{"title": "Lettura {n}", "url": "{UNREACHABLE}/{n}.mp3"}}}}"#,
+            ),
+        )
+        .unwrap();
+    }
+    // The first file has an older pad width, the second an older title, the third
+    // episode was never downloaded, and the last file is not from the show.
+    for name in [
+        "1 - lettura 1.mp3",
+        "02 - Lettura_2 (prima parte).mp3",
+        "bonus.mp3",
+    ] {
+        fs::write(folder.path().join(name), b"\xff\xfbaudio").unwrap();
+    }
+    // The second file has artwork to keep, the first an ID3v2.2 tag that can't be merged.
+    let artwork = b"APIC\x00\x00\x00\x0b\x00\x00\x00image/\x00\x03\x00\xff";
+    let mut tagged = b"ID3\x03\x00\x00\x00\x00\x00\x15".to_vec();
+    tagged.extend_from_slice(artwork);
+    tagged.extend_from_slice(b"\xff\xfbaudio");
+    fs::write(
+        folder.path().join("02 - Lettura_2 (prima parte).mp3"),
+        tagged,
+    )
+    .unwrap();
+    let old_tag = b"ID3\x02\x00\x00\x00\x00\x00\x00\xff\xfbaudio";
+    fs::write(folder.path().join("1 - lettura 1.mp3"), old_tag).unwrap();
+
+    rsnd(&cache, &folder).arg("retag").assert().code(1);
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--write-info-json", "--dry-run", "retag"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Would tag"));
+    assert!(stdout.contains("Matched 2 of the 3 episodes, 3 changes to make"));
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Warning: not tagging"));
+    assert!(stderr.contains("ID3v2.2 tags are not supported"));
+    assert!(!folder.path().join("1 - lettura 1.info.json").exists());
+    assert_eq!(
+        fs::read(folder.path().join("1 - lettura 1.mp3")).unwrap(),
+        old_tag
+    );
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--write-info-json", "retag"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("1 files match no episode and were left alone:"));
+    assert!(stdout.contains("bonus.mp3"));
+    assert!(stdout.contains("2 files are named after an older scheme"));
+    let tagged = fs::read(folder.path().join("02 - Lettura_2 (prima parte).mp3")).unwrap();
+    assert!(tagged.starts_with(b"ID3\x03"));
+    assert!(tagged.windows(artwork.len()).any(|frame| frame == artwork));
+    assert!(tagged.ends_with(b"\xff\xfbaudio"));
+    let info: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(folder.path().join("1 - lettura 1.info.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(info["id"], "lettura-1");
+    assert_eq!(info["throughput"], serde_json::Value::Null);
+    assert_eq!(info["downloaded_at"], serde_json::Value::Null);
+    assert_eq!(
+        fs::read(folder.path().join("bonus.mp3")).unwrap(),
+        b"\xff\xfbaudio"
+    );
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--write-info-json", "retag"])
+        .arg("--rename-to-current-scheme")
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Matched 2 of the 3 episodes, 2 changes made"));
+    for name in [
+        "01 - lettura 1.mp3",
+        "01 - lettura 1.info.json",
+        "02 - lettura 2.mp3",
+    ] {
+        assert!(folder.path().join(name).exists(), "{}", name);
+    }
+    assert!(!folder.path().join("1 - lettura 1.mp3").exists());
+}
+
+/// Requests received by the relinker of `test_episode_resolved_once_per_run`.
+static RESOLUTION_LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[test]
+fn test_episode_resolved_once_per_run() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve(|method, path| {
+        RESOLUTION_LOG
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", method, path));
+        audio_response(method, 100_000)
+    });
+    // Two shows list the same episode, which is saved in the folder of each.
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+    fs::copy(
+        cache.path().join("itremoschettieri.html"),
+        cache.path().join("ilconte.html"),
+    )
+    .unwrap();
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .args(["--url", "https://www.raiplaysound.it/audiolibri/ilconte"])
+        .args([
+            "--show-subdirs",
+            "--estimate",
+            "--yes",
+            "--min-filesize",
+            "1K",
+        ])
+        .args(["--min-size", "0"])
+        .assert()
+        .code(0);
+    let log = RESOLUTION_LOG.lock().unwrap();
+    // The estimate, the size check and both downloads share one resolution.
+    assert_eq!(
+        log.iter().filter(|line| line.starts_with("HEAD")).count(),
+        1,
+        "{:?}",
+        log
+    );
+    assert_eq!(log.iter().filter(|line| line.starts_with("GET")).count(), 2);
+}
+
+#[test]
+fn test_hostile_titles_stay_in_folder() {
+    let (cache, root) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let folder = root.path().join("libri");
+    let server = serve_audio();
+    let titles = [r"../../evil", r"..\\..\\evil", "a/b\\\\c", "..", ". ."];
+    cache_episodes(cache.path(), titles.len(), &server);
+    for (n, title) in titles.iter().enumerate() {
+        fs::write(
+            cache.path().join(format!("lettura-{}.json", n + 1)),
+            format!(
+                r#"{{"audio": {{"title": "{}", "url": "{}/{}.mp3"}}}}"#,
+                title,
+                server,
+                n + 1
+            ),
+        )
+        .unwrap();
+    }
+
+    Command::cargo_bin("rsnd")
+        .unwrap()
+        .arg("--cache")
+        .arg(cache.path())
+        .arg("--folder")
+        .arg(&folder)
+        .args(["--scrape-html", "--url", SHOW_URL, "--no-index"])
+        .args(["--min-size", "0"])
+        .assert()
+        .code(0);
+    let saved: Vec<String> = fs::read_dir(&folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".mp3"))
+        .collect();
+    // Every episode is saved inside the folder, under a sanitized name.
+    assert_eq!(saved.len(), titles.len(), "{:?}", saved);
+    assert!(saved.contains(&"a b c.mp3".to_string()));
+    let outside: Vec<_> = fs::read_dir(root.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(outside, ["libri"]);
+}
+
+#[test]
+fn test_show_json() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(
+        cache.path().join("itremoschettieri.html"),
+        r#"<meta property="og:title" content="I tre moschettieri - RaiPlay Sound">
+        <meta property="og:description" content="Il romanzo di Dumas">
+        <rps-play-with-labels options='{"url": "/audio/2015/06/lettura-1.json"}'></rps-play-with-labels>"#,
+    )
+    .unwrap();
+    fs::write(folder.path().join("001 - lettura i.mp3"), b"ID3").unwrap();
+
+    // Dry runs leave the folder alone.
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(4);
+    assert!(!folder.path().join("show.json").exists());
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .assert()
+        .code(0);
+    let show: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(folder.path().join("show.json")).unwrap())
+            .unwrap();
+    assert_eq!(show["schema"], 1);
+    assert_eq!(show["title"], "I tre moschettieri");
+    assert_eq!(show["slug"], "itremoschettieri");
+    assert_eq!(show["url"], SHOW_URL);
+    assert_eq!(show["description"], "Il romanzo di Dumas");
+    assert_eq!(show["image"], serde_json::Value::Null);
+    assert_eq!(show["episodes"], 1);
+    assert_eq!(show["rsnd_version"], env!("CARGO_PKG_VERSION"));
+
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--check"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains(&format!(
+        "with rsnd {}, when the show listed 1 episodes",
+        env!("CARGO_PKG_VERSION")
+    )));
+}
+
+#[test]
+fn test_resume_plan() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = serve_audio();
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", server));
+    let plan_file = folder.path().join("plan.json");
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--save-plan"])
+        .arg(&plan_file)
+        .assert()
+        .code(0);
+    let plan: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&plan_file).unwrap()).unwrap();
+    assert_eq!(plan["shows"][0]["url"], SHOW_URL);
+    assert_eq!(
+        plan["shows"][0]["episodes"][0]["path"],
+        "01 - lettura i.mp3"
+    );
+
+    // The show was renumbered and the episode renamed since: the plan stands.
+    fs::write(
+        cache.path().join("itremoschettieri.html"),
+        r#"<rps-play-with-labels options='{"url": "/audio/2015/06/prologo.json"}'></rps-play-with-labels>
+        <rps-play-with-labels options='{"url": "/audio/2015/06/lettura-1.json"}'></rps-play-with-labels>"#,
+    )
+    .unwrap();
+    fs::write(
+        cache.path().join("lettura-1.json"),
+        format!(
+            r#"{{"audio": {{"title": "Lettura prima", "url": "{}/lettura-1.mp3"}}}}"#,
+            server
+        ),
+    )
+    .unwrap();
+    let assert = rsnd_json(&cache, &folder)
+        .arg("--resume-plan")
+        .arg(&plan_file)
+        .args(["--min-size", "0"])
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(
+        stdout.contains("Resuming 1 of the 1 planned episodes"),
+        "{}",
+        stdout
+    );
+    assert!(folder.path().join("01 - lettura i.mp3").exists());
+
+    // The folder given relative to the current one is the planned one.
+    let assert = Command::cargo_bin("rsnd")
+        .unwrap()
+        .current_dir(folder.path())
+        .arg("--cache")
+        .arg(cache.path())
+        .args(["--folder", "."])
+        .arg("--resume-plan")
+        .arg(&plan_file)
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!stdout.contains("Folder:"), "{}", stdout);
+
+    let mut plan = plan;
+    plan["created_at"] = "2020-01-01T00:00:00Z".into();
+    fs::write(&plan_file, plan.to_string()).unwrap();
+    let assert = rsnd_json(&cache, &folder)
+        .arg("--resume-plan")
+        .arg(&plan_file)
+        .assert()
+        .code(0);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(
+        stdout.contains("Resuming 0 of the 1 planned episodes"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stderr.contains("the audio URLs it holds may have expired"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_removed_episodes() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    fs::write(
+        cache.path().join("lettura-2.json"),
+        format!(
+            r#"{{"audio": {{"title": "Lettura II", "url": "{}/lettura-2.mp3"}}}}"#,
+            UNREACHABLE
+        ),
+    )
+    .unwrap();
+    let page = |paths: &[&str]| {
+        let labels: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                format!(
+                    r#"<rps-play-with-labels options='{{"url": "/audio/2015/06/{}"}}'></rps-play-with-labels>"#,
+                    path
+                )
+            })
+            .collect();
+        fs::write(
+            cache.path().join("itremoschettieri.html"),
+            labels.join("\n"),
+        )
+        .unwrap();
+    };
+    page(&["lettura-1.json", "lettura-2.json"]);
+    fs::write(folder.path().join("01 - lettura i.mp3"), b"ID3").unwrap();
+    fs::write(folder.path().join("02 - lettura ii.mp3"), b"ID3").unwrap();
+    fs::write(folder.path().join("02 - lettura ii.info.json"), b"{}").unwrap();
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL])
+        .assert()
+        .code(0);
+
+    // The second episode was pulled: its file is reported, not touched.
+    page(&["lettura-1.json"]);
+    let summary = folder.path().join("summary.json");
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--summary-json"])
+        .arg(&summary)
+        .assert()
+        .code(0);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(
+        stderr.contains("002 - Lettura II is no longer available upstream: 02 - lettura ii.mp3 is the only copy left"),
+        "{}",
+        stderr
+    );
+    assert!(folder.path().join("02 - lettura ii.mp3").exists());
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    assert_eq!(report["removed"][0]["title"], "Lettura II");
+    let show: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(folder.path().join("show.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        show["removed"][0]["metadata_path"],
+        "/audio/2015/06/lettura-2.json"
+    );
+    assert_eq!(show["removed"][0]["file"], "02 - lettura ii.mp3");
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--prune-removed"])
+        .assert()
+        .code(0);
+    let removed = folder.path().join("removed");
+    assert!(removed.join("02 - lettura ii.mp3").exists());
+    assert!(removed.join("02 - lettura ii.info.json").exists());
+    assert!(!folder.path().join("02 - lettura ii.mp3").exists());
+    assert!(folder.path().join("01 - lettura i.mp3").exists());
+}
+
+#[test]
+fn test_match_existing_fuzzy() {
+    let (cache, folder) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    cache_show(cache.path(), &format!("{}/lettura-1.mp3", UNREACHABLE));
+    // Named by an older version, which kept underscores and the original case.
+    fs::write(folder.path().join("01_Lettura_I.mp3"), b"ID3").unwrap();
+
+    rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run"])
+        .assert()
+        .code(0);
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--match-existing", "fuzzy"])
+        .assert()
+        .code(4);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(
+        stdout.contains(
+            "Skipping 001 - Lettura I: found as 01_Lettura_I.mp3 (fuzzy match for 01 - lettura i.mp3)"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Skipped:        1"), "{}", stdout);
+
+    // The number of the file is the one the episode is named with.
+    fs::write(folder.path().join("27_Lettura_I.mp3"), b"ID3").unwrap();
+    let assert = rsnd(&cache, &folder)
+        .args(["--url", SHOW_URL, "--dry-run", "--match-existing", "fuzzy"])
+        .args(["--start-index", "27"])
+        .assert()
+        .code(4);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("found as 27_Lettura_I.mp3"), "{}", stdout);
+}